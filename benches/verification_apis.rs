@@ -0,0 +1,77 @@
+//! Compares the cost of verifying inputs with the legacy API, which
+//! deserializes the transaction for every input, and with a
+//! [`PrecomputedTx`], which deserializes it once:
+//!
+//! ```console
+//! cargo bench --bench verification_apis
+//! ```
+//!
+//! Each workload verifies the single input of a mainnet transaction the given
+//! number of times, as if the transaction had that many inputs.
+
+use std::time::{Duration, Instant};
+
+use zcash_script::{
+    precomputed::{Input, PrecomputedTx},
+    verify::verify_script,
+};
+
+const SCRIPT_PUBKEY: &str = "76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac";
+const SCRIPT_TX: &str = "0400008085202f8901fcaf44919d4a17f6181a02a7ebe0420be6f7dad1ef86755b81d5a9567456653c010000006a473044022035224ed7276e61affd53315eca059c92876bc2df61d84277cafd7af61d4dbf4002203ed72ea497a9f6b38eb29df08e830d99e32377edb8a574b8a289024f0241d7c40121031f54b095eae066d96b2557c1f99e40e967978a5fd117465dbec0986ca74201a6feffffff020050d6dc0100000017a9141b8a9bda4b62cd0d0582b55455d0778c86f8628f870d03c812030000001976a914e4ff5512ffafe9287992a1cd177ca6e408e0300388ac62070d0095070d000000000000000000000000";
+const AMOUNT: i64 = 212 * 100_000_000;
+const FLAGS: u32 = 1;
+const BRANCH_ID: u32 = 0x2bb4_0e60;
+
+/// The numbers of inputs to verify per transaction.
+const INPUT_COUNTS: [usize; 4] = [1, 10, 100, 1000];
+
+/// The minimum time to spend measuring each workload.
+const MIN_DURATION: Duration = Duration::from_millis(500);
+
+/// Returns the mean time per iteration of `f`.
+fn measure(mut f: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    let mut iterations = 0;
+    while start.elapsed() < MIN_DURATION {
+        f();
+        iterations += 1;
+    }
+    start.elapsed() / iterations
+}
+
+fn main() {
+    let script_pub_key = hex::decode(SCRIPT_PUBKEY).unwrap();
+    let tx_to = hex::decode(SCRIPT_TX).unwrap();
+
+    println!("inputs\tlegacy\tprecomputed\tbatch");
+    for inputs in INPUT_COUNTS {
+        let legacy = measure(|| {
+            for _ in 0..inputs {
+                verify_script(&script_pub_key, AMOUNT, &tx_to, 0, FLAGS, BRANCH_ID).unwrap();
+            }
+        });
+        let precomputed = measure(|| {
+            let tx = PrecomputedTx::new(&tx_to).unwrap();
+            for _ in 0..inputs {
+                tx.verify(0, &script_pub_key, AMOUNT, FLAGS, BRANCH_ID)
+                    .unwrap();
+            }
+        });
+        let batch_inputs = vec![
+            Input {
+                n_in: 0,
+                script_pub_key: &script_pub_key,
+                amount: AMOUNT,
+                flags: FLAGS,
+            };
+            inputs
+        ];
+        let batch = measure(|| {
+            let tx = PrecomputedTx::new(&tx_to).unwrap();
+            for result in tx.verify_batch(&batch_inputs, BRANCH_ID) {
+                result.unwrap();
+            }
+        });
+        println!("{}\t{:?}\t{:?}\t{:?}", inputs, legacy, precomputed, batch);
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::{env, fmt, fs, io::Read, path::PathBuf};
 
+use sha2::{Digest, Sha256};
 use syn::__private::ToTokens;
 
 type Result<T, E = Error> = std::result::Result<T, E>;
@@ -10,7 +11,10 @@ type Result<T, E = Error> = std::result::Result<T, E>;
 enum Error {
     GenerateBindings,
     WriteBindings(std::io::Error),
+    ReadSource(std::io::Error),
     Env(std::env::VarError),
+    #[cfg(feature = "external-secp")]
+    ExternalSecp256k1(String),
 }
 
 impl fmt::Display for Error {
@@ -18,13 +22,58 @@ impl fmt::Display for Error {
         match self {
             Error::GenerateBindings => write!(f, "unable to generate bindings: try running 'git submodule init' and 'git submodule update'"),
             Error::WriteBindings(source) => write!(f, "unable to write bindings: {}", source),
+            Error::ReadSource(source) => write!(f, "unable to read vendored source: {}", source),
             Error::Env(source) => source.fmt(f),
+            #[cfg(feature = "external-secp")]
+            Error::ExternalSecp256k1(reason) => {
+                write!(f, "unusable external libsecp256k1: {}", reason)
+            }
         }
     }
 }
 
 impl std::error::Error for Error {}
 
+/// The zcashd translation units that make up `libzcash_script.a`.
+///
+/// All of these are needed to link `zcash_script_verify`: even where
+/// `zcash_script` doesn't call a function, another file it does need
+/// references it, as noted below.
+const ZCASH_SOURCES: &[&str] = &[
+    "depend/zcash/src/script/zcash_script.cpp",
+    "depend/zcash/src/util/strencodings.cpp",
+    // `CFeeRate`, used by `CTxOut::GetDustThreshold` in transaction.cpp.
+    "depend/zcash/src/amount.cpp",
+    "depend/zcash/src/uint256.cpp",
+    "depend/zcash/src/pubkey.cpp",
+    "depend/zcash/src/hash.cpp",
+    // `ToRustStream`, used by the ZIP 243 signature hash in interpreter.cpp.
+    "depend/zcash/src/streams_rust.cpp",
+    // ZIP 317 logical actions, used by `CTransaction` in transaction.cpp.
+    "depend/zcash/src/zip317.cpp",
+    "depend/zcash/src/primitives/transaction.cpp",
+    "depend/zcash/src/crypto/ripemd160.cpp",
+    // `OP_SHA1`.
+    "depend/zcash/src/crypto/sha1.cpp",
+    "depend/zcash/src/crypto/sha256.cpp",
+    // BIP 32 public key derivation in pubkey.cpp, and the `hash` module.
+    "depend/zcash/src/crypto/sha512.cpp",
+    "depend/zcash/src/crypto/hmac_sha512.cpp",
+    "depend/zcash/src/script/interpreter.cpp",
+    "depend/zcash/src/script/script.cpp",
+    "depend/zcash/src/script/script_error.cpp",
+    "depend/zcash/src/support/cleanse.cpp",
+    // `NewBundleValidityCache`, declared in the cxx bridge we compile.
+    "depend/zcash/src/zcash/cache.cpp",
+];
+
+/// The secp256k1 translation units that make up `libsecp256k1.a`.
+const SECP256K1_SOURCES: &[&str] = &[
+    "depend/zcash/src/secp256k1/src/secp256k1.c",
+    "depend/zcash/src/secp256k1/src/precomputed_ecmult.c",
+    "depend/zcash/src/secp256k1/src/precomputed_ecmult_gen.c",
+];
+
 fn bindgen_headers() -> Result<()> {
     println!("cargo:rerun-if-changed=depend/zcash/src/script/zcash_script.h");
 
@@ -123,9 +172,53 @@ fn gen_cxxbridge() -> Result<()> {
     Ok(())
 }
 
+/// Hash the vendored source files we compile, and expose the digest to the
+/// crate as `ZCASH_SCRIPT_VENDORED_SOURCE_DIGEST`.
+///
+/// Each file contributes its path, its length and its contents, so the digest
+/// changes if a file is renamed, added or removed. Headers and the generated
+/// cxx bridge sources are not included.
+///
+/// When linking `prebuilt` libraries, the vendored sources aren't compiled,
+/// so the digest is the sentinel `prebuilt` instead.
+fn vendored_source_digest(prebuilt: bool) -> Result<()> {
+    if prebuilt {
+        println!("cargo:rustc-env=ZCASH_SCRIPT_VENDORED_SOURCE_DIGEST=prebuilt");
+        return Ok(());
+    }
+
+    let mut sources = ZCASH_SOURCES.to_vec();
+    if !cfg!(feature = "external-secp") {
+        sources.extend(SECP256K1_SOURCES);
+    }
+
+    let mut hasher = Sha256::new();
+    for source in sources {
+        println!("cargo:rerun-if-changed={}", source);
+        let content = fs::read(source).map_err(Error::ReadSource)?;
+        hasher.update((source.len() as u64).to_le_bytes());
+        hasher.update(source);
+        hasher.update((content.len() as u64).to_le_bytes());
+        hasher.update(content);
+    }
+    let digest: String = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    println!(
+        "cargo:rustc-env=ZCASH_SCRIPT_VENDORED_SOURCE_DIGEST={}",
+        digest
+    );
+
+    Ok(())
+}
+
 fn main() -> Result<()> {
     bindgen_headers()?;
     gen_cxxbridge()?;
+    let prebuilt = use_prebuilt();
+    vendored_source_digest(prebuilt)?;
 
     let rust_path = env::var("OUT_DIR").map_err(Error::Env)?;
     let rust_path = PathBuf::from(rust_path).join("rust");
@@ -168,6 +261,11 @@ fn main() -> Result<()> {
     let gen_path = PathBuf::from(gen_path).join("gen");
 
     let target = env::var("TARGET").expect("TARGET was not set");
+
+    if prebuilt {
+        return link_prebuilt(&target);
+    }
+
     let mut base_config = cc::Build::new();
 
     language_std(&mut base_config, "c++17");
@@ -192,34 +290,53 @@ fn main() -> Result<()> {
         .define("__STDC_FORMAT_MACROS", None);
 
     // **Secp256k1**
-    if !cfg!(feature = "external-secp") {
-        build_secp256k1();
-    }
+    #[cfg(feature = "external-secp")]
+    link_external_secp256k1()?;
+    #[cfg(not(feature = "external-secp"))]
+    build_secp256k1();
+    println!(
+        "cargo:rustc-env=ZCASH_SCRIPT_SECP256K1_TABLES_SIZE={}",
+        if cfg!(feature = "external-secp") {
+            0
+        } else {
+            secp256k1_tables_size()
+        }
+    );
 
     if target.contains("windows") {
         base_config.define("WIN32", "1");
     }
 
+    if cfg!(feature = "cxx-debug-assertions") {
+        debug_assertions(&mut base_config);
+        // Also check the standard library's preconditions, such as bounds on
+        // `std::vector::operator[]`.
+        base_config.define("_GLIBCXX_ASSERTIONS", None);
+    }
+
+    if cfg!(feature = "sanitizers") {
+        sanitizers(&mut base_config);
+    }
+
+    if cfg!(feature = "valgrind") {
+        // Drop the SHA-256 assembly and SSE4.1/AVX2/SHA-NI implementations,
+        // leaving the portable one.
+        valgrind(
+            &mut base_config,
+            &["USE_ASM", "ENABLE_SSE41", "ENABLE_AVX2", "ENABLE_SHANI"],
+        );
+    }
+
+    if cfg!(feature = "small") {
+        // The SHA-256 assembly and SIMD implementations are only faster.
+        small(
+            &mut base_config,
+            &["USE_ASM", "ENABLE_SSE41", "ENABLE_AVX2", "ENABLE_SHANI"],
+        );
+    }
+
     base_config
-        .file("depend/zcash/src/script/zcash_script.cpp")
-        .file("depend/zcash/src/util/strencodings.cpp")
-        .file("depend/zcash/src/amount.cpp")
-        .file("depend/zcash/src/uint256.cpp")
-        .file("depend/zcash/src/pubkey.cpp")
-        .file("depend/zcash/src/hash.cpp")
-        .file("depend/zcash/src/streams_rust.cpp")
-        .file("depend/zcash/src/zip317.cpp")
-        .file("depend/zcash/src/primitives/transaction.cpp")
-        .file("depend/zcash/src/crypto/ripemd160.cpp")
-        .file("depend/zcash/src/crypto/sha1.cpp")
-        .file("depend/zcash/src/crypto/sha256.cpp")
-        .file("depend/zcash/src/crypto/sha512.cpp")
-        .file("depend/zcash/src/crypto/hmac_sha512.cpp")
-        .file("depend/zcash/src/script/interpreter.cpp")
-        .file("depend/zcash/src/script/script.cpp")
-        .file("depend/zcash/src/script/script_error.cpp")
-        .file("depend/zcash/src/support/cleanse.cpp")
-        .file("depend/zcash/src/zcash/cache.cpp")
+        .files(ZCASH_SOURCES)
         // A subset of the files generated by gen_cxxbridge
         // which are required by zcash_script.
         .file(gen_path.join("src/blake2b.cpp"))
@@ -230,6 +347,57 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Returns true if prebuilt native libraries should be linked instead of
+/// compiling the vendored sources, because `ZCASH_SCRIPT_LIB_DIR` is set, or
+/// `ZCASH_SCRIPT_NO_VENDOR` is set to anything but `0`.
+fn use_prebuilt() -> bool {
+    println!("cargo:rerun-if-env-changed=ZCASH_SCRIPT_LIB_DIR");
+    println!("cargo:rerun-if-env-changed=ZCASH_SCRIPT_NO_VENDOR");
+    env::var_os("ZCASH_SCRIPT_LIB_DIR").is_some()
+        || env::var("ZCASH_SCRIPT_NO_VENDOR").map_or(false, |value| value != "0")
+}
+
+/// Links the prebuilt `libzcash_script.a`, and `libsecp256k1.a` unless using
+/// `external-secp`, from `ZCASH_SCRIPT_LIB_DIR` if it is set, or else from
+/// the linker's search path.
+///
+/// `libzcash_script.a` must contain the same translation units and cxx bridge
+/// sources the vendored build compiles, from the zcashd version this crate
+/// vendors. Its C++ standard library is linked as `cc` would, and can be
+/// overridden with `CXXSTDLIB`. Its C API version is checked against
+/// `ZCASH_SCRIPT_API_VER` at runtime, as the library can't be run here when
+/// cross-compiling.
+fn link_prebuilt(target: &str) -> Result<()> {
+    if let Some(lib_dir) = env::var_os("ZCASH_SCRIPT_LIB_DIR") {
+        println!(
+            "cargo:rustc-link-search=native={}",
+            PathBuf::from(lib_dir).display()
+        );
+    }
+    println!("cargo:rustc-link-lib=static=zcash_script");
+
+    #[cfg(feature = "external-secp")]
+    link_external_secp256k1()?;
+    #[cfg(not(feature = "external-secp"))]
+    println!("cargo:rustc-link-lib=static=secp256k1");
+    // The tables are in the prebuilt library, whose configuration is unknown.
+    println!("cargo:rustc-env=ZCASH_SCRIPT_SECP256K1_TABLES_SIZE=0");
+
+    println!("cargo:rerun-if-env-changed=CXXSTDLIB");
+    let stdlib = match env::var("CXXSTDLIB") {
+        Ok(stdlib) => Some(stdlib).filter(|stdlib| !stdlib.is_empty()),
+        Err(_) if target.contains("msvc") => None,
+        Err(_) if target.contains("apple") || target.contains("bsd") => Some("c++".to_string()),
+        Err(_) if target.contains("android") => Some("c++_shared".to_string()),
+        Err(_) => Some("stdc++".to_string()),
+    };
+    if let Some(stdlib) = stdlib {
+        println!("cargo:rustc-link-lib={}", stdlib);
+    }
+
+    Ok(())
+}
+
 /// Build the `secp256k1` library.
 fn build_secp256k1() {
     let mut build = cc::Build::new();
@@ -244,8 +412,14 @@ fn build_secp256k1() {
         .define("USE_NUM_NONE", "1")
         .define("USE_FIELD_INV_BUILTIN", "1")
         .define("USE_SCALAR_INV_BUILTIN", "1")
-        .define("ECMULT_WINDOW_SIZE", "15")
-        .define("ECMULT_GEN_PREC_BITS", "4")
+        .define(
+            "ECMULT_WINDOW_SIZE",
+            ecmult_window_size().to_string().as_str(),
+        )
+        .define(
+            "ECMULT_GEN_PREC_BITS",
+            ecmult_gen_prec_bits().to_string().as_str(),
+        )
         // Use the endomorphism optimization now that the patents have expired.
         .define("USE_ENDOMORPHISM", "1")
         // Technically libconsensus doesn't require the recovery feature, but `pubkey.cpp` does.
@@ -260,6 +434,25 @@ fn build_secp256k1() {
         build.define("WORDS_BIGENDIAN", "1");
     }
 
+    if cfg!(feature = "cxx-debug-assertions") {
+        debug_assertions(&mut build);
+        // Enable secp256k1's internal `VERIFY_CHECK`s.
+        build.define("VERIFY", None);
+    }
+
+    if cfg!(feature = "sanitizers") {
+        sanitizers(&mut build);
+    }
+
+    if cfg!(feature = "valgrind") {
+        // Use the C field and scalar arithmetic, not the x86_64 assembly.
+        valgrind(&mut build, &["USE_ASM_X86_64", "USE_EXTERNAL_ASM"]);
+    }
+
+    if cfg!(feature = "small") {
+        small(&mut build, &[]);
+    }
+
     if is_64bit_compilation() {
         build
             .define("USE_FIELD_5X52", "1")
@@ -271,11 +464,159 @@ fn build_secp256k1() {
             .define("USE_SCALAR_8X32", "1");
     }
 
+    build.files(SECP256K1_SOURCES).compile("libsecp256k1.a");
+}
+
+/// The oldest libsecp256k1 with `secp256k1_context_static`, which zcashd uses.
+#[cfg(feature = "external-secp")]
+const MIN_SECP256K1_VERSION: &str = "0.2.0";
+
+/// Finds the system libsecp256k1 with pkg-config and emits the directives to
+/// link it.
+///
+/// If pkg-config can't find it, linking is left to the consumer, as it was
+/// before this crate used pkg-config. A library that is found but too old, or
+/// built without the recovery module `pubkey.cpp` needs, is an error.
+#[cfg(feature = "external-secp")]
+fn link_external_secp256k1() -> Result<()> {
+    let library = match pkg_config::Config::new()
+        .cargo_metadata(false)
+        .probe("libsecp256k1")
+    {
+        Ok(library) => library,
+        Err(err) => {
+            println!(
+                "cargo:warning=libsecp256k1 not found with pkg-config, so it must be linked \
+                manually: {}",
+                err
+            );
+            return Ok(());
+        }
+    };
+
+    println!("cargo:rerun-if-changed=depend/check_secp256k1_recovery.c");
+    let has_recovery = cc::Build::new()
+        .file("depend/check_secp256k1_recovery.c")
+        .includes(&library.include_paths)
+        .cargo_metadata(false)
+        .try_compile("check_secp256k1_recovery")
+        .is_ok();
+    if !has_recovery {
+        return Err(Error::ExternalSecp256k1(
+            "the recovery module is required".to_string(),
+        ));
+    }
+
+    pkg_config::Config::new()
+        .atleast_version(MIN_SECP256K1_VERSION)
+        .probe("libsecp256k1")
+        .map_err(|_| {
+            Error::ExternalSecp256k1(format!(
+                "found version {}, but {} or later is required",
+                library.version, MIN_SECP256K1_VERSION
+            ))
+        })?;
+
+    Ok(())
+}
+
+/// The window size of the tables of multiples of the generator that speed up
+/// signature verification, from `ZCASH_SCRIPT_ECMULT_WINDOW_SIZE`.
+///
+/// The tables take `2^(w - 1) * 64` bytes: 1 MiB at the default of 15, which
+/// is also the largest size the vendored precomputed tables support, or 512
+/// bytes at the `small` default of 4.
+fn ecmult_window_size() -> u32 {
+    let default = if cfg!(feature = "small") { 4 } else { 15 };
+    env_param("ZCASH_SCRIPT_ECMULT_WINDOW_SIZE", default, |w| {
+        (2..=15).contains(&w)
+    })
+}
+
+/// The bits per entry of the table that speeds up signing and public key
+/// derivation, from `ZCASH_SCRIPT_ECMULT_GEN_PREC_BITS`.
+///
+/// The table takes `2^b * (256 / b) * 64` bytes: 32 KiB for 2, the `small`
+/// default, 64 KiB for the default of 4, and 512 KiB for 8.
+fn ecmult_gen_prec_bits() -> u32 {
+    let default = if cfg!(feature = "small") { 2 } else { 4 };
+    env_param("ZCASH_SCRIPT_ECMULT_GEN_PREC_BITS", default, |b| {
+        [2, 4, 8].contains(&b)
+    })
+}
+
+/// Returns the total size in bytes of the vendored secp256k1's precomputed
+/// tables, which is passed to the crate as `ZCASH_SCRIPT_SECP256K1_TABLES_SIZE`.
+fn secp256k1_tables_size() -> u32 {
+    let bits = ecmult_gen_prec_bits();
+    (64 << (ecmult_window_size() - 1)) + ((64 * (256 / bits)) << bits)
+}
+
+/// Reads a number from the environment variable `name`, or returns `default`
+/// if it isn't set.
+fn env_param(name: &str, default: u32, valid: impl Fn(u32) -> bool) -> u32 {
+    println!("cargo:rerun-if-env-changed={}", name);
+    match env::var(name) {
+        Err(_) => default,
+        Ok(value) => value
+            .parse()
+            .ok()
+            .filter(|&n| valid(n))
+            .unwrap_or_else(|| panic!("invalid {}: {:?}", name, value)),
+    }
+}
+
+/// Compile with `assert`s enabled, even if `NDEBUG` is set in `CFLAGS` or
+/// `CXXFLAGS`, and with debug info and frame pointers for debuggers.
+fn debug_assertions(build: &mut cc::Build) {
+    build
+        .debug(true)
+        .flag_if_supported("-UNDEBUG")
+        .flag_if_supported("-fno-omit-frame-pointer");
+}
+
+/// Instrument the code with AddressSanitizer and UndefinedBehaviorSanitizer.
+///
+/// Undefined behavior traps instead of calling into the UBSan runtime, so only
+/// the ASan runtime is needed. rustc links it when the final binary is built
+/// with `-Zsanitizer=address`, so we must not link a second copy here.
+fn sanitizers(build: &mut cc::Build) {
+    build
+        .flag("-fsanitize=address,undefined")
+        .flag_if_supported("-fsanitize-trap=undefined")
+        .flag_if_supported("-fsanitize-undefined-trap-on-error")
+        .flag("-fno-omit-frame-pointer");
+}
+
+/// Compile only portable code, so Valgrind can follow every instruction.
+///
+/// We never define `asm_defines` ourselves, but they might be set in
+/// `CFLAGS` or `CXXFLAGS`, so undefine them. AVX-512 is also disabled, because
+/// Valgrind doesn't support it, and the compiler can auto-vectorize with it
+/// when building for `-march=native`. Debug info makes reports readable.
+fn valgrind(build: &mut cc::Build, asm_defines: &[&str]) {
+    for define in asm_defines {
+        build.flag_if_supported(&format!("-U{}", define));
+    }
+    build
+        .debug(true)
+        .flag_if_supported("-mno-avx512f")
+        .flag_if_supported("-fno-omit-frame-pointer");
+}
+
+/// Optimize for size, and put each function and static in its own section,
+/// so the linker can drop the ones nothing calls.
+///
+/// Like [`valgrind`], this undefines `optional_defines`, which enable code
+/// that is only there for speed.
+fn small(build: &mut cc::Build, optional_defines: &[&str]) {
+    for define in optional_defines {
+        build.flag_if_supported(&format!("-U{}", define));
+    }
     build
-        .file("depend/zcash/src/secp256k1/src/secp256k1.c")
-        .file("depend/zcash/src/secp256k1/src/precomputed_ecmult.c")
-        .file("depend/zcash/src/secp256k1/src/precomputed_ecmult_gen.c")
-        .compile("libsecp256k1.a");
+        .opt_level_str("s")
+        .flag_if_supported("-ffunction-sections")
+        .flag_if_supported("-fdata-sections");
 }
 
 /// Checker whether the target architecture is big endian.
@@ -0,0 +1,10 @@
+#include <secp256k1.h>
+#include <secp256k1_recovery.h>
+
+int main(void) {
+    /* `pubkey.cpp` needs the recovery module and the static context. */
+    secp256k1_ecdsa_recoverable_signature sig;
+    secp256k1_pubkey pubkey;
+    unsigned char msg32[32] = {0};
+    return secp256k1_ecdsa_recover(secp256k1_context_static, &pubkey, &sig, msg32);
+}
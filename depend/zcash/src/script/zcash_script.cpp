@@ -7,12 +7,22 @@
 #include "zcash_script.h"
 
 #include "consensus/upgrades.h"
+#include "crypto/hmac_sha512.h"
+#include "crypto/sha512.h"
+#include "hash.h"
 #include "primitives/transaction.h"
 #include "pubkey.h"
 #include "script/interpreter.h"
 #include "version.h"
 
+// Defined in script/interpreter.cpp.
+bool CastToBool(const std::vector<unsigned char>& vch);
+
 namespace {
+// The ScriptError of the last script verified on this thread, reported by
+// zcash_script_last_script_error.
+thread_local ScriptError lastScriptError = SCRIPT_ERR_OK;
+
 inline int set_error(zcash_script_error* ret, zcash_script_error serror)
 {
     if (ret)
@@ -35,6 +45,81 @@ unsigned int GetLegacySigOpCount(const CTransaction& tx)
     }
     return nSigOps;
 }
+
+// Evaluates script with the given initial stack, and checks that it leaves a
+// true value on top of the stack, as VerifyScript does for a scriptPubKey.
+bool EvalWithStack(
+    std::vector<std::vector<unsigned char>> stack,
+    const CScript& script,
+    unsigned int flags,
+    const BaseSignatureChecker& checker,
+    uint32_t consensusBranchId,
+    ScriptError* serror)
+{
+    if (!EvalScript(stack, script, flags, checker, consensusBranchId, serror))
+        // serror is set
+        return false;
+    if (stack.empty() || !CastToBool(stack.back())) {
+        *serror = SCRIPT_ERR_EVAL_FALSE;
+        return false;
+    }
+    *serror = SCRIPT_ERR_OK;
+    return true;
+}
+
+// A signature checker whose signature checks are decided by a callback, and
+// whose lock time checks always succeed.
+class CallbackSignatureChecker : public BaseSignatureChecker
+{
+private:
+    zcash_script_check_sig_fn checkSig;
+    void* ctx;
+
+public:
+    CallbackSignatureChecker(zcash_script_check_sig_fn checkSigIn, void* ctxIn) : checkSig(checkSigIn), ctx(ctxIn) {}
+
+    bool CheckSig(
+        const std::vector<unsigned char>& scriptSig,
+        const std::vector<unsigned char>& vchPubKey,
+        const CScript& scriptCode,
+        uint32_t consensusBranchId) const
+    {
+        return checkSig(ctx, scriptSig.data(), scriptSig.size(), vchPubKey.data(), vchPubKey.size()) == 1;
+    }
+
+    bool CheckLockTime(const CScriptNum& nLockTime) const
+    {
+        return true;
+    }
+};
+
+// A transaction signature checker that asks a callback before each signature
+// check whether the verification is still within its budget.
+class BudgetedSignatureChecker : public TransactionSignatureChecker
+{
+private:
+    zcash_script_within_budget_fn withinBudget;
+    void* ctx;
+
+public:
+    BudgetedSignatureChecker(
+        const CTransaction* txToIn,
+        const PrecomputedTransactionData& txdataIn,
+        unsigned int nInIn,
+        const CAmount& amountIn,
+        zcash_script_within_budget_fn withinBudgetIn,
+        void* ctxIn) : TransactionSignatureChecker(txToIn, txdataIn, nInIn, amountIn), withinBudget(withinBudgetIn), ctx(ctxIn) {}
+
+    bool CheckSig(
+        const std::vector<unsigned char>& scriptSig,
+        const std::vector<unsigned char>& vchPubKey,
+        const CScript& scriptCode,
+        uint32_t consensusBranchId) const
+    {
+        return withinBudget(ctx) == 1 &&
+            TransactionSignatureChecker::CheckSig(scriptSig, vchPubKey, scriptCode, consensusBranchId);
+    }
+};
 }
 
 struct PrecomputedTransaction {
@@ -129,6 +214,7 @@ int zcash_script_verify_precomputed(
     uint32_t consensusBranchId,
     zcash_script_error* err)
 {
+    lastScriptError = SCRIPT_ERR_OK;
     const PrecomputedTransaction* preTx = static_cast<const PrecomputedTransaction*>(pre_preTx);
     if (nIn >= preTx->tx.vin.size())
         return set_error(err, zcash_script_ERR_TX_INDEX);
@@ -141,7 +227,65 @@ int zcash_script_verify_precomputed(
         flags,
         TransactionSignatureChecker(&preTx->tx, preTx->txdata, nIn, amount),
         consensusBranchId,
-        NULL);
+        &lastScriptError);
+}
+
+int zcash_script_verify_precomputed_budgeted(
+    const void* pre_preTx,
+    unsigned int nIn,
+    const unsigned char* scriptPubKey,
+    unsigned int scriptPubKeyLen,
+    int64_t amount,
+    unsigned int flags,
+    uint32_t consensusBranchId,
+    zcash_script_within_budget_fn withinBudget,
+    void* ctx,
+    zcash_script_error* err)
+{
+    lastScriptError = SCRIPT_ERR_OK;
+    const PrecomputedTransaction* preTx = static_cast<const PrecomputedTransaction*>(pre_preTx);
+    if (nIn >= preTx->tx.vin.size())
+        return set_error(err, zcash_script_ERR_TX_INDEX);
+
+    // Regardless of the verification result, the tx did not error.
+    set_error(err, zcash_script_ERR_OK);
+    return VerifyScript(
+        preTx->tx.vin[nIn].scriptSig,
+        CScript(scriptPubKey, scriptPubKey + scriptPubKeyLen),
+        flags,
+        BudgetedSignatureChecker(&preTx->tx, preTx->txdata, nIn, amount, withinBudget, ctx),
+        consensusBranchId,
+        &lastScriptError);
+}
+
+int zcash_script_verify_precomputed_batch(
+    const void* preTx,
+    const zcash_script_input* inputs,
+    unsigned int inputsLen,
+    uint32_t consensusBranchId,
+    int* results,
+    zcash_script_error* errs)
+{
+    int allValid = 1;
+    for (unsigned int i = 0; i < inputsLen; i++) {
+        const zcash_script_input& input = inputs[i];
+        int ret = zcash_script_verify_precomputed(
+            preTx,
+            input.nIn,
+            input.scriptPubKey,
+            input.scriptPubKeyLen,
+            input.amount,
+            input.flags,
+            consensusBranchId,
+            errs == NULL ? NULL : &errs[i]);
+        if (results != NULL) {
+            results[i] = ret;
+        }
+        if (ret != 1) {
+            allValid = 0;
+        }
+    }
+    return allValid;
 }
 
 int zcash_script_verify(
@@ -152,6 +296,7 @@ int zcash_script_verify(
     uint32_t consensusBranchId,
     zcash_script_error* err)
 {
+    lastScriptError = SCRIPT_ERR_OK;
     try {
         const char* txToEnd = (const char *)(txTo + txToLen);
         RustDataStream stream((const char *)txTo, txToEnd, SER_NETWORK, PROTOCOL_VERSION);
@@ -176,7 +321,7 @@ int zcash_script_verify(
             flags,
             TransactionSignatureChecker(&tx, txdata, nIn, amount),
             consensusBranchId,
-            NULL);
+            &lastScriptError);
     } catch (const std::exception&) {
         return set_error(err, zcash_script_ERR_TX_DESERIALIZE); // Error deserializing
     }
@@ -192,6 +337,7 @@ int zcash_script_verify_v5(
     uint32_t consensusBranchId,
     zcash_script_error* err)
 {
+    lastScriptError = SCRIPT_ERR_OK;
     CTransaction tx;
     try {
         const char* txToEnd = (const char *)(txTo + txToLen);
@@ -234,12 +380,92 @@ int zcash_script_verify_v5(
             flags,
             TransactionSignatureChecker(&tx, txdata, nIn, prevOutputs[nIn].nValue),
             consensusBranchId,
-            NULL);
+            &lastScriptError);
     } catch (const std::exception&) {
         return set_error(err, zcash_script_ERR_VERIFY_SCRIPT); // Error during script verification
     }
 }
 
+unsigned int zcash_script_last_script_error()
+{
+    return lastScriptError;
+}
+
+int zcash_script_eval_with_stack(
+    const unsigned char* const* stack,
+    const unsigned int* stackLens,
+    unsigned int stackLen,
+    const unsigned char* scriptPubKey,
+    unsigned int scriptPubKeyLen,
+    unsigned int flags,
+    const void* pre_preTx,
+    unsigned int nIn,
+    int64_t amount,
+    uint32_t consensusBranchId,
+    unsigned int* scriptErr,
+    zcash_script_error* err)
+{
+    std::vector<std::vector<unsigned char>> initialStack;
+    for (unsigned int i = 0; i < stackLen; i++) {
+        initialStack.emplace_back(stack[i], stack[i] + stackLens[i]);
+    }
+    CScript script(scriptPubKey, scriptPubKey + scriptPubKeyLen);
+
+    ScriptError serror = SCRIPT_ERR_UNKNOWN_ERROR;
+    int ret;
+    if (pre_preTx == NULL) {
+        set_error(err, zcash_script_ERR_OK);
+        ret = EvalWithStack(
+            initialStack, script, flags, BaseSignatureChecker(), consensusBranchId, &serror);
+    } else {
+        const PrecomputedTransaction* preTx = static_cast<const PrecomputedTransaction*>(pre_preTx);
+        if (nIn >= preTx->tx.vin.size())
+            return set_error(err, zcash_script_ERR_TX_INDEX);
+
+        // Regardless of the evaluation result, the tx did not error.
+        set_error(err, zcash_script_ERR_OK);
+        ret = EvalWithStack(
+            initialStack,
+            script,
+            flags,
+            TransactionSignatureChecker(&preTx->tx, preTx->txdata, nIn, amount),
+            consensusBranchId,
+            &serror);
+    }
+    if (scriptErr)
+        *scriptErr = serror;
+    return ret;
+}
+
+int zcash_script_eval_mocked(
+    const unsigned char* const* stack,
+    const unsigned int* stackLens,
+    unsigned int stackLen,
+    const unsigned char* scriptPubKey,
+    unsigned int scriptPubKeyLen,
+    unsigned int flags,
+    zcash_script_check_sig_fn checkSig,
+    void* ctx,
+    unsigned int* scriptErr)
+{
+    std::vector<std::vector<unsigned char>> initialStack;
+    for (unsigned int i = 0; i < stackLen; i++) {
+        initialStack.emplace_back(stack[i], stack[i] + stackLens[i]);
+    }
+
+    ScriptError serror = SCRIPT_ERR_UNKNOWN_ERROR;
+    int ret = EvalWithStack(
+        initialStack,
+        CScript(scriptPubKey, scriptPubKey + scriptPubKeyLen),
+        flags,
+        CallbackSignatureChecker(checkSig, ctx),
+        0,
+        &serror);
+    if (scriptErr)
+        *scriptErr = serror;
+    return ret;
+}
+
 unsigned int zcash_script_legacy_sigop_count_precomputed(
     const void* pre_preTx,
     zcash_script_error* err)
@@ -277,6 +503,94 @@ unsigned int zcash_script_legacy_sigop_count(
     }
 }
 
+void zcash_script_sha512(
+    const unsigned char* data,
+    unsigned int dataLen,
+    unsigned char* out)
+{
+    CSHA512().Write(data, dataLen).Finalize(out);
+}
+
+void zcash_script_hmac_sha512(
+    const unsigned char* key,
+    unsigned int keyLen,
+    const unsigned char* data,
+    unsigned int dataLen,
+    unsigned char* out)
+{
+    CHMAC_SHA512(key, keyLen).Write(data, dataLen).Finalize(out);
+}
+
+namespace {
+class Hasher
+{
+public:
+    virtual ~Hasher() {}
+    virtual Hasher* Clone() const = 0;
+    virtual void Write(const unsigned char* data, size_t len) = 0;
+    virtual void Finalize(unsigned char* out) = 0;
+    virtual void Reset() = 0;
+};
+
+template <typename H>
+class HasherImpl : public Hasher
+{
+    H hasher;
+
+public:
+    Hasher* Clone() const override { return new HasherImpl(*this); }
+    void Write(const unsigned char* data, size_t len) override { hasher.Write(data, len); }
+    void Finalize(unsigned char* out) override { hasher.Finalize(out); }
+    void Reset() override { hasher.Reset(); }
+};
+}
+
+void* zcash_script_hasher_new(zcash_script_hash_function function)
+{
+    switch (function) {
+    case zcash_script_HASH_SHA256:
+        return new HasherImpl<CSHA256>();
+    case zcash_script_HASH_RIPEMD160:
+        return new HasherImpl<CRIPEMD160>();
+    case zcash_script_HASH_HASH160:
+        return new HasherImpl<CHash160>();
+    case zcash_script_HASH_HASH256:
+        return new HasherImpl<CHash256>();
+    default:
+        return NULL;
+    }
+}
+
+void* zcash_script_hasher_clone(const void* hasher)
+{
+    return static_cast<const Hasher*>(hasher)->Clone();
+}
+
+void zcash_script_hasher_write(
+    void* hasher,
+    const unsigned char* data,
+    unsigned int dataLen)
+{
+    static_cast<Hasher*>(hasher)->Write(data, dataLen);
+}
+
+void zcash_script_hasher_finalize(void* hasher, unsigned char* out)
+{
+    Hasher* h = static_cast<Hasher*>(hasher);
+    h->Finalize(out);
+    h->Reset();
+}
+
+void zcash_script_hasher_reset(void* hasher)
+{
+    static_cast<Hasher*>(hasher)->Reset();
+}
+
+void zcash_script_hasher_free(void* hasher)
+{
+    delete static_cast<Hasher*>(hasher);
+}
+
 unsigned int zcash_script_version()
 {
     // Just use the API version for now
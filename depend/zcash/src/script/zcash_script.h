@@ -34,7 +34,11 @@
 extern "C" {
 #endif
 
-#define ZCASH_SCRIPT_API_VER 3
+// Version 4 added zcash_script_verify_precomputed_batch,
+// zcash_script_verify_precomputed_budgeted, zcash_script_last_script_error,
+// zcash_script_eval_with_stack, zcash_script_eval_mocked, and the SHA-512,
+// HMAC-SHA-512 and hasher functions.
+#define ZCASH_SCRIPT_API_VER 4
 
 typedef enum zcash_script_error_t
 {
@@ -57,6 +61,33 @@ enum
     zcash_script_SCRIPT_FLAGS_VERIFY_CHECKLOCKTIMEVERIFY = (1U << 9), // enable CHECKLOCKTIMEVERIFY (BIP65)
 };
 
+/// Decides the outcome of a signature check for zcash_script_eval_mocked,
+/// given the ctx passed to it. Returns 1 if the sigLen-byte signature sig is
+/// to be treated as a valid signature by the pubKeyLen-byte public key
+/// pubKey, and 0 otherwise.
+typedef int (*zcash_script_check_sig_fn)(
+    void* ctx,
+    const unsigned char* sig,
+    unsigned int sigLen,
+    const unsigned char* pubKey,
+    unsigned int pubKeyLen);
+
+/// Decides whether a verification by zcash_script_verify_precomputed_budgeted
+/// may make another signature check, given the ctx passed to it. Returns 1 if
+/// it may, and 0 if the budget is exhausted, which fails the check.
+typedef int (*zcash_script_within_budget_fn)(void* ctx);
+
+/// A transparent input of a precomputed transaction to be verified by
+/// zcash_script_verify_precomputed_batch.
+typedef struct zcash_script_input
+{
+    unsigned int nIn;
+    const unsigned char* scriptPubKey;
+    unsigned int scriptPubKeyLen;
+    int64_t amount;
+    unsigned int flags;
+} zcash_script_input;
+
 /// Deserializes the given transaction and precomputes values to improve
 /// script verification performance.
 ///
@@ -116,6 +147,40 @@ EXPORT_SYMBOL int zcash_script_verify_precomputed(
     uint32_t consensusBranchId,
     zcash_script_error* err);
 
+/// Verifies the input nIn of the precomputed transaction pointed to by preTx
+/// as zcash_script_verify_precomputed does, except that withinBudget is
+/// called with ctx before each signature check, and the check fails if it
+/// returns 0.
+///
+/// The interpreter can't be interrupted between opcodes, so this bounds the
+/// number and timing of signature checks, which dominate its cost.
+EXPORT_SYMBOL int zcash_script_verify_precomputed_budgeted(
+    const void* preTx,
+    unsigned int nIn,
+    const unsigned char* scriptPubKey,
+    unsigned int scriptPubKeyLen,
+    int64_t amount,
+    unsigned int flags,
+    uint32_t consensusBranchId,
+    zcash_script_within_budget_fn withinBudget,
+    void* ctx,
+    zcash_script_error* err);
+
+/// Verifies inputsLen inputs of the precomputed transaction pointed to by
+/// preTx in a single call. Each entry of inputs is checked as if by
+/// zcash_script_verify_precomputed.
+///
+/// Returns 1 if every input correctly spends its scriptPubKey, and 0 otherwise.
+/// If not NULL, results and errs must point to arrays of inputsLen elements,
+/// which will contain the return value and error/success code for each input.
+EXPORT_SYMBOL int zcash_script_verify_precomputed_batch(
+    const void* preTx,
+    const zcash_script_input* inputs,
+    unsigned int inputsLen,
+    uint32_t consensusBranchId,
+    int* results,
+    zcash_script_error* errs);
+
 /// Returns 1 if the input nIn of the serialized transaction pointed to by
 /// txTo correctly spends the scriptPubKey pointed to by scriptPubKey under
 /// the additional constraints specified by flags.
@@ -157,6 +222,61 @@ EXPORT_SYMBOL int zcash_script_verify_v5(
     uint32_t consensusBranchId,
     zcash_script_error* err);
 
+/// Returns the interpreter's ScriptError_t code for the last call on this
+/// thread to zcash_script_verify, zcash_script_verify_v5 or
+/// zcash_script_verify_precomputed, including those made by
+/// zcash_script_verify_precomputed_batch. This is SCRIPT_ERR_OK if that call
+/// failed before evaluating any script.
+EXPORT_SYMBOL unsigned int zcash_script_last_script_error();
+
+/// Evaluates the scriptPubKey pointed to by scriptPubKey under the
+/// constraints specified by flags, with the stack initially holding the
+/// stackLen elements pointed to by stack, whose lengths are pointed to by
+/// stackLens, from the bottom of the stack to the top. The scriptPubKey is
+/// evaluated as it is after a scriptSig, but P2SH redeem scripts are not
+/// evaluated.
+///
+/// If preTx is not NULL, signatures and lock times are checked as for the
+/// input nIn of the precomputed transaction it points to, spending amount.
+/// Otherwise, every signature and lock time check fails.
+///
+/// Returns 1 if the script evaluates successfully and leaves a true value on
+/// top of the stack, and 0 otherwise. If not NULL, scriptErr will contain the
+/// interpreter's ScriptError_t code, and err an error/success code for the
+/// operation.
+EXPORT_SYMBOL int zcash_script_eval_with_stack(
+    const unsigned char* const* stack,
+    const unsigned int* stackLens,
+    unsigned int stackLen,
+    const unsigned char* scriptPubKey,
+    unsigned int scriptPubKeyLen,
+    unsigned int flags,
+    const void* preTx,
+    unsigned int nIn,
+    int64_t amount,
+    uint32_t consensusBranchId,
+    unsigned int* scriptErr,
+    zcash_script_error* err);
+
+/// Evaluates the scriptPubKey pointed to by scriptPubKey as
+/// zcash_script_eval_with_stack does without a transaction, except that each
+/// signature check that passes the encoding checks required by flags has the
+/// outcome returned by checkSig, and every lock time check succeeds.
+///
+/// Returns 1 if the script evaluates successfully and leaves a true value on
+/// top of the stack, and 0 otherwise. If not NULL, scriptErr will contain the
+/// interpreter's ScriptError_t code.
+EXPORT_SYMBOL int zcash_script_eval_mocked(
+    const unsigned char* const* stack,
+    const unsigned int* stackLens,
+    unsigned int stackLen,
+    const unsigned char* scriptPubKey,
+    unsigned int scriptPubKeyLen,
+    unsigned int flags,
+    zcash_script_check_sig_fn checkSig,
+    void* ctx,
+    unsigned int* scriptErr);
+
 /// Returns the number of transparent signature operations in the
 /// transparent inputs and outputs of the precomputed transaction
 /// pointed to by preTx.
@@ -178,6 +298,61 @@ EXPORT_SYMBOL unsigned int zcash_script_legacy_sigop_count(
     unsigned int txToLen,
     zcash_script_error* err);
 
+/// Computes the SHA-512 digest of the dataLen bytes pointed to by data, and
+/// writes the 64-byte result to out.
+EXPORT_SYMBOL void zcash_script_sha512(
+    const unsigned char* data,
+    unsigned int dataLen,
+    unsigned char* out);
+
+/// Computes the HMAC-SHA512 of the dataLen bytes pointed to by data, keyed
+/// with the keyLen bytes pointed to by key, and writes the 64-byte result to
+/// out.
+EXPORT_SYMBOL void zcash_script_hmac_sha512(
+    const unsigned char* key,
+    unsigned int keyLen,
+    const unsigned char* data,
+    unsigned int dataLen,
+    unsigned char* out);
+
+/// The hash functions of the hashers returned by zcash_script_hasher_new.
+typedef enum zcash_script_hash_function_t
+{
+    zcash_script_HASH_SHA256 = 0,
+    zcash_script_HASH_RIPEMD160,
+    // RIPEMD-160 of SHA-256.
+    zcash_script_HASH_HASH160,
+    // Double SHA-256.
+    zcash_script_HASH_HASH256,
+} zcash_script_hash_function;
+
+/// Returns a streaming hasher computing the hash function function, which
+/// must be freed with zcash_script_hasher_free, or NULL if the function is
+/// unknown.
+EXPORT_SYMBOL void* zcash_script_hasher_new(zcash_script_hash_function function);
+
+/// Returns a copy of the hasher, in the same state, which must be freed with
+/// zcash_script_hasher_free.
+EXPORT_SYMBOL void* zcash_script_hasher_clone(const void* hasher);
+
+/// Feeds the dataLen bytes pointed to by data to the hasher.
+EXPORT_SYMBOL void zcash_script_hasher_write(
+    void* hasher,
+    const unsigned char* data,
+    unsigned int dataLen);
+
+/// Writes the digest of the data fed to the hasher to out, which must have
+/// room for the hash function's output (32 bytes, or 20 for RIPEMD-160 and
+/// HASH160), and resets the hasher.
+EXPORT_SYMBOL void zcash_script_hasher_finalize(void* hasher, unsigned char* out);
+
+/// Resets the hasher to its initial state.
+EXPORT_SYMBOL void zcash_script_hasher_reset(void* hasher);
+
+/// Frees a hasher returned by zcash_script_hasher_new or
+/// zcash_script_hasher_clone.
+EXPORT_SYMBOL void zcash_script_hasher_free(void* hasher);
+
 /// Returns the current version of the zcash_script library.
 EXPORT_SYMBOL unsigned int zcash_script_version();
 
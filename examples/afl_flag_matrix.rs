@@ -0,0 +1,20 @@
+//! A fuzzing harness for AFL++ and honggfuzz that verifies a single input
+//! from stdin under every combination of verification flags, checking the
+//! invariants of `zcash_script::fuzz::fuzz_flag_matrix`.
+//!
+//! Inputs use the same format as `afl_harness`, so its corpus can be reused,
+//! typically after minimizing it:
+//!
+//! ```console
+//! cargo afl build --example afl_flag_matrix --features test-dependencies
+//! cargo afl fuzz -i corpus -o findings target/debug/examples/afl_flag_matrix
+//! ```
+
+use std::io::{self, Read};
+
+fn main() -> io::Result<()> {
+    let mut data = Vec::new();
+    io::stdin().read_to_end(&mut data)?;
+    zcash_script::fuzz::fuzz_flag_matrix(&data);
+    Ok(())
+}
@@ -0,0 +1,21 @@
+//! A fuzzing harness for AFL++ and honggfuzz that reads a single input from
+//! stdin, in the format described by `zcash_script::fuzz::FuzzInput::decode`.
+//!
+//! Build it with the fuzzer's compiler wrappers, for example:
+//!
+//! ```console
+//! cargo afl build --example afl_harness --features test-dependencies
+//! cargo afl fuzz -i corpus -o findings target/debug/examples/afl_harness
+//! ```
+//!
+//! For persistent mode, wrap the call to `fuzz_one` in the `afl::fuzz!` macro
+//! (or honggfuzz's `fuzz!`) in a local copy of this harness.
+
+use std::io::{self, Read};
+
+fn main() -> io::Result<()> {
+    let mut data = Vec::new();
+    io::stdin().read_to_end(&mut data)?;
+    zcash_script::fuzz::fuzz_one(&data);
+    Ok(())
+}
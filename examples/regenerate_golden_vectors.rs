@@ -0,0 +1,20 @@
+//! Regenerates the golden vectors checked by `zcash_script::golden`, writing
+//! them to stdout:
+//!
+//! ```console
+//! cargo run --example regenerate_golden_vectors --features test-dependencies \
+//!     > tests/data/golden_vectors.tsv
+//! ```
+//!
+//! Only regenerate the vectors after an intentional behavior change, and
+//! review the diff.
+
+use zcash_script::golden;
+
+fn main() {
+    println!("# Generated by `cargo run --example regenerate_golden_vectors`.");
+    println!("# scriptPubKey\tamount\ttx\tnIn\tflags\tbranch ID\texpected");
+    for vector in golden::regenerate() {
+        println!("{}", vector);
+    }
+}
@@ -0,0 +1,138 @@
+//! Verifies every transparent input of a mainnet transaction through the
+//! stable `v1` API, choosing the consensus branch and flags by block height.
+//!
+//! Without arguments, this verifies an embedded Blossom transaction that
+//! spends a P2PKH output:
+//!
+//! ```console
+//! cargo run --example verify_mainnet_tx
+//! ```
+//!
+//! To verify another transaction, pass it and the height of the block it is
+//! mined in, followed by the scriptPubKey and value in zatoshis of each
+//! output it spends, in input order, for example from a node's
+//! `getrawtransaction` RPC:
+//!
+//! ```console
+//! cargo run --example verify_mainnet_tx -- <tx hex> <height> \
+//!     <scriptPubKey hex>:<amount>...
+//! ```
+
+use std::{collections::BTreeMap, env, process::ExitCode};
+
+use zcash_primitives::{
+    consensus::{BlockHeight, BranchId, MainNetwork},
+    transaction::Transaction,
+};
+use zcash_script::v1::{self, OutPoint, PrevOut, Script, TxOutcome, VerificationFlags};
+
+/// A mainnet Blossom transaction, whose input spends a P2PKH output worth
+/// 212 ZEC.
+const TX_HEX: &str = "0400008085202f8901fcaf44919d4a17f6181a02a7ebe0420be6f7dad1ef86755b81d5a9567456653c010000006a473044022035224ed7276e61affd53315eca059c92876bc2df61d84277cafd7af61d4dbf4002203ed72ea497a9f6b38eb29df08e830d99e32377edb8a574b8a289024f0241d7c40121031f54b095eae066d96b2557c1f99e40e967978a5fd117465dbec0986ca74201a6feffffff020050d6dc0100000017a9141b8a9bda4b62cd0d0582b55455d0778c86f8628f870d03c812030000001976a914e4ff5512ffafe9287992a1cd177ca6e408e0300388ac62070d0095070d000000000000000000000000";
+
+/// A height the transaction can be mined at. Its lock time and expiry height
+/// only allow heights 854883 to 854933, all in the Blossom epoch.
+const HEIGHT: u32 = 854883;
+
+/// The output spent by the transaction's input.
+const PREV_OUT: (&str, i64) = (
+    "76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac",
+    212 * 100_000_000,
+);
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Verifies the transaction, returning whether every input is valid.
+fn run(args: &[String]) -> Result<bool, String> {
+    let (tx_hex, height, prev_outs) = match args {
+        [] => (
+            TX_HEX.to_owned(),
+            HEIGHT,
+            vec![(PREV_OUT.0.to_owned(), PREV_OUT.1)],
+        ),
+        [tx, height, prev_outs @ ..] => {
+            let height: u32 = height
+                .parse()
+                .map_err(|e| format!("invalid height: {}", e))?;
+            let prev_outs = prev_outs
+                .iter()
+                .map(|prev_out| -> Result<(String, i64), String> {
+                    let (script, amount) = prev_out
+                        .split_once(':')
+                        .ok_or_else(|| format!("expected <scriptPubKey>:<amount>: {}", prev_out))?;
+                    let amount = amount
+                        .parse()
+                        .map_err(|e| format!("invalid amount: {}", e))?;
+                    Ok((script.to_owned(), amount))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            (tx.clone(), height, prev_outs)
+        }
+        _ => return Err("expected <tx hex> <height> <scriptPubKey>:<amount>...".to_owned()),
+    };
+
+    // The consensus branch and flags depend on the network upgrade active at
+    // the block's height, and `verify_tx_at` selects them.
+    let height = BlockHeight::from_u32(height);
+    let branch_id = BranchId::for_height(&MainNetwork, height);
+    let flags = VerificationFlags::consensus_at(&MainNetwork, height);
+    println!("height {}: branch {:?}, flags {}", height, branch_id, flags);
+
+    let tx_to = hex::decode(tx_hex).map_err(|e| format!("invalid transaction hex: {}", e))?;
+    let outpoints: Vec<OutPoint> = Transaction::read(&tx_to[..], branch_id)
+        .map_err(|e| format!("invalid transaction: {}", e))?
+        .transparent_bundle()
+        .map_or(vec![], |bundle| {
+            bundle
+                .vin
+                .iter()
+                .map(|input| input.prevout.clone())
+                .collect()
+        });
+    if outpoints.len() != prev_outs.len() {
+        return Err(format!(
+            "the transaction has {} transparent inputs, but {} spent outputs were given",
+            outpoints.len(),
+            prev_outs.len()
+        ));
+    }
+    let mut utxos = BTreeMap::new();
+    for (outpoint, (script, amount)) in outpoints.into_iter().zip(prev_outs) {
+        let script_pub_key =
+            Script(hex::decode(script).map_err(|e| format!("invalid scriptPubKey hex: {}", e))?);
+        utxos.insert(
+            outpoint,
+            PrevOut {
+                script_pub_key,
+                amount,
+            },
+        );
+    }
+
+    let outcome = v1::verify_tx_at(&tx_to, &utxos, &MainNetwork, height)
+        .map_err(|e| format!("can't verify the transaction: {}", e))?;
+    let results = match outcome {
+        TxOutcome::NothingToVerify => {
+            println!("no transparent inputs to verify");
+            return Ok(true);
+        }
+        TxOutcome::Verified(results) => results,
+    };
+    for (n_in, result) in results.iter().enumerate() {
+        match result {
+            Ok(()) => println!("input {}: valid", n_in),
+            Err(err) => println!("input {}: invalid: {}", n_in, err),
+        }
+    }
+    Ok(results.iter().all(Result::is_ok))
+}
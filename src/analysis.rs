@@ -0,0 +1,196 @@
+//! Extraction of the signatures and public keys involved in spending a
+//! standard scriptPubKey.
+//!
+//! This parses the scriptSig against the template identified by
+//! [`solve`], without evaluating either script. Signatures are not checked.
+
+use crate::{
+    encoding::{decode_with, FromHex, HexError},
+    script::Script,
+    sighash::SighashType,
+    standard::{hash160, solve, Template},
+};
+
+/// A signature pushed by a scriptSig.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Signature {
+    /// The DER-encoded signature, without the hash type.
+    pub der: Vec<u8>,
+    /// The hash type byte appended to the signature.
+    ///
+    /// This is kept as a byte, because signatures with undefined hash types
+    /// are valid in transactions that were verified without `STRICTENC`.
+    pub hash_type: u8,
+}
+
+impl Signature {
+    /// Splits a pushed signature into its DER encoding and hash type.
+    ///
+    /// Returns `None` for an empty push.
+    pub fn from_push(data: &[u8]) -> Option<Self> {
+        let (&hash_type, der) = data.split_last()?;
+        Some(Signature {
+            der: der.to_vec(),
+            hash_type,
+        })
+    }
+
+    /// Returns the signature as pushed by a scriptSig: its DER encoding
+    /// followed by the hash type.
+    pub fn to_push(&self) -> Vec<u8> {
+        let mut push = self.der.clone();
+        push.push(self.hash_type);
+        push
+    }
+
+    /// Returns the hex of the signature as pushed by a scriptSig.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.to_push())
+    }
+
+    /// Returns the signature's hash type, or `None` if it's undefined.
+    pub fn sighash_type(&self) -> Option<SighashType> {
+        SighashType::from_u8(self.hash_type)
+    }
+}
+
+impl FromHex for Signature {
+    type Error = HexError;
+
+    /// Decodes a signature as pushed by a scriptSig, like
+    /// [`Signature::from_push`].
+    fn from_hex<T: AsRef<[u8]>>(hex: T) -> Result<Self, Self::Error> {
+        decode_with(hex, Signature::from_push)
+    }
+}
+
+/// The signatures and public keys involved in a spend.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Spend {
+    /// The signatures provided by the scriptSig, in order.
+    pub signatures: Vec<Signature>,
+    /// The public keys that can sign for the output, in order. For P2PKH,
+    /// this is the public key revealed by the scriptSig.
+    pub pubkeys: Vec<Vec<u8>>,
+    /// The redeem script revealed by a P2SH scriptSig.
+    pub redeem_script: Option<Script>,
+}
+
+/// Returns the signatures and public keys involved when `script_sig` spends
+/// `script_pub_key`.
+///
+/// Returns `None` if `script_pub_key` isn't a spendable standard template,
+/// if `script_sig` isn't push-only, or if its pushes don't have the shape the
+/// template requires (including a P2PKH public key or P2SH redeem script that
+/// doesn't match the hash).
+pub fn extract_spend(script_sig: &Script, script_pub_key: &Script) -> Option<Spend> {
+    if !script_sig.is_push_only() {
+        return None;
+    }
+    let pushes: Vec<&[u8]> = script_sig
+        .instructions()
+        .map(|op| op.map(|op| op.data))
+        .collect::<Result<_, _>>()
+        .ok()?;
+
+    match solve(script_pub_key)? {
+        Template::ScriptHash(hash) => {
+            let (redeem_script, pushes) = pushes.split_last()?;
+            if hash160(redeem_script) != hash {
+                return None;
+            }
+            let redeem_script = Script::from(*redeem_script);
+            let spend = extract_from_pushes(pushes, solve(&redeem_script)?)?;
+            Some(Spend {
+                redeem_script: Some(redeem_script),
+                ..spend
+            })
+        }
+        template => extract_from_pushes(&pushes, template),
+    }
+}
+
+fn extract_from_pushes(pushes: &[&[u8]], template: Template) -> Option<Spend> {
+    let (signatures, pubkeys) = match (template, pushes) {
+        (Template::PubKey(pubkey), [sig]) => (vec![*sig], vec![pubkey]),
+        (Template::PubKeyHash(hash), [sig, pubkey]) => {
+            if hash160(pubkey) != hash {
+                return None;
+            }
+            (vec![*sig], vec![pubkey.to_vec()])
+        }
+        // The first push is the dummy element consumed by OP_CHECKMULTISIG.
+        (Template::MultiSig { pubkeys, .. }, [_, sigs @ ..]) => (sigs.to_vec(), pubkeys),
+        _ => return None,
+    };
+
+    Some(Spend {
+        signatures: signatures
+            .into_iter()
+            .filter_map(Signature::from_push)
+            .collect(),
+        pubkeys,
+        redeem_script: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcode::Opcode;
+    use hex::FromHex;
+
+    fn script(hex: &str) -> Script {
+        Script::from_hex(hex).unwrap()
+    }
+
+    #[test]
+    fn extracts_p2pkh_and_p2sh_spends() {
+        let script_sig = script("473044022035224ed7276e61affd53315eca059c92876bc2df61d84277cafd7af61d4dbf4002203ed72ea497a9f6b38eb29df08e830d99e32377edb8a574b8a289024f0241d7c40121031f54b095eae066d96b2557c1f99e40e967978a5fd117465dbec0986ca74201a6");
+        let p2pkh = script("76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac");
+
+        let spend = extract_spend(&script_sig, &p2pkh).unwrap();
+        assert_eq!(spend.signatures.len(), 1);
+        assert_eq!(spend.signatures[0].der.len(), 70);
+        assert_eq!(spend.signatures[0].sighash_type(), Some(SighashType::ALL));
+        let signature = &spend.signatures[0];
+        assert_eq!(&script_sig.as_bytes()[1..72], signature.to_push());
+        assert_eq!(
+            Signature::from_hex(signature.to_hex()).as_ref(),
+            Ok(signature)
+        );
+        assert_eq!(Signature::from_hex(""), Err(HexError::Invalid));
+        assert_eq!(spend.pubkeys.len(), 1);
+        assert_eq!(spend.redeem_script, None);
+
+        // The same scriptSig doesn't match a different public key hash.
+        let other = script("76a914000000000000000000000000000000000000000088ac");
+        assert_eq!(extract_spend(&script_sig, &other), None);
+
+        // A 1-of-1 multisig in P2SH, signed with SIGHASH_SINGLE|ANYONECANPAY.
+        let pubkey = &spend.pubkeys[0];
+        let mut redeem_script = Script::default();
+        redeem_script
+            .push_num(1)
+            .push_slice(pubkey)
+            .push_num(1)
+            .push_opcode(Opcode::OP_CHECKMULTISIG);
+        let mut p2sh = Script::default();
+        p2sh.push_opcode(Opcode::OP_HASH160)
+            .push_slice(&hash160(redeem_script.as_bytes()))
+            .push_opcode(Opcode::OP_EQUAL);
+        let mut script_sig = Script::default();
+        script_sig
+            .push_num(0)
+            .push_slice(&[0x30, 0x00, 0x83])
+            .push_slice(redeem_script.as_bytes());
+
+        let spend = extract_spend(&script_sig, &p2sh).unwrap();
+        assert_eq!(
+            spend.signatures[0].sighash_type(),
+            Some(SighashType::SINGLE.with_anyone_can_pay(true))
+        );
+        assert_eq!(spend.pubkeys, [pubkey.clone()]);
+        assert_eq!(spend.redeem_script, Some(redeem_script));
+    }
+}
@@ -0,0 +1,166 @@
+//! Asynchronous script verification.
+//!
+//! Verification is CPU-bound and blocks the calling thread, so these functions
+//! run it on the global `rayon` thread pool and return a future that resolves
+//! to its result. They don't depend on any particular async runtime.
+//!
+//! The functions take ownership of their buffers, which can be any shared
+//! byte container such as `Arc<[u8]>` or `bytes::Bytes`, so that bytes
+//! already held by the caller don't need to be copied for each verification.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crate::verify::{self, Error};
+
+/// The state shared between a [`Verification`] and the pool thread running it.
+#[derive(Default)]
+struct Shared {
+    result: Option<Result<(), Error>>,
+    waker: Option<Waker>,
+}
+
+/// A future that resolves to the result of a verification running on the
+/// `rayon` thread pool.
+#[must_use = "futures do nothing unless polled"]
+pub struct Verification {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl Verification {
+    /// Runs `verify` on the `rayon` thread pool.
+    fn spawn<F>(verify: F) -> Self
+    where
+        F: FnOnce() -> Result<(), Error> + Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let pool_shared = shared.clone();
+        rayon::spawn(move || {
+            let result = verify();
+            let mut shared = pool_shared.lock().expect("lock is not poisoned");
+            shared.result = Some(result);
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        });
+        Verification { shared }
+    }
+}
+
+impl Future for Verification {
+    type Output = Result<(), Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut shared = self.shared.lock().expect("lock is not poisoned");
+        match shared.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+/// Asynchronous version of [`verify::verify_script`].
+pub fn verify_script_async<S, T>(
+    script_pub_key: S,
+    amount: i64,
+    tx_to: T,
+    n_in: u32,
+    flags: u32,
+    consensus_branch_id: u32,
+) -> Verification
+where
+    S: AsRef<[u8]> + Send + 'static,
+    T: AsRef<[u8]> + Send + 'static,
+{
+    Verification::spawn(move || {
+        verify::verify_script(
+            script_pub_key.as_ref(),
+            amount,
+            tx_to.as_ref(),
+            n_in,
+            flags,
+            consensus_branch_id,
+        )
+    })
+}
+
+/// Asynchronous version of [`verify::verify_script_v5`].
+pub fn verify_script_v5_async<T, P>(
+    tx_to: T,
+    all_prev_outputs: P,
+    n_in: u32,
+    flags: u32,
+    consensus_branch_id: u32,
+) -> Verification
+where
+    T: AsRef<[u8]> + Send + 'static,
+    P: AsRef<[u8]> + Send + 'static,
+{
+    Verification::spawn(move || {
+        verify::verify_script_v5(
+            tx_to.as_ref(),
+            all_prev_outputs.as_ref(),
+            n_in,
+            flags,
+            consensus_branch_id,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        task::{Context, Poll, Wake},
+        thread::{self, Thread},
+    };
+
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    #[test]
+    fn it_works_async() {
+        let coin = i64::pow(10, 8);
+        let ok = verify_script_async(
+            SCRIPT_PUBKEY.clone(),
+            212 * coin,
+            SCRIPT_TX.clone(),
+            0,
+            1,
+            0x2bb40e60,
+        );
+        // Shared buffers can be passed instead of a `Vec`.
+        let tx_to: Arc<[u8]> = SCRIPT_TX.as_slice().into();
+        let err = verify_script_async(SCRIPT_PUBKEY.clone(), 212 * coin, tx_to, 0, 1, 0x2bb40e61);
+
+        block_on(ok).unwrap();
+        block_on(err).unwrap_err();
+    }
+}
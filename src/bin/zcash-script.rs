@@ -0,0 +1,309 @@
+//! A command-line interface to zcashd's script verification and this crate's
+//! script analysis, for operators and auditors:
+//!
+//! ```console
+//! cargo run --features cli -- verify --script-pubkey 76a914...88ac \
+//!     --amount 21200000000 --tx 0400008085202f89... --input 0 --branch-id 0x2bb40e60
+//! cargo run --features cli -- decode 76a914...88ac --network test
+//! cargo run --features cli -- classify 6a0568656c6c6f
+//! cargo run --features cli -- sighash --tx 0400008085202f89... \
+//!     --prevouts 21200000000:76a914...88ac --branch-id 0x2bb40e60
+//! cargo run --features cli -- replay failure.replay
+//! ```
+//!
+//! Scripts and transactions are given in hex. Verification runs the same
+//! consensus code as zcashd.
+
+use std::{collections::HashMap, env, fs, process::ExitCode, str::FromStr};
+
+use zcash_primitives::{consensus::BranchId, transaction::Transaction};
+use zcash_script::{
+    decode::decode_script,
+    diagnostics::explain_failure,
+    flags::VerificationFlags,
+    prevout::encode_all_prev_outputs,
+    replay::{Replay, SpentOutputs},
+    script::Script,
+    sighash::{self, signature_hash, signature_hash_v5, SighashType},
+    spendability::{classify, Spendability},
+    templates::script_type,
+    verify::verify_script,
+    Network,
+};
+
+const USAGE: &str = "\
+usage: zcash-script <command> [options]
+
+commands:
+  verify --script-pubkey <hex> --amount <zatoshis> --tx <hex> --input <index>
+         --branch-id <id> [--flags <flags>] [--record <file>]
+      Verifies a transparent input of a pre-v5 transaction. Flags are names
+      joined by '|', like 'P2SH|CHECKLOCKTIMEVERIFY', a number, 'standard',
+      or 'consensus' (the default). If it fails, the verification can be
+      recorded to a file for 'replay'.
+  decode <script hex> [--network main|test|regtest]
+      Decodes a script like zcashd's decodescript RPC, as JSON.
+  classify <script hex>
+      Prints the standard template and spendability of a scriptPubKey.
+  sighash --tx <hex> --prevouts <amount>:<script hex>,... --branch-id <id>
+          [--hash-type <type>]
+      Prints the signature hash of each transparent input, given the outputs
+      they spend in input order. The hash type is ALL (the default), NONE or
+      SINGLE, optionally followed by '|ANYONECANPAY', or a number. The script
+      code of a P2SH input is the redeem script its scriptSig reveals.
+  replay <file>
+      Runs a recorded verification again, and compares the results.";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(code) => code,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
+
+/// Runs a command, returning an error message for invalid arguments.
+fn run(args: &[String]) -> Result<ExitCode, String> {
+    let (command, args) = args.split_first().ok_or("missing command")?;
+    let (positional, options) = parse_args(args)?;
+    match (command.as_str(), positional.as_slice()) {
+        ("verify", []) => verify(&options),
+        ("sighash", []) => sighashes(&options),
+        ("decode", [script]) => {
+            let network = match options.get("network").map(String::as_str) {
+                None | Some("main") => Network::Main,
+                Some("test") => Network::Test,
+                Some("regtest") => Network::Regtest,
+                Some(other) => return Err(format!("unknown network {:?}", other)),
+            };
+            println!("{}", decode_script(&parse_hex(script)?, network).to_json());
+            Ok(ExitCode::SUCCESS)
+        }
+        ("classify", [script]) => {
+            let script = Script(parse_hex(script)?);
+            println!("type: {}", script_type(&script).name());
+            let spendability = match classify(&script) {
+                Spendability::Unspendable(reason) => format!("unspendable ({:?})", reason),
+                Spendability::AnyoneCanSpend => "anyone can spend".to_owned(),
+                Spendability::Conditional => "conditional".to_owned(),
+            };
+            println!("spendability: {}", spendability);
+            Ok(ExitCode::SUCCESS)
+        }
+        ("replay", [file]) => replay(file),
+        ("verify" | "decode" | "classify" | "sighash" | "replay", _) => {
+            Err(format!("wrong number of arguments to {}", command))
+        }
+        _ => Err(format!("unknown command {:?}", command)),
+    }
+}
+
+fn verify(options: &HashMap<String, String>) -> Result<ExitCode, String> {
+    let script_pub_key = parse_hex(required(options, "script-pubkey")?)?;
+    let amount = parse_number(required(options, "amount")?)?;
+    let tx = parse_hex(required(options, "tx")?)?;
+    let n_in = parse_number(required(options, "input")?)?;
+    let branch_id = parse_number(required(options, "branch-id")?)?;
+    let flags = match options.get("flags").map(String::as_str) {
+        None | Some("consensus") => VerificationFlags::consensus_for(branch_id)
+            .ok_or_else(|| format!("unknown branch ID {:#x}", branch_id))?,
+        Some("standard") => VerificationFlags::standard(),
+        Some(flags) => VerificationFlags::from_str(flags).map_err(|e| e.to_string())?,
+    };
+
+    match verify_script(&script_pub_key, amount, &tx, n_in, flags.bits(), branch_id) {
+        Ok(()) => {
+            println!("valid");
+            Ok(ExitCode::SUCCESS)
+        }
+        Err(error) => {
+            println!("invalid: {}", error);
+            if let Some(report) =
+                explain_failure(&script_pub_key, amount, &tx, n_in, flags, branch_id)
+            {
+                println!("{}", report);
+            }
+            if let Some(file) = options.get("record") {
+                let spent = SpentOutputs::Output {
+                    script_pub_key,
+                    amount,
+                };
+                let replay = Replay::record(&tx, spent, n_in, flags.bits(), branch_id);
+                fs::write(file, replay.to_string())
+                    .map_err(|e| format!("can't write {}: {}", file, e))?;
+                println!("recorded to {}", file);
+            }
+            Ok(ExitCode::FAILURE)
+        }
+    }
+}
+
+fn replay(file: &str) -> Result<ExitCode, String> {
+    let replay: Replay = fs::read_to_string(file)
+        .map_err(|e| format!("can't read {}: {}", file, e))?
+        .parse()
+        .map_err(|e| format!("invalid replay {}: {}", file, e))?;
+    let describe = |result: Result<(), _>| match result {
+        Ok(()) => "valid".to_owned(),
+        Err(error) => format!("invalid: {}", error),
+    };
+    println!(
+        "recorded: {} (zcash_script {}, {} {})",
+        describe(replay.result),
+        replay.crate_version,
+        replay.backend,
+        replay.source_digest
+    );
+    let result = replay.run();
+    println!(
+        "replayed: {} (zcash_script {}, {} {})",
+        describe(result),
+        env!("CARGO_PKG_VERSION"),
+        zcash_script::replay::BACKEND,
+        zcash_script::vendored_source_digest()
+    );
+    if result == replay.result {
+        println!("reproduced");
+        Ok(ExitCode::SUCCESS)
+    } else {
+        println!("not reproduced");
+        Ok(ExitCode::FAILURE)
+    }
+}
+
+fn sighashes(options: &HashMap<String, String>) -> Result<ExitCode, String> {
+    let tx = parse_hex(required(options, "tx")?)?;
+    let branch_id = parse_number(required(options, "branch-id")?)?;
+    let hash_type = match options.get("hash-type") {
+        None => SighashType::ALL,
+        Some(hash_type) => parse_hash_type(hash_type)?,
+    };
+    let prev_outs = required(options, "prevouts")?
+        .split(',')
+        .map(|prev_out| {
+            let (amount, script_pub_key) = prev_out
+                .split_once(':')
+                .ok_or_else(|| format!("invalid previous output {:?}", prev_out))?;
+            Ok((parse_number::<i64>(amount)?, parse_hex(script_pub_key)?))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let all_prev_outputs = encode_all_prev_outputs(
+        prev_outs
+            .iter()
+            .map(|(amount, script)| (&script[..], *amount)),
+    );
+    let script_sigs: Vec<Script> = BranchId::try_from(branch_id)
+        .ok()
+        .and_then(|branch_id| Transaction::read(&tx[..], branch_id).ok())
+        .and_then(|tx| {
+            let bundle = tx.transparent_bundle()?;
+            Some(
+                bundle
+                    .vin
+                    .iter()
+                    .map(|input| Script::from(&input.script_sig.0[..]))
+                    .collect(),
+            )
+        })
+        .unwrap_or_default();
+
+    let mut code = ExitCode::SUCCESS;
+    for (n_in, (amount, script_pub_key)) in prev_outs.iter().enumerate() {
+        let script_code = script_sigs
+            .get(n_in)
+            .and_then(|script_sig| redeem_script(script_sig, script_pub_key))
+            .unwrap_or(&script_pub_key[..]);
+        let n_in = n_in as u32;
+        let result = match signature_hash(script_code, *amount, &tx, n_in, hash_type, branch_id) {
+            Err(sighash::Error::TxVersion) => {
+                signature_hash_v5(&tx, &all_prev_outputs, n_in, hash_type)
+            }
+            result => result,
+        };
+        match result {
+            Ok(sighash) => println!("input {}: {}", n_in, hex::encode(sighash)),
+            Err(error) => {
+                println!("input {}: error: {}", n_in, error);
+                code = ExitCode::FAILURE;
+            }
+        }
+    }
+    Ok(code)
+}
+
+/// Returns the redeem script a push-only scriptSig reveals, if it spends a
+/// P2SH scriptPubKey.
+fn redeem_script<'a>(script_sig: &'a Script, script_pub_key: &[u8]) -> Option<&'a [u8]> {
+    if !Script::from(script_pub_key).is_pay_to_script_hash() || !script_sig.is_push_only() {
+        return None;
+    }
+    script_sig.instructions().last()?.ok().map(|op| op.data)
+}
+
+/// Parses a hash type like zcashd's `signrawtransaction`, or as a number.
+fn parse_hash_type(s: &str) -> Result<SighashType, String> {
+    let (base, anyone_can_pay) = match s.strip_suffix("|ANYONECANPAY") {
+        Some(base) => (base, true),
+        None => (s, false),
+    };
+    let hash_type = match base {
+        "ALL" => SighashType::ALL,
+        "NONE" => SighashType::NONE,
+        "SINGLE" => SighashType::SINGLE,
+        _ if !anyone_can_pay => {
+            return parse_number(s)
+                .ok()
+                .and_then(SighashType::from_u8)
+                .ok_or_else(|| format!("invalid hash type {:?}", s))
+        }
+        _ => return Err(format!("invalid hash type {:?}", s)),
+    };
+    Ok(hash_type.with_anyone_can_pay(anyone_can_pay))
+}
+
+/// Splits arguments into positional arguments and `--name value` options.
+fn parse_args(args: &[String]) -> Result<(Vec<&String>, HashMap<String, String>), String> {
+    let mut positional = Vec::new();
+    let mut options = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix("--") {
+            Some(name) => {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("missing value for --{}", name))?;
+                options.insert(name.to_owned(), value.clone());
+            }
+            None => positional.push(arg),
+        }
+    }
+    Ok((positional, options))
+}
+
+fn required<'a>(options: &'a HashMap<String, String>, name: &str) -> Result<&'a str, String> {
+    options
+        .get(name)
+        .map(String::as_str)
+        .ok_or_else(|| format!("missing --{}", name))
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    hex::decode(s).map_err(|e| format!("invalid hex: {}", e))
+}
+
+/// Parses a decimal or `0x`-prefixed hex number.
+fn parse_number<T>(s: &str) -> Result<T, String>
+where
+    T: FromStr + TryFrom<u64>,
+{
+    let number = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16)
+            .ok()
+            .and_then(|n| T::try_from(n).ok()),
+        None => s.parse().ok(),
+    };
+    number.ok_or_else(|| format!("invalid number {:?}", s))
+}
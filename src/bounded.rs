@@ -0,0 +1,228 @@
+//! Scripts stored inline, with a maximum size fixed by their type.
+//!
+//! A [`BoundedScript`] keeps its bytes in an array rather than on the heap,
+//! so embedded and firmware code can hold and inspect scripts without an
+//! allocator for their storage. Parsing with [`BoundedScript::instructions`]
+//! doesn't allocate either. Verification itself still goes through zcashd's
+//! interpreter, which allocates.
+
+use std::{fmt, hash};
+
+use crate::{
+    opcode::Opcode,
+    script::{self, Instructions, Script, MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE},
+};
+
+/// A script that can hold any script zcashd will execute.
+pub type MaxScript = BoundedScript<MAX_SCRIPT_SIZE>;
+
+/// A pushed stack element of the maximum size zcashd allows.
+pub type MaxElement = BoundedScript<MAX_SCRIPT_ELEMENT_SIZE>;
+
+/// An error returned when a script doesn't fit in a [`BoundedScript`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CapacityError {
+    /// The number of bytes the script would need.
+    pub needed: usize,
+    /// The capacity of the script type.
+    pub capacity: usize,
+}
+
+impl fmt::Display for CapacityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "script of {} bytes exceeds capacity of {} bytes",
+            self.needed, self.capacity
+        )
+    }
+}
+
+impl std::error::Error for CapacityError {}
+
+/// A serialized script of at most `N` bytes, stored inline.
+#[derive(Clone, Copy)]
+pub struct BoundedScript<const N: usize> {
+    bytes: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> BoundedScript<N> {
+    /// Returns an empty script.
+    pub const fn new() -> Self {
+        BoundedScript {
+            bytes: [0; N],
+            len: 0,
+        }
+    }
+
+    /// Copies a serialized script, returning an error if it is longer than
+    /// `N` bytes.
+    pub fn from_slice(script: &[u8]) -> Result<Self, CapacityError> {
+        let mut bounded = Self::new();
+        bounded.extend(script)?;
+        Ok(bounded)
+    }
+
+    /// Returns the serialized bytes of this script.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len]
+    }
+
+    /// Returns the length of this script in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the script is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the maximum length of this script in bytes, `N`.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Appends an opcode to this script.
+    pub fn push_opcode(&mut self, opcode: Opcode) -> Result<&mut Self, CapacityError> {
+        self.extend(&[opcode.0])
+    }
+
+    /// Appends a push of `data` to this script, like [`Script::push_slice`].
+    ///
+    /// Nothing is appended if the push doesn't fit.
+    pub fn push_slice(&mut self, data: &[u8]) -> Result<&mut Self, CapacityError> {
+        let (prefix, prefix_len) = script::push_prefix(data.len());
+        self.check_capacity(prefix_len + data.len())?;
+        self.extend(&prefix[..prefix_len])?;
+        self.extend(data)
+    }
+
+    /// Returns an iterator over the instructions of this script.
+    pub fn instructions(&self) -> Instructions<'_> {
+        script::instructions(self.as_bytes())
+    }
+
+    /// Returns true if the script only contains push operations, like
+    /// [`Script::is_push_only`].
+    pub fn is_push_only(&self) -> bool {
+        script::is_push_only(self.as_bytes())
+    }
+
+    /// Copies this script to the heap.
+    pub fn to_script(&self) -> Script {
+        Script::from(self.as_bytes())
+    }
+
+    fn check_capacity(&self, additional: usize) -> Result<(), CapacityError> {
+        let needed = self.len + additional;
+        if needed > N {
+            return Err(CapacityError {
+                needed,
+                capacity: N,
+            });
+        }
+        Ok(())
+    }
+
+    fn extend(&mut self, data: &[u8]) -> Result<&mut Self, CapacityError> {
+        self.check_capacity(data.len())?;
+        self.bytes[self.len..self.len + data.len()].copy_from_slice(data);
+        self.len += data.len();
+        Ok(self)
+    }
+}
+
+impl<const N: usize> Default for BoundedScript<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> PartialEq for BoundedScript<N> {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<const N: usize> Eq for BoundedScript<N> {}
+
+impl<const N: usize> hash::Hash for BoundedScript<N> {
+    fn hash<H: hash::Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state)
+    }
+}
+
+impl<const N: usize> fmt::Debug for BoundedScript<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BoundedScript<{}>({})", N, hex::encode(self.as_bytes()))
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for BoundedScript<N> {
+    fn as_ref(&self) -> &[u8] {
+        self.as_bytes()
+    }
+}
+
+impl<const N: usize> TryFrom<&[u8]> for BoundedScript<N> {
+    type Error = CapacityError;
+
+    fn try_from(script: &[u8]) -> Result<Self, Self::Error> {
+        Self::from_slice(script)
+    }
+}
+
+impl<const N: usize> TryFrom<&Script> for BoundedScript<N> {
+    type Error = CapacityError;
+
+    fn try_from(script: &Script) -> Result<Self, Self::Error> {
+        Self::from_slice(script.as_bytes())
+    }
+}
+
+impl<const N: usize> From<BoundedScript<N>> for Script {
+    fn from(script: BoundedScript<N>) -> Self {
+        script.to_script()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_scripts_like_script() {
+        let hash = [0x11; 20];
+        let mut bounded = BoundedScript::<25>::new();
+        bounded
+            .push_opcode(Opcode::OP_DUP)
+            .and_then(|s| s.push_opcode(Opcode::OP_HASH160))
+            .and_then(|s| s.push_slice(&hash))
+            .and_then(|s| s.push_opcode(Opcode::OP_EQUALVERIFY))
+            .and_then(|s| s.push_opcode(Opcode::OP_CHECKSIG))
+            .unwrap();
+        let script = crate::script!(OP_DUP OP_HASH160 [hash] OP_EQUALVERIFY OP_CHECKSIG);
+        assert_eq!(bounded.as_bytes(), script.as_bytes());
+        assert_eq!(BoundedScript::<25>::try_from(&script), Ok(bounded));
+        assert_eq!(Script::from(bounded), script);
+        assert_eq!(bounded.instructions().count(), 5);
+
+        assert_eq!(
+            bounded.push_opcode(Opcode::OP_1),
+            Err(CapacityError {
+                needed: 26,
+                capacity: 25
+            })
+        );
+        let mut element = MaxElement::new();
+        assert!(element
+            .push_slice(&[0; MAX_SCRIPT_ELEMENT_SIZE - 3])
+            .is_ok());
+        assert!(element.push_slice(&[0]).is_err());
+        // A push that doesn't fit leaves the script unchanged.
+        assert_eq!(element.len(), MAX_SCRIPT_ELEMENT_SIZE);
+        assert!(element.is_push_only());
+    }
+}
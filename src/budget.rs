@@ -0,0 +1,226 @@
+//! Verification with a budget, for services that verify untrusted inputs.
+//!
+//! The consensus rules already limit a script to 201 non-push opcodes, but a
+//! mempool-facing service may want a tighter bound on what one input can
+//! cost it. A [`Budget`] bounds the steps, signature checks and time of a
+//! verification, and [`verify_script_budgeted`] fails with
+//! [`BudgetError::BudgetExceeded`] when any of them runs out.
+//!
+//! Scripts have no loops, so the number of instructions in the scriptSig,
+//! the scriptPubKey and any P2SH redeem script bounds the steps the
+//! interpreter can take, and is checked before verifying. zcashd's
+//! interpreter can't be interrupted between opcodes, so the signature check
+//! count and the time limit are checked before each signature check, which
+//! dominates the cost of a verification.
+
+use std::{
+    ffi::c_void,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    flags::VerificationFlags,
+    precomputed::PrecomputedTx,
+    script::{self, Script},
+    verify::{self, Error},
+    zcash_script_verify_precomputed_budgeted,
+};
+
+/// The limits on a verification. Every limit is optional, and the default
+/// budget is unlimited.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Budget {
+    /// The most instructions the scripts evaluated for the input may have.
+    pub max_steps: Option<usize>,
+    /// The most signature checks the verification may make.
+    pub max_sig_checks: Option<u32>,
+    /// The longest the verification may take, checked before each signature
+    /// check.
+    pub time_limit: Option<Duration>,
+}
+
+/// An error returned by [`verify_script_budgeted`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BudgetError {
+    /// The verification would have exceeded its [`Budget`].
+    BudgetExceeded,
+    /// The verification failed within its budget.
+    Verify(Error),
+}
+
+impl fmt::Display for BudgetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetError::BudgetExceeded => write!(f, "verification budget exceeded"),
+            BudgetError::Verify(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for BudgetError {}
+
+impl From<Error> for BudgetError {
+    fn from(err: Error) -> Self {
+        BudgetError::Verify(err)
+    }
+}
+
+/// Returns the number of instructions evaluated when `script_sig` spends
+/// `script_pub_key` under `flags`, including the redeem script of a P2SH
+/// spend, which bounds the steps the interpreter takes.
+pub fn step_bound(script_sig: &Script, script_pub_key: &Script, flags: VerificationFlags) -> usize {
+    let redeem_script = verify::redeem_script(script_sig, script_pub_key, flags);
+    script_sig.instructions().count()
+        + script_pub_key.instructions().count()
+        + redeem_script.map_or(0, |script| script::instructions(script).count())
+}
+
+/// Returns `Ok(())` if the input `n_in` of the serialized pre-v5 transaction
+/// `tx_to` correctly spends `script_pub_key` under `flags`, like
+/// [`verify_script`](verify::verify_script), within `budget`.
+///
+/// This uses `zcash_script_verify_precomputed_budgeted`. Budgeted
+/// verifications are not reported to the installed
+/// [`VerificationObserver`](crate::observer::VerificationObserver).
+pub fn verify_script_budgeted(
+    script_pub_key: &[u8],
+    amount: i64,
+    tx_to: &[u8],
+    n_in: u32,
+    flags: u32,
+    consensus_branch_id: u32,
+    budget: &Budget,
+) -> Result<(), BudgetError> {
+    let mut meter = Meter {
+        sig_checks: 0,
+        max_sig_checks: budget.max_sig_checks,
+        deadline: budget.time_limit.map(|limit| Instant::now() + limit),
+        exceeded: false,
+    };
+    let tx = PrecomputedTx::new(tx_to)?;
+
+    if let Some(max_steps) = budget.max_steps {
+        // An input that doesn't exist is reported by the verification.
+        if let Some(script_sig) = verify::script_sig(tx_to, n_in, consensus_branch_id)? {
+            let steps = step_bound(
+                &script_sig,
+                &Script::from(script_pub_key),
+                VerificationFlags(flags),
+            );
+            if steps > max_steps {
+                return Err(BudgetError::BudgetExceeded);
+            }
+        }
+    }
+
+    let mut err = 0;
+    let ret = unsafe {
+        zcash_script_verify_precomputed_budgeted(
+            tx.as_ptr(),
+            n_in,
+            script_pub_key.as_ptr(),
+            script_pub_key.len() as _,
+            amount,
+            flags,
+            consensus_branch_id,
+            Some(within_budget),
+            &mut meter as *mut Meter as *mut c_void,
+            &mut err,
+        )
+    };
+    // A signature check that ran out of budget failed, whatever the result.
+    if meter.exceeded {
+        return Err(BudgetError::BudgetExceeded);
+    }
+    verify::from_ret(ret, err).map_err(BudgetError::Verify)
+}
+
+/// The budget state passed to [`within_budget`].
+struct Meter {
+    sig_checks: u32,
+    max_sig_checks: Option<u32>,
+    deadline: Option<Instant>,
+    /// Whether a signature check was refused.
+    exceeded: bool,
+}
+
+unsafe extern "C" fn within_budget(ctx: *mut c_void) -> i32 {
+    // SAFETY: `ctx` is the `Meter` passed by `verify_script_budgeted`, which
+    // outlives the verification.
+    let meter = unsafe { &mut *(ctx as *mut Meter) };
+    let out_of_checks = meter
+        .max_sig_checks
+        .is_some_and(|max| meter.sig_checks >= max);
+    let out_of_time = meter
+        .deadline
+        .is_some_and(|deadline| Instant::now() >= deadline);
+    if meter.exceeded || out_of_checks || out_of_time {
+        meter.exceeded = true;
+        return 0;
+    }
+    meter.sig_checks += 1;
+    1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+
+    #[test]
+    fn enforces_budgets() {
+        let verify = |budget: Budget, consensus_branch_id| {
+            verify_script_budgeted(
+                &SCRIPT_PUBKEY,
+                212 * 100_000_000,
+                &SCRIPT_TX,
+                0,
+                1,
+                consensus_branch_id,
+                &budget,
+            )
+        };
+        let unlimited = Budget::default();
+        assert_eq!(verify(unlimited, 0x2bb40e60), Ok(()));
+        assert_eq!(
+            verify(unlimited, 0x2bb40e61),
+            Err(BudgetError::Verify(Error::ScriptInvalid))
+        );
+
+        // The scriptSig pushes a signature and a public key, and the P2PKH
+        // scriptPubKey has five opcodes.
+        let steps = |max_steps| Budget {
+            max_steps: Some(max_steps),
+            ..unlimited
+        };
+        assert_eq!(verify(steps(7), 0x2bb40e60), Ok(()));
+        assert_eq!(
+            verify(steps(6), 0x2bb40e60),
+            Err(BudgetError::BudgetExceeded)
+        );
+
+        let sig_checks = |max_sig_checks| Budget {
+            max_sig_checks: Some(max_sig_checks),
+            ..unlimited
+        };
+        assert_eq!(verify(sig_checks(1), 0x2bb40e60), Ok(()));
+        assert_eq!(
+            verify(sig_checks(0), 0x2bb40e60),
+            Err(BudgetError::BudgetExceeded)
+        );
+
+        let no_time = Budget {
+            time_limit: Some(Duration::ZERO),
+            ..unlimited
+        };
+        assert_eq!(
+            verify(no_time, 0x2bb40e60),
+            Err(BudgetError::BudgetExceeded)
+        );
+        assert_eq!(
+            verify_script_budgeted(&SCRIPT_PUBKEY, 0, &[], 0, 1, 0x2bb40e60, &unlimited),
+            Err(BudgetError::Verify(Error::TxDeserialize))
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! A cache of successful verifications, so that inputs verified when a
+//! transaction enters the mempool aren't verified again when it is mined.
+//!
+//! Entries are per input, and keyed by the transaction, the flags and the
+//! consensus branch ID, which signatures commit to even where the transaction
+//! doesn't, so a success isn't reused after a network upgrade. A key built
+//! with [`CacheKey::new`] assumes the outputs spent by a transaction are
+//! determined by their outpoints, which holds for callers that look them up
+//! in a UTXO set; [`CacheKey::with_spent_outputs`] also commits to them, so a
+//! success isn't reused when the same input is verified against a different
+//! output.
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::verify::Error;
+
+/// Identifies a verification of one input of a transaction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    /// The double SHA-256 hash of the serialized transaction. This is the
+    /// txid of v4 and earlier transactions. Unlike the txid of v5
+    /// transactions, it also commits to their scriptSigs.
+    pub tx_hash: [u8; 32],
+    /// The index of the input.
+    pub n_in: u32,
+    /// The script verification flags.
+    pub flags: u32,
+    /// The consensus branch ID.
+    pub consensus_branch_id: u32,
+    /// The SHA-256 hash of the spent output and, for v5 transactions, the
+    /// encoding of all the outputs spent by the transaction, if the key
+    /// commits to them.
+    pub spent_outputs_hash: Option<[u8; 32]>,
+}
+
+impl CacheKey {
+    /// Returns the key for verifying input `n_in` of the serialized
+    /// transaction `tx_to` under `flags`, in the epoch of
+    /// `consensus_branch_id`.
+    pub fn new(tx_to: &[u8], n_in: u32, flags: u32, consensus_branch_id: u32) -> Self {
+        CacheKey {
+            tx_hash: Sha256::digest(Sha256::digest(tx_to)).into(),
+            n_in,
+            flags,
+            consensus_branch_id,
+            spent_outputs_hash: None,
+        }
+    }
+
+    /// Returns this key, also committing to the spent output's
+    /// `script_pub_key` and `amount` and, for v5 transactions, the encoding
+    /// of all the outputs spent by the transaction.
+    pub fn with_spent_outputs(
+        self,
+        script_pub_key: &[u8],
+        amount: i64,
+        all_prev_outputs: Option<&[u8]>,
+    ) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(amount.to_le_bytes());
+        hasher.update((script_pub_key.len() as u64).to_le_bytes());
+        hasher.update(script_pub_key);
+        if let Some(all_prev_outputs) = all_prev_outputs {
+            hasher.update(all_prev_outputs);
+        }
+        CacheKey {
+            spent_outputs_hash: Some(hasher.finalize().into()),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    /// The last use of each key.
+    last_used: HashMap<CacheKey, u64>,
+    /// The keys, ordered by last use.
+    by_use: BTreeMap<u64, CacheKey>,
+    clock: u64,
+}
+
+impl Entries {
+    fn touch(&mut self, key: CacheKey) {
+        self.clock += 1;
+        if let Some(previous) = self.last_used.insert(key, self.clock) {
+            self.by_use.remove(&previous);
+        }
+        self.by_use.insert(self.clock, key);
+    }
+}
+
+/// A least-recently-used cache of successful verifications.
+///
+/// Failures are never cached.
+#[derive(Debug)]
+pub struct ScriptCache {
+    capacity: usize,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ScriptCache {
+    /// Creates an empty cache that holds at most `capacity` entries.
+    pub fn new(capacity: usize) -> Self {
+        ScriptCache {
+            capacity,
+            entries: Mutex::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns true if `key` has been verified successfully, marking it as
+    /// recently used.
+    pub fn contains(&self, key: &CacheKey) -> bool {
+        let mut entries = self.entries.lock().expect("lock is not poisoned");
+        let found = entries.last_used.contains_key(key);
+        if found {
+            entries.touch(*key);
+        }
+        found
+    }
+
+    /// Records that `key` has been verified successfully, evicting the least
+    /// recently used entry if the cache is full.
+    pub fn insert(&self, key: CacheKey) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().expect("lock is not poisoned");
+        entries.touch(key);
+        while entries.last_used.len() > self.capacity {
+            let (_, oldest) = entries.by_use.pop_first().expect("cache is not empty");
+            entries.last_used.remove(&oldest);
+        }
+    }
+
+    /// Returns `Ok(())` if `key` is cached, and otherwise runs `verify`,
+    /// caching its result if it succeeds.
+    pub fn verify(
+        &self,
+        key: CacheKey,
+        verify: impl FnOnce() -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = crate::trace::cache_span(&key).entered();
+
+        let hit = self.contains(&key);
+        #[cfg(feature = "tracing")]
+        crate::trace::record_cache_lookup(hit);
+        if hit {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(());
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let result = verify();
+
+        // The verification emits its own events, so only the error code is
+        // recorded here.
+        #[cfg(feature = "tracing")]
+        crate::trace::record_error_code(&result);
+
+        result?;
+        self.insert(key);
+        Ok(())
+    }
+
+    /// Returns the number of calls to [`verify`](Self::verify) that found
+    /// their key in the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of calls to [`verify`](Self::verify) that had to
+    /// verify.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of cached entries.
+    pub fn len(&self) -> usize {
+        self.entries
+            .lock()
+            .expect("lock is not poisoned")
+            .last_used
+            .len()
+    }
+
+    /// Returns true if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an estimate of the heap memory used by the cache's entries, in
+    /// bytes, not counting the allocator's own overhead.
+    pub fn memory_usage(&self) -> usize {
+        let entries = self.entries.lock().expect("lock is not poisoned");
+        // Each slot of the hash map also has a control byte, and each
+        // B-tree entry is in a node of up to 11.
+        entries.last_used.capacity() * (mem::size_of::<(CacheKey, u64)>() + 1)
+            + entries.by_use.len() * mem::size_of::<(u64, CacheKey)>() * 12 / 11
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        tests::{SCRIPT_PUBKEY, SCRIPT_TX},
+        verify::verify_script,
+    };
+
+    #[test]
+    fn caches_successes() {
+        let cache = ScriptCache::new(2);
+        let amount = 212 * 100_000_000;
+        let verify = |branch_id| {
+            let key = CacheKey::new(&SCRIPT_TX, 0, 1, branch_id);
+            cache.verify(key, || {
+                verify_script(&SCRIPT_PUBKEY, amount, &SCRIPT_TX, 0, 1, branch_id)
+            })
+        };
+
+        assert_eq!(verify(0x2bb40e61), Err(Error::ScriptInvalid));
+        assert!(cache.is_empty());
+        assert_eq!(verify(0x2bb40e60), Ok(()));
+        // The cached success is returned without verifying again.
+        assert_eq!(verify(0x2bb40e60), Ok(()));
+        assert_eq!((cache.hits(), cache.misses()), (1, 2));
+        // Signatures commit to the branch ID, so a success in one epoch isn't
+        // reused in another.
+        assert_eq!(verify(0x2bb40e61), Err(Error::ScriptInvalid));
+        assert_eq!((cache.hits(), cache.misses()), (1, 3));
+
+        let key = CacheKey::new(&SCRIPT_TX, 0, 1, 0x2bb40e60);
+
+        // The least recently used entry is evicted.
+        let other = CacheKey { n_in: 1, ..key };
+        cache.insert(other);
+        assert!(cache.contains(&key));
+        cache.insert(CacheKey { n_in: 2, ..key });
+        assert_eq!(cache.len(), 2);
+        assert!(!cache.contains(&other));
+
+        // A key that commits to the spent output differs from one that
+        // doesn't, and from one for a different output.
+        let spent = key.with_spent_outputs(&SCRIPT_PUBKEY, amount, None);
+        assert_ne!(spent, key);
+        assert_ne!(
+            spent,
+            key.with_spent_outputs(&SCRIPT_PUBKEY, amount - 1, None)
+        );
+        assert_ne!(
+            spent,
+            key.with_spent_outputs(&SCRIPT_PUBKEY, amount, Some(&[]))
+        );
+        assert!(cache.memory_usage() >= 2 * mem::size_of::<CacheKey>());
+        assert_eq!(ScriptCache::new(2).memory_usage(), 0);
+    }
+}
@@ -0,0 +1,149 @@
+//! Calibration of batch sizes and queue capacities to the host.
+//!
+//! How long a verification takes varies by an order of magnitude between a
+//! small VPS and a large server, so fixed batch sizes are either too small
+//! to amortize their overhead or too large to cancel promptly. [`calibration`]
+//! measures it once per process, and [`Calibration`] derives sizes for the
+//! parallel APIs from the measurement.
+
+use std::{
+    num::NonZeroUsize,
+    sync::OnceLock,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    pool::PoolConfig,
+    precomputed::{Input, PrecomputedTx},
+};
+
+/// The time a single batch of verifications should take, which bounds how
+/// long cancelling a batch verification can take.
+pub const TARGET_BATCH_TIME: Duration = Duration::from_millis(5);
+
+/// The time the jobs in a full pool queue should take all the workers, so
+/// that submitters can't run far ahead of verification.
+pub const TARGET_QUEUE_TIME: Duration = Duration::from_millis(100);
+
+/// The largest batch size chosen by calibration.
+pub const MAX_BATCH_SIZE: usize = 1024;
+
+/// The number of verifications measured in each round of calibration.
+const SAMPLES: usize = 32;
+
+/// The number of rounds of calibration, of which the fastest is used, as the
+/// slower ones were interrupted by other work.
+const ROUNDS: usize = 3;
+
+/// A mainnet Blossom transaction spending a P2PKH output, the most common
+/// kind of input.
+const TX_HEX: &str = "0400008085202f8901fcaf44919d4a17f6181a02a7ebe0420be6f7dad1ef86755b81d5a9567456653c010000006a473044022035224ed7276e61affd53315eca059c92876bc2df61d84277cafd7af61d4dbf4002203ed72ea497a9f6b38eb29df08e830d99e32377edb8a574b8a289024f0241d7c40121031f54b095eae066d96b2557c1f99e40e967978a5fd117465dbec0986ca74201a6feffffff020050d6dc0100000017a9141b8a9bda4b62cd0d0582b55455d0778c86f8628f870d03c812030000001976a914e4ff5512ffafe9287992a1cd177ca6e408e0300388ac62070d0095070d000000000000000000000000";
+
+/// The P2PKH scriptPubKey spent by [`TX_HEX`].
+const SCRIPT_PUBKEY_HEX: &str = "76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac";
+
+/// The measured cost of verification on this host.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Calibration {
+    /// The time to verify a P2PKH input of a precomputed transaction.
+    pub per_verification: Duration,
+    /// The number of CPUs available to the process.
+    pub threads: usize,
+}
+
+impl Calibration {
+    /// Measures the cost of verification on the current thread, taking a few
+    /// milliseconds.
+    pub fn measure() -> Self {
+        let tx = hex::decode(TX_HEX).expect("valid hex");
+        let script_pub_key = hex::decode(SCRIPT_PUBKEY_HEX).expect("valid hex");
+        let precomputed = PrecomputedTx::new(&tx).expect("transaction deserializes");
+        let input = Input {
+            n_in: 0,
+            script_pub_key: &script_pub_key,
+            amount: 212 * 100_000_000,
+            flags: 1,
+        };
+        let inputs = [input; SAMPLES];
+        let per_verification = (0..ROUNDS)
+            .map(|_| {
+                let start = Instant::now();
+                let results = precomputed.verify_batch(&inputs, 0x2bb40e60);
+                let elapsed = start.elapsed();
+                debug_assert!(results.iter().all(Result::is_ok));
+                elapsed / SAMPLES as u32
+            })
+            .min()
+            .expect("there is a round");
+        Calibration {
+            // A zero measurement would make every derived size the maximum.
+            per_verification: per_verification.max(Duration::from_nanos(1)),
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+        }
+    }
+
+    /// Returns the number of inputs to verify in each FFI call, so that a
+    /// batch takes about [`TARGET_BATCH_TIME`].
+    pub fn batch_size(&self) -> usize {
+        self.count_in(TARGET_BATCH_TIME).clamp(1, MAX_BATCH_SIZE)
+    }
+
+    /// Returns the number of jobs a pool queue should hold, so that a full
+    /// queue takes all the workers about [`TARGET_QUEUE_TIME`].
+    pub fn queue_capacity(&self) -> usize {
+        (self.count_in(TARGET_QUEUE_TIME) * self.threads).max(self.threads)
+    }
+
+    /// Returns a [`PoolConfig`] with a worker per CPU and calibrated queue
+    /// capacities.
+    pub fn pool_config(&self) -> PoolConfig {
+        PoolConfig {
+            threads: self.threads,
+            block_capacity: self.queue_capacity(),
+            mempool_capacity: self.queue_capacity(),
+            ..PoolConfig::default()
+        }
+    }
+
+    /// Returns the number of verifications that take `duration`.
+    fn count_in(&self, duration: Duration) -> usize {
+        (duration.as_nanos() / self.per_verification.as_nanos())
+            .try_into()
+            .unwrap_or(usize::MAX)
+    }
+}
+
+/// Returns the calibration for this process, measuring it on first use.
+///
+/// Call this at startup to avoid delaying the first batch verification that
+/// needs it.
+pub fn calibration() -> &'static Calibration {
+    static CALIBRATION: OnceLock<Calibration> = OnceLock::new();
+    CALIBRATION.get_or_init(Calibration::measure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_sizes_from_measurement() {
+        assert!(calibration().per_verification > Duration::ZERO);
+        assert!((1..=MAX_BATCH_SIZE).contains(&calibration().batch_size()));
+
+        let slow = Calibration {
+            per_verification: Duration::from_millis(10),
+            threads: 4,
+        };
+        assert_eq!(slow.batch_size(), 1);
+        assert_eq!(slow.queue_capacity(), 40);
+        let fast = Calibration {
+            per_verification: Duration::from_micros(50),
+            threads: 64,
+        };
+        assert_eq!(fast.batch_size(), 100);
+        assert_eq!(fast.queue_capacity(), 2000 * 64);
+        assert_eq!(fast.pool_config().threads, 64);
+    }
+}
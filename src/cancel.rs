@@ -0,0 +1,50 @@
+//! Cancellation of batch and pooled verification.
+//!
+//! A node that has started verifying a block can cancel a
+//! [`CancellationToken`] when the block is orphaned, so the inputs that
+//! haven't been verified yet are skipped. A verification that has already
+//! started runs to completion, as zcashd's interpreter can't be interrupted.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/// A flag shared between the code that cancels verification and the
+/// verifications it cancels.
+///
+/// Clones share the same flag.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    /// Returns a token that isn't cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancels the verifications using this token.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if the token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// An error returned when verification was cancelled before it finished.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "verification was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
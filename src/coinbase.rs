@@ -0,0 +1,102 @@
+//! The block height that coinbase scriptSigs must start with (BIP 34).
+//!
+//! zcashd's `ContextualCheckBlock` requires the coinbase scriptSig of every
+//! block after genesis to start with `CScript() << nHeight`.
+
+use std::fmt;
+
+use crate::{script::Script, script_num::ScriptNum};
+
+/// The maximum size of a height push in a coinbase scriptSig.
+const MAX_HEIGHT_NUM_SIZE: usize = 5;
+
+/// The reason a coinbase scriptSig doesn't commit to the expected height.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoinbaseHeightError {
+    /// The scriptSig doesn't start with a canonical height push.
+    Missing,
+    /// The scriptSig commits to a different height.
+    Mismatch(u32),
+}
+
+impl fmt::Display for CoinbaseHeightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoinbaseHeightError::Missing => write!(f, "coinbase scriptSig has no height"),
+            CoinbaseHeightError::Mismatch(height) => {
+                write!(f, "coinbase scriptSig has height {}", height)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CoinbaseHeightError {}
+
+/// Returns the script that a coinbase scriptSig for a block at `height` must
+/// start with, matching `CScript() << nHeight`.
+pub fn height_script(height: u32) -> Script {
+    let mut script = Script::default();
+    script.push_num(height.into());
+    script
+}
+
+/// Returns the height that a coinbase scriptSig commits to, or `None` if it
+/// doesn't start with a height pushed the way [`height_script`] pushes it.
+pub fn decode_height(script_sig: &Script) -> Option<u32> {
+    let instruction = script_sig.instructions().next()?.ok()?;
+    let height = if instruction.opcode.is_push_data() {
+        let num = ScriptNum::decode(instruction.data, true, MAX_HEIGHT_NUM_SIZE).ok()?;
+        u32::try_from(num.value()).ok()?
+    } else {
+        instruction.opcode.decode_op_n()?.into()
+    };
+    script_sig
+        .as_bytes()
+        .starts_with(height_script(height).as_bytes())
+        .then_some(height)
+}
+
+/// Checks that a coinbase scriptSig commits to `height`, as
+/// `ContextualCheckBlock` does for blocks after genesis.
+pub fn check_height(script_sig: &Script, height: u32) -> Result<(), CoinbaseHeightError> {
+    if script_sig
+        .as_bytes()
+        .starts_with(height_script(height).as_bytes())
+    {
+        return Ok(());
+    }
+    match decode_height(script_sig) {
+        Some(found) => Err(CoinbaseHeightError::Mismatch(found)),
+        None => Err(CoinbaseHeightError::Missing),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex::FromHex;
+
+    #[test]
+    fn coinbase_heights() {
+        // A coinbase scriptSig for height 2,000,000, followed by extra data.
+        let script_sig = Script::from_hex("0380841e00").unwrap();
+        assert_eq!(height_script(2_000_000).as_bytes(), &script_sig.0[..4]);
+        assert_eq!(decode_height(&script_sig), Some(2_000_000));
+        assert_eq!(check_height(&script_sig, 2_000_000), Ok(()));
+        assert_eq!(
+            check_height(&script_sig, 2_000_001),
+            Err(CoinbaseHeightError::Mismatch(2_000_000))
+        );
+
+        // Small heights use small integer opcodes.
+        let script_sig = Script(vec![0x5a, 0x00]);
+        assert_eq!(decode_height(&script_sig), Some(10));
+        // A push of 10 isn't canonical.
+        let script_sig = Script(vec![0x01, 0x0a]);
+        assert_eq!(decode_height(&script_sig), None);
+        assert_eq!(
+            check_height(&script_sig, 10),
+            Err(CoinbaseHeightError::Missing)
+        );
+    }
+}
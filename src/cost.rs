@@ -0,0 +1,111 @@
+//! A heuristic estimate of the work verifying a script can take.
+//!
+//! Relay layers can use [`cost_score`] to prioritize transactions or
+//! penalize peers before spending full verification effort on pathological
+//! scripts. The score is an upper bound over the script's instructions, as if
+//! every branch ran; it doesn't evaluate the script.
+
+use crate::{opcode::Opcode, script::Script};
+
+/// The cost of a signature operation, which dominates verification.
+pub const SIG_OP_COST: u64 = 10_000;
+
+/// The cost of a hash operation, enough to hash a maximum-size element.
+pub const HASH_OP_COST: u64 = 500;
+
+/// The cost of each byte pushed.
+pub const PUSH_BYTE_COST: u64 = 1;
+
+/// The cost of any other operation.
+pub const OP_COST: u64 = 1;
+
+/// The operations in a script that contribute to its cost.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct CostScore {
+    /// The signature operations, counted accurately, as for P2SH redeem
+    /// scripts.
+    pub sig_ops: u32,
+    /// The `OP_RIPEMD160`, `OP_SHA1`, `OP_SHA256`, `OP_HASH160` and
+    /// `OP_HASH256` operations.
+    pub hash_ops: u32,
+    /// The total size of the pushed data.
+    pub push_bytes: u32,
+    /// The number of other operations, including the push opcodes.
+    pub other_ops: u32,
+}
+
+impl CostScore {
+    /// Returns the weighted sum of the operations.
+    pub fn total(&self) -> u64 {
+        u64::from(self.sig_ops) * SIG_OP_COST
+            + u64::from(self.hash_ops) * HASH_OP_COST
+            + u64::from(self.push_bytes) * PUSH_BYTE_COST
+            + u64::from(self.other_ops) * OP_COST
+    }
+}
+
+/// Returns the estimated cost of verifying `script`.
+///
+/// For a P2SH spend, score the redeem script as well as the scriptSig. A
+/// script that fails to parse is scored up to the truncated push, where
+/// zcashd stops evaluating it.
+pub fn cost_score(script: &Script) -> CostScore {
+    let mut score = CostScore {
+        sig_ops: script.sig_op_count(true),
+        ..CostScore::default()
+    };
+    for op in script.instructions() {
+        let Ok(op) = op else {
+            break;
+        };
+        match op.opcode {
+            Opcode::OP_RIPEMD160
+            | Opcode::OP_SHA1
+            | Opcode::OP_SHA256
+            | Opcode::OP_HASH160
+            | Opcode::OP_HASH256 => score.hash_ops += 1,
+            Opcode::OP_CHECKSIG
+            | Opcode::OP_CHECKSIGVERIFY
+            | Opcode::OP_CHECKMULTISIG
+            | Opcode::OP_CHECKMULTISIGVERIFY => {}
+            _ => {
+                score.push_bytes += op.data.len() as u32;
+                score.other_ops += 1;
+            }
+        }
+    }
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_scripts() {
+        let hash = [0x11; 20];
+        let p2pkh = crate::script!(OP_DUP OP_HASH160 [hash] OP_EQUALVERIFY OP_CHECKSIG);
+        let score = cost_score(&p2pkh);
+        assert_eq!(
+            score,
+            CostScore {
+                sig_ops: 1,
+                hash_ops: 1,
+                push_bytes: 20,
+                other_ops: 3,
+            }
+        );
+        assert_eq!(score.total(), SIG_OP_COST + HASH_OP_COST + 23);
+
+        // Without a small integer key count, a multisig is scored as checking
+        // the maximum number of keys.
+        let bare = crate::script!(OP_CHECKMULTISIG);
+        assert_eq!(cost_score(&bare).sig_ops, 20);
+        let hashes = crate::script!(OP_SHA256 OP_SHA256 OP_SHA256);
+        assert!(cost_score(&hashes).total() < cost_score(&p2pkh).total());
+
+        // A truncated push stops the scoring.
+        let truncated = Script(vec![0x4c]);
+        assert_eq!(cost_score(&truncated), CostScore::default());
+    }
+}
@@ -0,0 +1,125 @@
+//! Human-readable script decoding, matching zcashd's `decodescript` RPC.
+
+use zcash_address::Network;
+
+use crate::{
+    script::Script,
+    standard::{Destination, ScriptType},
+    templates::{destinations, script_type},
+};
+
+/// The decoded form of a script, as returned by zcashd's `decodescript` RPC.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DecodedScript {
+    /// The script disassembly.
+    pub asm: String,
+    /// The number of required signatures, if the script is standard.
+    pub req_sigs: Option<usize>,
+    /// The script type.
+    pub script_type: ScriptType,
+    /// The addresses the script pays to, if the script is standard.
+    pub addresses: Option<Vec<String>>,
+    /// The P2SH address of the script, treating it as a redeem script.
+    pub p2sh: String,
+}
+
+/// Decodes a serialized script for `network`, matching `decodescript`.
+///
+/// Scripts matching a registered [template](crate::templates) are decoded
+/// with its type and addresses.
+pub fn decode_script(script: &[u8], network: Network) -> DecodedScript {
+    let script = Script::from(script);
+
+    let script_type = script_type(&script);
+    let destinations = destinations(&script);
+
+    DecodedScript {
+        asm: script.to_asm(),
+        req_sigs: destinations.as_ref().map(|d| d.required),
+        script_type,
+        addresses: destinations.map(|d| {
+            d.addresses
+                .iter()
+                .map(|address| address.encode(network))
+                .collect()
+        }),
+        p2sh: Destination::for_script(script.as_bytes()).encode(network),
+    }
+}
+
+impl DecodedScript {
+    /// Serializes this decoded script as a compact JSON object, with the
+    /// same keys and key order as `decodescript`.
+    pub fn to_json(&self) -> String {
+        let mut json = format!("{{\"asm\":{}", json_string(&self.asm));
+        if let Some(req_sigs) = self.req_sigs {
+            json.push_str(&format!(",\"reqSigs\":{}", req_sigs));
+        }
+        json.push_str(&format!(
+            ",\"type\":{}",
+            json_string(self.script_type.name())
+        ));
+        if let Some(addresses) = &self.addresses {
+            let addresses: Vec<_> = addresses.iter().map(|a| json_string(a)).collect();
+            json.push_str(&format!(",\"addresses\":[{}]", addresses.join(",")));
+        }
+        json.push_str(&format!(",\"p2sh\":{}}}", json_string(&self.p2sh)));
+        json
+    }
+}
+
+/// Quotes and escapes a string for JSON output.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex::FromHex;
+
+    #[test]
+    fn decodes_p2pkh() {
+        let script =
+            <Vec<u8>>::from_hex("76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac").unwrap();
+        let decoded = decode_script(&script, Network::Main);
+        assert_eq!(decoded.script_type, ScriptType::PubKeyHash);
+        assert_eq!(decoded.req_sigs, Some(1));
+        assert_eq!(
+            decoded.to_json(),
+            format!(
+                "{{\"asm\":\"OP_DUP OP_HASH160 f47cac1e6fec195c055994e8064ffccce0044dd7 OP_EQUALVERIFY OP_CHECKSIG\",\"reqSigs\":1,\"type\":\"pubkeyhash\",\"addresses\":[\"t1gALEXb92y6auReVBfwhEuWiyguzNiQ68n\"],\"p2sh\":\"{}\"}}",
+                decoded.p2sh
+            )
+        );
+    }
+
+    #[test]
+    fn decodes_nonstandard() {
+        let decoded = decode_script(&[0x51], Network::Test);
+        assert_eq!(decoded.script_type, ScriptType::NonStandard);
+        assert_eq!(decoded.addresses, None);
+        assert!(decoded.p2sh.starts_with("t2"));
+        assert_eq!(
+            decoded.to_json(),
+            format!(
+                "{{\"asm\":\"1\",\"type\":\"nonstandard\",\"p2sh\":\"{}\"}}",
+                decoded.p2sh
+            )
+        );
+    }
+}
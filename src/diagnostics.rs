@@ -0,0 +1,865 @@
+//! Reporting every policy violation in a spend at once.
+//!
+//! zcashd's interpreter stops at the first error, so a scriptSig with a
+//! non-minimal push and a high-S signature fails with `MINIMALDATA` until the
+//! push is fixed, and only then with `LOW_S`. [`diagnose`] checks the
+//! encoding rules statically instead, without evaluating either script, and
+//! returns all the violations it finds.
+//!
+//! When verification of a spend does fail, [`attribute_failure`] re-runs it
+//! with each flag removed in turn, to find the flags the failure is due to,
+//! and [`explain_failure`] combines everything known about the failure into
+//! a report for support tickets and node logs.
+//!
+//! These are advisory, non-consensus facilities for script authors and
+//! operators. [`diagnose`] doesn't check signatures against a transaction, or
+//! evaluate the scripts, so a spend without violations can still fail
+//! [`verify_script`], and a spend that only passes once a flag is removed is
+//! still invalid under the flags it was verified with. Verification with the
+//! intended flags remains the only authority on validity.
+
+use std::fmt;
+
+use zcash_primitives::{consensus::BranchId, transaction::Transaction};
+
+use crate::{
+    analysis::extract_spend,
+    flags::VerificationFlags,
+    opcode::Opcode,
+    script::{Instruction, Script},
+    standard::{solve, Template},
+    verify::{self, verify_script},
+};
+
+/// Half the order of the secp256k1 curve, the largest S value a low-S
+/// signature can have.
+const HALF_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// The order of the secp256k1 curve.
+const ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
+/// The script a violation was found in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Location {
+    ScriptSig,
+    ScriptPubKey,
+    /// The redeem script revealed by a P2SH scriptSig.
+    RedeemScript,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Location::ScriptSig => write!(f, "scriptSig"),
+            Location::ScriptPubKey => write!(f, "scriptPubKey"),
+            Location::RedeemScript => write!(f, "redeem script"),
+        }
+    }
+}
+
+/// A broken encoding rule, named after the `ScriptError` zcashd fails with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Violation {
+    /// A push could have used a shorter opcode (`MINIMALDATA`).
+    MinimalData,
+    /// The scriptSig contains a non-push opcode (`SIGPUSHONLY`).
+    SigPushOnly,
+    /// A signature isn't strictly DER-encoded. This is always enforced.
+    SigDer,
+    /// A signature has an undefined hash type (`STRICTENC`).
+    SigHashType,
+    /// A signature has a high S value (`LOW_S`).
+    SigHighS,
+    /// A public key is neither compressed nor uncompressed (`STRICTENC`).
+    PubKeyType,
+    /// The `OP_CHECKMULTISIG` dummy argument isn't empty (`NULLDUMMY`).
+    SigNullDummy,
+    /// The script uses an upgradable NOP (`DISCOURAGE_UPGRADABLE_NOPS`).
+    DiscourageUpgradableNops,
+    /// The scriptSig pushes more signatures than `OP_CHECKMULTISIG` consumes,
+    /// leaving them on the stack (`CLEANSTACK`).
+    CleanStack,
+}
+
+impl Violation {
+    /// Returns the flag that enforces this rule, or `None` for strict DER
+    /// encoding, which consensus enforces whatever the flags.
+    pub fn flag(self) -> Option<VerificationFlags> {
+        Some(match self {
+            Violation::MinimalData => VerificationFlags::MINIMALDATA,
+            Violation::SigPushOnly => VerificationFlags::SIGPUSHONLY,
+            Violation::SigDer => return None,
+            Violation::SigHashType | Violation::PubKeyType => VerificationFlags::STRICTENC,
+            Violation::SigHighS => VerificationFlags::LOW_S,
+            Violation::SigNullDummy => VerificationFlags::NULLDUMMY,
+            Violation::DiscourageUpgradableNops => VerificationFlags::DISCOURAGE_UPGRADABLE_NOPS,
+            Violation::CleanStack => VerificationFlags::CLEANSTACK,
+        })
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::MinimalData => write!(f, "data push larger than necessary"),
+            Violation::SigPushOnly => write!(f, "only push operators allowed in signatures"),
+            Violation::SigDer => write!(f, "non-canonical DER signature"),
+            Violation::SigHashType => write!(f, "signature hash type missing or not understood"),
+            Violation::SigHighS => {
+                write!(f, "non-canonical signature: S value is unnecessarily high")
+            }
+            Violation::PubKeyType => write!(f, "public key is neither compressed or uncompressed"),
+            Violation::SigNullDummy => write!(f, "dummy CHECKMULTISIG argument must be zero"),
+            Violation::DiscourageUpgradableNops => {
+                write!(f, "NOPx reserved for soft-fork upgrades")
+            }
+            Violation::CleanStack => write!(f, "extra items left on stack after execution"),
+        }
+    }
+}
+
+/// A violation, and where it was found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Diagnostic {
+    /// The script containing the offending instruction.
+    pub location: Location,
+    /// The byte offset of the offending instruction within the script.
+    pub offset: usize,
+    /// The rule it breaks.
+    pub violation: Violation,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at {}:{}", self.violation, self.location, self.offset)
+    }
+}
+
+/// Returns every violation of the rules `flags` enable when `script_sig`
+/// spends `script_pub_key`, in the order the scripts are checked.
+///
+/// Pushes and NOPs are checked in all scripts. Signatures, public keys and
+/// the multisig dummy are only found in spends of standard templates,
+/// including P2SH-wrapped ones when `flags` contains
+/// [`VerificationFlags::P2SH`]. The checks stop at the first parse error in
+/// each script.
+pub fn diagnose(
+    script_sig: &Script,
+    script_pub_key: &Script,
+    flags: VerificationFlags,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let sig_ops = parse(script_sig);
+    check_opcodes(Location::ScriptSig, &sig_ops, flags, &mut diagnostics);
+    if flags.contains(VerificationFlags::SIGPUSHONLY) {
+        for op in sig_ops.iter().filter(|op| op.opcode > Opcode::OP_16) {
+            diagnostics.push(Diagnostic {
+                location: Location::ScriptSig,
+                offset: op.offset,
+                violation: Violation::SigPushOnly,
+            });
+        }
+    }
+    check_opcodes(
+        Location::ScriptPubKey,
+        &parse(script_pub_key),
+        flags,
+        &mut diagnostics,
+    );
+
+    // The redeem script is evaluated, and so checked, even if it isn't
+    // standard.
+    let mut pushes = &sig_ops[..];
+    let mut template_location = Location::ScriptPubKey;
+    let mut template_script = script_pub_key.clone();
+    if flags.contains(VerificationFlags::P2SH)
+        && script_pub_key.is_pay_to_script_hash()
+        && script_sig.is_push_only()
+    {
+        if let Some((redeem, rest)) = pushes.split_last() {
+            template_script = Script::from(redeem.data);
+            template_location = Location::RedeemScript;
+            check_opcodes(
+                template_location,
+                &parse(&template_script),
+                flags,
+                &mut diagnostics,
+            );
+            pushes = rest;
+        }
+    }
+
+    // Signatures and public keys are only found in the layout of a standard
+    // spend, with the redeem script taking the place of a P2SH scriptPubKey.
+    if extract_spend(script_sig, script_pub_key).is_none() {
+        return diagnostics;
+    }
+    let Some(template) = solve(&template_script) else {
+        return diagnostics;
+    };
+
+    let mut check_sig = |op: &Instruction<'_>| {
+        for violation in signature_violations(op.data, flags) {
+            diagnostics.push(Diagnostic {
+                location: Location::ScriptSig,
+                offset: op.offset,
+                violation,
+            });
+        }
+    };
+    let template_keys = || {
+        parse(&template_script)
+            .into_iter()
+            .filter(|op| op.opcode.is_push_data() && !op.data.is_empty())
+            .map(|op| (op.offset, op.data.to_vec()))
+            .collect::<Vec<_>>()
+    };
+    let pubkeys = match template {
+        Template::PubKey(_) => {
+            check_sig(&pushes[0]);
+            template_keys()
+                .into_iter()
+                .map(|(offset, key)| (template_location, offset, key))
+                .collect()
+        }
+        Template::PubKeyHash(_) => {
+            check_sig(&pushes[0]);
+            vec![(
+                Location::ScriptSig,
+                pushes[1].offset,
+                pushes[1].data.to_vec(),
+            )]
+        }
+        Template::MultiSig { required, .. } => {
+            let (dummy, sigs) = pushes.split_first().expect("multisig spends push a dummy");
+            sigs.iter().for_each(&mut check_sig);
+            if flags.contains(VerificationFlags::NULLDUMMY) && !dummy.data.is_empty() {
+                diagnostics.push(Diagnostic {
+                    location: Location::ScriptSig,
+                    offset: dummy.offset,
+                    violation: Violation::SigNullDummy,
+                });
+            }
+            if flags.contains(VerificationFlags::CLEANSTACK) {
+                for extra in sigs.iter().skip(required.into()) {
+                    diagnostics.push(Diagnostic {
+                        location: Location::ScriptSig,
+                        offset: extra.offset,
+                        violation: Violation::CleanStack,
+                    });
+                }
+            }
+            template_keys()
+                .into_iter()
+                .map(|(offset, key)| (template_location, offset, key))
+                .collect()
+        }
+        Template::ScriptHash(_) | Template::NullData => vec![],
+    };
+    if flags.contains(VerificationFlags::STRICTENC) {
+        for (location, offset, key) in pubkeys {
+            if !is_compressed_or_uncompressed_pubkey(&key) {
+                diagnostics.push(Diagnostic {
+                    location,
+                    offset,
+                    violation: Violation::PubKeyType,
+                });
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Returns the offset and opcode of each NOP in `script` that is reserved
+/// for soft-fork upgrades under `flags`.
+///
+/// These are the NOPs that [`VerificationFlags::DISCOURAGE_UPGRADABLE_NOPS`]
+/// rejects when executed. A script using them is valid by consensus today,
+/// but may become unspendable if a soft fork gives them a meaning, which is
+/// why zcashd doesn't relay spends that execute them. Only the
+/// [`VerificationFlags::CHECKLOCKTIMEVERIFY`] bit of `flags` matters: without
+/// it, `OP_CHECKLOCKTIMEVERIFY` is the upgradable `OP_NOP2`. NOPs in
+/// unexecuted branches are reported too. The search stops at the first parse
+/// error.
+pub fn upgradable_nops(script: &Script, flags: VerificationFlags) -> Vec<(usize, Opcode)> {
+    parse(script)
+        .into_iter()
+        .filter(|op| op.opcode.is_upgradable_nop(flags))
+        .map(|op| (op.offset, op.opcode))
+        .collect()
+}
+
+/// The flags a verification failure is due to, found by
+/// [`attribute_failure`].
+///
+/// This is diagnostic information, not a consensus result: the spend is
+/// invalid under the flags it was verified with, whatever it is attributed
+/// to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attribution {
+    /// The error verification fails with under all the flags.
+    pub error: verify::Error,
+    /// Each flag whose removal, on its own, makes verification pass.
+    pub flags: Vec<VerificationFlags>,
+    /// Whether verification passes with no flags at all. If it doesn't, the
+    /// failure isn't due to the flags, but to the scripts themselves, such as
+    /// a wrong signature.
+    pub passes_without_flags: bool,
+}
+
+impl fmt::Display for Attribution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if !self.flags.is_empty() {
+            let flags = self
+                .flags
+                .iter()
+                .fold(VerificationFlags::NONE, |acc, &flag| acc | flag);
+            write!(f, ", because of {}", flags.to_string().replace('|', " or "))
+        } else if self.passes_without_flags {
+            write!(f, ", because of a combination of flags")
+        } else {
+            write!(f, ", whatever the flags")
+        }
+    }
+}
+
+/// Verifies input `n_in` of `tx_to` like
+/// [`verify_script`], and if it fails, attributes the
+/// failure to the flags whose removal makes it pass.
+///
+/// Returns `None` if verification passes under `flags`. This verifies the
+/// spend once for each flag set, and twice more, so it is only meant for
+/// investigating failures, not for validation.
+pub fn attribute_failure(
+    script_pub_key: &[u8],
+    amount: i64,
+    tx_to: &[u8],
+    n_in: u32,
+    flags: VerificationFlags,
+    consensus_branch_id: u32,
+) -> Option<Attribution> {
+    attribute_failure_with(flags, |flags| {
+        verify_script(
+            script_pub_key,
+            amount,
+            tx_to,
+            n_in,
+            flags.bits(),
+            consensus_branch_id,
+        )
+    })
+}
+
+/// Like [`attribute_failure`], but verifying with `verify`, such as a call to
+/// [`verify_script_v5`](crate::verify::verify_script_v5) for v5 transactions.
+///
+/// Removing [`VerificationFlags::P2SH`] also removes
+/// [`VerificationFlags::CLEANSTACK`], which requires it.
+pub fn attribute_failure_with<F>(flags: VerificationFlags, verify: F) -> Option<Attribution>
+where
+    F: Fn(VerificationFlags) -> Result<(), verify::Error>,
+{
+    let error = verify(flags).err()?;
+    Some(Attribution {
+        error,
+        flags: flags
+            .iter()
+            .filter(|&flag| {
+                let mut removed = flag;
+                // zcashd asserts that CLEANSTACK is only used with P2SH.
+                if flag == VerificationFlags::P2SH {
+                    removed |= VerificationFlags::CLEANSTACK;
+                }
+                verify(VerificationFlags(flags.bits() & !removed.bits())).is_ok()
+            })
+            .collect(),
+        passes_without_flags: verify(VerificationFlags::NONE).is_ok(),
+    })
+}
+
+/// A human-readable account of why an input failed verification, returned
+/// by [`explain_failure`].
+///
+/// zcashd's interpreter doesn't report the opcode a script failed at or the
+/// stack at the time. The report instead shows the stack the
+/// scriptPubKey starts with, and the positions of the static violations
+/// [`diagnose`] finds. Its `Display` implementation formats it over several
+/// lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailureReport {
+    /// The index of the input that failed.
+    pub n_in: u32,
+    /// The flags it failed under.
+    pub flags: VerificationFlags,
+    /// The error, and the flags it is due to.
+    pub attribution: Attribution,
+    /// The input's scriptSig, if the transaction could be parsed.
+    pub script_sig: Option<Script>,
+    /// The scriptPubKey of the spent output.
+    pub script_pub_key: Script,
+    /// The redeem script, if the output is P2SH and `flags` evaluate it.
+    pub redeem_script: Option<Script>,
+    /// The stack after evaluating the scriptSig, from bottom to top, if it
+    /// is push-only.
+    pub initial_stack: Option<Vec<Vec<u8>>>,
+    /// The violations found by [`diagnose`].
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl fmt::Display for FailureReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "input {} failed verification", self.n_in)?;
+        writeln!(f, "  error: {}", self.attribution)?;
+        writeln!(f, "  flags: {}", self.flags)?;
+        match &self.script_sig {
+            Some(script_sig) => writeln!(f, "  scriptSig: {}", script_sig.to_asm())?,
+            None => writeln!(f, "  scriptSig: unavailable, transaction not parsed")?,
+        }
+        writeln!(f, "  scriptPubKey: {}", self.script_pub_key.to_asm())?;
+        if let Some(redeem_script) = &self.redeem_script {
+            writeln!(f, "  redeem script: {}", redeem_script.to_asm())?;
+        }
+        match &self.initial_stack {
+            Some(stack) if stack.is_empty() => writeln!(f, "  initial stack: empty")?,
+            Some(stack) => {
+                writeln!(f, "  initial stack, top first:")?;
+                for (depth, item) in stack.iter().rev().enumerate() {
+                    writeln!(f, "    {}: {}", depth, hex::encode(item))?;
+                }
+            }
+            None => writeln!(
+                f,
+                "  initial stack: unavailable, scriptSig is not push-only"
+            )?,
+        }
+        if self.diagnostics.is_empty() {
+            write!(f, "  violations: none found")
+        } else {
+            write!(f, "  violations:")?;
+            for diagnostic in &self.diagnostics {
+                write!(f, "\n    {}", diagnostic)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Verifies input `n_in` of `tx_to` like [`verify_script`], and if it fails,
+/// explains why in a [`FailureReport`].
+///
+/// Returns `None` if verification passes under `flags`. Like
+/// [`attribute_failure`], this is diagnostic and verifies the spend several
+/// times, so it is only meant for investigating failures.
+pub fn explain_failure(
+    script_pub_key: &[u8],
+    amount: i64,
+    tx_to: &[u8],
+    n_in: u32,
+    flags: VerificationFlags,
+    consensus_branch_id: u32,
+) -> Option<FailureReport> {
+    let attribution = attribute_failure(
+        script_pub_key,
+        amount,
+        tx_to,
+        n_in,
+        flags,
+        consensus_branch_id,
+    )?;
+    let script_pub_key = Script::from(script_pub_key);
+    let script_sig = BranchId::try_from(consensus_branch_id)
+        .ok()
+        .and_then(|branch_id| Transaction::read(tx_to, branch_id).ok())
+        .and_then(|tx| {
+            let input = tx.transparent_bundle()?.vin.get(n_in as usize)?;
+            Some(Script::from(&input.script_sig.0[..]))
+        });
+    let initial_stack = script_sig.as_ref().and_then(initial_stack);
+    let redeem_script = initial_stack
+        .as_ref()
+        .filter(|_| {
+            flags.contains(VerificationFlags::P2SH) && script_pub_key.is_pay_to_script_hash()
+        })
+        .and_then(|stack| stack.last())
+        .map(|redeem_script| Script::from(&redeem_script[..]));
+    let diagnostics = script_sig
+        .as_ref()
+        .map(|script_sig| diagnose(script_sig, &script_pub_key, flags))
+        .unwrap_or_default();
+    Some(FailureReport {
+        n_in,
+        flags,
+        attribution,
+        script_sig,
+        script_pub_key,
+        redeem_script,
+        initial_stack,
+        diagnostics,
+    })
+}
+
+/// Returns the stack a push-only scriptSig leaves for the scriptPubKey.
+fn initial_stack(script_sig: &Script) -> Option<Vec<Vec<u8>>> {
+    script_sig
+        .instructions()
+        .map(|op| {
+            let op = op.ok()?;
+            if op.opcode.is_push_data() {
+                Some(op.data.to_vec())
+            } else if op.opcode == Opcode::OP_1NEGATE {
+                Some(vec![0x81])
+            } else {
+                // OP_RESERVED is push-only, but fails when executed.
+                op.opcode.decode_op_n().map(|n| vec![n])
+            }
+        })
+        .collect()
+}
+
+/// Returns the instructions of `script` up to its first parse error.
+fn parse(script: &Script) -> Vec<Instruction<'_>> {
+    script.instructions().map_while(Result::ok).collect()
+}
+
+/// Checks the push and NOP rules, which apply to every script.
+fn check_opcodes(
+    location: Location,
+    ops: &[Instruction<'_>],
+    flags: VerificationFlags,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for op in ops {
+        let violation = if flags.contains(VerificationFlags::MINIMALDATA)
+            && op.opcode.is_push_data()
+            && !is_minimal_push(op.data, op.opcode)
+        {
+            Violation::MinimalData
+        } else if flags.contains(VerificationFlags::DISCOURAGE_UPGRADABLE_NOPS)
+            && op.opcode.is_upgradable_nop(flags)
+        {
+            Violation::DiscourageUpgradableNops
+        } else {
+            continue;
+        };
+        diagnostics.push(Diagnostic {
+            location,
+            offset: op.offset,
+            violation,
+        });
+    }
+}
+
+/// Returns the violations of a pushed signature, matching zcashd's
+/// `CheckSignatureEncoding`. Empty signatures have none.
+fn signature_violations(sig: &[u8], flags: VerificationFlags) -> Vec<Violation> {
+    if sig.is_empty() {
+        return vec![];
+    }
+    if !is_valid_signature_encoding(sig) {
+        return vec![Violation::SigDer];
+    }
+    let mut violations = vec![];
+    if flags.contains(VerificationFlags::LOW_S) && is_high_s(sig) {
+        violations.push(Violation::SigHighS);
+    }
+    let hash_type = sig[sig.len() - 1] & !0x80;
+    if flags.contains(VerificationFlags::STRICTENC) && !(1..=3).contains(&hash_type) {
+        violations.push(Violation::SigHashType);
+    }
+    violations
+}
+
+/// Returns true if `data` is pushed with the shortest possible opcode,
+/// matching zcashd's `CheckMinimalPush`.
+fn is_minimal_push(data: &[u8], opcode: Opcode) -> bool {
+    match data {
+        [] => opcode == Opcode::OP_0,
+        &[n @ 1..=16] => opcode == Opcode(Opcode::OP_1.0 + n - 1),
+        [0x81] => opcode == Opcode::OP_1NEGATE,
+        _ if data.len() <= 75 => usize::from(opcode.0) == data.len(),
+        _ if data.len() <= 255 => opcode == Opcode::OP_PUSHDATA1,
+        _ if data.len() <= 65535 => opcode == Opcode::OP_PUSHDATA2,
+        _ => true,
+    }
+}
+
+/// Returns true if `sig`, with its hash type, is a strict DER signature, as
+/// BIP 66 and zcashd's `IsValidSignatureEncoding` specify.
+fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    if !(9..=73).contains(&sig.len()) || sig[0] != 0x30 || usize::from(sig[1]) != sig.len() - 3 {
+        return false;
+    }
+    let len_r = usize::from(sig[3]);
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+    let len_s = usize::from(sig[5 + len_r]);
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+    let is_integer = |start: usize, len: usize| {
+        sig[start - 2] == 0x02
+            && len != 0
+            && sig[start] & 0x80 == 0
+            && !(len > 1 && sig[start] == 0x00 && sig[start + 1] & 0x80 == 0)
+    };
+    is_integer(4, len_r) && is_integer(len_r + 6, len_s)
+}
+
+/// Returns true if the S value of a strict DER signature is more than half
+/// the curve order, matching zcashd's `CPubKey::CheckLowS`.
+///
+/// Like libsecp256k1's lax parser, an S value not less than the curve order
+/// is treated as zero, and so as low.
+fn is_high_s(sig: &[u8]) -> bool {
+    let len_r = usize::from(sig[3]);
+    let s = &sig[len_r + 6..sig.len() - 1];
+    let s = &s[s.iter().take_while(|&&b| b == 0).count()..];
+    if s.len() > 32 {
+        return false;
+    }
+    let mut padded = [0; 32];
+    padded[32 - s.len()..].copy_from_slice(s);
+    padded > HALF_ORDER && padded < ORDER
+}
+
+/// Returns true if `key` has the length its header byte requires, matching
+/// zcashd's `IsCompressedOrUncompressedPubKey`.
+fn is_compressed_or_uncompressed_pubkey(key: &[u8]) -> bool {
+    match key.first() {
+        Some(0x02 | 0x03) => key.len() == 33,
+        Some(0x04) => key.len() == 65,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        standard::hash160,
+        tests::{SCRIPT_PUBKEY, SCRIPT_TX},
+    };
+
+    /// A strict DER signature with the given S value, and hash type.
+    fn signature(s: &[u8], hash_type: u8) -> Vec<u8> {
+        let mut sig = vec![
+            0x30,
+            (s.len() + 5) as u8,
+            0x02,
+            0x01,
+            0x01,
+            0x02,
+            s.len() as u8,
+        ];
+        sig.extend_from_slice(s);
+        sig.push(hash_type);
+        sig
+    }
+
+    #[test]
+    fn reports_every_violation() {
+        let mut high_s = vec![0x00, 0x80];
+        high_s.resize(33, 0);
+        let pubkey = [&[0x06][..], &[0x11; 64]].concat();
+
+        // A 1-of-1 multisig in P2SH.
+        let mut redeem_script = Script::default();
+        redeem_script
+            .push_num(1)
+            .push_slice(&pubkey)
+            .push_num(1)
+            .push_opcode(Opcode::OP_CHECKMULTISIG);
+        let mut p2sh = Script::default();
+        p2sh.push_opcode(Opcode::OP_HASH160)
+            .push_slice(&hash160(redeem_script.as_bytes()))
+            .push_opcode(Opcode::OP_EQUAL);
+
+        // A non-null dummy pushed with OP_PUSHDATA1, then a high-S signature
+        // with an undefined hash type, and an extra non-DER signature.
+        let mut script_sig = Script(vec![Opcode::OP_PUSHDATA1.0, 1, 7]);
+        script_sig
+            .push_slice(&signature(&high_s, 0x04))
+            .push_slice(&[0x30, 0x01])
+            .push_slice(redeem_script.as_bytes());
+
+        let found: Vec<_> = diagnose(&script_sig, &p2sh, VerificationFlags::standard())
+            .into_iter()
+            .map(|d| (d.location, d.offset, d.violation))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (Location::ScriptSig, 0, Violation::MinimalData),
+                (Location::ScriptSig, 3, Violation::SigHighS),
+                (Location::ScriptSig, 3, Violation::SigHashType),
+                (Location::ScriptSig, 45, Violation::SigDer),
+                (Location::ScriptSig, 0, Violation::SigNullDummy),
+                (Location::ScriptSig, 45, Violation::CleanStack),
+                (Location::RedeemScript, 1, Violation::PubKeyType),
+            ]
+        );
+
+        // Without the policy flags, only the DER violation remains.
+        assert_eq!(
+            diagnose(&script_sig, &p2sh, VerificationFlags::P2SH),
+            [Diagnostic {
+                location: Location::ScriptSig,
+                offset: 45,
+                violation: Violation::SigDer,
+            }]
+        );
+
+        // Non-standard scripts still have their pushes and NOPs checked.
+        let mut script_pub_key = Script::default();
+        script_pub_key
+            .push_opcode(Opcode::OP_NOP1)
+            .push_opcode(Opcode::OP_CHECKLOCKTIMEVERIFY)
+            .push_opcode(Opcode::OP_NOP10);
+        let script_sig = Script(vec![0x01, 0x05, Opcode::OP_DUP.0]);
+        let found: Vec<_> = diagnose(
+            &script_sig,
+            &script_pub_key,
+            VerificationFlags::standard() | VerificationFlags::SIGPUSHONLY,
+        )
+        .into_iter()
+        .map(|d| (d.location, d.offset, d.violation))
+        .collect();
+        assert_eq!(
+            found,
+            [
+                (Location::ScriptSig, 0, Violation::MinimalData),
+                (Location::ScriptSig, 2, Violation::SigPushOnly),
+                (
+                    Location::ScriptPubKey,
+                    0,
+                    Violation::DiscourageUpgradableNops
+                ),
+                (
+                    Location::ScriptPubKey,
+                    2,
+                    Violation::DiscourageUpgradableNops
+                ),
+            ]
+        );
+        assert_eq!(
+            diagnose(
+                &Script::default(),
+                &script_pub_key,
+                VerificationFlags::DISCOURAGE_UPGRADABLE_NOPS
+            )
+            .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn finds_upgradable_nops() {
+        let mut script = Script::default();
+        script
+            .push_opcode(Opcode::OP_NOP)
+            .push_opcode(Opcode::OP_NOP1)
+            .push_opcode(Opcode::OP_CHECKLOCKTIMEVERIFY)
+            .push_slice(&[Opcode::OP_NOP3.0])
+            .push_opcode(Opcode::OP_NOP10);
+        assert_eq!(
+            upgradable_nops(&script, VerificationFlags::standard()),
+            [(1, Opcode::OP_NOP1), (5, Opcode::OP_NOP10)]
+        );
+        assert_eq!(
+            upgradable_nops(&script, VerificationFlags::P2SH),
+            [
+                (1, Opcode::OP_NOP1),
+                (2, Opcode::OP_NOP2),
+                (5, Opcode::OP_NOP10)
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes_failures_to_flags() {
+        let amount = 212 * 100_000_000;
+        let flags = VerificationFlags::P2SH | VerificationFlags::CLEANSTACK;
+        assert_eq!(
+            attribute_failure(&SCRIPT_PUBKEY, amount, &SCRIPT_TX, 0, flags, 0x2bb40e60),
+            None
+        );
+
+        // An extra push before the signature, which it doesn't commit to, is
+        // left on the stack.
+        let mut tx = SCRIPT_TX.clone();
+        tx[45] += 1;
+        tx.insert(46, Opcode::OP_1.0);
+        let attribution =
+            attribute_failure(&SCRIPT_PUBKEY, amount, &tx, 0, flags, 0x2bb40e60).unwrap();
+        assert_eq!(attribution.error, verify::Error::ScriptInvalid);
+        assert_eq!(attribution.flags, [VerificationFlags::CLEANSTACK]);
+        assert!(attribution.passes_without_flags);
+
+        // A wrong amount invalidates the signature whatever the flags.
+        let attribution =
+            attribute_failure(&SCRIPT_PUBKEY, amount + 1, &tx, 0, flags, 0x2bb40e60).unwrap();
+        assert_eq!(attribution.flags, []);
+        assert!(!attribution.passes_without_flags);
+        assert_eq!(
+            attribution.to_string(),
+            "script verification failed, whatever the flags"
+        );
+    }
+
+    #[test]
+    fn explains_failures() {
+        let amount = 212 * 100_000_000;
+        let flags = VerificationFlags::P2SH | VerificationFlags::CLEANSTACK;
+        let mut tx = SCRIPT_TX.clone();
+        tx[45] += 1;
+        tx.insert(46, Opcode::OP_1.0);
+
+        let report = explain_failure(&SCRIPT_PUBKEY, amount, &tx, 0, flags, 0x2bb40e60).unwrap();
+        assert_eq!(report.attribution.flags, [VerificationFlags::CLEANSTACK]);
+        assert_eq!(report.redeem_script, None);
+        let stack = report.initial_stack.as_ref().unwrap();
+        assert_eq!(stack.len(), 3);
+        assert_eq!(stack[0], [1]);
+        assert_eq!(report.diagnostics, []);
+
+        let text = report.to_string();
+        assert!(text.starts_with(
+            "input 0 failed verification\n  \
+             error: script verification failed, because of CLEANSTACK\n  \
+             flags: P2SH|CLEANSTACK\n  \
+             scriptSig: 1 3044"
+        ));
+        assert!(text.contains("\n    0: 031f54b0"));
+        assert!(text.ends_with("\n    2: 01\n  violations: none found"));
+
+        assert_eq!(
+            explain_failure(&SCRIPT_PUBKEY, amount, &SCRIPT_TX, 0, flags, 0x2bb40e60),
+            None
+        );
+    }
+
+    #[test]
+    fn accepts_clean_spends() {
+        let script_sig = Script(hex::decode("473044022035224ed7276e61affd53315eca059c92876bc2df61d84277cafd7af61d4dbf4002203ed72ea497a9f6b38eb29df08e830d99e32377edb8a574b8a289024f0241d7c40121031f54b095eae066d96b2557c1f99e40e967978a5fd117465dbec0986ca74201a6").unwrap());
+        let p2pkh =
+            Script(hex::decode("76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac").unwrap());
+        assert_eq!(
+            diagnose(&script_sig, &p2pkh, VerificationFlags::standard()),
+            []
+        );
+        assert!(is_valid_signature_encoding(&signature(&[0x01], 0x01)));
+        assert!(!is_valid_signature_encoding(&signature(
+            &[0x00, 0x01],
+            0x01
+        )));
+        assert!(!is_high_s(&signature(&HALF_ORDER, 0x01)));
+    }
+}
@@ -0,0 +1,55 @@
+//! Hex encoding of this crate's types, with the `hex` crate's traits.
+//!
+//! [`Script`](crate::script::Script), [`PublicKey`](crate::keys::PublicKey),
+//! [`SecretKey`](crate::keys::SecretKey) and
+//! [`Signature`](crate::analysis::Signature) implement [`FromHex`]. Scripts
+//! implement [`ToHex`] through `AsRef<[u8]>`, and keys and signatures have a
+//! `to_hex` method, as they have no single byte representation to borrow.
+//! Hashes are byte arrays, which `hex` already supports, except for
+//! [`TxId`](crate::txid::TxId) and [`AuthDigest`](crate::txid::AuthDigest),
+//! whose hex is reversed.
+
+use std::fmt;
+
+pub use hex::{FromHex, ToHex};
+
+/// An error decoding a value from hex.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HexError {
+    /// The string isn't valid hex.
+    Hex(hex::FromHexError),
+    /// The bytes aren't a valid encoding of the value.
+    Invalid,
+}
+
+impl fmt::Display for HexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HexError::Hex(err) => write!(f, "invalid hex: {}", err),
+            HexError::Invalid => write!(f, "invalid encoding"),
+        }
+    }
+}
+
+impl std::error::Error for HexError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            HexError::Hex(err) => Some(err),
+            HexError::Invalid => None,
+        }
+    }
+}
+
+impl From<hex::FromHexError> for HexError {
+    fn from(err: hex::FromHexError) -> Self {
+        HexError::Hex(err)
+    }
+}
+
+/// Decodes `hex` and parses the bytes with `parse`.
+pub(crate) fn decode_with<T>(
+    hex: impl AsRef<[u8]>,
+    parse: impl FnOnce(&[u8]) -> Option<T>,
+) -> Result<T, HexError> {
+    parse(&Vec::from_hex(hex)?).ok_or(HexError::Invalid)
+}
@@ -0,0 +1,400 @@
+//! Evaluation of a scriptPubKey against a caller-provided stack.
+//!
+//! Verification always evaluates a scriptSig to build the stack the
+//! scriptPubKey runs on, so testing an output script through it means
+//! encoding every case as pushes. [`eval_with_stack`] seeds the stack
+//! directly instead, and reports the interpreter's [`ScriptError`].
+//!
+//! [`eval_mocked`] goes further and lets the caller decide the outcome of
+//! each signature check, so the spending conditions of a script can be
+//! explored without real signatures. [`signer_sets`] uses it to find which
+//! sets of keys can spend an output. [`eval_with_checker`] does the same with
+//! a [`SignatureChecker`], such as a [`MockChecker`](crate::mock::MockChecker)
+//! programmed and inspected by tests.
+
+use std::{
+    any::Any,
+    ffi::c_void,
+    fmt,
+    panic::{self, AssertUnwindSafe},
+    slice,
+};
+
+use crate::{
+    precomputed::PrecomputedTx,
+    script_error::ScriptError,
+    verify::{self, Error},
+    zcash_script_error_t_zcash_script_ERR_OK, zcash_script_eval_mocked,
+    zcash_script_eval_with_stack,
+};
+
+/// The most keys [`signer_sets`] accepts, as it tries every subset of them.
+pub const MAX_SIGNER_KEYS: usize = 16;
+
+/// The spend a scriptPubKey is evaluated for, which signatures and lock times
+/// are checked against.
+#[derive(Clone, Copy, Debug)]
+pub struct SpendContext<'a> {
+    /// The spending transaction.
+    pub tx: &'a PrecomputedTx,
+    /// The index of the spending input.
+    pub n_in: u32,
+    /// The value of the output being spent.
+    pub amount: i64,
+    /// The consensus branch ID that signatures commit to.
+    pub consensus_branch_id: u32,
+}
+
+/// An error returned by [`eval_with_stack`] and
+/// [`verify_script_with_limits`](crate::limits::verify_script_with_limits).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EvalError {
+    /// The spend context was invalid, such as an input index out of range.
+    Tx(Error),
+    /// The script failed, or left a false value on top of the stack.
+    Script(ScriptError),
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EvalError::Tx(err) => err.fmt(f),
+            EvalError::Script(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// Decides whether signatures are valid, for [`eval_with_checker`].
+///
+/// Closures taking the signature and the public key implement it.
+pub trait SignatureChecker {
+    /// Returns true if `sig`, including its sighash type byte, is a valid
+    /// signature by `pubkey`.
+    fn check_sig(&mut self, sig: &[u8], pubkey: &[u8]) -> bool;
+}
+
+impl<F: FnMut(&[u8], &[u8]) -> bool> SignatureChecker for F {
+    fn check_sig(&mut self, sig: &[u8], pubkey: &[u8]) -> bool {
+        self(sig, pubkey)
+    }
+}
+
+/// Evaluates `script_pub_key` under `flags`, with the stack initially holding
+/// `stack`, from the bottom to the top, as if a scriptSig had pushed it.
+///
+/// Returns `Ok(())` if the script succeeds and leaves a true value on top of
+/// the stack. P2SH redeem scripts aren't evaluated, so a P2SH scriptPubKey
+/// only checks the hash of the top element.
+///
+/// Without a `context`, every signature and lock time check fails, which
+/// suits scripts whose other branches are being tested.
+///
+/// This uses `zcash_script_eval_with_stack`.
+pub fn eval_with_stack(
+    script_pub_key: &[u8],
+    stack: &[impl AsRef<[u8]>],
+    flags: u32,
+    context: Option<SpendContext<'_>>,
+) -> Result<(), EvalError> {
+    verify::check_api_version();
+    let (pointers, lens) = raw_stack(stack);
+    let (pre_tx, n_in, amount, consensus_branch_id) = match context {
+        Some(context) => (
+            context.tx.as_ptr(),
+            context.n_in,
+            context.amount,
+            context.consensus_branch_id,
+        ),
+        None => (std::ptr::null(), 0, 0, 0),
+    };
+
+    let mut script_err = 0;
+    let mut err = 0;
+    let ret = unsafe {
+        zcash_script_eval_with_stack(
+            pointers.as_ptr(),
+            lens.as_ptr(),
+            pointers.len() as _,
+            script_pub_key.as_ptr(),
+            script_pub_key.len() as _,
+            flags,
+            pre_tx,
+            n_in,
+            amount,
+            consensus_branch_id,
+            &mut script_err,
+            &mut err,
+        )
+    };
+    if ret == 1 {
+        Ok(())
+    } else if err != zcash_script_error_t_zcash_script_ERR_OK {
+        Err(EvalError::Tx(Error::from_code(err)))
+    } else {
+        Err(EvalError::Script(script_error(script_err)))
+    }
+}
+
+/// Evaluates `script_pub_key` as [`eval_with_stack`] does without a
+/// [`SpendContext`], except that `check_sig` decides whether each signature
+/// is valid for a public key, and every lock time check succeeds.
+///
+/// `check_sig` is called with the signature, including its sighash type
+/// byte, and the public key, for each check that passes the encoding rules
+/// `flags` require, so with strict encoding flags the mocked signatures must
+/// still be DER-encoded.
+///
+/// This uses `zcash_script_eval_mocked`. If `check_sig` panics, the
+/// evaluation is abandoned and the panic resumed.
+pub fn eval_mocked<F>(
+    script_pub_key: &[u8],
+    stack: &[impl AsRef<[u8]>],
+    flags: u32,
+    mut check_sig: F,
+) -> Result<(), ScriptError>
+where
+    F: FnMut(&[u8], &[u8]) -> bool,
+{
+    eval_with_checker(script_pub_key, stack, flags, &mut check_sig)
+}
+
+/// Evaluates `script_pub_key` as [`eval_mocked`] does, with `checker`
+/// deciding whether each signature is valid.
+///
+/// The checker is borrowed, so it can be inspected afterwards, such as for
+/// the checks a [`MockChecker`](crate::mock::MockChecker) recorded.
+pub fn eval_with_checker<C: SignatureChecker + ?Sized>(
+    script_pub_key: &[u8],
+    stack: &[impl AsRef<[u8]>],
+    flags: u32,
+    checker: &mut C,
+) -> Result<(), ScriptError> {
+    verify::check_api_version();
+    let (pointers, lens) = raw_stack(stack);
+    let mut callback = Callback {
+        checker,
+        panic: None,
+    };
+
+    let mut script_err = 0;
+    let ret = unsafe {
+        zcash_script_eval_mocked(
+            pointers.as_ptr(),
+            lens.as_ptr(),
+            pointers.len() as _,
+            script_pub_key.as_ptr(),
+            script_pub_key.len() as _,
+            flags,
+            Some(check_sig_trampoline::<C>),
+            &mut callback as *mut Callback<'_, C> as *mut c_void,
+            &mut script_err,
+        )
+    };
+    if let Some(payload) = callback.panic {
+        panic::resume_unwind(payload);
+    }
+    if ret == 1 {
+        Ok(())
+    } else {
+        Err(script_error(script_err))
+    }
+}
+
+/// Returns the minimal sets of `keys` whose signatures let `script_pub_key`
+/// succeed on `stack`, as indices into `keys` in increasing order.
+///
+/// The stack must hold the signatures and branch selectors the script
+/// consumes, such as `OP_0` dummies and placeholder signatures for a
+/// multisig. Each placeholder is treated as a valid signature by exactly the
+/// keys in the set being tried. Returns `None` if there are more than
+/// [`MAX_SIGNER_KEYS`] keys.
+pub fn signer_sets(
+    script_pub_key: &[u8],
+    stack: &[impl AsRef<[u8]>],
+    flags: u32,
+    keys: &[impl AsRef<[u8]>],
+) -> Option<Vec<Vec<usize>>> {
+    if keys.len() > MAX_SIGNER_KEYS {
+        return None;
+    }
+    let mut masks: Vec<u32> = (0..1 << keys.len()).collect();
+    masks.sort_by_key(|mask| mask.count_ones());
+
+    let mut minimal: Vec<u32> = vec![];
+    for mask in masks {
+        if minimal.iter().any(|found| mask & found == *found) {
+            continue;
+        }
+        let signs = |pubkey: &[u8]| {
+            keys.iter()
+                .enumerate()
+                .any(|(i, key)| mask & (1 << i) != 0 && key.as_ref() == pubkey)
+        };
+        if eval_mocked(script_pub_key, stack, flags, |_, pubkey| signs(pubkey)).is_ok() {
+            minimal.push(mask);
+        }
+    }
+    Some(
+        minimal
+            .into_iter()
+            .map(|mask| (0..keys.len()).filter(|i| mask & (1 << i) != 0).collect())
+            .collect(),
+    )
+}
+
+/// Returns pointers to the elements of `stack`, and their lengths.
+fn raw_stack(stack: &[impl AsRef<[u8]>]) -> (Vec<*const u8>, Vec<u32>) {
+    stack
+        .iter()
+        .map(|element| (element.as_ref().as_ptr(), element.as_ref().len() as u32))
+        .unzip()
+}
+
+fn script_error(code: u32) -> ScriptError {
+    ScriptError::from_code(code).unwrap_or(ScriptError::UnknownError)
+}
+
+/// The state passed to [`check_sig_trampoline`].
+struct Callback<'a, C: ?Sized> {
+    checker: &'a mut C,
+    /// The payload of a panic in `check_sig`, which can't unwind through C++.
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+unsafe extern "C" fn check_sig_trampoline<C: SignatureChecker + ?Sized>(
+    ctx: *mut c_void,
+    sig: *const u8,
+    sig_len: u32,
+    pubkey: *const u8,
+    pubkey_len: u32,
+) -> i32 {
+    // SAFETY: `ctx` is the `Callback<C>` passed by `eval_with_checker`,
+    // which outlives the evaluation, and the data pointers are valid for
+    // their lengths during the call.
+    let callback = unsafe { &mut *(ctx as *mut Callback<'_, C>) };
+    if callback.panic.is_some() {
+        return 0;
+    }
+    let bytes = |data: *const u8, len: u32| match len {
+        0 => &[][..],
+        len => unsafe { slice::from_raw_parts(data, len as usize) },
+    };
+    let (sig, pubkey) = (bytes(sig, sig_len), bytes(pubkey, pubkey_len));
+    match panic::catch_unwind(AssertUnwindSafe(|| callback.checker.check_sig(sig, pubkey))) {
+        Ok(valid) => valid.into(),
+        Err(payload) => {
+            callback.panic = Some(payload);
+            0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        script::Script,
+        tests::{SCRIPT_PUBKEY, SCRIPT_TX},
+    };
+
+    #[test]
+    fn evaluates_with_stack() {
+        let add = crate::script!(OP_ADD 5 OP_EQUAL);
+        let eval = |stack: &[&[u8]]| eval_with_stack(add.as_bytes(), stack, 0, None);
+        assert_eq!(eval(&[&[2], &[3]]), Ok(()));
+        assert_eq!(
+            eval(&[&[2], &[2]]),
+            Err(EvalError::Script(ScriptError::EvalFalse))
+        );
+        assert_eq!(
+            eval(&[&[2]]),
+            Err(EvalError::Script(ScriptError::InvalidStackOperation))
+        );
+
+        // The signature and public key pushed by the transaction's scriptSig,
+        // which starts after the version, version group ID, input count,
+        // outpoint and script length.
+        let script_sig = Script::from(&SCRIPT_TX[46..46 + 0x6a]);
+        let stack: Vec<_> = script_sig
+            .instructions()
+            .map(|op| op.unwrap().data.to_vec())
+            .collect();
+        let tx = PrecomputedTx::new(&SCRIPT_TX).unwrap();
+        let context = |n_in| SpendContext {
+            tx: &tx,
+            n_in,
+            amount: 212 * 100_000_000,
+            consensus_branch_id: 0x2bb40e60,
+        };
+        assert_eq!(
+            eval_with_stack(&SCRIPT_PUBKEY, &stack, 1, Some(context(0))),
+            Ok(())
+        );
+        // Without a transaction, the signature check fails.
+        assert_eq!(
+            eval_with_stack(&SCRIPT_PUBKEY, &stack, 1, None),
+            Err(EvalError::Script(ScriptError::EvalFalse))
+        );
+        assert_eq!(
+            eval_with_stack(&SCRIPT_PUBKEY, &stack[..1], 1, Some(context(0))),
+            Err(EvalError::Script(ScriptError::EqualVerify))
+        );
+        assert_eq!(
+            eval_with_stack(&SCRIPT_PUBKEY, &stack, 1, Some(context(1))),
+            Err(EvalError::Tx(Error::TxIndex))
+        );
+    }
+
+    #[test]
+    fn evaluates_with_mocked_signatures() {
+        let keys = [[2u8; 33], [3; 33], [4; 33]];
+        let multisig = crate::script!(2 [keys[0]] [keys[1]] [keys[2]] 3 OP_CHECKMULTISIG);
+        let stack: [&[u8]; 3] = [&[], &[1], &[1]];
+
+        // Signatures are checked from the top of the stack, against the keys
+        // from last to first, so key 1 not signing only skips it.
+        let mut checks = vec![];
+        let result = eval_mocked(multisig.as_bytes(), &stack, 0, |_, pubkey| {
+            checks.push(pubkey[0]);
+            pubkey != keys[1]
+        });
+        assert_eq!(result, Ok(()));
+        assert_eq!(checks, [4, 3, 2]);
+        assert_eq!(
+            eval_mocked(multisig.as_bytes(), &stack, 0, |_, _| false),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            signer_sets(multisig.as_bytes(), &stack, 0, &keys),
+            Some(vec![vec![0, 1], vec![0, 2], vec![1, 2]])
+        );
+
+        // Each branch of a script is explored with its own selector.
+        let either = crate::script!(
+            OP_IF [keys[0]] OP_CHECKSIG
+            OP_ELSE (854882) OP_CHECKLOCKTIMEVERIFY OP_DROP [keys[1]] OP_CHECKSIG OP_ENDIF
+        );
+        let flags = 1 << 9;
+        let first: [&[u8]; 2] = [&[1], &[1]];
+        let second: [&[u8]; 2] = [&[1], &[]];
+        assert_eq!(
+            signer_sets(either.as_bytes(), &first, flags, &keys),
+            Some(vec![vec![0]])
+        );
+        assert_eq!(
+            signer_sets(either.as_bytes(), &second, flags, &keys),
+            Some(vec![vec![1]])
+        );
+        assert_eq!(
+            signer_sets(either.as_bytes(), &first, flags, &[[0u8; 33]; 17]),
+            None
+        );
+
+        let panicked = panic::catch_unwind(|| {
+            eval_mocked(multisig.as_bytes(), &stack, 0, |_, _| panic!("mock failed"))
+        });
+        assert!(panicked.is_err());
+    }
+}
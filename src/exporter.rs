@@ -0,0 +1,209 @@
+//! Prometheus metrics about verification, enabled by the `prometheus`
+//! feature.
+//!
+//! [`PrometheusObserver`] is a [`VerificationObserver`] that records the
+//! outcome and latency of verifications in a [`Registry`] provided by the
+//! node, and [`register_cache`] exports the hits and misses of a
+//! [`ScriptCache`], from which dashboards can compute its hit rate. Like every
+//! observer, it doesn't see the inputs verified by the batch APIs.
+
+use std::sync::{Arc, Mutex};
+
+use prometheus::{
+    core::{Collector, Desc},
+    exponential_buckets,
+    proto::MetricFamily,
+    Histogram, HistogramOpts, IntCounter, IntCounterVec, Opts, Registry,
+};
+
+use crate::{
+    cache::ScriptCache,
+    observer::{set_observer, VerificationEvent, VerificationObserver},
+    verify::Error,
+};
+
+/// Records verifications as Prometheus metrics:
+///
+/// - `zcash_script_verifications_total`, counting verifications by their
+///   `result`, which is `ok` or the kind of error, such as `script_invalid`.
+/// - `zcash_script_verification_duration_seconds`, a histogram of how long
+///   verifications took.
+#[derive(Clone, Debug)]
+pub struct PrometheusObserver {
+    verifications: IntCounterVec,
+    duration: Histogram,
+}
+
+impl PrometheusObserver {
+    /// Creates the metrics and registers them with `registry`.
+    pub fn register(registry: &Registry) -> prometheus::Result<Arc<Self>> {
+        let verifications = IntCounterVec::new(
+            Opts::new(
+                "zcash_script_verifications_total",
+                "Script verifications, by result",
+            ),
+            &["result"],
+        )?;
+        // Verifications take tens of microseconds, far below the default
+        // buckets, so these range from 10µs to about 0.3s.
+        let duration = Histogram::with_opts(
+            HistogramOpts::new(
+                "zcash_script_verification_duration_seconds",
+                "Time taken by script verifications",
+            )
+            .buckets(exponential_buckets(1e-5, 2.0, 16)?),
+        )?;
+        registry.register(Box::new(verifications.clone()))?;
+        registry.register(Box::new(duration.clone()))?;
+        Ok(Arc::new(PrometheusObserver {
+            verifications,
+            duration,
+        }))
+    }
+
+    /// Registers the metrics with `registry` and installs the observer with
+    /// [`set_observer`].
+    pub fn install(registry: &Registry) -> prometheus::Result<Arc<Self>> {
+        let observer = Self::register(registry)?;
+        set_observer(observer.clone());
+        Ok(observer)
+    }
+}
+
+impl VerificationObserver for PrometheusObserver {
+    fn on_verification(&self, event: &VerificationEvent) {
+        let result = match event.result {
+            Ok(()) => "ok",
+            Err(err) => error_label(err),
+        };
+        self.verifications.with_label_values(&[result]).inc();
+        self.duration.observe(event.duration.as_secs_f64());
+    }
+}
+
+/// Returns the `result` label of verifications that failed with `err`.
+fn error_label(err: Error) -> &'static str {
+    match err {
+        Error::ScriptInvalid => "script_invalid",
+        Error::TxIndex => "tx_index",
+        Error::TxSizeMismatch => "tx_size_mismatch",
+        Error::TxDeserialize => "tx_deserialize",
+        Error::TxVersion => "tx_version",
+        Error::AllPrevOutputsSizeMismatch => "all_prev_outputs_size_mismatch",
+        Error::AllPrevOutputsDeserialize => "all_prev_outputs_deserialize",
+        Error::VerifyScript => "verify_script",
+        Error::Unknown(_) => "unknown",
+    }
+}
+
+/// Exports the counters of a [`ScriptCache`] when the registry is gathered.
+struct CacheCollector {
+    cache: Arc<ScriptCache>,
+    hits: IntCounter,
+    misses: IntCounter,
+    /// Serializes collections, which update the counters from the cache.
+    lock: Mutex<()>,
+}
+
+impl Collector for CacheCollector {
+    fn desc(&self) -> Vec<&Desc> {
+        self.hits
+            .desc()
+            .into_iter()
+            .chain(self.misses.desc())
+            .collect()
+    }
+
+    fn collect(&self) -> Vec<MetricFamily> {
+        let _guard = self.lock.lock().expect("lock is not poisoned");
+        for (counter, value) in [
+            (&self.hits, self.cache.hits()),
+            (&self.misses, self.cache.misses()),
+        ] {
+            counter.reset();
+            counter.inc_by(value);
+        }
+        self.hits
+            .collect()
+            .into_iter()
+            .chain(self.misses.collect())
+            .collect()
+    }
+}
+
+/// Registers `zcash_script_cache_hits_total` and
+/// `zcash_script_cache_misses_total` with `registry`, reading them from
+/// `cache` when the registry is gathered.
+pub fn register_cache(registry: &Registry, cache: Arc<ScriptCache>) -> prometheus::Result<()> {
+    let collector = CacheCollector {
+        cache,
+        hits: IntCounter::new(
+            "zcash_script_cache_hits_total",
+            "Verifications found in the script cache",
+        )?,
+        misses: IntCounter::new(
+            "zcash_script_cache_misses_total",
+            "Verifications not found in the script cache",
+        )?,
+        lock: Mutex::new(()),
+    };
+    registry.register(Box::new(collector))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{cache::CacheKey, tests::SCRIPT_TX};
+
+    fn value(registry: &Registry, name: &str, result: Option<&str>) -> f64 {
+        let family = registry
+            .gather()
+            .into_iter()
+            .find(|family| family.get_name() == name)
+            .expect("metric is registered");
+        family
+            .get_metric()
+            .iter()
+            .filter(|metric| {
+                result.map_or(true, |result| {
+                    metric
+                        .get_label()
+                        .iter()
+                        .any(|label| label.get_value() == result)
+                })
+            })
+            .map(|metric| metric.get_counter().get_value())
+            .sum()
+    }
+
+    #[test]
+    fn exports_metrics() {
+        let registry = Registry::new();
+        let observer = PrometheusObserver::register(&registry).unwrap();
+        for result in [Ok(()), Ok(()), Err(Error::ScriptInvalid)] {
+            observer.on_verification(&VerificationEvent {
+                duration: Duration::from_micros(50),
+                result,
+                script_error: None,
+                flags: 1,
+                script_type: None,
+            });
+        }
+        let total = "zcash_script_verifications_total";
+        assert_eq!(value(&registry, total, Some("ok")), 2.0);
+        assert_eq!(value(&registry, total, Some("script_invalid")), 1.0);
+
+        let cache = Arc::new(ScriptCache::new(4));
+        register_cache(&registry, cache.clone()).unwrap();
+        let key = CacheKey::new(&SCRIPT_TX, 0, 1, 0x2bb40e60);
+        cache.verify(key, || Ok(())).unwrap();
+        cache.verify(key, || Ok(())).unwrap();
+        assert_eq!(value(&registry, "zcash_script_cache_hits_total", None), 1.0);
+        assert_eq!(
+            value(&registry, "zcash_script_cache_misses_total", None),
+            1.0
+        );
+    }
+}
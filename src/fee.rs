@@ -0,0 +1,268 @@
+//! Estimation of the serialized size of transparent inputs and outputs, and
+//! of their effect on the ZIP 317 conventional fee, so that wallets can compute
+//! fees before signing.
+
+use crate::{
+    script::Script,
+    standard::{hash160, solve, Template},
+};
+
+/// The maximum size of a signature with its hash type appended: a 72-byte DER
+/// signature, as allowed by consensus, and one hash type byte.
+pub const MAX_SIGNATURE_SIZE: usize = 73;
+
+/// The maximum size of a public key, which is uncompressed.
+pub const MAX_PUBKEY_SIZE: usize = 65;
+
+/// The size of a signature with its hash type appended, when it has a low S
+/// value as [`VerificationFlags::LOW_S`](crate::flags::VerificationFlags::LOW_S)
+/// requires: at most 71 bytes of DER, and one hash type byte.
+pub const EXPECTED_SIGNATURE_SIZE: usize = 72;
+
+/// The size of a compressed public key.
+pub const COMPRESSED_PUBKEY_SIZE: usize = 33;
+
+/// The ZIP 317 marginal fee per logical action, in zatoshis.
+pub const MARGINAL_FEE: u64 = 5_000;
+
+/// The number of logical actions that ZIP 317 charges for even if a
+/// transaction has fewer.
+pub const GRACE_ACTIONS: usize = 2;
+
+/// The input size that ZIP 317 counts as one logical action.
+pub const P2PKH_STANDARD_INPUT_SIZE: usize = 150;
+
+/// The output size that ZIP 317 counts as one logical action.
+pub const P2PKH_STANDARD_OUTPUT_SIZE: usize = 34;
+
+/// An upper bound on the cost of spending a scriptPubKey.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpendEstimate {
+    /// The maximum size of the scriptSig, in bytes.
+    pub script_sig_size: usize,
+    /// The maximum serialized size of the input, including its outpoint and
+    /// sequence number.
+    pub input_size: usize,
+    /// The number of signature operations counted against the block limit,
+    /// including the P2SH redeem script's.
+    pub sig_ops: u32,
+}
+
+/// Estimates the cost of spending `script_pub_key`.
+///
+/// P2SH outputs need the `redeem_script`, which must match the script hash
+/// and be a standard template itself. Returns `None` for null-data and
+/// non-standard scripts, and for P2SH outputs without a matching redeem
+/// script.
+pub fn estimate_spend(
+    script_pub_key: &Script,
+    redeem_script: Option<&Script>,
+) -> Option<SpendEstimate> {
+    let script_sig_size = max_script_sig_size(script_pub_key, redeem_script)?;
+    let p2sh_sig_ops = match solve(script_pub_key)? {
+        Template::ScriptHash(_) => redeem_script?.sig_op_count(true),
+        _ => 0,
+    };
+    Some(SpendEstimate {
+        script_sig_size,
+        input_size: input_size(script_sig_size),
+        sig_ops: script_pub_key.sig_op_count(false) + p2sh_sig_ops,
+    })
+}
+
+/// Returns the maximum size of a scriptSig spending `script_pub_key`, using
+/// [`MAX_SIGNATURE_SIZE`] signatures and, for P2PKH, a [`MAX_PUBKEY_SIZE`]
+/// public key.
+///
+/// See [`estimate_spend`] for the requirements on `redeem_script`.
+pub fn max_script_sig_size(
+    script_pub_key: &Script,
+    redeem_script: Option<&Script>,
+) -> Option<usize> {
+    script_sig_size(
+        script_pub_key,
+        redeem_script,
+        MAX_SIGNATURE_SIZE,
+        MAX_PUBKEY_SIZE,
+    )
+}
+
+/// Returns the expected size of a scriptSig spending `script_pub_key` once it
+/// is signed, for coin selection.
+///
+/// This assumes [`EXPECTED_SIGNATURE_SIZE`] signatures and, for P2PKH, a
+/// compressed public key, as zcashd's wallet produces. See [`estimate_spend`]
+/// for the requirements on `redeem_script`.
+pub fn expected_script_sig_size(
+    script_pub_key: &Script,
+    redeem_script: Option<&Script>,
+) -> Option<usize> {
+    script_sig_size(
+        script_pub_key,
+        redeem_script,
+        EXPECTED_SIGNATURE_SIZE,
+        COMPRESSED_PUBKEY_SIZE,
+    )
+}
+
+/// Returns the expected size of a scriptSig spending an `m`-of-`n` multisig
+/// P2SH output once it is signed, without needing its redeem script.
+///
+/// This assumes compressed public keys and [`EXPECTED_SIGNATURE_SIZE`]
+/// signatures.
+pub fn expected_p2sh_multisig_script_sig_size(m: usize, n: usize) -> usize {
+    // <m> <pubkey>... <n> OP_CHECKMULTISIG, with small integer opcodes.
+    let redeem_script_size = 1 + n * push_size(COMPRESSED_PUBKEY_SIZE) + 1 + 1;
+    1 + m * push_size(EXPECTED_SIGNATURE_SIZE) + push_size(redeem_script_size)
+}
+
+fn script_sig_size(
+    script_pub_key: &Script,
+    redeem_script: Option<&Script>,
+    signature_size: usize,
+    pubkey_size: usize,
+) -> Option<usize> {
+    match solve(script_pub_key)? {
+        Template::PubKey(_) => Some(push_size(signature_size)),
+        Template::PubKeyHash(_) => Some(push_size(signature_size) + push_size(pubkey_size)),
+        // OP_CHECKMULTISIG's dummy element is pushed with OP_0.
+        Template::MultiSig { required, .. } => {
+            Some(1 + usize::from(required) * push_size(signature_size))
+        }
+        Template::ScriptHash(hash) => {
+            let redeem_script = redeem_script?;
+            if hash160(redeem_script.as_bytes()) != hash {
+                return None;
+            }
+            let inner = script_sig_size(redeem_script, None, signature_size, pubkey_size)?;
+            Some(inner + push_size(redeem_script.len()))
+        }
+        Template::NullData => None,
+    }
+}
+
+/// Returns the serialized size of a transparent input with a scriptSig of
+/// the given size.
+pub fn input_size(script_sig_size: usize) -> usize {
+    // The outpoint's txid and index, then the scriptSig, then nSequence.
+    32 + 4 + compact_size_len(script_sig_size) + script_sig_size + 4
+}
+
+/// Returns the serialized size of a transparent output paying to
+/// `script_pub_key`.
+pub fn output_size(script_pub_key: &Script) -> usize {
+    8 + compact_size_len(script_pub_key.len()) + script_pub_key.len()
+}
+
+/// The total sizes of a transaction's transparent inputs and outputs, from
+/// which ZIP 317 computes their logical actions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TransparentSizes {
+    /// The total serialized size of the inputs.
+    pub inputs: usize,
+    /// The total serialized size of the outputs.
+    pub outputs: usize,
+}
+
+impl TransparentSizes {
+    /// Adds an input with a scriptSig of the given size.
+    pub fn add_input(&mut self, script_sig_size: usize) -> &mut Self {
+        self.inputs += input_size(script_sig_size);
+        self
+    }
+
+    /// Adds an output paying to `script_pub_key`.
+    pub fn add_output(&mut self, script_pub_key: &Script) -> &mut Self {
+        self.outputs += output_size(script_pub_key);
+        self
+    }
+
+    /// Returns the number of ZIP 317 logical actions for the transparent part
+    /// of the transaction.
+    pub fn logical_actions(&self) -> usize {
+        self.inputs
+            .div_ceil(P2PKH_STANDARD_INPUT_SIZE)
+            .max(self.outputs.div_ceil(P2PKH_STANDARD_OUTPUT_SIZE))
+    }
+}
+
+/// Returns the ZIP 317 conventional fee, in zatoshis, for a transaction with
+/// the given number of logical actions across all of its pools.
+pub fn conventional_fee(logical_actions: usize) -> u64 {
+    MARGINAL_FEE * logical_actions.max(GRACE_ACTIONS) as u64
+}
+
+/// Returns the size of a push of `len` bytes, using the same push opcode as
+/// [`Script::push_slice`].
+fn push_size(len: usize) -> usize {
+    let prefix = match len {
+        0..=0x4b => 1,
+        0x4c..=0xff => 2,
+        0x100..=0xffff => 3,
+        _ => 5,
+    };
+    prefix + len
+}
+
+/// Returns the size of the `CompactSize` encoding of `n`.
+fn compact_size_len(n: usize) -> usize {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x1_0000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{opcode::Opcode, tests::SCRIPT_PUBKEY};
+
+    #[test]
+    fn estimates_spends() {
+        let p2pkh = Script(SCRIPT_PUBKEY.to_vec());
+        let spend = estimate_spend(&p2pkh, None).unwrap();
+        assert_eq!(spend.script_sig_size, 74 + 66);
+        assert_eq!(spend.input_size, 181);
+        assert_eq!(spend.sig_ops, 1);
+
+        // A 2-of-3 multisig in P2SH.
+        let mut redeem_script = Script::default();
+        redeem_script.push_num(2);
+        for _ in 0..3 {
+            redeem_script.push_slice(&[2; 33]);
+        }
+        redeem_script
+            .push_num(3)
+            .push_opcode(Opcode::OP_CHECKMULTISIG);
+        let mut p2sh = Script::default();
+        p2sh.push_opcode(Opcode::OP_HASH160)
+            .push_slice(&hash160(redeem_script.as_bytes()))
+            .push_opcode(Opcode::OP_EQUAL);
+        let spend = estimate_spend(&p2sh, Some(&redeem_script)).unwrap();
+        assert_eq!(spend.script_sig_size, 1 + 2 * 74 + 2 + 105);
+        assert_eq!(spend.sig_ops, 3);
+        assert_eq!(estimate_spend(&p2sh, Some(&p2pkh)), None);
+
+        assert_eq!(expected_script_sig_size(&p2pkh, None), Some(73 + 34));
+        assert_eq!(
+            expected_script_sig_size(&p2sh, Some(&redeem_script)),
+            Some(expected_p2sh_multisig_script_sig_size(2, 3))
+        );
+        assert_eq!(
+            expected_p2sh_multisig_script_sig_size(2, 3),
+            1 + 2 * 73 + 2 + 105
+        );
+
+        let mut sizes = TransparentSizes::default();
+        sizes
+            .add_input(spend.script_sig_size)
+            .add_output(&p2pkh)
+            .add_output(&p2sh);
+        assert_eq!(sizes.outputs, 34 + 32);
+        assert_eq!(sizes.logical_actions(), 2);
+        assert_eq!(conventional_fee(sizes.logical_actions()), 10_000);
+        assert_eq!(conventional_fee(5), 25_000);
+    }
+}
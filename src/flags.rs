@@ -0,0 +1,354 @@
+//! Script verification flags, matching the `SCRIPT_VERIFY_*` flags of
+//! zcashd's interpreter (`depend/zcash/src/script/interpreter.h`).
+//!
+//! The C API only names [`VerificationFlags::P2SH`] and
+//! [`VerificationFlags::CHECKLOCKTIMEVERIFY`], but passes every flag through
+//! to the interpreter.
+
+use std::{
+    fmt,
+    ops::{BitAnd, BitOr, BitOrAssign},
+    str::FromStr,
+};
+
+use zcash_primitives::consensus::{BlockHeight, BranchId, Parameters};
+
+/// Every flag that zcashd's interpreter implements.
+pub(crate) const KNOWN_FLAGS: [VerificationFlags; 9] = [
+    VerificationFlags::P2SH,
+    VerificationFlags::STRICTENC,
+    VerificationFlags::LOW_S,
+    VerificationFlags::NULLDUMMY,
+    VerificationFlags::SIGPUSHONLY,
+    VerificationFlags::MINIMALDATA,
+    VerificationFlags::DISCOURAGE_UPGRADABLE_NOPS,
+    VerificationFlags::CLEANSTACK,
+    VerificationFlags::CHECKLOCKTIMEVERIFY,
+];
+
+/// A set of script verification flags.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VerificationFlags(pub u32);
+
+impl VerificationFlags {
+    pub const NONE: VerificationFlags = VerificationFlags(0);
+    /// Evaluate P2SH subscripts (BIP 16).
+    pub const P2SH: VerificationFlags = VerificationFlags(1 << 0);
+    /// Require strict encoding of signatures and public keys.
+    pub const STRICTENC: VerificationFlags = VerificationFlags(1 << 1);
+    /// Require signatures to have a low S value.
+    pub const LOW_S: VerificationFlags = VerificationFlags(1 << 3);
+    /// Require the `OP_CHECKMULTISIG` dummy argument to be empty.
+    pub const NULLDUMMY: VerificationFlags = VerificationFlags(1 << 4);
+    /// Require the scriptSig to only contain push operations.
+    pub const SIGPUSHONLY: VerificationFlags = VerificationFlags(1 << 5);
+    /// Require pushes and numbers to be minimally encoded.
+    pub const MINIMALDATA: VerificationFlags = VerificationFlags(1 << 6);
+    /// Fail on the upgradable `OP_NOP1` and `OP_NOP3` to `OP_NOP10`, and on
+    /// `OP_NOP2` unless [`VerificationFlags::CHECKLOCKTIMEVERIFY`] is set.
+    ///
+    /// This is a relay policy, not a consensus rule, so that soft forks can
+    /// give these NOPs a meaning without old nodes relaying spends that the
+    /// new rules make invalid. [`upgradable_nops`] finds them in a script.
+    ///
+    /// [`upgradable_nops`]: crate::diagnostics::upgradable_nops
+    pub const DISCOURAGE_UPGRADABLE_NOPS: VerificationFlags = VerificationFlags(1 << 7);
+    /// Require exactly one element on the stack after evaluation.
+    pub const CLEANSTACK: VerificationFlags = VerificationFlags(1 << 8);
+    /// Enable `OP_CHECKLOCKTIMEVERIFY` (BIP 65).
+    pub const CHECKLOCKTIMEVERIFY: VerificationFlags = VerificationFlags(1 << 9);
+
+    /// Returns the flags as passed to the C API.
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    /// Returns true if all of `other`'s flags are set.
+    pub const fn contains(self, other: VerificationFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the flags that consensus requires for blocks in the epoch with
+    /// the given consensus branch ID, or `None` if the branch ID is unknown.
+    ///
+    /// This matches the flags zcashd's `ConnectBlock` passes to
+    /// `ContextualCheckInputs`. Zcash has enforced P2SH and
+    /// `OP_CHECKLOCKTIMEVERIFY` since genesis, so every epoch currently uses
+    /// the same flags.
+    pub fn consensus_for(consensus_branch_id: u32) -> Option<Self> {
+        let branch_id = BranchId::try_from(consensus_branch_id).ok()?;
+        Some(Self::consensus_for_branch(branch_id))
+    }
+
+    /// Returns the flags that consensus requires for a block at `height` on
+    /// the network described by `params`.
+    pub fn consensus_at<P: Parameters>(params: &P, height: BlockHeight) -> Self {
+        Self::consensus_for_branch(BranchId::for_height(params, height))
+    }
+
+    /// Returns the flags zcashd's mempool checks transactions with, matching
+    /// `STANDARD_SCRIPT_VERIFY_FLAGS`.
+    ///
+    /// Scripts that violate these flags may still appear in valid blocks.
+    pub const fn standard() -> Self {
+        VerificationFlags(
+            Self::mandatory().0
+                | Self::STRICTENC.0
+                | Self::MINIMALDATA.0
+                | Self::NULLDUMMY.0
+                | Self::DISCOURAGE_UPGRADABLE_NOPS.0
+                | Self::CLEANSTACK.0
+                | Self::CHECKLOCKTIMEVERIFY.0
+                | Self::LOW_S.0,
+        )
+    }
+
+    /// Returns the flags whose violation makes zcashd reject a transaction
+    /// from the mempool as invalid, rather than merely non-standard,
+    /// matching `MANDATORY_SCRIPT_VERIFY_FLAGS`.
+    pub const fn mandatory() -> Self {
+        Self::P2SH
+    }
+
+    /// Returns each flag that is set, as a single-flag set, from the lowest
+    /// bit to the highest.
+    pub fn iter(self) -> impl Iterator<Item = VerificationFlags> {
+        (0..32)
+            .map(|bit| VerificationFlags(1 << bit))
+            .filter(move |&flag| self.contains(flag))
+    }
+
+    /// Returns the name of a single flag, without the `SCRIPT_VERIFY_` prefix,
+    /// or `None` if this isn't exactly one known flag.
+    pub fn name(self) -> Option<&'static str> {
+        Some(match self {
+            Self::P2SH => "P2SH",
+            Self::STRICTENC => "STRICTENC",
+            Self::LOW_S => "LOW_S",
+            Self::NULLDUMMY => "NULLDUMMY",
+            Self::SIGPUSHONLY => "SIGPUSHONLY",
+            Self::MINIMALDATA => "MINIMALDATA",
+            Self::DISCOURAGE_UPGRADABLE_NOPS => "DISCOURAGE_UPGRADABLE_NOPS",
+            Self::CLEANSTACK => "CLEANSTACK",
+            Self::CHECKLOCKTIMEVERIFY => "CHECKLOCKTIMEVERIFY",
+            _ => return None,
+        })
+    }
+
+    fn consensus_for_branch(_branch_id: BranchId) -> Self {
+        // No network upgrade has changed the consensus flags yet.
+        Self::P2SH | Self::CHECKLOCKTIMEVERIFY
+    }
+}
+
+impl BitOr for VerificationFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        VerificationFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for VerificationFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitAnd for VerificationFlags {
+    type Output = Self;
+
+    fn bitand(self, rhs: Self) -> Self {
+        VerificationFlags(self.0 & rhs.0)
+    }
+}
+
+/// Formats the flags as their names joined by `|`, like `P2SH|CLEANSTACK`,
+/// with unknown flags in hex, and no flags as `NONE`.
+impl fmt::Display for VerificationFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if *self == Self::NONE {
+            return write!(f, "NONE");
+        }
+        for (i, flag) in self.iter().enumerate() {
+            if i > 0 {
+                write!(f, "|")?;
+            }
+            match flag.name() {
+                Some(name) => write!(f, "{}", name)?,
+                None => write!(f, "{:#x}", flag.0)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An error parsing [`VerificationFlags`] from a string.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseFlagsError(String);
+
+impl fmt::Display for ParseFlagsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown verification flag {:?}", self.0)
+    }
+}
+
+impl std::error::Error for ParseFlagsError {}
+
+/// Parses flags in the format [`Display`](fmt::Display) writes, also
+/// accepting a decimal or `0x`-prefixed hex number for each flag, or for all
+/// the flags at once.
+impl FromStr for VerificationFlags {
+    type Err = ParseFlagsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut flags = Self::NONE;
+        for name in s.split('|').map(str::trim) {
+            let bits = match name.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => name.parse().ok(),
+            };
+            flags |= match (name, bits) {
+                ("NONE", _) => Self::NONE,
+                (_, Some(bits)) => VerificationFlags(bits),
+                _ => KNOWN_FLAGS
+                    .into_iter()
+                    .find(|flag| flag.name() == Some(name))
+                    .ok_or_else(|| ParseFlagsError(name.to_owned()))?,
+            };
+        }
+        Ok(flags)
+    }
+}
+
+/// Generates any combination of the known flags that zcashd accepts, which
+/// excludes `CLEANSTACK` without `P2SH`.
+#[cfg(feature = "arbitrary")]
+impl<'a> ::arbitrary::Arbitrary<'a> for VerificationFlags {
+    fn arbitrary(u: &mut ::arbitrary::Unstructured<'a>) -> ::arbitrary::Result<Self> {
+        let mut flags = Self::NONE;
+        for flag in KNOWN_FLAGS {
+            if u.arbitrary()? {
+                flags |= flag;
+            }
+        }
+        if flags.contains(Self::CLEANSTACK) {
+            flags |= Self::P2SH;
+        }
+        Ok(flags)
+    }
+
+    fn size_hint(_depth: usize) -> (usize, Option<usize>) {
+        (KNOWN_FLAGS.len(), Some(KNOWN_FLAGS.len()))
+    }
+}
+
+/// Serializes the flags in the format [`Display`](fmt::Display) writes.
+#[cfg(feature = "serde")]
+impl serde::Serialize for VerificationFlags {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Deserializes flags in any format [`FromStr`] accepts.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for VerificationFlags {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <String as serde::Deserialize>::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl From<VerificationFlags> for u32 {
+    fn from(flags: VerificationFlags) -> u32 {
+        flags.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use zcash_primitives::consensus::{MainNetwork, NetworkUpgrade};
+
+    use super::*;
+    use crate::{
+        zcash_script_SCRIPT_FLAGS_VERIFY_CHECKLOCKTIMEVERIFY, zcash_script_SCRIPT_FLAGS_VERIFY_P2SH,
+    };
+
+    #[test]
+    fn consensus_flags() {
+        let expected = zcash_script_SCRIPT_FLAGS_VERIFY_P2SH
+            | zcash_script_SCRIPT_FLAGS_VERIFY_CHECKLOCKTIMEVERIFY;
+        for branch_id in [0, 0x5ba8_1b19, 0x2bb4_0e60, 0xc2d6_d0b4] {
+            assert_eq!(
+                VerificationFlags::consensus_for(branch_id).map(u32::from),
+                Some(expected)
+            );
+        }
+        assert_eq!(VerificationFlags::consensus_for(0x2bb4_0e61), None);
+
+        let nu5 = MainNetwork.activation_height(NetworkUpgrade::Nu5).unwrap();
+        assert_eq!(
+            VerificationFlags::consensus_at(&MainNetwork, nu5).bits(),
+            expected
+        );
+    }
+
+    #[test]
+    fn standard_flags() {
+        let standard = VerificationFlags::standard();
+        assert_eq!(standard.bits(), 0x3db);
+        assert!(standard.contains(VerificationFlags::mandatory()));
+        assert!(standard.contains(VerificationFlags::consensus_for(0xc2d6_d0b4).unwrap()));
+        assert!(!standard.contains(VerificationFlags::SIGPUSHONLY));
+    }
+
+    #[test]
+    fn display_flags() {
+        assert_eq!(VerificationFlags::NONE.to_string(), "NONE");
+        assert_eq!(
+            (VerificationFlags::P2SH | VerificationFlags::CLEANSTACK).to_string(),
+            "P2SH|CLEANSTACK"
+        );
+        assert_eq!(
+            (VerificationFlags::LOW_S | VerificationFlags(1 << 20)).to_string(),
+            "LOW_S|0x100000"
+        );
+        assert_eq!(VerificationFlags::standard().iter().count(), 8);
+
+        for flags in [
+            VerificationFlags::NONE,
+            VerificationFlags::standard(),
+            VerificationFlags::LOW_S | VerificationFlags(1 << 20),
+        ] {
+            assert_eq!(flags.to_string().parse(), Ok(flags));
+        }
+        assert_eq!("0x3db".parse(), Ok(VerificationFlags::standard()));
+        assert_eq!(
+            "1 | CLEANSTACK".parse(),
+            Ok(VerificationFlags::P2SH | VerificationFlags::CLEANSTACK)
+        );
+        assert_eq!(
+            "P2SH|DERSIG".parse::<VerificationFlags>(),
+            Err(ParseFlagsError("DERSIG".into()))
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_as_names() {
+        let flags = VerificationFlags::P2SH | VerificationFlags::CHECKLOCKTIMEVERIFY;
+        let json = serde_json::to_string(&flags).unwrap();
+        assert_eq!(json, "\"P2SH|CHECKLOCKTIMEVERIFY\"");
+        assert_eq!(
+            serde_json::from_str::<VerificationFlags>(&json).unwrap(),
+            flags
+        );
+        assert_eq!(
+            serde_json::from_str::<VerificationFlags>("\"0x201\"").unwrap(),
+            flags
+        );
+        assert!(serde_json::from_str::<VerificationFlags>("\"DERSIG\"").is_err());
+    }
+}
@@ -0,0 +1,335 @@
+//! Decoding of raw fuzzer inputs into verification calls, enabled by the
+//! `test-dependencies` or `arbitrary` features.
+//!
+//! The same input format is used by every fuzzing harness, so corpora can be
+//! shared between them. Structure-aware fuzzers can generate a [`FuzzSpend`]
+//! instead, with the `arbitrary` feature.
+
+use zcash_encoding::CompactSize;
+
+use crate::{
+    flags::{VerificationFlags, KNOWN_FLAGS},
+    precomputed::PrecomputedTx,
+    script::Script,
+    verify::{verify_script, Error},
+};
+
+/// The consensus branch ID used for fuzzed verifications (NU5).
+const FUZZ_BRANCH_ID: u32 = 0xc2d6_d0b4;
+
+/// A verification call decoded from fuzzer input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FuzzInput<'a> {
+    pub script_pub_key: &'a [u8],
+    pub flags: u32,
+    pub amount: i64,
+    pub n_in: u32,
+    pub tx_to: &'a [u8],
+}
+
+impl<'a> FuzzInput<'a> {
+    /// Decodes fuzzer input laid out as:
+    ///
+    /// - scriptPubKey length (2 bytes, little-endian)
+    /// - scriptPubKey
+    /// - flags (4 bytes, little-endian)
+    /// - amount (8 bytes, little-endian)
+    /// - input index (1 byte)
+    /// - the rest is the serialized transaction
+    ///
+    /// Returns `None` if `data` is too short.
+    pub fn decode(data: &'a [u8]) -> Option<Self> {
+        let (len, data) = split::<2>(data)?;
+        let len = usize::from(u16::from_le_bytes(len));
+        let script_pub_key = data.get(..len)?;
+        let data = &data[len..];
+        let (flags, data) = split::<4>(data)?;
+        let (amount, data) = split::<8>(data)?;
+        let (&n_in, tx_to) = data.split_first()?;
+
+        Some(FuzzInput {
+            script_pub_key,
+            flags: u32::from_le_bytes(flags),
+            amount: i64::from_le_bytes(amount),
+            n_in: n_in.into(),
+            tx_to,
+        })
+    }
+
+    /// Encodes this input in the format read by [`FuzzInput::decode`].
+    pub fn encode(&self) -> Vec<u8> {
+        let mut data = (self.script_pub_key.len() as u16).to_le_bytes().to_vec();
+        data.extend_from_slice(self.script_pub_key);
+        data.extend(self.flags.to_le_bytes());
+        data.extend(self.amount.to_le_bytes());
+        data.push(self.n_in as u8);
+        data.extend_from_slice(self.tx_to);
+        data
+    }
+
+    /// Runs the verification.
+    pub fn verify(&self) -> Result<(), Error> {
+        verify_script(
+            self.script_pub_key,
+            self.amount,
+            self.tx_to,
+            self.n_in,
+            self.flags,
+            FUZZ_BRANCH_ID,
+        )
+    }
+}
+
+/// Decodes and verifies a single fuzzer input, ignoring the result.
+///
+/// Harnesses only need to call this; crashes and sanitizer reports are the
+/// findings.
+pub fn fuzz_one(data: &[u8]) {
+    if let Some(input) = FuzzInput::decode(data) {
+        let _ = input.verify();
+    }
+}
+
+/// Verifies a fuzzer input under every combination of the known flags that
+/// zcashd accepts, ignoring the input's own flags, and panics if an invariant
+/// is broken:
+///
+/// - adding a flag never makes a failing verification pass, since every flag
+///   only adds restrictions, and
+/// - verifying through a [`PrecomputedTx`] agrees with [`verify_script`].
+///
+/// This makes hundreds of verifications per input, so it is meant to be run
+/// over a fixed corpus collected by the [`fuzz_one`] harnesses.
+pub fn fuzz_flag_matrix(data: &[u8]) {
+    let Some(input) = FuzzInput::decode(data) else {
+        return;
+    };
+    let precomputed = PrecomputedTx::new(input.tx_to).ok();
+    let combinations = 1usize << KNOWN_FLAGS.len();
+    let flags_of = |combination: usize| {
+        KNOWN_FLAGS
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| combination & (1 << i) != 0)
+            .fold(VerificationFlags::NONE, |acc, (_, &flag)| acc | flag)
+    };
+    let accepted = |flags: VerificationFlags| {
+        !flags.contains(VerificationFlags::CLEANSTACK) || flags.contains(VerificationFlags::P2SH)
+    };
+
+    let mut passes = vec![None; combinations];
+    for (combination, passed) in passes.iter_mut().enumerate() {
+        let flags = flags_of(combination);
+        if !accepted(flags) {
+            continue;
+        }
+        let input = FuzzInput {
+            flags: flags.bits(),
+            ..input.clone()
+        };
+        let result = input.verify();
+        if let Some(precomputed) = &precomputed {
+            let precomputed_result = precomputed.verify(
+                input.n_in,
+                input.script_pub_key,
+                input.amount,
+                input.flags,
+                FUZZ_BRANCH_ID,
+            );
+            assert_eq!(
+                result.is_ok(),
+                precomputed_result.is_ok(),
+                "backends disagree under {}: {:?} and {:?}",
+                flags,
+                result,
+                precomputed_result,
+            );
+        }
+        *passed = Some(result.is_ok());
+    }
+
+    for (combination, passed) in passes.iter().enumerate() {
+        for bit in 0..KNOWN_FLAGS.len() {
+            let with_flag = combination | (1 << bit);
+            if let (Some(false), Some(true)) = (passed, passes[with_flag]) {
+                panic!(
+                    "adding {} to {} made verification pass",
+                    KNOWN_FLAGS[bit],
+                    flags_of(combination),
+                );
+            }
+        }
+    }
+}
+
+/// A transparent input of a [`FuzzTx`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(::arbitrary::Arbitrary))]
+pub struct FuzzTxIn {
+    /// The serialized outpoint: the previous transaction ID and output index.
+    pub prevout: [u8; 36],
+    pub script_sig: Script,
+    pub sequence: u32,
+}
+
+/// A transparent-only Sapling (v4) transaction, the view of a transaction
+/// that script verification depends on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(::arbitrary::Arbitrary))]
+pub struct FuzzTx {
+    pub inputs: Vec<FuzzTxIn>,
+    /// The outputs' amounts and scriptPubKeys.
+    pub outputs: Vec<(i64, Script)>,
+    pub lock_time: u32,
+    pub expiry_height: u32,
+}
+
+impl FuzzTx {
+    /// Serializes the transaction, with no shielded components.
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut tx = vec![];
+        // The overwintered flag with version 4, then the Sapling version group
+        // ID.
+        tx.extend(0x8000_0004u32.to_le_bytes());
+        tx.extend(0x892f_2085u32.to_le_bytes());
+        write_compact_size(&mut tx, self.inputs.len());
+        for input in &self.inputs {
+            tx.extend_from_slice(&input.prevout);
+            write_script(&mut tx, &input.script_sig);
+            tx.extend(input.sequence.to_le_bytes());
+        }
+        write_compact_size(&mut tx, self.outputs.len());
+        for (amount, script_pub_key) in &self.outputs {
+            tx.extend(amount.to_le_bytes());
+            write_script(&mut tx, script_pub_key);
+        }
+        tx.extend(self.lock_time.to_le_bytes());
+        tx.extend(self.expiry_height.to_le_bytes());
+        // The Sapling value balance, then no spends, outputs or JoinSplits.
+        tx.extend(0i64.to_le_bytes());
+        tx.extend([0, 0, 0]);
+        tx
+    }
+}
+
+/// A structured verification call, for structure-aware fuzzers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(::arbitrary::Arbitrary))]
+pub struct FuzzSpend {
+    pub script_pub_key: Script,
+    pub flags: VerificationFlags,
+    pub amount: i64,
+    pub n_in: u8,
+    pub tx: FuzzTx,
+}
+
+impl FuzzSpend {
+    /// Runs the verification.
+    pub fn verify(&self) -> Result<(), Error> {
+        self.to_input(&self.tx.serialize()).verify()
+    }
+
+    /// Returns the equivalent raw input, with the serialized transaction
+    /// `tx_to`, so that findings can be added to the shared corpus with
+    /// [`FuzzInput::encode`].
+    pub fn to_input<'a>(&'a self, tx_to: &'a [u8]) -> FuzzInput<'a> {
+        FuzzInput {
+            script_pub_key: self.script_pub_key.as_bytes(),
+            flags: self.flags.bits(),
+            amount: self.amount,
+            n_in: self.n_in.into(),
+            tx_to,
+        }
+    }
+}
+
+fn write_compact_size(out: &mut Vec<u8>, size: usize) {
+    CompactSize::write(out, size).expect("writes to a Vec succeed");
+}
+
+fn write_script(out: &mut Vec<u8>, script: &Script) {
+    write_compact_size(out, script.len());
+    out.extend_from_slice(script.as_bytes());
+}
+
+fn split<const N: usize>(data: &[u8]) -> Option<([u8; N], &[u8])> {
+    let head = data.get(..N)?.try_into().ok()?;
+    Some((head, &data[N..]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let input = FuzzInput {
+            script_pub_key: &[0x51],
+            flags: 1,
+            amount: 5,
+            n_in: 2,
+            tx_to: &[1, 2, 3],
+        };
+        assert_eq!(FuzzInput::decode(&input.encode()), Some(input));
+        assert_eq!(FuzzInput::decode(&[1, 0, 0x51]), None);
+        fuzz_one(&[]);
+    }
+
+    #[test]
+    fn flag_matrix() {
+        // A non-minimal push of 1, left on the stack under the scriptPubKey's
+        // OP_1, so the spend fails with MINIMALDATA or CLEANSTACK.
+        let spend = FuzzSpend {
+            script_pub_key: Script(vec![0x51]),
+            flags: VerificationFlags::NONE,
+            amount: 1,
+            n_in: 0,
+            tx: FuzzTx {
+                inputs: vec![FuzzTxIn {
+                    prevout: [7; 36],
+                    script_sig: Script(vec![0x01, 0x01]),
+                    sequence: u32::MAX,
+                }],
+                outputs: vec![],
+                lock_time: 0,
+                expiry_height: 0,
+            },
+        };
+        let tx = spend.tx.serialize();
+        fuzz_flag_matrix(&spend.to_input(&tx).encode());
+    }
+
+    #[test]
+    fn serializes_transactions() {
+        let spend = FuzzSpend {
+            script_pub_key: Script(vec![0x51]),
+            flags: VerificationFlags::P2SH,
+            amount: 1,
+            n_in: 0,
+            tx: FuzzTx {
+                inputs: vec![FuzzTxIn {
+                    prevout: [7; 36],
+                    script_sig: Script::default(),
+                    sequence: u32::MAX,
+                }],
+                outputs: vec![(1, Script(vec![0x51]))],
+                lock_time: 0,
+                expiry_height: 0,
+            },
+        };
+        assert_eq!(spend.verify(), Ok(()));
+        assert_eq!(
+            FuzzSpend {
+                n_in: 1,
+                ..spend.clone()
+            }
+            .verify(),
+            Err(Error::TxIndex)
+        );
+        let tx = spend.tx.serialize();
+        assert_eq!(
+            FuzzInput::decode(&spend.to_input(&tx).encode()),
+            Some(spend.to_input(&tx))
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! Seeded generation of random scripts for fuzz corpora and soak tests,
+//! enabled by the `test-dependencies` feature.
+//!
+//! Generated scripts are biased toward structures that exercise interpreter
+//! edge cases: nested conditionals, pushes at size boundaries, multisig with
+//! boundary arities, and lock times around the height/time threshold.
+
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    rngs::StdRng,
+    Rng, SeedableRng,
+};
+
+use crate::{
+    lock_time::LOCKTIME_THRESHOLD,
+    opcode::Opcode,
+    script::{Script, MAX_PUBKEYS_PER_MULTISIG, MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE},
+};
+
+/// Push sizes around the boundaries of each push opcode and of the element
+/// size limit.
+const BOUNDARY_PUSH_SIZES: [usize; 10] = [
+    0,
+    1,
+    4,
+    5,
+    75,
+    76,
+    255,
+    256,
+    MAX_SCRIPT_ELEMENT_SIZE,
+    MAX_SCRIPT_ELEMENT_SIZE + 1,
+];
+
+/// The kinds of fragment a generated script is built from.
+#[derive(Clone, Copy, Debug)]
+enum Fragment {
+    Opcode,
+    SmallNum,
+    BoundaryPush,
+    Conditional,
+    MultiSig,
+    LockTime,
+}
+
+const FRAGMENTS: [(Fragment, u32); 6] = [
+    (Fragment::Opcode, 40),
+    (Fragment::SmallNum, 15),
+    (Fragment::BoundaryPush, 15),
+    (Fragment::Conditional, 15),
+    (Fragment::MultiSig, 10),
+    (Fragment::LockTime, 5),
+];
+
+/// A reproducible generator of random scripts.
+///
+/// The same seed always produces the same sequence of scripts.
+#[derive(Clone, Debug)]
+pub struct ScriptGenerator {
+    rng: StdRng,
+    fragments: WeightedIndex<u32>,
+    max_fragments: usize,
+    max_depth: usize,
+}
+
+impl ScriptGenerator {
+    /// Creates a generator from `seed`.
+    pub fn new(seed: u64) -> Self {
+        ScriptGenerator {
+            rng: StdRng::seed_from_u64(seed),
+            fragments: WeightedIndex::new(FRAGMENTS.iter().map(|(_, weight)| weight))
+                .expect("weights are valid"),
+            max_fragments: 32,
+            max_depth: 8,
+        }
+    }
+
+    /// Sets the maximum number of top-level fragments per script.
+    pub fn max_fragments(mut self, max_fragments: usize) -> Self {
+        self.max_fragments = max_fragments;
+        self
+    }
+
+    /// Sets the maximum nesting depth of conditionals.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Generates the next script.
+    ///
+    /// Scripts are usually, but not always, within [`MAX_SCRIPT_SIZE`].
+    pub fn generate(&mut self) -> Script {
+        let mut script = Script::default();
+        let fragments = self.rng.gen_range(1..=self.max_fragments.max(1));
+        for _ in 0..fragments {
+            self.fragment(&mut script, 0);
+            if script.len() > MAX_SCRIPT_SIZE {
+                break;
+            }
+        }
+        script
+    }
+
+    fn fragment(&mut self, script: &mut Script, depth: usize) {
+        match FRAGMENTS[self.fragments.sample(&mut self.rng)].0 {
+            Fragment::Opcode => {
+                script.push_opcode(Opcode(self.rng.gen_range(Opcode::OP_1NEGATE.0..=0xff)));
+            }
+            Fragment::SmallNum => {
+                script.push_num(self.rng.gen_range(-1..=16));
+            }
+            Fragment::BoundaryPush => {
+                let size = BOUNDARY_PUSH_SIZES[self.rng.gen_range(0..BOUNDARY_PUSH_SIZES.len())];
+                let byte = self.rng.gen();
+                script.push_slice(&vec![byte; size]);
+            }
+            Fragment::Conditional => {
+                let opcode = if self.rng.gen() {
+                    Opcode::OP_IF
+                } else {
+                    Opcode::OP_NOTIF
+                };
+                script.push_opcode(opcode);
+                if depth < self.max_depth {
+                    for _ in 0..self.rng.gen_range(0..=2) {
+                        self.fragment(script, depth + 1);
+                    }
+                }
+                if self.rng.gen_bool(0.5) {
+                    script.push_opcode(Opcode::OP_ELSE);
+                    if depth < self.max_depth {
+                        self.fragment(script, depth + 1);
+                    }
+                }
+                // Occasionally leave the conditional unbalanced.
+                if self.rng.gen_bool(0.95) {
+                    script.push_opcode(Opcode::OP_ENDIF);
+                }
+            }
+            Fragment::MultiSig => {
+                let max_keys = MAX_PUBKEYS_PER_MULTISIG as i64;
+                let keys = self.rng.gen_range(0..=max_keys + 1);
+                let required = self.rng.gen_range(0..=keys + 1);
+                script.push_num(required);
+                for _ in 0..keys {
+                    let mut pubkey = [0; 33];
+                    self.rng.fill(&mut pubkey[..]);
+                    pubkey[0] = 2 + (pubkey[0] & 1);
+                    script.push_slice(&pubkey);
+                }
+                script.push_num(keys);
+                let opcode = if self.rng.gen() {
+                    Opcode::OP_CHECKMULTISIG
+                } else {
+                    Opcode::OP_CHECKMULTISIGVERIFY
+                };
+                script.push_opcode(opcode);
+            }
+            Fragment::LockTime => {
+                let threshold = i64::from(LOCKTIME_THRESHOLD);
+                let lock_time = match self.rng.gen_range(0..4) {
+                    0 => threshold - 1,
+                    1 => threshold,
+                    2 => -1,
+                    _ => self.rng.gen_range(0..=i64::from(u32::MAX) + 1),
+                };
+                script
+                    .push_num(lock_time)
+                    .push_opcode(Opcode::OP_CHECKLOCKTIMEVERIFY)
+                    .push_opcode(Opcode::OP_DROP);
+            }
+        }
+    }
+}
+
+impl Iterator for ScriptGenerator {
+    type Item = Script;
+
+    fn next(&mut self) -> Option<Script> {
+        Some(self.generate())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_reproducible() {
+        let first: Vec<_> = ScriptGenerator::new(7).take(20).collect();
+        let second: Vec<_> = ScriptGenerator::new(7).take(20).collect();
+        assert_eq!(first, second);
+        assert_ne!(first, ScriptGenerator::new(8).take(20).collect::<Vec<_>>());
+        assert!(first.iter().all(|script| !script.is_empty()));
+    }
+}
@@ -0,0 +1,241 @@
+//! Golden verification vectors, enabled by the `test-dependencies` feature.
+//!
+//! The vectors in `tests/data/golden_vectors.tsv` record the result of
+//! [`verify::verify_script`] for a curated set of inputs. They are checked by this
+//! module's tests, so any change in behavior (of the C++ interpreter, the FFI
+//! layer, or the safe wrappers) shows up as a test failure.
+//!
+//! After an intentional behavior change, regenerate them with:
+//!
+//! ```console
+//! cargo run --example regenerate_golden_vectors --features test-dependencies \
+//!     > tests/data/golden_vectors.tsv
+//! ```
+
+use std::fmt;
+
+use crate::{
+    generator::ScriptGenerator,
+    verify::{verify_script, Error},
+    zcash_script_SCRIPT_FLAGS_VERIFY_CHECKLOCKTIMEVERIFY, zcash_script_SCRIPT_FLAGS_VERIFY_P2SH,
+};
+
+/// A mainnet Blossom transaction spending a P2PKH output.
+const TX_HEX: &str = "0400008085202f8901fcaf44919d4a17f6181a02a7ebe0420be6f7dad1ef86755b81d5a9567456653c010000006a473044022035224ed7276e61affd53315eca059c92876bc2df61d84277cafd7af61d4dbf4002203ed72ea497a9f6b38eb29df08e830d99e32377edb8a574b8a289024f0241d7c40121031f54b095eae066d96b2557c1f99e40e967978a5fd117465dbec0986ca74201a6feffffff020050d6dc0100000017a9141b8a9bda4b62cd0d0582b55455d0778c86f8628f870d03c812030000001976a914e4ff5512ffafe9287992a1cd177ca6e408e0300388ac62070d0095070d000000000000000000000000";
+
+/// The P2PKH scriptPubKey spent by [`TX_HEX`].
+const SCRIPT_PUBKEY_HEX: &str = "76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac";
+
+/// The amount of the output spent by [`TX_HEX`].
+const AMOUNT: i64 = 212 * 100_000_000;
+
+const BLOSSOM_BRANCH_ID: u32 = 0x2bb4_0e60;
+const NU5_BRANCH_ID: u32 = 0xc2d6_d0b4;
+
+/// The seed of the generated scripts included in the vectors.
+const GENERATOR_SEED: u64 = 606;
+
+/// The number of generated scripts included in the vectors.
+const GENERATED_VECTORS: usize = 32;
+
+/// A verification input and its expected result.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GoldenVector {
+    pub script_pub_key: Vec<u8>,
+    pub amount: i64,
+    pub tx_to: Vec<u8>,
+    pub n_in: u32,
+    pub flags: u32,
+    pub consensus_branch_id: u32,
+    pub expected: Result<(), Error>,
+}
+
+/// An error returned when a line of the vector file can't be parsed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseVectorError(String);
+
+impl fmt::Display for ParseVectorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid golden vector: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseVectorError {}
+
+impl GoldenVector {
+    /// Creates a vector for the given input, recording the current result of
+    /// [`verify_script`] as the expected one.
+    pub fn record(
+        script_pub_key: Vec<u8>,
+        amount: i64,
+        tx_to: Vec<u8>,
+        n_in: u32,
+        flags: u32,
+        consensus_branch_id: u32,
+    ) -> Self {
+        let expected = verify_script(
+            &script_pub_key,
+            amount,
+            &tx_to,
+            n_in,
+            flags,
+            consensus_branch_id,
+        );
+        GoldenVector {
+            script_pub_key,
+            amount,
+            tx_to,
+            n_in,
+            flags,
+            consensus_branch_id,
+            expected,
+        }
+    }
+
+    /// Runs the verification, returning its actual result.
+    pub fn verify(&self) -> Result<(), Error> {
+        verify_script(
+            &self.script_pub_key,
+            self.amount,
+            &self.tx_to,
+            self.n_in,
+            self.flags,
+            self.consensus_branch_id,
+        )
+    }
+
+    /// Parses a vector from a line of tab-separated fields: scriptPubKey
+    /// (hex), amount, transaction (hex), input index, flags, consensus branch
+    /// ID (hex), and either `ok` or the expected error code.
+    pub fn from_line(line: &str) -> Result<Self, ParseVectorError> {
+        let err = || ParseVectorError(line.to_owned());
+        let fields: Vec<_> = line.split('\t').collect();
+        let [script_pub_key, amount, tx_to, n_in, flags, branch_id, expected] = fields[..] else {
+            return Err(err());
+        };
+
+        Ok(GoldenVector {
+            script_pub_key: hex::decode(script_pub_key).map_err(|_| err())?,
+            amount: amount.parse().map_err(|_| err())?,
+            tx_to: hex::decode(tx_to).map_err(|_| err())?,
+            n_in: n_in.parse().map_err(|_| err())?,
+            flags: flags.parse().map_err(|_| err())?,
+            consensus_branch_id: u32::from_str_radix(branch_id, 16).map_err(|_| err())?,
+            expected: match expected {
+                "ok" => Ok(()),
+                code => Err(Error::from_code(code.parse().map_err(|_| err())?)),
+            },
+        })
+    }
+}
+
+impl fmt::Display for GoldenVector {
+    /// Formats the vector in the format read by [`GoldenVector::from_line`].
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}\t{}\t{}\t{}\t{}\t{:08x}\t",
+            hex::encode(&self.script_pub_key),
+            self.amount,
+            hex::encode(&self.tx_to),
+            self.n_in,
+            self.flags,
+            self.consensus_branch_id,
+        )?;
+        match self.expected {
+            Ok(()) => write!(f, "ok"),
+            Err(error) => write!(f, "{}", error.code()),
+        }
+    }
+}
+
+/// Parses a vector file, skipping blank lines and `#` comments.
+pub fn parse_vectors(data: &str) -> Result<Vec<GoldenVector>, ParseVectorError> {
+    data.lines()
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(GoldenVector::from_line)
+        .collect()
+}
+
+/// Returns the curated vectors, with expected results recorded from the
+/// current implementation.
+///
+/// This covers a valid spend under different flags and branch IDs, the
+/// errors for a malformed transaction or input index, and scripts from
+/// [`ScriptGenerator`] spent by the same transaction.
+pub fn regenerate() -> Vec<GoldenVector> {
+    let tx = hex::decode(TX_HEX).expect("valid hex");
+    let script_pub_key = hex::decode(SCRIPT_PUBKEY_HEX).expect("valid hex");
+    let p2sh = zcash_script_SCRIPT_FLAGS_VERIFY_P2SH;
+    let cltv = zcash_script_SCRIPT_FLAGS_VERIFY_CHECKLOCKTIMEVERIFY;
+
+    let mut vectors = vec![];
+    for flags in [0, p2sh, p2sh | cltv] {
+        for branch_id in [BLOSSOM_BRANCH_ID, NU5_BRANCH_ID] {
+            vectors.push(GoldenVector::record(
+                script_pub_key.clone(),
+                AMOUNT,
+                tx.clone(),
+                0,
+                flags,
+                branch_id,
+            ));
+        }
+    }
+    let record = |script_pub_key: &[u8], amount, tx: &[u8], n_in| {
+        GoldenVector::record(
+            script_pub_key.to_vec(),
+            amount,
+            tx.to_vec(),
+            n_in,
+            p2sh,
+            BLOSSOM_BRANCH_ID,
+        )
+    };
+    vectors.extend([
+        // Wrong amount, which changes the signature hash.
+        record(&script_pub_key, AMOUNT + 1, &tx, 0),
+        // Input index out of range.
+        record(&script_pub_key, AMOUNT, &tx, 1),
+        // Truncated transaction.
+        record(&script_pub_key, AMOUNT, &tx[..tx.len() - 1], 0),
+        // Trailing data after the transaction.
+        record(&script_pub_key, AMOUNT, &[&tx[..], &[0]].concat(), 0),
+        // Empty scriptPubKey.
+        record(&[], AMOUNT, &tx, 0),
+    ]);
+    vectors.extend(
+        ScriptGenerator::new(GENERATOR_SEED)
+            .max_fragments(8)
+            .take(GENERATED_VECTORS)
+            .map(|script| record(script.as_bytes(), AMOUNT, &tx, 0)),
+    );
+    vectors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VECTORS: &str = include_str!("../tests/data/golden_vectors.tsv");
+
+    #[test]
+    fn golden_vectors_match() {
+        let vectors = parse_vectors(VECTORS).unwrap();
+        assert!(!vectors.is_empty());
+        for vector in vectors {
+            assert_eq!(vector.verify(), vector.expected, "{}", vector);
+        }
+    }
+
+    #[test]
+    fn golden_vectors_are_current() {
+        let regenerated: Vec<_> = regenerate().iter().map(ToString::to_string).collect();
+        let vectors: Vec<_> = parse_vectors(VECTORS)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(regenerated, vectors);
+    }
+}
@@ -0,0 +1,108 @@
+//! Tracking of live precomputed transaction handles, enabled by the
+//! `debug-handles` feature.
+//!
+//! Every handle created by [`PrecomputedTx`](crate::precomputed::PrecomputedTx)
+//! is registered with a backtrace of its creation, and unregistered when it
+//! is freed. Freeing an unregistered handle panics, and handles still live at
+//! process exit are reported before aborting.
+//!
+//! Code that calls `zcash_script_new_precomputed_tx` and
+//! `zcash_script_free_precomputed_tx` directly can use [`register`] and
+//! [`unregister`] to be checked in the same way.
+
+use std::{
+    backtrace::Backtrace,
+    collections::HashMap,
+    ffi::c_void,
+    sync::{Mutex, MutexGuard, Once, PoisonError},
+};
+
+static HANDLES: Mutex<Option<HashMap<usize, Backtrace>>> = Mutex::new(None);
+
+static INSTALL_EXIT_CHECK: Once = Once::new();
+
+/// Records that `handle` was just created.
+///
+/// Panics if `handle` is already live, which means it was freed without
+/// being unregistered.
+pub fn register(handle: *const c_void) {
+    INSTALL_EXIT_CHECK.call_once(|| unsafe {
+        libc::atexit(check_at_exit);
+    });
+    let backtrace = Backtrace::force_capture();
+    let previous = handles()
+        .get_or_insert_with(HashMap::new)
+        .insert(handle as usize, backtrace);
+    if let Some(previous) = previous {
+        panic!(
+            "precomputed transaction {:p} registered twice, first created at:\n{}",
+            handle, previous
+        );
+    }
+}
+
+/// Records that `handle` is about to be freed.
+///
+/// Panics if `handle` isn't live, which means it is being double-freed (or
+/// was never registered).
+pub fn unregister(handle: *const c_void) {
+    let removed = handles()
+        .as_mut()
+        .and_then(|handles| handles.remove(&(handle as usize)));
+    assert!(
+        removed.is_some(),
+        "precomputed transaction {:p} freed but not live",
+        handle
+    );
+}
+
+/// Returns the number of live handles.
+pub fn live_count() -> usize {
+    handles().as_ref().map_or(0, HashMap::len)
+}
+
+/// Panics if any handles are live, listing their creation backtraces.
+///
+/// This is also checked automatically at process exit.
+pub fn assert_no_leaks() {
+    if let Some(report) = leak_report() {
+        panic!("{}", report);
+    }
+}
+
+fn leak_report() -> Option<String> {
+    let handles = handles();
+    let handles = handles.as_ref().filter(|handles| !handles.is_empty())?;
+    let mut report = format!("{} precomputed transactions leaked", handles.len());
+    for (handle, backtrace) in handles {
+        report.push_str(&format!("\n\n{:#x} created at:\n{}", handle, backtrace));
+    }
+    Some(report)
+}
+
+/// Locks the registry, ignoring poisoning from the panics above.
+fn handles() -> MutexGuard<'static, Option<HashMap<usize, Backtrace>>> {
+    HANDLES.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+extern "C" fn check_at_exit() {
+    // Panicking can't unwind out of an `atexit` handler, so abort instead.
+    if let Some(report) = leak_report() {
+        eprintln!("{}", report);
+        std::process::abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "freed but not live")]
+    fn detects_double_free() {
+        let handle = 0x10 as *const c_void;
+        register(handle);
+        unregister(handle);
+        unregister(handle);
+    }
+}
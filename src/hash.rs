@@ -0,0 +1,289 @@
+//! The hash functions compiled for zcashd's script verification and key
+//! derivation.
+//!
+//! These are the same implementations zcashd uses, so downstream code, such
+//! as BIP 32 key derivation, can use them without depending on other crypto
+//! crates.
+//!
+//! The streaming hashers [`Sha256`], [`Ripemd160`], [`Hash160`] and
+//! [`Hash256`] implement the RustCrypto [`Digest`] trait, so they can be used
+//! with generic hashing APIs while hashing exactly as consensus does.
+
+use std::{ffi::c_void, fmt};
+
+pub use digest::Digest;
+use digest::{
+    consts::{U20, U32},
+    Output,
+};
+
+use crate::ffi::{
+    zcash_script_hash_function, zcash_script_hash_function_t_zcash_script_HASH_HASH160,
+    zcash_script_hash_function_t_zcash_script_HASH_HASH256,
+    zcash_script_hash_function_t_zcash_script_HASH_RIPEMD160,
+    zcash_script_hash_function_t_zcash_script_HASH_SHA256, zcash_script_hasher_clone,
+    zcash_script_hasher_finalize, zcash_script_hasher_free, zcash_script_hasher_new,
+    zcash_script_hasher_reset, zcash_script_hasher_write, zcash_script_hmac_sha512,
+    zcash_script_sha512,
+};
+
+/// Converts the length of a buffer passed to the C API, which takes lengths
+/// as `unsigned int`.
+fn c_len(bytes: &[u8]) -> u32 {
+    u32::try_from(bytes.len()).expect("inputs are shorter than 4 GiB")
+}
+
+/// Returns the SHA-512 digest of `data`.
+///
+/// # Panics
+///
+/// If `data` is 4 GiB or longer.
+pub fn sha512(data: &[u8]) -> [u8; 64] {
+    let mut out = [0; 64];
+    // SAFETY: the length is that of the buffer, and the output is 64 bytes.
+    unsafe { zcash_script_sha512(data.as_ptr(), c_len(data), out.as_mut_ptr()) };
+    out
+}
+
+/// Returns `HMAC-SHA512(key, data)`.
+///
+/// # Panics
+///
+/// If `key` or `data` is 4 GiB or longer.
+pub fn hmac_sha512(key: &[u8], data: &[u8]) -> [u8; 64] {
+    let mut out = [0; 64];
+    // SAFETY: the lengths are those of the buffers, and the output is 64
+    // bytes.
+    unsafe {
+        zcash_script_hmac_sha512(
+            key.as_ptr(),
+            c_len(key),
+            data.as_ptr(),
+            c_len(data),
+            out.as_mut_ptr(),
+        );
+    }
+    out
+}
+
+/// A C++ hasher returned by `zcash_script_hasher_new`.
+struct Hasher {
+    handle: *mut c_void,
+}
+
+// SAFETY: The hasher is only mutated through `&mut self`, and `clone` only
+// reads it.
+unsafe impl Send for Hasher {}
+unsafe impl Sync for Hasher {}
+
+impl Hasher {
+    fn new(function: zcash_script_hash_function) -> Self {
+        // SAFETY: any function is accepted, and unknown ones return null.
+        let handle = unsafe { zcash_script_hasher_new(function) };
+        assert!(!handle.is_null(), "the hash function is known");
+        Hasher { handle }
+    }
+
+    fn write(&mut self, data: &[u8]) {
+        // The C API takes lengths as `unsigned int`.
+        for chunk in data.chunks(u32::MAX as usize) {
+            // SAFETY: the length is that of the chunk.
+            unsafe { zcash_script_hasher_write(self.handle, chunk.as_ptr(), chunk.len() as u32) };
+        }
+    }
+
+    /// Writes the digest to `out`, which must be the hash function's output
+    /// size, and resets the hasher.
+    fn finalize(&mut self, out: &mut [u8]) {
+        // SAFETY: the caller's output type has the hash function's size.
+        unsafe { zcash_script_hasher_finalize(self.handle, out.as_mut_ptr()) };
+    }
+
+    fn reset(&mut self) {
+        unsafe { zcash_script_hasher_reset(self.handle) };
+    }
+}
+
+impl Clone for Hasher {
+    fn clone(&self) -> Self {
+        Hasher {
+            handle: unsafe { zcash_script_hasher_clone(self.handle) },
+        }
+    }
+}
+
+impl Drop for Hasher {
+    fn drop(&mut self) {
+        unsafe { zcash_script_hasher_free(self.handle) };
+    }
+}
+
+macro_rules! hasher_type {
+    ($(#[$doc:meta])* $name:ident, $function:ident, $size:ty) => {
+        $(#[$doc])*
+        #[derive(Clone)]
+        pub struct $name(Hasher);
+
+        impl Default for $name {
+            fn default() -> Self {
+                $name(Hasher::new($function))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}(..)", stringify!($name))
+            }
+        }
+
+        impl digest::HashMarker for $name {}
+
+        impl digest::OutputSizeUser for $name {
+            type OutputSize = $size;
+        }
+
+        impl digest::Update for $name {
+            fn update(&mut self, data: &[u8]) {
+                self.0.write(data);
+            }
+        }
+
+        impl digest::FixedOutput for $name {
+            fn finalize_into(mut self, out: &mut Output<Self>) {
+                self.0.finalize(out);
+            }
+        }
+
+        impl digest::Reset for $name {
+            fn reset(&mut self) {
+                self.0.reset();
+            }
+        }
+
+        impl digest::FixedOutputReset for $name {
+            fn finalize_into_reset(&mut self, out: &mut Output<Self>) {
+                self.0.finalize(out);
+            }
+        }
+    };
+}
+
+hasher_type!(
+    /// A streaming SHA-256 hasher, zcashd's `CSHA256`.
+    Sha256,
+    zcash_script_hash_function_t_zcash_script_HASH_SHA256,
+    U32
+);
+
+hasher_type!(
+    /// A streaming RIPEMD-160 hasher, zcashd's `CRIPEMD160`.
+    Ripemd160,
+    zcash_script_hash_function_t_zcash_script_HASH_RIPEMD160,
+    U20
+);
+
+hasher_type!(
+    /// A streaming hasher of RIPEMD-160 of SHA-256, zcashd's `CHash160`, which
+    /// hashes public keys and scripts into P2PKH and P2SH addresses.
+    Hash160,
+    zcash_script_hash_function_t_zcash_script_HASH_HASH160,
+    U20
+);
+
+hasher_type!(
+    /// A streaming double SHA-256 hasher, zcashd's `CHash256`, which hashes
+    /// pre-v5 transactions into their IDs.
+    Hash256,
+    zcash_script_hash_function_t_zcash_script_HASH_HASH256,
+    U32
+);
+
+#[cfg(test)]
+mod tests {
+    use ripemd::Ripemd160 as RustRipemd160;
+    use sha2::{Sha256 as RustSha256, Sha512};
+
+    use super::*;
+
+    #[test]
+    fn sha512_vectors() {
+        assert_eq!(
+            hex::encode(sha512(b"abc")),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+        // Lengths around the 128-byte block size.
+        for len in [0, 1, 111, 112, 127, 128, 129, 1000] {
+            let data: Vec<u8> = (0..len).map(|i| i as u8).collect();
+            assert_eq!(sha512(&data)[..], Sha512::digest(&data)[..], "{len}");
+        }
+    }
+
+    /// The first two test cases of RFC 4231.
+    #[test]
+    fn hmac_sha512_vectors() {
+        assert_eq!(
+            hex::encode(hmac_sha512(&[0x0b; 20], b"Hi There")),
+            "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cde\
+             daa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854"
+        );
+        assert_eq!(
+            hex::encode(hmac_sha512(b"Jefe", b"what do ya want for nothing?")),
+            "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea250554\
+             9758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737"
+        );
+    }
+
+    fn hash<D: Digest>(chunks: &[&[u8]]) -> Vec<u8> {
+        let mut hasher = D::new();
+        for chunk in chunks {
+            hasher.update(chunk);
+        }
+        hasher.finalize().to_vec()
+    }
+
+    #[test]
+    fn hashers_match_rustcrypto() {
+        let data: Vec<u8> = (0..1000).map(|i| i as u8).collect();
+        for len in [0, 1, 55, 56, 63, 64, 65, 1000] {
+            let data = &data[..len];
+            let sha256 = hash::<RustSha256>(&[data]);
+            assert_eq!(hash::<Sha256>(&[data]), sha256, "{len}");
+            assert_eq!(
+                hash::<Ripemd160>(&[data]),
+                hash::<RustRipemd160>(&[data]),
+                "{len}"
+            );
+            assert_eq!(
+                hash::<Hash160>(&[data]),
+                hash::<RustRipemd160>(&[&sha256[..]]),
+                "{len}"
+            );
+            assert_eq!(
+                hash::<Hash256>(&[data]),
+                hash::<RustSha256>(&[&sha256[..]]),
+                "{len}"
+            );
+            // Splitting the data doesn't change the digest.
+            let (left, right) = data.split_at(len / 3);
+            assert_eq!(hash::<Hash256>(&[left, right]), hash::<Hash256>(&[data]));
+        }
+    }
+
+    #[test]
+    fn hashers_clone_and_reset() {
+        let mut hasher = Hash160::new_with_prefix(b"zcash");
+        let mut copy = hasher.clone();
+        hasher.update(b"script");
+        copy.update(b"script");
+        let digest = hasher.finalize_reset();
+        assert_eq!(copy.finalize(), digest);
+        assert_eq!(digest[..], hash::<Hash160>(&[&b"zcashscript"[..]]));
+
+        // Finalizing resets the hasher.
+        assert_eq!(hasher.finalize_reset()[..], hash::<Hash160>(&[]));
+        hasher.update(b"zcash");
+        Digest::reset(&mut hasher);
+        assert_eq!(hasher.finalize()[..], hash::<Hash160>(&[]));
+    }
+}
@@ -0,0 +1,270 @@
+//! BIP 32 hierarchical deterministic derivation of transparent keys, along the
+//! BIP 44 paths that Zcash wallets use: `m/44'/133'/account'/change/index` on
+//! mainnet, and coin type 1 on the test networks.
+//!
+//! The HMAC-SHA512 and elliptic curve arithmetic are those compiled for
+//! zcashd's script verification.
+
+use std::fmt;
+
+use zcash_address::Network;
+
+use crate::{
+    hash,
+    keys::{PublicKey, SecretKey},
+};
+
+/// The BIP 44 coin type of Zcash mainnet, registered in SLIP 44.
+pub const MAINNET_COIN_TYPE: u32 = 133;
+
+/// The BIP 44 coin type of the Zcash test networks, shared by all coins.
+pub const TESTNET_COIN_TYPE: u32 = 1;
+
+/// The flag that marks a child number as hardened.
+const HARDENED: u32 = 1 << 31;
+
+/// An error deriving a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// The seed isn't between 16 and 64 bytes long.
+    InvalidSeedLength,
+    /// A child index is not less than 2^31.
+    InvalidIndex,
+    /// The derived key is invalid, which happens with probability less than
+    /// 2^-127. BIP 32 wallets skip to the next index.
+    InvalidKey,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidSeedLength => write!(f, "seed must be 16 to 64 bytes"),
+            Error::InvalidIndex => write!(f, "child index out of range"),
+            Error::InvalidKey => write!(f, "derived key is invalid"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A step of a derivation path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChildNumber {
+    /// A child that can also be derived from the parent's public key.
+    Normal(u32),
+    /// A child that can only be derived from the parent's secret key, written
+    /// `index'`.
+    Hardened(u32),
+}
+
+impl ChildNumber {
+    /// Returns the 32-bit encoding of this child number, with the top bit set
+    /// for hardened children.
+    pub fn to_u32(self) -> Result<u32, Error> {
+        match self {
+            ChildNumber::Normal(index) if index < HARDENED => Ok(index),
+            ChildNumber::Hardened(index) if index < HARDENED => Ok(index | HARDENED),
+            _ => Err(Error::InvalidIndex),
+        }
+    }
+}
+
+/// Whether an address is for receiving funds, or for change.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// Addresses given out to receive payments, with BIP 44 change level 0.
+    External,
+    /// Change addresses, with BIP 44 change level 1.
+    Internal,
+}
+
+/// Returns the BIP 44 coin type for `network`.
+pub fn coin_type(network: Network) -> u32 {
+    match network {
+        Network::Main => MAINNET_COIN_TYPE,
+        Network::Test | Network::Regtest => TESTNET_COIN_TYPE,
+    }
+}
+
+/// Returns the BIP 44 path of the transparent key at `index` in `account`.
+pub fn bip44_path(network: Network, account: u32, scope: Scope, index: u32) -> [ChildNumber; 5] {
+    let change = match scope {
+        Scope::External => 0,
+        Scope::Internal => 1,
+    };
+    [
+        ChildNumber::Hardened(44),
+        ChildNumber::Hardened(coin_type(network)),
+        ChildNumber::Hardened(account),
+        ChildNumber::Normal(change),
+        ChildNumber::Normal(index),
+    ]
+}
+
+/// A secret key with its BIP 32 chain code, from which child keys are derived.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExtendedSecretKey {
+    secret_key: SecretKey,
+    chain_code: [u8; 32],
+}
+
+impl ExtendedSecretKey {
+    /// Returns the master key for `seed`, such as a BIP 39 seed.
+    pub fn from_seed(seed: &[u8]) -> Result<Self, Error> {
+        if !(16..=64).contains(&seed.len()) {
+            return Err(Error::InvalidSeedLength);
+        }
+        let (key, chain_code) = hmac_sha512(b"Bitcoin seed", seed);
+        Ok(ExtendedSecretKey {
+            secret_key: SecretKey::from_bytes(key).ok_or(Error::InvalidKey)?,
+            chain_code,
+        })
+    }
+
+    /// Returns the secret key.
+    pub fn secret_key(&self) -> &SecretKey {
+        &self.secret_key
+    }
+
+    /// Returns the public key.
+    pub fn public_key(&self) -> PublicKey {
+        self.secret_key.public_key()
+    }
+
+    /// Returns the chain code.
+    pub fn chain_code(&self) -> [u8; 32] {
+        self.chain_code
+    }
+
+    /// Derives the child key `child`, as BIP 32's `CKDpriv` specifies.
+    pub fn derive_child(&self, child: ChildNumber) -> Result<Self, Error> {
+        let mut data = Vec::with_capacity(37);
+        match child {
+            ChildNumber::Normal(_) => data.extend_from_slice(&self.public_key().serialize()),
+            ChildNumber::Hardened(_) => {
+                data.push(0);
+                data.extend_from_slice(&self.secret_key.to_bytes());
+            }
+        }
+        data.extend_from_slice(&child.to_u32()?.to_be_bytes());
+
+        let (tweak, chain_code) = hmac_sha512(&self.chain_code, &data);
+        Ok(ExtendedSecretKey {
+            secret_key: self.secret_key.add_tweak(&tweak).ok_or(Error::InvalidKey)?,
+            chain_code,
+        })
+    }
+
+    /// Derives the key at `path`, relative to this key.
+    pub fn derive_path(&self, path: &[ChildNumber]) -> Result<Self, Error> {
+        let mut key = self.clone();
+        for &child in path {
+            key = key.derive_child(child)?;
+        }
+        Ok(key)
+    }
+
+    /// Derives the transparent secret key at `index` in `account`, from this
+    /// master key.
+    ///
+    /// Its address is given by the public key's
+    /// [`destination`](PublicKey::destination).
+    pub fn derive_bip44(
+        &self,
+        network: Network,
+        account: u32,
+        scope: Scope,
+        index: u32,
+    ) -> Result<SecretKey, Error> {
+        let path = bip44_path(network, account, scope, index);
+        Ok(self.derive_path(&path)?.secret_key)
+    }
+}
+
+/// Computes `HMAC-SHA512(key, data)`, split into its left and right halves.
+fn hmac_sha512(key: &[u8], data: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let out = hash::hmac_sha512(key, data);
+    let (left, right) = out.split_at(32);
+    (
+        left.try_into().expect("split at 32"),
+        right.try_into().expect("split at 32"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The first test vector from BIP 32.
+    #[test]
+    fn bip32_vector() {
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedSecretKey::from_seed(&seed).unwrap();
+        assert_eq!(
+            hex::encode(master.secret_key().to_bytes()),
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35"
+        );
+        assert_eq!(
+            hex::encode(master.chain_code()),
+            "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508"
+        );
+        assert_eq!(
+            hex::encode(master.public_key().serialize()),
+            "0339a36013301597daef41fbe593a02cc513d0b55527ec2df1050e2e8ff49c85c2"
+        );
+
+        let path = [
+            ChildNumber::Hardened(0),
+            ChildNumber::Normal(1),
+            ChildNumber::Hardened(2),
+            ChildNumber::Normal(2),
+            ChildNumber::Normal(1_000_000_000),
+        ];
+        let expected = [
+            "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+            "cbce0d719ecf7431d88e6a89fa1483e02e35092af60c042b1df2ff59fa424dca",
+            "0f479245fb19a38a1954c5c7c0ebab2f9bdfd96a17563ef28a6a4b1a2a764ef4",
+            "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8",
+        ];
+        let mut key = master;
+        for (child, expected) in path.into_iter().zip(expected) {
+            key = key.derive_child(child).unwrap();
+            assert_eq!(hex::encode(key.secret_key().to_bytes()), expected);
+        }
+    }
+
+    #[test]
+    fn derives_bip44_keys() {
+        let master = ExtendedSecretKey::from_seed(&[7; 32]).unwrap();
+        let key = master
+            .derive_bip44(Network::Main, 0, Scope::Internal, 5)
+            .unwrap();
+        let path = [
+            ChildNumber::Hardened(44),
+            ChildNumber::Hardened(133),
+            ChildNumber::Hardened(0),
+            ChildNumber::Normal(1),
+            ChildNumber::Normal(5),
+        ];
+        assert_eq!(&key, master.derive_path(&path).unwrap().secret_key());
+        assert!(key
+            .public_key()
+            .destination()
+            .encode(Network::Main)
+            .starts_with("t1"));
+        assert_ne!(
+            master.derive_bip44(Network::Test, 0, Scope::Internal, 5),
+            Ok(key)
+        );
+
+        assert_eq!(
+            master.derive_child(ChildNumber::Hardened(HARDENED)),
+            Err(Error::InvalidIndex)
+        );
+        assert_eq!(
+            ExtendedSecretKey::from_seed(&[0; 15]),
+            Err(Error::InvalidSeedLength)
+        );
+    }
+}
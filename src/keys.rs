@@ -0,0 +1,406 @@
+//! secp256k1 keys for transparent addresses.
+//!
+//! The key operations use the same libsecp256k1 that verifies signatures.
+
+use std::{fmt, str::FromStr};
+
+use zcash_address::Network;
+
+use crate::{
+    encoding::{decode_with, FromHex, HexError},
+    script::Script,
+    secp,
+    sighash::SighashType,
+    standard::Destination,
+};
+
+/// The WIF prefix of mainnet secret keys, zcashd's `SECRET_KEY` base58 prefix.
+const MAINNET_WIF_PREFIX: u8 = 0x80;
+/// The WIF prefix of testnet and regtest secret keys.
+const TESTNET_WIF_PREFIX: u8 = 0xef;
+
+/// A secp256k1 secret key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SecretKey([u8; 32]);
+
+impl SecretKey {
+    /// Returns the secret key with the big-endian encoding `bytes`, or `None`
+    /// if it's zero or not less than the curve order.
+    pub fn from_bytes(bytes: [u8; 32]) -> Option<Self> {
+        secp::seckey_verify(&bytes).then_some(SecretKey(bytes))
+    }
+
+    /// Returns the big-endian encoding of the secret key.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        self.0
+    }
+
+    /// Returns the public key for this secret key.
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(secp::pubkey_create(&self.0).expect("secret keys are valid"))
+    }
+
+    /// Signs the signature hash `sighash`, returning the DER-encoded signature,
+    /// without a hash type.
+    ///
+    /// Signatures are deterministic (RFC 6979), and have the low S values that
+    /// zcashd's `LOW_S` policy requires.
+    pub fn sign(&self, sighash: &[u8; 32]) -> Vec<u8> {
+        secp::ecdsa_sign(&self.0, sighash)
+    }
+
+    /// Returns this key plus `tweak` modulo the curve order, or `None` if the
+    /// tweak is out of range or the result is zero.
+    #[cfg(feature = "hd-wallet")]
+    pub(crate) fn add_tweak(&self, tweak: &[u8; 32]) -> Option<Self> {
+        let mut bytes = self.0;
+        secp::seckey_tweak_add(&mut bytes, tweak).then_some(SecretKey(bytes))
+    }
+}
+
+impl FromHex for SecretKey {
+    type Error = HexError;
+
+    /// Decodes the big-endian encoding of a secret key.
+    fn from_hex<T: AsRef<[u8]>>(hex: T) -> Result<Self, Self::Error> {
+        decode_with(hex, |bytes| SecretKey::from_bytes(bytes.try_into().ok()?))
+    }
+}
+
+impl fmt::Debug for SecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Don't leak the key into logs.
+        f.write_str("SecretKey(..)")
+    }
+}
+
+/// An error decoding a WIF secret key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WifError {
+    /// The string isn't valid Base58Check.
+    Base58,
+    /// The prefix isn't that of a mainnet or testnet secret key.
+    Prefix(u8),
+    /// The payload isn't a 32-byte key, optionally followed by the compressed
+    /// flag.
+    Length,
+    /// The key is zero or not less than the curve order.
+    InvalidKey,
+}
+
+impl fmt::Display for WifError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WifError::Base58 => write!(f, "invalid Base58Check encoding"),
+            WifError::Prefix(prefix) => write!(f, "unknown secret key prefix {:#04x}", prefix),
+            WifError::Length => write!(f, "invalid secret key length"),
+            WifError::InvalidKey => write!(f, "invalid secret key"),
+        }
+    }
+}
+
+impl std::error::Error for WifError {}
+
+/// A secret key in the wallet import format used by zcashd's `dumpprivkey`
+/// and `importprivkey`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WifKey {
+    pub secret_key: SecretKey,
+    /// The network the key is for. Regtest keys use the testnet prefix, so
+    /// they decode as [`Network::Test`].
+    pub network: Network,
+    /// Whether the key's address hashes the compressed encoding of its public
+    /// key. zcashd only creates compressed keys, but keys imported from older
+    /// wallets may be uncompressed.
+    pub compressed: bool,
+}
+
+impl WifKey {
+    /// Decodes a WIF secret key.
+    pub fn decode(wif: &str) -> Result<Self, WifError> {
+        let payload = bs58::decode(wif)
+            .with_check(None)
+            .into_vec()
+            .map_err(|_| WifError::Base58)?;
+        let (&prefix, key) = payload.split_first().ok_or(WifError::Length)?;
+        let network = match prefix {
+            MAINNET_WIF_PREFIX => Network::Main,
+            TESTNET_WIF_PREFIX => Network::Test,
+            _ => return Err(WifError::Prefix(prefix)),
+        };
+        let (key, compressed) = match key {
+            [key @ .., 1] if key.len() == 32 => (key, true),
+            key if key.len() == 32 => (key, false),
+            _ => return Err(WifError::Length),
+        };
+        let secret_key = SecretKey::from_bytes(key.try_into().expect("checked length"))
+            .ok_or(WifError::InvalidKey)?;
+        Ok(WifKey {
+            secret_key,
+            network,
+            compressed,
+        })
+    }
+
+    /// Encodes the key in WIF.
+    pub fn encode(&self) -> String {
+        let prefix = match self.network {
+            Network::Main => MAINNET_WIF_PREFIX,
+            Network::Test | Network::Regtest => TESTNET_WIF_PREFIX,
+        };
+        let mut payload = vec![prefix];
+        payload.extend_from_slice(&self.secret_key.to_bytes());
+        if self.compressed {
+            payload.push(1);
+        }
+        bs58::encode(payload).with_check().into_string()
+    }
+
+    /// Returns the public key, encoded as the compressed flag specifies.
+    pub fn public_key(&self) -> Vec<u8> {
+        let public_key = self.secret_key.public_key();
+        if self.compressed {
+            public_key.serialize().to_vec()
+        } else {
+            public_key.serialize_uncompressed().to_vec()
+        }
+    }
+
+    /// Returns the P2PKH destination of the key.
+    pub fn destination(&self) -> Destination {
+        Destination::for_pubkey(&self.public_key())
+    }
+
+    /// Returns the scriptSig spending a P2PKH output of this key, signing
+    /// `sighash` with `hash_type`.
+    ///
+    /// `sighash` is the input's signature hash, as computed by
+    /// [`signature_hash`](crate::sighash::signature_hash) with the P2PKH
+    /// scriptPubKey as the script code.
+    pub fn p2pkh_script_sig(&self, sighash: &[u8; 32], hash_type: SighashType) -> Script {
+        let signature = hash_type.encode_signature(&self.secret_key.sign(sighash));
+        crate::signing::p2pkh_script_sig(&signature, &self.public_key())
+    }
+}
+
+impl FromStr for WifKey {
+    type Err = WifError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        WifKey::decode(s)
+    }
+}
+
+/// A secp256k1 public key.
+#[derive(Clone, Copy)]
+pub struct PublicKey(secp::RawPublicKey);
+
+impl PublicKey {
+    /// Parses a compressed (33-byte) or uncompressed (65-byte) public key,
+    /// returning `None` if it isn't a valid point.
+    pub fn from_slice(bytes: &[u8]) -> Option<Self> {
+        secp::pubkey_parse(bytes).map(PublicKey)
+    }
+
+    /// Returns the 33-byte compressed encoding of the public key.
+    pub fn serialize(&self) -> [u8; 33] {
+        secp::pubkey_serialize(&self.0, true)
+            .try_into()
+            .expect("compressed public keys are 33 bytes")
+    }
+
+    /// Returns the 65-byte uncompressed encoding of the public key.
+    pub fn serialize_uncompressed(&self) -> [u8; 65] {
+        secp::pubkey_serialize(&self.0, false)
+            .try_into()
+            .expect("uncompressed public keys are 65 bytes")
+    }
+
+    /// Returns the hex of the compressed encoding of the public key.
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.serialize())
+    }
+
+    /// Returns the P2PKH destination for the compressed encoding of the public
+    /// key, which is the one zcashd uses for new keys.
+    pub fn destination(&self) -> Destination {
+        Destination::for_pubkey(&self.serialize())
+    }
+
+    /// Returns true if the DER-encoded `signature`, without a hash type, is a
+    /// valid signature of `sighash` by this key.
+    pub fn verify(&self, sighash: &[u8; 32], signature: &[u8]) -> bool {
+        secp::ecdsa_verify(&self.0, signature, sighash)
+    }
+}
+
+impl PartialEq for PublicKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.serialize() == other.serialize()
+    }
+}
+
+impl Eq for PublicKey {}
+
+impl FromHex for PublicKey {
+    type Error = HexError;
+
+    /// Decodes a compressed or uncompressed public key.
+    fn from_hex<T: AsRef<[u8]>>(hex: T) -> Result<Self, Self::Error> {
+        decode_with(hex, PublicKey::from_slice)
+    }
+}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PublicKey({})", self.to_hex())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::opcode::Opcode;
+
+    #[test]
+    fn derives_public_keys() {
+        let mut one = [0; 32];
+        one[31] = 1;
+        let key = SecretKey::from_bytes(one).unwrap().public_key();
+        assert_eq!(
+            hex::encode(key.serialize()),
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+        );
+        assert_eq!(
+            PublicKey::from_slice(&key.serialize_uncompressed()),
+            Some(key)
+        );
+        assert_eq!(PublicKey::from_slice(&[5; 33]), None);
+        assert_eq!(PublicKey::from_hex(key.to_hex()), Ok(key));
+        assert_eq!(
+            PublicKey::from_hex(hex::encode(key.serialize_uncompressed())),
+            Ok(key)
+        );
+        assert_eq!(PublicKey::from_hex("05"), Err(HexError::Invalid));
+        assert_eq!(
+            PublicKey::from_hex("0g"),
+            Err(HexError::Hex(hex::FromHexError::InvalidHexCharacter {
+                c: 'g',
+                index: 1
+            }))
+        );
+        assert_eq!(
+            SecretKey::from_hex(hex::encode(one)).map(|key| key.public_key()),
+            Ok(key)
+        );
+        assert_eq!(SecretKey::from_hex("00"), Err(HexError::Invalid));
+
+        assert_eq!(SecretKey::from_bytes([0; 32]), None);
+        assert_eq!(SecretKey::from_bytes([0xff; 32]), None);
+    }
+
+    #[test]
+    fn encodes_wif() {
+        let mut one = [0; 32];
+        one[31] = 1;
+        let key = WifKey {
+            secret_key: SecretKey::from_bytes(one).unwrap(),
+            network: Network::Main,
+            compressed: true,
+        };
+        let wif = "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn";
+        assert_eq!(key.encode(), wif);
+        assert_eq!(wif.parse(), Ok(key.clone()));
+
+        let uncompressed = WifKey {
+            compressed: false,
+            ..key.clone()
+        };
+        let wif = "5HpHagT65TZzG1PH3CSu63k8DbpvD8s5ip4nEB3kEsreAnchuDf";
+        assert_eq!(uncompressed.encode(), wif);
+        assert_eq!(WifKey::decode(wif), Ok(uncompressed.clone()));
+        assert_ne!(uncompressed.destination(), key.destination());
+
+        let testnet = WifKey {
+            network: Network::Regtest,
+            ..key
+        };
+        assert_eq!(
+            WifKey::decode(&testnet.encode()).map(|key| key.network),
+            Ok(Network::Test)
+        );
+
+        assert_eq!(
+            WifKey::decode("KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWo"),
+            Err(WifError::Base58)
+        );
+        let zero = bs58::encode([&[0x80][..], &[0; 32]].concat())
+            .with_check()
+            .into_string();
+        assert_eq!(WifKey::decode(&zero), Err(WifError::InvalidKey));
+        let p2pkh = bs58::encode([0x1c, 0xb8, 0]).with_check().into_string();
+        assert_eq!(WifKey::decode(&p2pkh), Err(WifError::Prefix(0x1c)));
+    }
+
+    #[test]
+    fn verifies_signatures() {
+        let key = SecretKey::from_bytes([1; 32]).unwrap();
+        let signature = key.sign(&[2; 32]);
+        assert!(key.public_key().verify(&[2; 32], &signature));
+        assert!(!key.public_key().verify(&[3; 32], &signature));
+        let other = SecretKey::from_bytes([4; 32]).unwrap();
+        assert!(!other.public_key().verify(&[2; 32], &signature));
+        assert!(!key.public_key().verify(&[2; 32], &signature[1..]));
+    }
+
+    /// Signs a P2PKH input with a WIF key, and checks the result with zcashd's
+    /// interpreter.
+    #[test]
+    fn signs_p2pkh_inputs() {
+        let key = WifKey::decode("KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn").unwrap();
+        let Destination::PubKeyHash(hash) = key.destination() else {
+            unreachable!("keys have P2PKH destinations");
+        };
+        let mut script_pub_key = Script::default();
+        script_pub_key
+            .push_opcode(Opcode::OP_DUP)
+            .push_opcode(Opcode::OP_HASH160)
+            .push_slice(&hash)
+            .push_opcode(Opcode::OP_EQUALVERIFY)
+            .push_opcode(Opcode::OP_CHECKSIG);
+
+        // A v1 transaction spending one output, with the given scriptSig.
+        let tx = |script_sig: &[u8]| {
+            let mut tx = vec![1, 0, 0, 0, 1];
+            tx.extend_from_slice(&[0x11; 36]);
+            tx.push(script_sig.len() as u8);
+            tx.extend_from_slice(script_sig);
+            tx.extend_from_slice(&[0xff; 4]);
+            tx.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0x51]);
+            tx.extend_from_slice(&[0; 4]);
+            tx
+        };
+        let sighash = crate::sighash::signature_hash(
+            script_pub_key.as_bytes(),
+            0,
+            &tx(&[]),
+            0,
+            SighashType::ALL,
+            0,
+        )
+        .unwrap();
+        let script_sig = key.p2pkh_script_sig(&sighash, SighashType::ALL);
+        let flags = crate::flags::VerificationFlags::standard();
+        assert_eq!(
+            crate::verify::verify_script(
+                script_pub_key.as_bytes(),
+                0,
+                &tx(script_sig.as_bytes()),
+                0,
+                flags.0,
+                0
+            ),
+            Ok(())
+        );
+    }
+}
@@ -11,8 +11,103 @@
 #![allow(unused_imports)]
 #![allow(clippy::unwrap_or_default)]
 
-// Use the generated C++ bindings
-include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+/// The generated bindings to the C++ library.
+///
+/// These are only public with the `unsafe-bindings` feature. Otherwise, only
+/// the error codes and flags are exported, for use with the safe API.
+mod ffi {
+    // Not all of the generated items are used by the safe API.
+    #![allow(dead_code)]
+
+    // Use the generated C++ bindings
+    include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
+}
+
+#[cfg(not(feature = "unsafe-bindings"))]
+use ffi::*;
+#[cfg(feature = "unsafe-bindings")]
+pub use ffi::*;
+
+pub use ffi::{
+    zcash_script_SCRIPT_FLAGS_VERIFY_CHECKLOCKTIMEVERIFY, zcash_script_SCRIPT_FLAGS_VERIFY_NONE,
+    zcash_script_SCRIPT_FLAGS_VERIFY_P2SH, zcash_script_error_t,
+    zcash_script_error_t_zcash_script_ERR_ALL_PREV_OUTPUTS_DESERIALIZE,
+    zcash_script_error_t_zcash_script_ERR_ALL_PREV_OUTPUTS_SIZE_MISMATCH,
+    zcash_script_error_t_zcash_script_ERR_OK, zcash_script_error_t_zcash_script_ERR_TX_DESERIALIZE,
+    zcash_script_error_t_zcash_script_ERR_TX_INDEX,
+    zcash_script_error_t_zcash_script_ERR_TX_SIZE_MISMATCH,
+    zcash_script_error_t_zcash_script_ERR_TX_VERSION,
+    zcash_script_error_t_zcash_script_ERR_VERIFY_SCRIPT, ZCASH_SCRIPT_API_VER,
+};
+
+/// Returns `Ok(())` if the input `n_in` of the serialized transaction `tx_to`
+/// correctly spends `script_pub_key` under the additional constraints
+/// specified by `flags`.
+///
+/// Like the C API, a script verification failure is reported as
+/// `Err(zcash_script_error_t_zcash_script_ERR_OK)`.
+#[deprecated(note = "use `v1::verify_script`, which returns a `v1::Error`")]
+pub fn verify_script(
+    script_pub_key: &[u8],
+    amount: i64,
+    tx_to: &[u8],
+    n_in: u32,
+    flags: u32,
+    consensus_branch_id: u32,
+) -> Result<(), zcash_script_error_t> {
+    v1::verify_script(
+        script_pub_key,
+        amount,
+        tx_to,
+        n_in,
+        flags::VerificationFlags(flags),
+        consensus_branch_id,
+    )
+    .map_err(v1::Error::code)
+}
+
+/// Returns `Ok(())` if the input `n_in` of the serialized transaction `tx_to`
+/// correctly spends the matching output in `all_prev_outputs` under the
+/// additional constraints specified by `flags`.
+///
+/// Like the C API, a script verification failure is reported as
+/// `Err(zcash_script_error_t_zcash_script_ERR_OK)`.
+#[deprecated(note = "use `v1::verify_script_v5`, which returns a `v1::Error`")]
+pub fn verify_script_v5(
+    tx_to: &[u8],
+    all_prev_outputs: &[u8],
+    n_in: u32,
+    flags: u32,
+    consensus_branch_id: u32,
+) -> Result<(), zcash_script_error_t> {
+    v1::verify_script_v5(
+        tx_to,
+        all_prev_outputs,
+        n_in,
+        flags::VerificationFlags(flags),
+        consensus_branch_id,
+    )
+    .map_err(v1::Error::code)
+}
+
+/// Returns the hex-encoded SHA-256 digest of the vendored zcashd (and
+/// secp256k1, unless using `external-secp`) source files compiled into this
+/// crate.
+///
+/// The digest is computed by the build script over each file's path, length
+/// and contents, so deployments can attest to exactly which consensus code
+/// their binary contains. Headers are not included.
+///
+/// When prebuilt libraries are linked, because `ZCASH_SCRIPT_LIB_DIR` or
+/// `ZCASH_SCRIPT_NO_VENDOR` was set at build time, the vendored sources
+/// aren't what was compiled, and this returns [`PREBUILT_SOURCE_DIGEST`]
+/// instead of a digest.
+pub fn vendored_source_digest() -> &'static str {
+    env!("ZCASH_SCRIPT_VENDORED_SOURCE_DIGEST")
+}
+
+/// The [`vendored_source_digest`] of builds that link prebuilt libraries.
+pub const PREBUILT_SOURCE_DIGEST: &str = "prebuilt";
 
 // Include the items from depend/zcash/src/rust/src/rustzcash.rs (librustzcash/lib.rs)
 // that we need
@@ -70,6 +165,81 @@ mod orchard_ffi;
 mod streams_ffi;
 mod transaction_ffi;
 
+pub mod analysis;
+#[cfg(feature = "async")]
+pub mod async_verify;
+pub mod bounded;
+pub mod budget;
+pub mod cache;
+pub mod calibrate;
+pub mod cancel;
+pub mod coinbase;
+pub mod cost;
+pub mod decode;
+pub mod diagnostics;
+pub mod encoding;
+pub mod eval;
+#[cfg(feature = "prometheus")]
+pub mod exporter;
+pub mod fee;
+pub mod flags;
+#[cfg(any(test, feature = "test-dependencies", feature = "arbitrary"))]
+pub mod fuzz;
+#[cfg(any(test, feature = "test-dependencies"))]
+pub mod generator;
+#[cfg(any(test, feature = "test-dependencies"))]
+pub mod golden;
+#[cfg(feature = "debug-handles")]
+pub mod handles;
+pub mod hash;
+#[cfg(feature = "hd-wallet")]
+pub mod hd;
+pub mod keys;
+pub mod limits;
+pub mod lock_time;
+pub mod memory;
+pub mod message;
+#[cfg(any(test, feature = "test-dependencies"))]
+pub mod minimize;
+#[cfg(any(test, feature = "test-dependencies"))]
+pub mod mock;
+pub mod observer;
+pub mod opcode;
+pub mod policy;
+pub mod pool;
+pub mod precomputed;
+pub mod prevout;
+#[cfg(feature = "primitives")]
+pub mod primitives;
+pub mod replay;
+pub mod script;
+pub mod script_error;
+pub mod script_num;
+pub mod secp;
+#[cfg(feature = "tower")]
+pub mod service;
+pub mod sighash;
+pub mod signing;
+pub mod sigops;
+pub mod spendability;
+pub mod standard;
+pub mod stats;
+#[cfg(any(test, feature = "test-dependencies"))]
+pub mod strategies;
+pub mod telemetry;
+pub mod templates;
+#[cfg(feature = "tracing")]
+mod trace;
+pub mod txid;
+pub mod v1;
+pub mod verifier;
+pub mod verify;
+pub mod violation;
+#[cfg(feature = "zebra")]
+pub mod zebra;
+
+pub use zcash_address::Network;
+
 #[cfg(test)]
 mod tests {
     pub use super::zcash_script_error_t;
@@ -275,4 +445,57 @@ mod tests {
 
         verify_script_precompute(script_pub_key, amount, tx_to, nIn, flags, branch_id).unwrap_err();
     }
+
+    #[test]
+    fn vendored_source_digest() {
+        let digest = super::vendored_source_digest();
+        if digest != super::PREBUILT_SOURCE_DIGEST {
+            assert_eq!(digest.len(), 64);
+            assert!(digest.bytes().all(|byte| byte.is_ascii_hexdigit()));
+        }
+    }
+
+    #[test]
+    fn it_works_batch() {
+        let coin = i64::pow(10, 8);
+        let script_pub_key = &*SCRIPT_PUBKEY;
+        let tx_to = &*SCRIPT_TX;
+        let flags = 1;
+        let branch_id = 0x2bb40e60;
+
+        let input = |amount| super::zcash_script_input {
+            nIn: 0,
+            scriptPubKey: script_pub_key.as_ptr(),
+            scriptPubKeyLen: script_pub_key.len() as _,
+            amount,
+            flags,
+        };
+        // The second input commits to the wrong amount, so its signature is invalid.
+        let inputs = [input(212 * coin), input(213 * coin)];
+        let mut results = [0; 2];
+        let mut errs = [0; 2];
+        let mut err = 0;
+
+        let precomputed = unsafe {
+            super::zcash_script_new_precomputed_tx(tx_to.as_ptr(), tx_to.len() as _, &mut err)
+        };
+        assert_eq!(err, super::zcash_script_error_t_zcash_script_ERR_OK);
+
+        let ret = unsafe {
+            super::zcash_script_verify_precomputed_batch(
+                precomputed,
+                inputs.as_ptr(),
+                inputs.len() as _,
+                branch_id,
+                results.as_mut_ptr(),
+                errs.as_mut_ptr(),
+            )
+        };
+
+        unsafe { super::zcash_script_free_precomputed_tx(precomputed) };
+
+        assert_eq!(ret, 0);
+        assert_eq!(results, [1, 0]);
+        assert_eq!(errs, [super::zcash_script_error_t_zcash_script_ERR_OK; 2]);
+    }
 }
@@ -0,0 +1,187 @@
+//! Tighter interpreter limits, for testing how code handles scripts that hit
+//! them.
+//!
+//! The consensus limits are constants compiled into zcashd's interpreter,
+//! which this crate vendors unmodified, so they can't be changed for it.
+//! [`Limits`] instead checks the scripts evaluated for an input against
+//! smaller limits before verifying, failing with the [`ScriptError`] the
+//! interpreter reports for the limit, so tests can reach those errors
+//! without megabyte scripts.
+//!
+//! These limits are not consensus rules. Limits above the consensus ones
+//! have no effect, since the interpreter still enforces those. The stack
+//! size depends on evaluation, so it can't be checked beforehand; tests of
+//! it can seed a large stack with
+//! [`eval_with_stack`](crate::eval::eval_with_stack) instead.
+
+use crate::{
+    eval::EvalError,
+    flags::VerificationFlags,
+    opcode::Opcode,
+    script::{self, Script, MAX_OPS_PER_SCRIPT, MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE},
+    script_error::ScriptError,
+    verify::{self, verify_script},
+};
+
+/// Non-consensus limits on the scripts evaluated for an input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Limits {
+    /// The largest script, in bytes.
+    pub max_script_size: usize,
+    /// The largest pushed element, in bytes.
+    pub max_element_size: usize,
+    /// The most non-push opcodes in a script.
+    pub max_ops_per_script: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits::CONSENSUS
+    }
+}
+
+impl Limits {
+    /// The consensus limits.
+    pub const CONSENSUS: Limits = Limits {
+        max_script_size: MAX_SCRIPT_SIZE,
+        max_element_size: MAX_SCRIPT_ELEMENT_SIZE,
+        max_ops_per_script: MAX_OPS_PER_SCRIPT,
+    };
+
+    /// Returns the error the interpreter reports for the first limit
+    /// `script` exceeds, checking it as the interpreter would if it ran
+    /// every instruction.
+    ///
+    /// Opcodes are counted as the interpreter counts them, in branches that
+    /// aren't executed too, except for the keys the interpreter adds for each
+    /// executed `OP_CHECKMULTISIG`.
+    pub fn check(&self, script: &[u8]) -> Result<(), ScriptError> {
+        if script.len() > self.max_script_size {
+            return Err(ScriptError::ScriptSize);
+        }
+        let mut ops = 0;
+        for op in script::instructions(script) {
+            // Parse errors are left to the interpreter.
+            let Ok(op) = op else { break };
+            if op.data.len() > self.max_element_size {
+                return Err(ScriptError::PushSize);
+            }
+            if op.opcode > Opcode::OP_16 {
+                ops += 1;
+                if ops > self.max_ops_per_script {
+                    return Err(ScriptError::OpCount);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Returns `Ok(())` if the input `n_in` of the serialized pre-v5 transaction
+/// `tx_to` correctly spends `script_pub_key` under `flags`, like
+/// [`verify_script`], and its scriptSig, `script_pub_key` and any P2SH
+/// redeem script are within `limits`.
+///
+/// Scripts that fail verification report the interpreter's error.
+pub fn verify_script_with_limits(
+    script_pub_key: &[u8],
+    amount: i64,
+    tx_to: &[u8],
+    n_in: u32,
+    flags: u32,
+    consensus_branch_id: u32,
+    limits: &Limits,
+) -> Result<(), EvalError> {
+    // An input that doesn't exist is reported by the verification.
+    if let Some(script_sig) =
+        verify::script_sig(tx_to, n_in, consensus_branch_id).map_err(EvalError::Tx)?
+    {
+        let script_pub_key = Script::from(script_pub_key);
+        let redeem_script =
+            verify::redeem_script(&script_sig, &script_pub_key, VerificationFlags(flags));
+        // The interpreter evaluates the scriptSig first, and the redeem
+        // script last.
+        for script in [script_sig.as_bytes(), script_pub_key.as_bytes()]
+            .into_iter()
+            .chain(redeem_script)
+        {
+            limits.check(script).map_err(EvalError::Script)?;
+        }
+    }
+
+    match verify_script(
+        script_pub_key,
+        amount,
+        tx_to,
+        n_in,
+        flags,
+        consensus_branch_id,
+    ) {
+        Ok(()) => Ok(()),
+        Err(verify::Error::ScriptInvalid) => Err(EvalError::Script(verify::last_script_error())),
+        Err(err) => Err(EvalError::Tx(err)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+
+    #[test]
+    fn checks_tighter_limits() {
+        let limits = Limits {
+            max_script_size: 20,
+            max_element_size: 4,
+            max_ops_per_script: 2,
+        };
+        assert_eq!(Limits::default().check(&SCRIPT_PUBKEY), Ok(()));
+        assert_eq!(limits.check(&SCRIPT_PUBKEY), Err(ScriptError::ScriptSize));
+        assert_eq!(
+            limits.check(crate::script!([[0; 5]]).as_bytes()),
+            Err(ScriptError::PushSize)
+        );
+        assert_eq!(
+            limits.check(crate::script!(OP_IF OP_DUP OP_ENDIF).as_bytes()),
+            Err(ScriptError::OpCount)
+        );
+        assert_eq!(
+            limits.check(crate::script!(1 [[0; 4]] OP_DUP).as_bytes()),
+            Ok(())
+        );
+
+        let verify = |limits: &Limits, consensus_branch_id| {
+            verify_script_with_limits(
+                &SCRIPT_PUBKEY,
+                212 * 100_000_000,
+                &SCRIPT_TX,
+                0,
+                1,
+                consensus_branch_id,
+                limits,
+            )
+        };
+        assert_eq!(verify(&Limits::CONSENSUS, 0x2bb40e60), Ok(()));
+        // The scriptSig pushes a 71-byte signature.
+        let small_elements = Limits {
+            max_element_size: 70,
+            ..Limits::CONSENSUS
+        };
+        assert_eq!(
+            verify(&small_elements, 0x2bb40e60),
+            Err(EvalError::Script(ScriptError::PushSize))
+        );
+        let few_ops = Limits {
+            max_ops_per_script: 3,
+            ..Limits::CONSENSUS
+        };
+        assert_eq!(
+            verify(&few_ops, 0x2bb40e60),
+            Err(EvalError::Script(ScriptError::OpCount))
+        );
+        assert_eq!(
+            verify(&Limits::CONSENSUS, 0x2bb40e61),
+            Err(EvalError::Script(ScriptError::EvalFalse))
+        );
+    }
+}
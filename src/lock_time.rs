@@ -0,0 +1,512 @@
+//! Evaluation of `OP_CHECKLOCKTIMEVERIFY` constraints against a transaction
+//! and the current chain state.
+//!
+//! This mirrors `TransactionSignatureChecker::CheckLockTime` and `IsFinalTx`
+//! from zcashd. Zcash has not activated BIP 68/112, so `OP_CHECKSEQUENCEVERIFY`
+//! is still `OP_NOP3` and relative lock times are not enforced.
+//!
+//! The transaction-level checks of `nLockTime` and `nExpiryHeight`, which are
+//! always validated alongside scripts, are in [`check_tx_lock_and_expiry`].
+//!
+//! [`lock_time_constraints`] finds the absolute and relative lock times a
+//! script requires, so wallets can show when time-locked outputs become
+//! spendable.
+
+use std::fmt;
+
+use zcash_primitives::transaction::Transaction;
+
+use crate::{
+    opcode::Opcode,
+    script::{Instruction, Script},
+    script_num::{ScriptNum, ScriptNumError},
+};
+
+/// Lock times below this value are block heights; others are UNIX timestamps.
+pub const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+
+/// The `nSequence` value that marks an input as final, disabling lock times.
+pub const SEQUENCE_FINAL: u32 = 0xffff_ffff;
+
+/// Expiry heights must be below this value, matching zcashd's
+/// `TX_EXPIRY_HEIGHT_THRESHOLD`.
+pub const TX_EXPIRY_HEIGHT_THRESHOLD: u32 = 500_000_000;
+
+/// zcashd doesn't accept transactions into its mempool that expire within
+/// this many blocks, matching `TX_EXPIRING_SOON_THRESHOLD`.
+pub const TX_EXPIRING_SOON_THRESHOLD: u32 = 3;
+
+/// The maximum encoded size of an `OP_CHECKLOCKTIMEVERIFY` operand.
+const MAX_LOCK_TIME_NUM_SIZE: usize = 5;
+
+/// The bit of a BIP 68 relative lock time that disables it.
+pub const SEQUENCE_LOCKTIME_DISABLE_FLAG: u32 = 1 << 31;
+
+/// The bit of a BIP 68 relative lock time that makes it a time rather than a
+/// number of blocks.
+pub const SEQUENCE_LOCKTIME_TYPE_FLAG: u32 = 1 << 22;
+
+/// The bits of a BIP 68 relative lock time that hold its value.
+pub const SEQUENCE_LOCKTIME_MASK: u32 = 0x0000_ffff;
+
+/// The number of seconds in each unit of a BIP 68 relative lock time.
+pub const SEQUENCE_LOCKTIME_GRANULARITY: u32 = 512;
+
+/// An absolute lock time, as used by `nLockTime` and `OP_CHECKLOCKTIMEVERIFY`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LockTime {
+    /// The earliest block height.
+    Height(u32),
+    /// The earliest block time, as a UNIX timestamp.
+    Time(u32),
+}
+
+impl LockTime {
+    /// Interprets a raw lock time value, using [`LOCKTIME_THRESHOLD`].
+    pub fn from_consensus(value: u32) -> Self {
+        if value < LOCKTIME_THRESHOLD {
+            LockTime::Height(value)
+        } else {
+            LockTime::Time(value)
+        }
+    }
+
+    /// Returns the raw lock time value.
+    pub fn to_consensus(self) -> u32 {
+        match self {
+            LockTime::Height(value) | LockTime::Time(value) => value,
+        }
+    }
+
+    fn is_same_kind(self, other: LockTime) -> bool {
+        matches!(
+            (self, other),
+            (LockTime::Height(_), LockTime::Height(_)) | (LockTime::Time(_), LockTime::Time(_))
+        )
+    }
+}
+
+impl fmt::Display for LockTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockTime::Height(height) => write!(f, "height {}", height),
+            LockTime::Time(time) => write!(f, "time {}", time),
+        }
+    }
+}
+
+/// A relative lock time, as BIP 68 encodes it in `nSequence` and BIP 112's
+/// `OP_CHECKSEQUENCEVERIFY` takes it.
+///
+/// Zcash hasn't activated BIP 68 or BIP 112, so relative lock times are
+/// never enforced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RelativeLockTime {
+    /// The number of blocks after the spent output was mined.
+    Blocks(u16),
+    /// The number of seconds after the spent output was mined, a multiple of
+    /// [`SEQUENCE_LOCKTIME_GRANULARITY`].
+    Seconds(u32),
+}
+
+impl RelativeLockTime {
+    /// Decodes an encoded relative lock time, returning `None` if it is
+    /// disabled.
+    pub fn from_sequence(sequence: u32) -> Option<Self> {
+        if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return None;
+        }
+        let value = (sequence & SEQUENCE_LOCKTIME_MASK) as u16;
+        Some(if sequence & SEQUENCE_LOCKTIME_TYPE_FLAG != 0 {
+            RelativeLockTime::Seconds(u32::from(value) * SEQUENCE_LOCKTIME_GRANULARITY)
+        } else {
+            RelativeLockTime::Blocks(value)
+        })
+    }
+}
+
+impl fmt::Display for RelativeLockTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RelativeLockTime::Blocks(blocks) => write!(f, "{} blocks", blocks),
+            RelativeLockTime::Seconds(seconds) => write!(f, "{} seconds", seconds),
+        }
+    }
+}
+
+/// A lock time required by a script.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum LockTimeConstraint {
+    /// Required by an `OP_CHECKLOCKTIMEVERIFY`.
+    Absolute(LockTime),
+    /// Required by an `OP_CHECKSEQUENCEVERIFY`, which Zcash doesn't enforce.
+    Relative(RelativeLockTime),
+}
+
+impl fmt::Display for LockTimeConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockTimeConstraint::Absolute(lock_time) => write!(f, "after {}", lock_time),
+            LockTimeConstraint::Relative(lock_time) => {
+                write!(f, "{} after confirmation", lock_time)
+            }
+        }
+    }
+}
+
+/// The reason a lock time constraint is not satisfied.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LockTimeError {
+    /// The `OP_CHECKLOCKTIMEVERIFY` operand is not a valid script number.
+    InvalidNumber(ScriptNumError),
+    /// The `OP_CHECKLOCKTIMEVERIFY` operand is negative.
+    Negative,
+    /// The `OP_CHECKLOCKTIMEVERIFY` operand is larger than any `nLockTime`.
+    OutOfRange,
+    /// The required lock time is a height and the transaction's is a time, or
+    /// the reverse.
+    KindMismatch,
+    /// The transaction's `nLockTime` is earlier than the required lock time.
+    Unsatisfied,
+    /// The input's `nSequence` is final, which disables lock time checks.
+    InputFinal,
+    /// The transaction's `nLockTime` has not been reached by the chain, so it
+    /// can't be mined yet.
+    NotFinal,
+    /// The transaction's `nExpiryHeight` has passed, so it can't be mined.
+    Expired,
+    /// The transaction's `nExpiryHeight` is not below
+    /// [`TX_EXPIRY_HEIGHT_THRESHOLD`].
+    ExpiryHeightTooHigh,
+}
+
+impl fmt::Display for LockTimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockTimeError::InvalidNumber(e) => write!(f, "invalid lock time operand: {}", e),
+            LockTimeError::Negative => write!(f, "negative lock time"),
+            LockTimeError::OutOfRange => write!(f, "lock time larger than any nLockTime"),
+            LockTimeError::KindMismatch => write!(f, "lock time is of a different kind"),
+            LockTimeError::Unsatisfied => write!(f, "transaction nLockTime is too early"),
+            LockTimeError::InputFinal => write!(f, "input nSequence is final"),
+            LockTimeError::NotFinal => write!(f, "transaction nLockTime has not been reached"),
+            LockTimeError::Expired => write!(f, "transaction has expired"),
+            LockTimeError::ExpiryHeightTooHigh => write!(f, "expiry height is too high"),
+        }
+    }
+}
+
+impl std::error::Error for LockTimeError {}
+
+/// Returns the lock times required by the `OP_CHECKLOCKTIMEVERIFY` operations
+/// in `script`.
+///
+/// Only operands pushed directly before the opcode are found; operands that are
+/// computed at runtime can't be determined without executing the script.
+pub fn required_lock_times(script: &Script) -> Result<Vec<LockTime>, LockTimeError> {
+    let mut lock_times = vec![];
+    let mut previous = None;
+    for instruction in script.instructions() {
+        let Ok(instruction) = instruction else {
+            break;
+        };
+        if instruction.opcode == Opcode::OP_CHECKLOCKTIMEVERIFY {
+            if let Some(num) = previous.and_then(pushed_num).transpose()? {
+                lock_times.push(lock_time_from_num(num)?);
+            }
+        }
+        previous = Some(instruction);
+    }
+    Ok(lock_times)
+}
+
+/// Returns the lock times required by the `OP_CHECKLOCKTIMEVERIFY` and
+/// `OP_CHECKSEQUENCEVERIFY` operations in `script`, in script order.
+///
+/// As with [`required_lock_times`], only operands pushed directly before the
+/// opcode are found. An `OP_CHECKSEQUENCEVERIFY` whose operand disables its
+/// relative lock time requires nothing, so it is skipped.
+pub fn lock_time_constraints(script: &Script) -> Result<Vec<LockTimeConstraint>, LockTimeError> {
+    let mut constraints = vec![];
+    let mut previous = None;
+    for instruction in script.instructions() {
+        let Ok(instruction) = instruction else {
+            break;
+        };
+        let num = || previous.and_then(pushed_num).transpose();
+        if instruction.opcode == Opcode::OP_CHECKLOCKTIMEVERIFY {
+            if let Some(num) = num()? {
+                constraints.push(LockTimeConstraint::Absolute(lock_time_from_num(num)?));
+            }
+        } else if instruction.opcode == Opcode::OP_CHECKSEQUENCEVERIFY {
+            if let Some(num) = num()? {
+                if num < 0 {
+                    return Err(LockTimeError::Negative);
+                }
+                // BIP 112 only looks at the bits that BIP 68 defines, which
+                // are all in the low 32.
+                if let Some(lock_time) = RelativeLockTime::from_sequence(num.value() as u32) {
+                    constraints.push(LockTimeConstraint::Relative(lock_time));
+                }
+            }
+        }
+        previous = Some(instruction);
+    }
+    Ok(constraints)
+}
+
+/// Returns the number pushed by `instruction`, or `None` if it isn't a push.
+fn pushed_num(instruction: Instruction<'_>) -> Option<Result<ScriptNum, LockTimeError>> {
+    if instruction.opcode.is_push_data() {
+        Some(
+            ScriptNum::decode(instruction.data, false, MAX_LOCK_TIME_NUM_SIZE)
+                .map_err(LockTimeError::InvalidNumber),
+        )
+    } else if instruction.opcode == Opcode::OP_1NEGATE {
+        Some(Ok(ScriptNum::new(-1)))
+    } else {
+        instruction
+            .opcode
+            .decode_op_n()
+            .map(|n| Ok(ScriptNum::new(n.into())))
+    }
+}
+
+fn lock_time_from_num(num: ScriptNum) -> Result<LockTime, LockTimeError> {
+    if num < 0 {
+        return Err(LockTimeError::Negative);
+    }
+    u32::try_from(num.value())
+        .map(LockTime::from_consensus)
+        .map_err(|_| LockTimeError::OutOfRange)
+}
+
+/// Checks a required lock time against a transaction's `nLockTime` and the
+/// spending input's `nSequence`, matching `CheckLockTime`.
+pub fn check_lock_time(
+    required: LockTime,
+    tx_lock_time: u32,
+    input_sequence: u32,
+) -> Result<(), LockTimeError> {
+    let tx_lock_time = LockTime::from_consensus(tx_lock_time);
+    if !required.is_same_kind(tx_lock_time) {
+        return Err(LockTimeError::KindMismatch);
+    }
+    if required.to_consensus() > tx_lock_time.to_consensus() {
+        return Err(LockTimeError::Unsatisfied);
+    }
+    if input_sequence == SEQUENCE_FINAL {
+        return Err(LockTimeError::InputFinal);
+    }
+    Ok(())
+}
+
+/// Returns true if a transaction with the given `nLockTime` and input
+/// `nSequence`s can be included in a block at `height` with block time
+/// `block_time`, matching `IsFinalTx`.
+///
+/// To check whether a transaction can be mined in the next block, pass the
+/// next block height and the median time past of the chain tip.
+pub fn is_final_tx(tx_lock_time: u32, sequences: &[u32], height: u32, block_time: i64) -> bool {
+    if tx_lock_time == 0 {
+        return true;
+    }
+    let reached = match LockTime::from_consensus(tx_lock_time) {
+        LockTime::Height(value) => value < height,
+        LockTime::Time(value) => i64::from(value) < block_time,
+    };
+    reached || sequences.iter().all(|&sequence| sequence == SEQUENCE_FINAL)
+}
+
+/// Returns true if a transaction with the given `nExpiryHeight` can't be
+/// included in a block at `height`, matching `IsExpiredTx`.
+///
+/// An expiry height of zero means the transaction never expires, and coinbase
+/// transactions never do either.
+pub fn is_expired_tx(expiry_height: u32, is_coinbase: bool, height: u32) -> bool {
+    expiry_height != 0 && !is_coinbase && height > expiry_height
+}
+
+/// Returns true if a transaction with the given `nExpiryHeight` expires too
+/// soon for zcashd to accept it into its mempool, when the next block is at
+/// `next_height`, matching `IsExpiringSoonTx`.
+pub fn is_expiring_soon_tx(expiry_height: u32, is_coinbase: bool, next_height: u32) -> bool {
+    is_expired_tx(
+        expiry_height,
+        is_coinbase,
+        next_height.saturating_add(TX_EXPIRING_SOON_THRESHOLD),
+    )
+}
+
+/// Checks that `tx` can be included in the block at `height`, whose previous
+/// block has `median_time_past`, under the consensus rules for its
+/// `nLockTime` (`IsFinalTx`) and `nExpiryHeight` (in
+/// `ContextualCheckTransaction`).
+///
+/// NU5's rule that a coinbase transaction's expiry height is its block's
+/// height isn't checked, as it is part of checking the coinbase.
+pub fn check_tx_lock_and_expiry(
+    tx: &Transaction,
+    height: u32,
+    median_time_past: i64,
+) -> Result<(), LockTimeError> {
+    let (sequences, is_coinbase) = match tx.transparent_bundle() {
+        Some(bundle) => (
+            bundle.vin.iter().map(|input| input.sequence).collect(),
+            bundle.is_coinbase(),
+        ),
+        None => (vec![], false),
+    };
+    let expiry_height = u32::from(tx.expiry_height());
+
+    if expiry_height >= TX_EXPIRY_HEIGHT_THRESHOLD {
+        return Err(LockTimeError::ExpiryHeightTooHigh);
+    }
+    if is_expired_tx(expiry_height, is_coinbase, height) {
+        return Err(LockTimeError::Expired);
+    }
+    if !is_final_tx(tx.lock_time(), &sequences, height, median_time_past) {
+        return Err(LockTimeError::NotFinal);
+    }
+    Ok(())
+}
+
+/// Checks whether all the `OP_CHECKLOCKTIMEVERIFY` constraints in
+/// `script_pub_key` can currently be satisfied by a spending input with
+/// `input_sequence`, in a transaction with `tx_lock_time` that is mined in the
+/// next block at `next_height` with the chain's `median_time_past`.
+pub fn check_script_lock_times(
+    script_pub_key: &Script,
+    tx_lock_time: u32,
+    input_sequence: u32,
+    next_height: u32,
+    median_time_past: i64,
+) -> Result<(), LockTimeError> {
+    let required = required_lock_times(script_pub_key)?;
+    for lock_time in &required {
+        check_lock_time(*lock_time, tx_lock_time, input_sequence)?;
+    }
+    // A satisfied CHECKLOCKTIMEVERIFY implies this input is not final, so the
+    // transaction's own lock time must have been reached.
+    if !required.is_empty()
+        && !is_final_tx(
+            tx_lock_time,
+            &[input_sequence],
+            next_height,
+            median_time_past,
+        )
+    {
+        return Err(LockTimeError::NotFinal);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cltv_script(lock_time: i64) -> Script {
+        let mut script = vec![];
+        let num = ScriptNum::new(lock_time).to_bytes();
+        script.push(num.len() as u8);
+        script.extend(num);
+        script.extend([Opcode::OP_CHECKLOCKTIMEVERIFY.0, Opcode::OP_DROP.0]);
+        Script(script)
+    }
+
+    #[test]
+    fn finds_required_lock_times() {
+        assert_eq!(
+            required_lock_times(&cltv_script(1_000_000)),
+            Ok(vec![LockTime::Height(1_000_000)])
+        );
+        assert_eq!(
+            required_lock_times(&cltv_script(1_700_000_000)),
+            Ok(vec![LockTime::Time(1_700_000_000)])
+        );
+        assert_eq!(
+            required_lock_times(&cltv_script(-1)),
+            Err(LockTimeError::Negative)
+        );
+    }
+
+    #[test]
+    fn finds_lock_time_constraints() {
+        let csv = |sequence: i64| crate::script!((sequence) OP_CHECKSEQUENCEVERIFY OP_DROP);
+        let htlc = crate::script!(
+            OP_IF OP_SHA256 [[1; 32]] OP_EQUALVERIFY
+            OP_ELSE (854882) OP_CHECKLOCKTIMEVERIFY OP_DROP
+            OP_ENDIF (144) OP_CHECKSEQUENCEVERIFY OP_DROP OP_TRUE
+        );
+        let constraints = lock_time_constraints(&htlc).unwrap();
+        assert_eq!(
+            constraints,
+            [
+                LockTimeConstraint::Absolute(LockTime::Height(854882)),
+                LockTimeConstraint::Relative(RelativeLockTime::Blocks(144)),
+            ]
+        );
+        assert_eq!(constraints[0].to_string(), "after height 854882");
+        assert_eq!(constraints[1].to_string(), "144 blocks after confirmation");
+
+        let seconds = i64::from(SEQUENCE_LOCKTIME_TYPE_FLAG) | 10;
+        assert_eq!(
+            lock_time_constraints(&csv(seconds)),
+            Ok(vec![LockTimeConstraint::Relative(
+                RelativeLockTime::Seconds(5120)
+            )])
+        );
+        let disabled = i64::from(SEQUENCE_LOCKTIME_DISABLE_FLAG) | 10;
+        assert_eq!(lock_time_constraints(&csv(disabled)), Ok(vec![]));
+        assert_eq!(
+            lock_time_constraints(&csv(-1)),
+            Err(LockTimeError::Negative)
+        );
+    }
+
+    #[test]
+    fn checks_transaction_lock_and_expiry() {
+        use zcash_primitives::consensus::BranchId;
+
+        // The test transaction has an nLockTime of 854882 and an
+        // nExpiryHeight of 854933.
+        let tx = Transaction::read(&crate::tests::SCRIPT_TX[..], BranchId::Blossom).unwrap();
+        assert_eq!(check_tx_lock_and_expiry(&tx, 854_883, 0), Ok(()));
+        assert_eq!(check_tx_lock_and_expiry(&tx, 854_933, 0), Ok(()));
+        assert_eq!(
+            check_tx_lock_and_expiry(&tx, 854_882, 0),
+            Err(LockTimeError::NotFinal)
+        );
+        assert_eq!(
+            check_tx_lock_and_expiry(&tx, 854_934, 0),
+            Err(LockTimeError::Expired)
+        );
+
+        assert!(!is_expired_tx(0, false, u32::MAX));
+        assert!(!is_expired_tx(10, true, 11));
+        assert!(is_expiring_soon_tx(10, false, 8));
+        assert!(!is_expiring_soon_tx(10, false, 7));
+    }
+
+    #[test]
+    fn checks_against_chain_state() {
+        let script = cltv_script(1_000);
+        assert_eq!(check_script_lock_times(&script, 1_000, 0, 1_001, 0), Ok(()));
+        assert_eq!(
+            check_script_lock_times(&script, 999, 0, 1_001, 0),
+            Err(LockTimeError::Unsatisfied)
+        );
+        assert_eq!(
+            check_script_lock_times(&script, 1_000, SEQUENCE_FINAL, 1_001, 0),
+            Err(LockTimeError::InputFinal)
+        );
+        assert_eq!(
+            check_script_lock_times(&script, 1_000, 0, 1_000, 0),
+            Err(LockTimeError::NotFinal)
+        );
+        assert_eq!(
+            check_script_lock_times(&script, 1_700_000_000, 0, 1_001, 1_800_000_000),
+            Err(LockTimeError::KindMismatch)
+        );
+    }
+}
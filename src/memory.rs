@@ -0,0 +1,88 @@
+//! Reporting the memory this crate uses, for node memory budgets.
+//!
+//! The only memory the crate holds for the life of the process is the
+//! vendored libsecp256k1's precomputed tables, which are static data whose
+//! size is set at build time. Caches and pools are created by the caller,
+//! so they are added to a report explicitly.
+
+use std::fmt;
+
+use crate::{cache::ScriptCache, pool::VerificationPool};
+
+/// The memory used by this crate, in bytes.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// The static data of the vendored libsecp256k1's precomputed tables, or
+    /// zero with the `external-secp` feature or prebuilt libraries.
+    pub secp256k1_tables: usize,
+    /// The estimated heap memory of the [`ScriptCache`]s added to the report.
+    pub script_caches: usize,
+    /// The queues of the [`VerificationPool`]s added to the report.
+    pub pools: usize,
+}
+
+impl MemoryUsage {
+    /// Adds the memory used by `cache`.
+    pub fn add_cache(&mut self, cache: &ScriptCache) -> &mut Self {
+        self.script_caches += cache.memory_usage();
+        self
+    }
+
+    /// Adds the memory used by `pool`.
+    pub fn add_pool(&mut self, pool: &VerificationPool) -> &mut Self {
+        self.pools += pool.memory_usage();
+        self
+    }
+
+    /// Returns the total memory in the report.
+    pub fn total(&self) -> usize {
+        self.secp256k1_tables + self.script_caches + self.pools
+    }
+}
+
+impl fmt::Display for MemoryUsage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bytes (secp256k1 tables: {}, script caches: {}, pools: {})",
+            self.total(),
+            self.secp256k1_tables,
+            self.script_caches,
+            self.pools
+        )
+    }
+}
+
+/// Returns a report of the memory this crate always uses, to which caches
+/// and pools can be added.
+pub fn memory_usage() -> MemoryUsage {
+    MemoryUsage {
+        secp256k1_tables: env!("ZCASH_SCRIPT_SECP256K1_TABLES_SIZE")
+            .parse()
+            .expect("build script sets a number"),
+        ..MemoryUsage::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn adds_caches_and_pools() {
+        let base = memory_usage();
+        assert_eq!(base.total(), base.secp256k1_tables);
+
+        let cache = ScriptCache::new(10);
+        cache.insert(crate::cache::CacheKey::new(&[], 0, 0, 0));
+        let pool = VerificationPool::new(1, 8);
+        let mut usage = base;
+        usage.add_cache(&cache).add_pool(&pool);
+        assert_eq!(usage.script_caches, cache.memory_usage());
+        assert_eq!(usage.pools, pool.memory_usage());
+        assert_eq!(
+            usage.total(),
+            base.total() + cache.memory_usage() + pool.memory_usage()
+        );
+    }
+}
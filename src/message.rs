@@ -0,0 +1,237 @@
+//! Signed messages, compatible with zcashd's `signmessage` and
+//! `verifymessage` RPCs.
+//!
+//! A message signature is a compact recoverable ECDSA signature of the double
+//! SHA-256 of the magic string and the message, encoded in Base64. Verifiers
+//! recover the public key from the signature, and compare its hash to the
+//! P2PKH address.
+
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+use zcash_address::Network;
+use zcash_encoding::CompactSize;
+
+use crate::{keys::WifKey, secp, standard::Destination};
+
+/// The string prepended to messages before hashing, zcashd's
+/// `strMessageMagic`.
+pub const MESSAGE_MAGIC: &str = "Zcash Signed Message:\n";
+
+/// The size of a compact signature: a header byte, then `r` and `s`.
+const COMPACT_SIGNATURE_SIZE: usize = 65;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// An error verifying a message signature, matching the errors zcashd's
+/// `verifymessage` returns.
+///
+/// Signatures that are well-formed but don't match the address aren't errors:
+/// [`verify_message`] returns `Ok(false)` for them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageError {
+    /// The address isn't a transparent address for the network.
+    InvalidAddress,
+    /// The address is a P2SH address, which has no key to sign with.
+    NotKeyAddress,
+    /// The signature isn't valid Base64.
+    MalformedBase64,
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::InvalidAddress => write!(f, "Invalid address"),
+            MessageError::NotKeyAddress => write!(f, "Address does not refer to key"),
+            MessageError::MalformedBase64 => write!(f, "Malformed base64 encoding"),
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+/// Returns the hash that message signatures sign: the double SHA-256 of
+/// [`MESSAGE_MAGIC`] and `message`, each prefixed by its length.
+pub fn message_hash(message: &[u8]) -> [u8; 32] {
+    let mut data = Vec::with_capacity(MESSAGE_MAGIC.len() + message.len() + 10);
+    for part in [MESSAGE_MAGIC.as_bytes(), message] {
+        CompactSize::write(&mut data, part.len()).expect("writes to a Vec succeed");
+        data.extend_from_slice(part);
+    }
+    Sha256::digest(Sha256::digest(&data)).into()
+}
+
+/// Signs `message` with `key`, returning the Base64 signature, like zcashd's
+/// `signmessage`.
+pub fn sign_message(key: &WifKey, message: &[u8]) -> String {
+    let (signature, recid) =
+        secp::ecdsa_sign_recoverable(&key.secret_key.to_bytes(), &message_hash(message));
+    let mut compact = Vec::with_capacity(COMPACT_SIGNATURE_SIZE);
+    compact.push(27 + recid + if key.compressed { 4 } else { 0 });
+    compact.extend_from_slice(&signature);
+    encode_base64(&compact)
+}
+
+/// Recovers the public key that signed `message` from the Base64 signature,
+/// matching zcashd's `CPubKey::RecoverCompact`.
+///
+/// The key is returned in the encoding its signature's header specifies,
+/// which determines its address. Returns `Ok(None)` if no key can be
+/// recovered.
+pub fn recover_public_key(
+    signature: &str,
+    message: &[u8],
+) -> Result<Option<Vec<u8>>, MessageError> {
+    let signature = decode_base64(signature).ok_or(MessageError::MalformedBase64)?;
+    let signature: [u8; COMPACT_SIGNATURE_SIZE] = match signature.try_into() {
+        Ok(signature) => signature,
+        Err(_) => return Ok(None),
+    };
+    // Like zcashd, this doesn't check the rest of the header.
+    let header = signature[0].wrapping_sub(27);
+    let compact = signature[1..].try_into().expect("64 bytes");
+    let Some(public_key) = secp::ecdsa_recover(compact, header & 3, &message_hash(message)) else {
+        return Ok(None);
+    };
+    Ok(Some(secp::pubkey_serialize(&public_key, header & 4 != 0)))
+}
+
+/// Returns whether `signature` is a signature of `message` by the key of the
+/// P2PKH `address` on `network`, like zcashd's `verifymessage`.
+pub fn verify_message(
+    address: &str,
+    signature: &str,
+    message: &[u8],
+    network: Network,
+) -> Result<bool, MessageError> {
+    let destination = Destination::decode(address, network).ok_or(MessageError::InvalidAddress)?;
+    if let Destination::ScriptHash(_) = destination {
+        return Err(MessageError::NotKeyAddress);
+    }
+    Ok(recover_public_key(signature, message)?
+        .is_some_and(|public_key| Destination::for_pubkey(&public_key) == destination))
+}
+
+fn encode_base64(data: &[u8]) -> String {
+    let mut out = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let group = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3f;
+                out.push(BASE64_ALPHABET[index as usize].into());
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Decodes Base64 like zcashd's `DecodeBase64`, which requires padding to a
+/// multiple of four characters, and zero trailing bits.
+fn decode_base64(s: &str) -> Option<Vec<u8>> {
+    let data = s.trim_end_matches('=');
+    if s.len() % 4 != 0 || s.len() - data.len() >= 4 {
+        return None;
+    }
+    let mut out = Vec::with_capacity(data.len() * 3 / 4);
+    let (mut acc, mut bits) = (0u32, 0);
+    for c in data.bytes() {
+        let value = BASE64_ALPHABET.iter().position(|&b| b == c)?;
+        acc = ((acc << 6) | value as u32) & 0xfff;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push((acc >> bits) as u8);
+        }
+    }
+    (bits < 6 && acc & ((1 << bits) - 1) == 0).then_some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_and_verifies_messages() {
+        let key = WifKey::decode("KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn").unwrap();
+        let address = key.destination().encode(Network::Main);
+        let signature = sign_message(&key, b"I own this address");
+        assert_eq!(
+            verify_message(&address, &signature, b"I own this address", Network::Main),
+            Ok(true)
+        );
+        assert_eq!(
+            verify_message(&address, &signature, b"I own that address", Network::Main),
+            Ok(false)
+        );
+        assert_eq!(
+            recover_public_key(&signature, b"I own this address"),
+            Ok(Some(key.public_key()))
+        );
+
+        // The header determines the key encoding, and so the address.
+        let uncompressed = WifKey {
+            compressed: false,
+            ..key.clone()
+        };
+        let signature = sign_message(&uncompressed, b"message");
+        assert_eq!(
+            verify_message(&address, &signature, b"message", Network::Main),
+            Ok(false)
+        );
+        let address = uncompressed.destination().encode(Network::Main);
+        assert_eq!(
+            verify_message(&address, &signature, b"message", Network::Main),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn reports_errors() {
+        let key = WifKey::decode("KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn").unwrap();
+        let address = key.destination().encode(Network::Main);
+        let signature = sign_message(&key, b"message");
+        assert_eq!(
+            verify_message(&address, &signature, b"message", Network::Test),
+            Err(MessageError::InvalidAddress)
+        );
+        let p2sh = Destination::ScriptHash([0; 20]).encode(Network::Main);
+        assert_eq!(
+            verify_message(&p2sh, &signature, b"message", Network::Main),
+            Err(MessageError::NotKeyAddress)
+        );
+        assert_eq!(
+            verify_message(&address, "not base64!", b"message", Network::Main),
+            Err(MessageError::MalformedBase64)
+        );
+        assert_eq!(
+            verify_message(&address, "AAAA", b"message", Network::Main),
+            Ok(false)
+        );
+    }
+
+    #[test]
+    fn encodes_base64() {
+        for (data, encoded) in [
+            (&b""[..], ""),
+            (b"f", "Zg=="),
+            (b"fo", "Zm8="),
+            (b"foo", "Zm9v"),
+            (b"foob", "Zm9vYg=="),
+        ] {
+            assert_eq!(encode_base64(data), encoded);
+            assert_eq!(decode_base64(encoded).as_deref(), Some(data));
+        }
+        assert_eq!(decode_base64("Zm9"), None);
+        assert_eq!(decode_base64("Zh=="), None);
+        assert_eq!(decode_base64("Z==="), None);
+    }
+}
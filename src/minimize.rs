@@ -0,0 +1,162 @@
+//! Shrinking of failing verifications into minimal reproducers, enabled by
+//! the `test-dependencies` feature.
+//!
+//! The scriptPubKey is shrunk by delta debugging over its instructions, and
+//! then each verification flag that isn't needed for the failure is cleared.
+//! The transaction is kept as is, since changing it would invalidate its
+//! signatures.
+
+use crate::{
+    script::{Instruction, Script},
+    verify::{verify_script, Error},
+};
+
+/// A verification that fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FailingCase {
+    pub script_pub_key: Script,
+    pub amount: i64,
+    pub tx_to: Vec<u8>,
+    pub n_in: u32,
+    pub flags: u32,
+    pub consensus_branch_id: u32,
+}
+
+impl FailingCase {
+    /// Runs the verification.
+    pub fn verify(&self) -> Result<(), Error> {
+        verify_script(
+            self.script_pub_key.as_bytes(),
+            self.amount,
+            &self.tx_to,
+            self.n_in,
+            self.flags,
+            self.consensus_branch_id,
+        )
+    }
+
+    /// Returns a case with a minimal scriptPubKey and flags that fails with
+    /// the same error, or `None` if this case doesn't fail.
+    pub fn minimize(&self) -> Option<FailingCase> {
+        let error = self.verify().err()?;
+        let fails = |case: &FailingCase| case.verify() == Err(error);
+
+        let mut case = self.clone();
+        case.script_pub_key = minimize_script(&self.script_pub_key, |script_pub_key| {
+            fails(&FailingCase {
+                script_pub_key: script_pub_key.clone(),
+                ..self.clone()
+            })
+        });
+        for bit in 0..u32::BITS {
+            let flag = 1 << bit;
+            if case.flags & flag != 0 {
+                let without = FailingCase {
+                    flags: case.flags & !flag,
+                    ..case.clone()
+                };
+                if fails(&without) {
+                    case = without;
+                }
+            }
+        }
+        Some(case)
+    }
+}
+
+/// Shrinks `script` to a smaller script for which `fails` still returns
+/// true, using the ddmin delta debugging algorithm over its instructions.
+///
+/// The result is 1-minimal: removing any single instruction from it makes
+/// `fails` return false. A truncated push at the end of `script` is treated
+/// as one instruction.
+pub fn minimize_script(script: &Script, mut fails: impl FnMut(&Script) -> bool) -> Script {
+    let mut units = instruction_bytes(script);
+    let mut granularity = 2;
+
+    while units.len() >= 2 {
+        let chunk = units.len().div_ceil(granularity);
+        let reduced = (0..units.len()).step_by(chunk).find_map(|start| {
+            let complement: Vec<_> = units[..start]
+                .iter()
+                .chain(units.get(start + chunk..).unwrap_or_default())
+                .copied()
+                .collect();
+            fails(&join(&complement)).then_some(complement)
+        });
+
+        match reduced {
+            Some(complement) => {
+                units = complement;
+                granularity = (granularity - 1).max(2);
+            }
+            None if granularity >= units.len() => break,
+            None => granularity = (granularity * 2).min(units.len()),
+        }
+    }
+
+    // A single remaining instruction may also be unnecessary.
+    if units.len() == 1 && fails(&Script::default()) {
+        units.clear();
+    }
+    join(&units)
+}
+
+/// Splits `script` into the bytes of each instruction.
+fn instruction_bytes(script: &Script) -> Vec<&[u8]> {
+    let mut offsets: Vec<_> = script
+        .instructions()
+        .map(|op| match op {
+            Ok(Instruction { offset, .. }) => offset,
+            Err(err) => err.offset,
+        })
+        .collect();
+    offsets.push(script.len());
+    offsets
+        .windows(2)
+        .map(|w| &script.as_bytes()[w[0]..w[1]])
+        .collect()
+}
+
+fn join(units: &[&[u8]]) -> Script {
+    Script(units.concat())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        opcode::Opcode,
+        tests::{SCRIPT_PUBKEY, SCRIPT_TX},
+    };
+
+    #[test]
+    fn minimizes_failures() {
+        // The P2PKH scriptPubKey, followed by OP_1 OP_0 OP_VERIFY OP_NOP.
+        let mut script_pub_key = Script(SCRIPT_PUBKEY.to_vec());
+        script_pub_key
+            .push_opcode(Opcode::OP_1)
+            .push_opcode(Opcode::OP_0)
+            .push_opcode(Opcode::OP_VERIFY)
+            .push_opcode(Opcode::OP_NOP);
+        let case = FailingCase {
+            script_pub_key,
+            amount: 212 * 100_000_000,
+            tx_to: SCRIPT_TX.to_vec(),
+            n_in: 0,
+            flags: 1 | 2,
+            consensus_branch_id: 0x2bb40e60,
+        };
+
+        let minimal = case.minimize().unwrap();
+        assert_eq!(minimal.verify(), Err(Error::ScriptInvalid));
+        assert_eq!(minimal.script_pub_key.len(), 1);
+        assert_eq!(minimal.flags, 0);
+
+        let passing = FailingCase {
+            script_pub_key: Script(SCRIPT_PUBKEY.to_vec()),
+            ..case
+        };
+        assert_eq!(passing.minimize(), None);
+    }
+}
@@ -0,0 +1,161 @@
+//! A programmable [`SignatureChecker`], enabled by the `test-dependencies`
+//! feature.
+//!
+//! A [`MockChecker`] accepts or rejects the (signature, public key) pairs it
+//! has been told about, and records every check, so spend-policy logic built
+//! on this crate can be unit-tested with [`eval_with_checker`] without
+//! generating real signatures.
+//!
+//! [`eval_with_checker`]: crate::eval::eval_with_checker
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::eval::SignatureChecker;
+
+/// A signature check made through a [`MockChecker`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Check {
+    /// The signature, including its sighash type byte.
+    pub sig: Vec<u8>,
+    /// The public key.
+    pub pubkey: Vec<u8>,
+    /// Whether the checker accepted the signature.
+    pub valid: bool,
+}
+
+/// A [`SignatureChecker`] that decides each check from the pairs and keys it
+/// was programmed with, and records the checks.
+///
+/// A check is decided by the first of these that applies:
+/// - the outcome set for its (signature, public key) pair,
+/// - the outcome set for its public key, with any signature,
+/// - the default outcome, which is to reject.
+#[derive(Clone, Debug, Default)]
+pub struct MockChecker {
+    pairs: BTreeMap<(Vec<u8>, Vec<u8>), bool>,
+    keys: BTreeMap<Vec<u8>, bool>,
+    default: bool,
+    checks: Vec<Check>,
+}
+
+impl MockChecker {
+    /// Creates a checker that rejects every signature.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a checker that accepts every signature, unless told
+    /// otherwise.
+    pub fn accepting() -> Self {
+        MockChecker {
+            default: true,
+            ..Self::default()
+        }
+    }
+
+    /// Accepts `sig` as a signature by `pubkey`.
+    pub fn accept(&mut self, sig: &[u8], pubkey: &[u8]) -> &mut Self {
+        self.pairs.insert((sig.to_vec(), pubkey.to_vec()), true);
+        self
+    }
+
+    /// Rejects `sig` as a signature by `pubkey`.
+    pub fn reject(&mut self, sig: &[u8], pubkey: &[u8]) -> &mut Self {
+        self.pairs.insert((sig.to_vec(), pubkey.to_vec()), false);
+        self
+    }
+
+    /// Accepts any signature by `pubkey` that isn't rejected as a pair.
+    pub fn accept_key(&mut self, pubkey: &[u8]) -> &mut Self {
+        self.keys.insert(pubkey.to_vec(), true);
+        self
+    }
+
+    /// Rejects any signature by `pubkey` that isn't accepted as a pair.
+    pub fn reject_key(&mut self, pubkey: &[u8]) -> &mut Self {
+        self.keys.insert(pubkey.to_vec(), false);
+        self
+    }
+
+    /// Returns the checks made so far, in order.
+    pub fn checks(&self) -> &[Check] {
+        &self.checks
+    }
+
+    /// Returns the distinct public keys whose signatures were accepted.
+    pub fn accepted_keys(&self) -> BTreeSet<&[u8]> {
+        self.checks
+            .iter()
+            .filter(|check| check.valid)
+            .map(|check| &check.pubkey[..])
+            .collect()
+    }
+
+    /// Forgets the checks made so far, keeping the programmed outcomes.
+    pub fn clear_checks(&mut self) {
+        self.checks.clear();
+    }
+}
+
+impl SignatureChecker for MockChecker {
+    fn check_sig(&mut self, sig: &[u8], pubkey: &[u8]) -> bool {
+        let valid = self
+            .pairs
+            .get(&(sig.to_vec(), pubkey.to_vec()))
+            .or_else(|| self.keys.get(pubkey))
+            .copied()
+            .unwrap_or(self.default);
+        self.checks.push(Check {
+            sig: sig.to_vec(),
+            pubkey: pubkey.to_vec(),
+            valid,
+        });
+        valid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{eval::eval_with_checker, script_error::ScriptError};
+
+    #[test]
+    fn programs_and_records_checks() {
+        let keys = [[2u8; 33], [3; 33], [4; 33]];
+        let multisig = crate::script!(2 [keys[0]] [keys[1]] [keys[2]] 3 OP_CHECKMULTISIG);
+        let stack: [&[u8]; 3] = [&[], &[1], &[2]];
+
+        let mut checker = MockChecker::new();
+        checker.accept(&[2], &keys[2]).accept_key(&keys[0]);
+        assert_eq!(
+            eval_with_checker(multisig.as_bytes(), &stack, 0, &mut checker),
+            Ok(())
+        );
+        // Signatures are checked from the top of the stack, against the keys
+        // from last to first.
+        let pubkeys: Vec<_> = checker.checks().iter().map(|c| c.pubkey[0]).collect();
+        assert_eq!(pubkeys, [4, 3, 2]);
+        assert_eq!(
+            checker.accepted_keys(),
+            BTreeSet::from([&keys[0][..], &keys[2][..]])
+        );
+
+        // A rejected pair overrides its key, and the default is to reject.
+        checker.clear_checks();
+        checker.reject(&[1], &keys[0]);
+        assert_eq!(
+            eval_with_checker(multisig.as_bytes(), &stack, 0, &mut checker),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(checker.checks().len(), 3);
+        assert!(checker.checks().iter().skip(1).all(|check| !check.valid));
+
+        let mut accepting = MockChecker::accepting();
+        accepting.reject_key(&keys[2]);
+        assert_eq!(
+            eval_with_checker(multisig.as_bytes(), &stack, 0, &mut accepting),
+            Ok(())
+        );
+        assert_eq!(accepting.accepted_keys().len(), 2);
+    }
+}
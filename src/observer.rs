@@ -0,0 +1,136 @@
+//! Hooks for recording metrics about verifications.
+//!
+//! Install a [`VerificationObserver`] with [`set_observer`] to be called after
+//! every verification made through the [`verify`](crate::verify) functions and
+//! [`PrecomputedTx::verify`](crate::precomputed::PrecomputedTx::verify), including those run by
+//! the `async_verify` functions and [`VerificationPool`](crate::pool::VerificationPool).
+
+use std::{
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    script::Script,
+    script_error::ScriptError,
+    standard::ScriptType,
+    templates,
+    verify::{self, Error},
+};
+
+/// The details of a finished verification.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerificationEvent {
+    /// How long the verification took.
+    pub duration: Duration,
+    /// The result of the verification.
+    pub result: Result<(), Error>,
+    /// The interpreter error that made the script fail, if the result is
+    /// [`Error::ScriptInvalid`].
+    pub script_error: Option<ScriptError>,
+    /// The script verification flags.
+    pub flags: u32,
+    /// The type of the scriptPubKey being spent, if it was passed directly
+    /// rather than as part of all the previous outputs. This includes
+    /// registered [templates](crate::templates).
+    pub script_type: Option<ScriptType>,
+}
+
+/// A recipient of [`VerificationEvent`]s.
+///
+/// This is called on the thread that ran the verification, so it should be
+/// cheap, for example by incrementing counters.
+pub trait VerificationObserver: Send + Sync {
+    /// Called after each verification.
+    fn on_verification(&self, event: &VerificationEvent);
+}
+
+static OBSERVER: RwLock<Option<Arc<dyn VerificationObserver>>> = RwLock::new(None);
+
+/// Installs `observer`, replacing any previously installed observer.
+pub fn set_observer(observer: Arc<dyn VerificationObserver>) {
+    *OBSERVER.write().expect("lock is not poisoned") = Some(observer);
+}
+
+/// Removes the installed observer, if any.
+pub fn clear_observer() {
+    *OBSERVER.write().expect("lock is not poisoned") = None;
+}
+
+fn observer() -> Option<Arc<dyn VerificationObserver>> {
+    OBSERVER.read().expect("lock is not poisoned").clone()
+}
+
+/// Runs `verify`, reporting it to the installed observer.
+///
+/// When no observer is installed, this only adds a lock acquisition.
+pub(crate) fn observe(
+    flags: u32,
+    script_pub_key: Option<&[u8]>,
+    verify: impl FnOnce() -> Result<(), Error>,
+) -> Result<(), Error> {
+    let Some(observer) = observer() else {
+        return verify();
+    };
+
+    let start = Instant::now();
+    let result = verify();
+    let duration = start.elapsed();
+    // Every `verify` calls one of the C verify functions on this thread, which
+    // leave the interpreter's error behind.
+    let script_error = (result == Err(Error::ScriptInvalid)).then(verify::last_script_error);
+
+    let script_type =
+        script_pub_key.map(|script_pub_key| templates::script_type(&Script::from(script_pub_key)));
+    observer.on_verification(&VerificationEvent {
+        duration,
+        result,
+        script_error,
+        flags,
+        script_type,
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<VerificationEvent>>);
+
+    impl VerificationObserver for Recorder {
+        fn on_verification(&self, event: &VerificationEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn observes_verifications() {
+        let recorder = Arc::new(Recorder::default());
+        set_observer(recorder.clone());
+
+        let coin = i64::pow(10, 8);
+        crate::verify::verify_script(&SCRIPT_PUBKEY, 212 * coin, &SCRIPT_TX, 0, 1, 0x2bb40e60)
+            .unwrap();
+        // The wrong amount changes the signature hash.
+        crate::verify::verify_script(&SCRIPT_PUBKEY, 212 * coin - 1, &SCRIPT_TX, 0, 3, 0x2bb40e60)
+            .unwrap_err();
+        clear_observer();
+
+        // Other tests may verify concurrently while the observer is installed.
+        let events = recorder.0.lock().unwrap();
+        assert!(events.iter().any(|event| event.result.is_ok()
+            && event.script_error.is_none()
+            && event.flags == 1
+            && event.script_type == Some(ScriptType::PubKeyHash)));
+        assert!(events
+            .iter()
+            .any(|event| event.result == Err(Error::ScriptInvalid)
+                && event.script_error == Some(ScriptError::EvalFalse)
+                && event.flags == 3));
+    }
+}
@@ -0,0 +1,350 @@
+//! Script opcodes, as defined in `depend/zcash/src/script/script.h`.
+
+use std::fmt;
+
+use crate::flags::VerificationFlags;
+
+/// A script opcode.
+///
+/// This is a thin wrapper around the opcode byte, so that every possible byte
+/// value (including unassigned ones) can be represented. The named opcodes are
+/// available as associated constants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Opcode(pub u8);
+
+impl Opcode {
+    // push value
+    pub const OP_0: Opcode = Opcode(0x00);
+    pub const OP_FALSE: Opcode = Opcode::OP_0;
+    pub const OP_PUSHDATA1: Opcode = Opcode(0x4c);
+    pub const OP_PUSHDATA2: Opcode = Opcode(0x4d);
+    pub const OP_PUSHDATA4: Opcode = Opcode(0x4e);
+    pub const OP_1NEGATE: Opcode = Opcode(0x4f);
+    pub const OP_RESERVED: Opcode = Opcode(0x50);
+    pub const OP_1: Opcode = Opcode(0x51);
+    pub const OP_TRUE: Opcode = Opcode::OP_1;
+    pub const OP_2: Opcode = Opcode(0x52);
+    pub const OP_3: Opcode = Opcode(0x53);
+    pub const OP_4: Opcode = Opcode(0x54);
+    pub const OP_5: Opcode = Opcode(0x55);
+    pub const OP_6: Opcode = Opcode(0x56);
+    pub const OP_7: Opcode = Opcode(0x57);
+    pub const OP_8: Opcode = Opcode(0x58);
+    pub const OP_9: Opcode = Opcode(0x59);
+    pub const OP_10: Opcode = Opcode(0x5a);
+    pub const OP_11: Opcode = Opcode(0x5b);
+    pub const OP_12: Opcode = Opcode(0x5c);
+    pub const OP_13: Opcode = Opcode(0x5d);
+    pub const OP_14: Opcode = Opcode(0x5e);
+    pub const OP_15: Opcode = Opcode(0x5f);
+    pub const OP_16: Opcode = Opcode(0x60);
+
+    // control
+    pub const OP_NOP: Opcode = Opcode(0x61);
+    pub const OP_VER: Opcode = Opcode(0x62);
+    pub const OP_IF: Opcode = Opcode(0x63);
+    pub const OP_NOTIF: Opcode = Opcode(0x64);
+    pub const OP_VERIF: Opcode = Opcode(0x65);
+    pub const OP_VERNOTIF: Opcode = Opcode(0x66);
+    pub const OP_ELSE: Opcode = Opcode(0x67);
+    pub const OP_ENDIF: Opcode = Opcode(0x68);
+    pub const OP_VERIFY: Opcode = Opcode(0x69);
+    pub const OP_RETURN: Opcode = Opcode(0x6a);
+
+    // stack ops
+    pub const OP_TOALTSTACK: Opcode = Opcode(0x6b);
+    pub const OP_FROMALTSTACK: Opcode = Opcode(0x6c);
+    pub const OP_2DROP: Opcode = Opcode(0x6d);
+    pub const OP_2DUP: Opcode = Opcode(0x6e);
+    pub const OP_3DUP: Opcode = Opcode(0x6f);
+    pub const OP_2OVER: Opcode = Opcode(0x70);
+    pub const OP_2ROT: Opcode = Opcode(0x71);
+    pub const OP_2SWAP: Opcode = Opcode(0x72);
+    pub const OP_IFDUP: Opcode = Opcode(0x73);
+    pub const OP_DEPTH: Opcode = Opcode(0x74);
+    pub const OP_DROP: Opcode = Opcode(0x75);
+    pub const OP_DUP: Opcode = Opcode(0x76);
+    pub const OP_NIP: Opcode = Opcode(0x77);
+    pub const OP_OVER: Opcode = Opcode(0x78);
+    pub const OP_PICK: Opcode = Opcode(0x79);
+    pub const OP_ROLL: Opcode = Opcode(0x7a);
+    pub const OP_ROT: Opcode = Opcode(0x7b);
+    pub const OP_SWAP: Opcode = Opcode(0x7c);
+    pub const OP_TUCK: Opcode = Opcode(0x7d);
+
+    // splice ops
+    pub const OP_CAT: Opcode = Opcode(0x7e);
+    pub const OP_SUBSTR: Opcode = Opcode(0x7f);
+    pub const OP_LEFT: Opcode = Opcode(0x80);
+    pub const OP_RIGHT: Opcode = Opcode(0x81);
+    pub const OP_SIZE: Opcode = Opcode(0x82);
+
+    // bit logic
+    pub const OP_INVERT: Opcode = Opcode(0x83);
+    pub const OP_AND: Opcode = Opcode(0x84);
+    pub const OP_OR: Opcode = Opcode(0x85);
+    pub const OP_XOR: Opcode = Opcode(0x86);
+    pub const OP_EQUAL: Opcode = Opcode(0x87);
+    pub const OP_EQUALVERIFY: Opcode = Opcode(0x88);
+    pub const OP_RESERVED1: Opcode = Opcode(0x89);
+    pub const OP_RESERVED2: Opcode = Opcode(0x8a);
+
+    // numeric
+    pub const OP_1ADD: Opcode = Opcode(0x8b);
+    pub const OP_1SUB: Opcode = Opcode(0x8c);
+    pub const OP_2MUL: Opcode = Opcode(0x8d);
+    pub const OP_2DIV: Opcode = Opcode(0x8e);
+    pub const OP_NEGATE: Opcode = Opcode(0x8f);
+    pub const OP_ABS: Opcode = Opcode(0x90);
+    pub const OP_NOT: Opcode = Opcode(0x91);
+    pub const OP_0NOTEQUAL: Opcode = Opcode(0x92);
+    pub const OP_ADD: Opcode = Opcode(0x93);
+    pub const OP_SUB: Opcode = Opcode(0x94);
+    pub const OP_MUL: Opcode = Opcode(0x95);
+    pub const OP_DIV: Opcode = Opcode(0x96);
+    pub const OP_MOD: Opcode = Opcode(0x97);
+    pub const OP_LSHIFT: Opcode = Opcode(0x98);
+    pub const OP_RSHIFT: Opcode = Opcode(0x99);
+    pub const OP_BOOLAND: Opcode = Opcode(0x9a);
+    pub const OP_BOOLOR: Opcode = Opcode(0x9b);
+    pub const OP_NUMEQUAL: Opcode = Opcode(0x9c);
+    pub const OP_NUMEQUALVERIFY: Opcode = Opcode(0x9d);
+    pub const OP_NUMNOTEQUAL: Opcode = Opcode(0x9e);
+    pub const OP_LESSTHAN: Opcode = Opcode(0x9f);
+    pub const OP_GREATERTHAN: Opcode = Opcode(0xa0);
+    pub const OP_LESSTHANOREQUAL: Opcode = Opcode(0xa1);
+    pub const OP_GREATERTHANOREQUAL: Opcode = Opcode(0xa2);
+    pub const OP_MIN: Opcode = Opcode(0xa3);
+    pub const OP_MAX: Opcode = Opcode(0xa4);
+    pub const OP_WITHIN: Opcode = Opcode(0xa5);
+
+    // crypto
+    pub const OP_RIPEMD160: Opcode = Opcode(0xa6);
+    pub const OP_SHA1: Opcode = Opcode(0xa7);
+    pub const OP_SHA256: Opcode = Opcode(0xa8);
+    pub const OP_HASH160: Opcode = Opcode(0xa9);
+    pub const OP_HASH256: Opcode = Opcode(0xaa);
+    pub const OP_CODESEPARATOR: Opcode = Opcode(0xab);
+    pub const OP_CHECKSIG: Opcode = Opcode(0xac);
+    pub const OP_CHECKSIGVERIFY: Opcode = Opcode(0xad);
+    pub const OP_CHECKMULTISIG: Opcode = Opcode(0xae);
+    pub const OP_CHECKMULTISIGVERIFY: Opcode = Opcode(0xaf);
+
+    // expansion
+    pub const OP_NOP1: Opcode = Opcode(0xb0);
+    pub const OP_NOP2: Opcode = Opcode(0xb1);
+    pub const OP_CHECKLOCKTIMEVERIFY: Opcode = Opcode::OP_NOP2;
+    pub const OP_NOP3: Opcode = Opcode(0xb2);
+    /// BIP 112's name for `OP_NOP3`, which Zcash hasn't activated.
+    pub const OP_CHECKSEQUENCEVERIFY: Opcode = Opcode::OP_NOP3;
+    pub const OP_NOP4: Opcode = Opcode(0xb3);
+    pub const OP_NOP5: Opcode = Opcode(0xb4);
+    pub const OP_NOP6: Opcode = Opcode(0xb5);
+    pub const OP_NOP7: Opcode = Opcode(0xb6);
+    pub const OP_NOP8: Opcode = Opcode(0xb7);
+    pub const OP_NOP9: Opcode = Opcode(0xb8);
+    pub const OP_NOP10: Opcode = Opcode(0xb9);
+
+    pub const OP_INVALIDOPCODE: Opcode = Opcode(0xff);
+
+    /// Returns the name of this opcode, matching `GetOpName` in zcashd.
+    ///
+    /// Direct pushes (`0x01..=0x4b`) and unassigned opcodes are named
+    /// `OP_UNKNOWN`, as in zcashd.
+    pub fn name(self) -> &'static str {
+        match self.0 {
+            // push value
+            0x00 => "0",
+            0x4c => "OP_PUSHDATA1",
+            0x4d => "OP_PUSHDATA2",
+            0x4e => "OP_PUSHDATA4",
+            0x4f => "-1",
+            0x50 => "OP_RESERVED",
+            0x51 => "1",
+            0x52 => "2",
+            0x53 => "3",
+            0x54 => "4",
+            0x55 => "5",
+            0x56 => "6",
+            0x57 => "7",
+            0x58 => "8",
+            0x59 => "9",
+            0x5a => "10",
+            0x5b => "11",
+            0x5c => "12",
+            0x5d => "13",
+            0x5e => "14",
+            0x5f => "15",
+            0x60 => "16",
+
+            // control
+            0x61 => "OP_NOP",
+            0x62 => "OP_VER",
+            0x63 => "OP_IF",
+            0x64 => "OP_NOTIF",
+            0x65 => "OP_VERIF",
+            0x66 => "OP_VERNOTIF",
+            0x67 => "OP_ELSE",
+            0x68 => "OP_ENDIF",
+            0x69 => "OP_VERIFY",
+            0x6a => "OP_RETURN",
+
+            // stack ops
+            0x6b => "OP_TOALTSTACK",
+            0x6c => "OP_FROMALTSTACK",
+            0x6d => "OP_2DROP",
+            0x6e => "OP_2DUP",
+            0x6f => "OP_3DUP",
+            0x70 => "OP_2OVER",
+            0x71 => "OP_2ROT",
+            0x72 => "OP_2SWAP",
+            0x73 => "OP_IFDUP",
+            0x74 => "OP_DEPTH",
+            0x75 => "OP_DROP",
+            0x76 => "OP_DUP",
+            0x77 => "OP_NIP",
+            0x78 => "OP_OVER",
+            0x79 => "OP_PICK",
+            0x7a => "OP_ROLL",
+            0x7b => "OP_ROT",
+            0x7c => "OP_SWAP",
+            0x7d => "OP_TUCK",
+
+            // splice ops
+            0x7e => "OP_CAT",
+            0x7f => "OP_SUBSTR",
+            0x80 => "OP_LEFT",
+            0x81 => "OP_RIGHT",
+            0x82 => "OP_SIZE",
+
+            // bit logic
+            0x83 => "OP_INVERT",
+            0x84 => "OP_AND",
+            0x85 => "OP_OR",
+            0x86 => "OP_XOR",
+            0x87 => "OP_EQUAL",
+            0x88 => "OP_EQUALVERIFY",
+            0x89 => "OP_RESERVED1",
+            0x8a => "OP_RESERVED2",
+
+            // numeric
+            0x8b => "OP_1ADD",
+            0x8c => "OP_1SUB",
+            0x8d => "OP_2MUL",
+            0x8e => "OP_2DIV",
+            0x8f => "OP_NEGATE",
+            0x90 => "OP_ABS",
+            0x91 => "OP_NOT",
+            0x92 => "OP_0NOTEQUAL",
+            0x93 => "OP_ADD",
+            0x94 => "OP_SUB",
+            0x95 => "OP_MUL",
+            0x96 => "OP_DIV",
+            0x97 => "OP_MOD",
+            0x98 => "OP_LSHIFT",
+            0x99 => "OP_RSHIFT",
+            0x9a => "OP_BOOLAND",
+            0x9b => "OP_BOOLOR",
+            0x9c => "OP_NUMEQUAL",
+            0x9d => "OP_NUMEQUALVERIFY",
+            0x9e => "OP_NUMNOTEQUAL",
+            0x9f => "OP_LESSTHAN",
+            0xa0 => "OP_GREATERTHAN",
+            0xa1 => "OP_LESSTHANOREQUAL",
+            0xa2 => "OP_GREATERTHANOREQUAL",
+            0xa3 => "OP_MIN",
+            0xa4 => "OP_MAX",
+            0xa5 => "OP_WITHIN",
+
+            // crypto
+            0xa6 => "OP_RIPEMD160",
+            0xa7 => "OP_SHA1",
+            0xa8 => "OP_SHA256",
+            0xa9 => "OP_HASH160",
+            0xaa => "OP_HASH256",
+            0xab => "OP_CODESEPARATOR",
+            0xac => "OP_CHECKSIG",
+            0xad => "OP_CHECKSIGVERIFY",
+            0xae => "OP_CHECKMULTISIG",
+            0xaf => "OP_CHECKMULTISIGVERIFY",
+
+            // expansion
+            0xb0 => "OP_NOP1",
+            0xb1 => "OP_NOP2",
+            0xb2 => "OP_NOP3",
+            0xb3 => "OP_NOP4",
+            0xb4 => "OP_NOP5",
+            0xb5 => "OP_NOP6",
+            0xb6 => "OP_NOP7",
+            0xb7 => "OP_NOP8",
+            0xb8 => "OP_NOP9",
+            0xb9 => "OP_NOP10",
+
+            0xff => "OP_INVALIDOPCODE",
+
+            _ => "OP_UNKNOWN",
+        }
+    }
+
+    /// Returns true if this opcode pushes data from the script itself
+    /// (`OP_0`, direct pushes, and `OP_PUSHDATA1/2/4`).
+    pub fn is_push_data(self) -> bool {
+        self <= Opcode::OP_PUSHDATA4
+    }
+
+    /// Returns true if this is a NOP reserved for soft-fork upgrades under
+    /// `flags`: `OP_NOP1` or `OP_NOP3` to `OP_NOP10`, or `OP_NOP2` if `flags`
+    /// doesn't enable `OP_CHECKLOCKTIMEVERIFY`.
+    pub fn is_upgradable_nop(self, flags: VerificationFlags) -> bool {
+        match self {
+            Opcode::OP_NOP1 => true,
+            Opcode::OP_NOP2 => !flags.contains(VerificationFlags::CHECKLOCKTIMEVERIFY),
+            _ => (Opcode::OP_NOP3..=Opcode::OP_NOP10).contains(&self),
+        }
+    }
+
+    /// Returns true if this is one of `OP_1` through `OP_16`.
+    pub fn is_small_integer(self) -> bool {
+        self >= Opcode::OP_1 && self <= Opcode::OP_16
+    }
+
+    /// Decodes a small integer opcode (`OP_0`, `OP_1` through `OP_16`),
+    /// matching `CScript::DecodeOP_N`.
+    pub fn decode_op_n(self) -> Option<u8> {
+        if self == Opcode::OP_0 {
+            Some(0)
+        } else if self.is_small_integer() {
+            Some(self.0 - (Opcode::OP_1.0 - 1))
+        } else {
+            None
+        }
+    }
+
+    /// Encodes `n` (0 through 16) as a small integer opcode, matching
+    /// `CScript::EncodeOP_N`.
+    pub fn encode_op_n(n: u8) -> Option<Opcode> {
+        match n {
+            0 => Some(Opcode::OP_0),
+            1..=16 => Some(Opcode(Opcode::OP_1.0 + n - 1)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Opcode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl From<u8> for Opcode {
+    fn from(byte: u8) -> Self {
+        Opcode(byte)
+    }
+}
+
+impl From<Opcode> for u8 {
+    fn from(opcode: Opcode) -> Self {
+        opcode.0
+    }
+}
@@ -0,0 +1,474 @@
+//! A small policy language for transparent spending conditions, in the style
+//! of miniscript, with a compiler to P2SH redeem scripts and a satisfier that
+//! builds the scriptSig.
+//!
+//! Policies are written as `and(pk(A), or(pk(B), after(1000)))`, where keys
+//! are hex-encoded public keys. The supported fragments are:
+//!
+//! - `pk(KEY)`: a signature for `KEY`
+//! - `multi(K, KEY, ...)`: signatures for `K` of the keys
+//! - `after(N)`: a transaction `nLockTime` of at least `N`, which is a height
+//!   or a time as described in [`LockTime`]
+//! - `and(X, Y)`: both `X` and `Y`
+//! - `or(X, Y)`: either `X` or `Y`
+//!
+//! Zcash has not activated BIP 68/112, so relative lock times (miniscript's
+//! `older`) are not supported.
+
+use std::{fmt, str::FromStr};
+
+use crate::{
+    lock_time::LockTime,
+    opcode::Opcode,
+    script::{Script, MAX_OPS_PER_SCRIPT, MAX_PUBKEYS_PER_MULTISIG, MAX_SCRIPT_ELEMENT_SIZE},
+    standard::{hash160, is_valid_pubkey_size, MAX_P2SH_SIGOPS},
+};
+
+/// A spending policy.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Policy {
+    /// A signature for the public key.
+    Key(Vec<u8>),
+    /// Signatures for the given number of the public keys.
+    Multi(usize, Vec<Vec<u8>>),
+    /// A transaction lock time of at least the given value.
+    After(LockTime),
+    /// Both policies.
+    And(Box<Policy>, Box<Policy>),
+    /// Either policy.
+    Or(Box<Policy>, Box<Policy>),
+}
+
+/// An error parsing or compiling a policy.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PolicyError {
+    /// The policy is not well formed.
+    Syntax,
+    /// The policy uses a fragment that doesn't exist, or with the wrong
+    /// number of arguments.
+    UnknownFragment(String),
+    /// The policy uses `older`, which Zcash doesn't support.
+    RelativeLockTime,
+    /// A public key is not hex-encoded, or has the wrong size.
+    InvalidKey,
+    /// A number is not a valid decimal integer, or a lock time is zero.
+    InvalidNumber,
+    /// A `multi` threshold is zero or larger than its number of keys, or it
+    /// has more than [`MAX_PUBKEYS_PER_MULTISIG`] keys.
+    InvalidThreshold,
+    /// The compiled script is larger than a P2SH redeem script can be.
+    TooLarge,
+    /// The compiled script has more than [`MAX_P2SH_SIGOPS`] signature
+    /// operations.
+    TooManySigOps,
+    /// The compiled script has more than [`MAX_OPS_PER_SCRIPT`] non-push
+    /// opcodes.
+    TooManyOps,
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::Syntax => write!(f, "malformed policy"),
+            PolicyError::UnknownFragment(name) => write!(f, "unknown policy fragment {}", name),
+            PolicyError::RelativeLockTime => write!(f, "relative lock times are not supported"),
+            PolicyError::InvalidKey => write!(f, "invalid public key"),
+            PolicyError::InvalidNumber => write!(f, "invalid number"),
+            PolicyError::InvalidThreshold => write!(f, "invalid multisig threshold"),
+            PolicyError::TooLarge => write!(f, "redeem script is too large"),
+            PolicyError::TooManySigOps => write!(f, "redeem script has too many sigops"),
+            PolicyError::TooManyOps => write!(f, "redeem script has too many opcodes"),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+/// Provides the signatures and lock time checks needed to satisfy a policy.
+pub trait Satisfier {
+    /// Returns a signature for `pubkey`, with its hash type appended.
+    fn signature(&self, pubkey: &[u8]) -> Option<Vec<u8>>;
+
+    /// Returns true if the spending transaction meets the lock time, as
+    /// checked by [`check_lock_time`](crate::lock_time::check_lock_time).
+    fn check_after(&self, lock_time: LockTime) -> bool;
+}
+
+impl Policy {
+    /// Compiles the policy to a P2SH redeem script.
+    ///
+    /// The script only uses opcodes enabled by consensus, and meets zcashd's
+    /// standardness limits for P2SH redeem scripts.
+    pub fn compile(&self) -> Result<Script, PolicyError> {
+        let mut script = Script::default();
+        self.compile_bool(&mut script);
+
+        if script.len() > MAX_SCRIPT_ELEMENT_SIZE {
+            return Err(PolicyError::TooLarge);
+        }
+        if script.sig_op_count(true) > MAX_P2SH_SIGOPS {
+            return Err(PolicyError::TooManySigOps);
+        }
+        let ops = script
+            .instructions()
+            .filter(|op| op.as_ref().is_ok_and(|op| op.opcode > Opcode::OP_16))
+            .count();
+        if ops > MAX_OPS_PER_SCRIPT {
+            return Err(PolicyError::TooManyOps);
+        }
+        Ok(script)
+    }
+
+    /// Compiles the policy and returns the P2SH scriptPubKey paying to it.
+    pub fn script_pub_key(&self) -> Result<Script, PolicyError> {
+        let redeem_script = self.compile()?;
+        let mut script = Script::default();
+        script
+            .push_opcode(Opcode::OP_HASH160)
+            .push_slice(&hash160(redeem_script.as_bytes()))
+            .push_opcode(Opcode::OP_EQUAL);
+        Ok(script)
+    }
+
+    /// Builds the scriptSig spending the P2SH output for this policy, choosing
+    /// the smallest satisfaction available from `satisfier`.
+    ///
+    /// Returns `None` if the policy can't be satisfied, or doesn't compile.
+    pub fn satisfy<S: Satisfier>(&self, satisfier: &S) -> Option<Script> {
+        let redeem_script = self.compile().ok()?;
+        let mut script_sig = Script::default();
+        for element in self.witness(satisfier)? {
+            push_element(&mut script_sig, &element);
+        }
+        script_sig.push_slice(redeem_script.as_bytes());
+        Some(script_sig)
+    }
+
+    /// Appends the script for this policy, leaving true on the stack if it is
+    /// satisfied.
+    fn compile_bool(&self, script: &mut Script) {
+        match self {
+            Policy::Key(key) => {
+                script.push_slice(key).push_opcode(Opcode::OP_CHECKSIG);
+            }
+            Policy::Multi(k, keys) => {
+                push_multi(script, *k, keys).push_opcode(Opcode::OP_CHECKMULTISIG);
+            }
+            // OP_CHECKLOCKTIMEVERIFY leaves its nonzero operand on the stack.
+            Policy::After(lock_time) => {
+                script
+                    .push_num(lock_time.to_consensus().into())
+                    .push_opcode(Opcode::OP_CHECKLOCKTIMEVERIFY);
+            }
+            Policy::And(x, y) => {
+                x.compile_verify(script);
+                y.compile_bool(script);
+            }
+            Policy::Or(x, y) => {
+                script.push_opcode(Opcode::OP_IF);
+                x.compile_bool(script);
+                script.push_opcode(Opcode::OP_ELSE);
+                y.compile_bool(script);
+                script.push_opcode(Opcode::OP_ENDIF);
+            }
+        }
+    }
+
+    /// Appends the script for this policy, failing if it isn't satisfied and
+    /// leaving nothing on the stack otherwise.
+    fn compile_verify(&self, script: &mut Script) {
+        match self {
+            Policy::Key(key) => {
+                script
+                    .push_slice(key)
+                    .push_opcode(Opcode::OP_CHECKSIGVERIFY);
+            }
+            Policy::Multi(k, keys) => {
+                push_multi(script, *k, keys).push_opcode(Opcode::OP_CHECKMULTISIGVERIFY);
+            }
+            Policy::After(lock_time) => {
+                script
+                    .push_num(lock_time.to_consensus().into())
+                    .push_opcode(Opcode::OP_CHECKLOCKTIMEVERIFY)
+                    .push_opcode(Opcode::OP_DROP);
+            }
+            Policy::And(x, y) => {
+                x.compile_verify(script);
+                y.compile_verify(script);
+            }
+            Policy::Or(x, y) => {
+                script.push_opcode(Opcode::OP_IF);
+                x.compile_verify(script);
+                script.push_opcode(Opcode::OP_ELSE);
+                y.compile_verify(script);
+                script.push_opcode(Opcode::OP_ENDIF);
+            }
+        }
+    }
+
+    /// Returns the stack elements satisfying this policy, in the order the
+    /// scriptSig pushes them.
+    fn witness<S: Satisfier>(&self, satisfier: &S) -> Option<Vec<Vec<u8>>> {
+        match self {
+            Policy::Key(key) => Some(vec![satisfier.signature(key)?]),
+            Policy::Multi(k, keys) => {
+                // OP_CHECKMULTISIG pops an extra dummy element, and needs the
+                // signatures in the same order as the keys.
+                let sigs: Vec<_> = keys
+                    .iter()
+                    .filter_map(|key| satisfier.signature(key))
+                    .take(*k)
+                    .collect();
+                (sigs.len() == *k).then(|| [vec![vec![]], sigs].concat())
+            }
+            Policy::After(lock_time) => satisfier.check_after(*lock_time).then(Vec::new),
+            // The script checks `x` first, so its elements must be on top.
+            Policy::And(x, y) => Some([y.witness(satisfier)?, x.witness(satisfier)?].concat()),
+            Policy::Or(x, y) => {
+                let x = x.witness(satisfier).map(|w| [w, vec![vec![1]]].concat());
+                let y = y.witness(satisfier).map(|w| [w, vec![vec![]]].concat());
+                match (x, y) {
+                    (Some(x), Some(y)) if witness_size(&y) < witness_size(&x) => Some(y),
+                    (Some(x), _) => Some(x),
+                    (None, y) => y,
+                }
+            }
+        }
+    }
+}
+
+fn push_multi<'a>(script: &'a mut Script, k: usize, keys: &[Vec<u8>]) -> &'a mut Script {
+    script.push_num(k as i64);
+    for key in keys {
+        script.push_slice(key);
+    }
+    script.push_num(keys.len() as i64)
+}
+
+/// Pushes a stack element with the smallest encoding, as `MINIMALDATA`
+/// requires.
+fn push_element(script: &mut Script, element: &[u8]) {
+    match element {
+        [] => script.push_opcode(Opcode::OP_0),
+        [n @ 1..=16] => script.push_num((*n).into()),
+        _ => script.push_slice(element),
+    };
+}
+
+fn witness_size(witness: &[Vec<u8>]) -> usize {
+    witness
+        .iter()
+        .map(|element| {
+            let mut script = Script::default();
+            push_element(&mut script, element);
+            script.len()
+        })
+        .sum()
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Policy::Key(key) => write!(f, "pk({})", hex::encode(key)),
+            Policy::Multi(k, keys) => {
+                write!(f, "multi({}", k)?;
+                for key in keys {
+                    write!(f, ",{}", hex::encode(key))?;
+                }
+                write!(f, ")")
+            }
+            Policy::After(lock_time) => write!(f, "after({})", lock_time.to_consensus()),
+            Policy::And(x, y) => write!(f, "and({},{})", x, y),
+            Policy::Or(x, y) => write!(f, "or({},{})", x, y),
+        }
+    }
+}
+
+impl FromStr for Policy {
+    type Err = PolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (tree, rest) = Tree::parse(s)?;
+        if !rest.trim().is_empty() {
+            return Err(PolicyError::Syntax);
+        }
+        Policy::from_tree(&tree)
+    }
+}
+
+impl Policy {
+    fn from_tree(tree: &Tree<'_>) -> Result<Self, PolicyError> {
+        match (tree.name, tree.args.as_slice()) {
+            ("pk", [key]) => Ok(Policy::Key(key.key()?)),
+            ("multi", [k, keys @ ..]) => {
+                let k = k.number()?;
+                let keys = keys.iter().map(Tree::key).collect::<Result<Vec<_>, _>>()?;
+                if k == 0 || k > keys.len() || keys.len() > MAX_PUBKEYS_PER_MULTISIG {
+                    return Err(PolicyError::InvalidThreshold);
+                }
+                Ok(Policy::Multi(k, keys))
+            }
+            ("after", [n]) => match u32::try_from(n.number()?) {
+                Ok(n) if n > 0 => Ok(Policy::After(LockTime::from_consensus(n))),
+                _ => Err(PolicyError::InvalidNumber),
+            },
+            ("older", _) => Err(PolicyError::RelativeLockTime),
+            ("and", [x, y]) => Ok(Policy::And(
+                Box::new(Policy::from_tree(x)?),
+                Box::new(Policy::from_tree(y)?),
+            )),
+            ("or", [x, y]) => Ok(Policy::Or(
+                Box::new(Policy::from_tree(x)?),
+                Box::new(Policy::from_tree(y)?),
+            )),
+            (name, _) => Err(PolicyError::UnknownFragment(name.to_string())),
+        }
+    }
+}
+
+/// A parsed `name(arg, ...)` expression.
+struct Tree<'a> {
+    name: &'a str,
+    args: Vec<Tree<'a>>,
+}
+
+impl<'a> Tree<'a> {
+    /// Parses an expression from the start of `s`, returning the rest.
+    fn parse(s: &'a str) -> Result<(Self, &'a str), PolicyError> {
+        let s = s.trim_start();
+        let end = s.find(['(', ')', ',']).unwrap_or(s.len());
+        let name = s[..end].trim();
+        if name.is_empty() {
+            return Err(PolicyError::Syntax);
+        }
+        let mut rest = &s[end..];
+        let mut args = vec![];
+
+        if let Some(mut inner) = rest.strip_prefix('(') {
+            loop {
+                let (arg, after) = Tree::parse(inner)?;
+                args.push(arg);
+                let after = after.trim_start();
+                if let Some(after) = after.strip_prefix(',') {
+                    inner = after;
+                } else if let Some(after) = after.strip_prefix(')') {
+                    rest = after;
+                    break;
+                } else {
+                    return Err(PolicyError::Syntax);
+                }
+            }
+        }
+        Ok((Tree { name, args }, rest))
+    }
+
+    fn leaf(&self) -> Result<&'a str, PolicyError> {
+        if self.args.is_empty() {
+            Ok(self.name)
+        } else {
+            Err(PolicyError::Syntax)
+        }
+    }
+
+    fn key(&self) -> Result<Vec<u8>, PolicyError> {
+        hex::decode(self.leaf()?)
+            .ok()
+            .filter(|key| is_valid_pubkey_size(key))
+            .ok_or(PolicyError::InvalidKey)
+    }
+
+    fn number(&self) -> Result<usize, PolicyError> {
+        self.leaf()?.parse().map_err(|_| PolicyError::InvalidNumber)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::lock_time::{check_lock_time, SEQUENCE_FINAL};
+
+    const KEY_A: &str = "031f54b095eae066d96b2557c1f99e40e967978a5fd117465dbec0986ca74201a6";
+    const KEY_B: &str = "02c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5";
+
+    struct TestSatisfier {
+        signatures: BTreeMap<Vec<u8>, Vec<u8>>,
+        lock_time: u32,
+    }
+
+    impl Satisfier for TestSatisfier {
+        fn signature(&self, pubkey: &[u8]) -> Option<Vec<u8>> {
+            self.signatures.get(pubkey).cloned()
+        }
+
+        fn check_after(&self, lock_time: LockTime) -> bool {
+            check_lock_time(lock_time, self.lock_time, SEQUENCE_FINAL - 1).is_ok()
+        }
+    }
+
+    #[test]
+    fn compiles_and_satisfies() {
+        let source = format!("and(pk({}), or(pk({}), after(1000)))", KEY_A, KEY_B);
+        let policy: Policy = source.parse().unwrap();
+        assert_eq!(policy.to_string().parse::<Policy>(), Ok(policy.clone()));
+
+        let redeem_script = policy.compile().unwrap();
+        assert_eq!(
+            redeem_script.to_asm(),
+            format!(
+                "{} OP_CHECKSIGVERIFY OP_IF {} OP_CHECKSIG OP_ELSE 1000 \
+                 OP_NOP2 OP_ENDIF",
+                KEY_A, KEY_B
+            )
+        );
+        assert!(policy.script_pub_key().unwrap().is_pay_to_script_hash());
+
+        let sig_a = vec![0x30, 0xaa, 0x01];
+        let sig_b = vec![0x30, 0xbb, 0x01];
+        let mut satisfier = TestSatisfier {
+            signatures: [(hex::decode(KEY_A).unwrap(), sig_a.clone())].into(),
+            lock_time: 999,
+        };
+        assert_eq!(policy.satisfy(&satisfier), None);
+
+        // Once the lock time is reached, the timelocked branch is selected.
+        satisfier.lock_time = 1000;
+        let mut expected = Script::default();
+        expected
+            .push_opcode(Opcode::OP_0)
+            .push_slice(&sig_a)
+            .push_slice(redeem_script.as_bytes());
+        assert_eq!(policy.satisfy(&satisfier), Some(expected));
+
+        satisfier.lock_time = 0;
+        satisfier
+            .signatures
+            .insert(hex::decode(KEY_B).unwrap(), sig_b.clone());
+        let mut expected = Script::default();
+        expected
+            .push_slice(&sig_b)
+            .push_num(1)
+            .push_slice(&sig_a)
+            .push_slice(redeem_script.as_bytes());
+        assert_eq!(policy.satisfy(&satisfier), Some(expected));
+    }
+
+    #[test]
+    fn rejects_invalid_policies() {
+        assert_eq!(
+            "older(10)".parse::<Policy>(),
+            Err(PolicyError::RelativeLockTime)
+        );
+        assert_eq!("pk(00)".parse::<Policy>(), Err(PolicyError::InvalidKey));
+        assert_eq!("and(after(1)".parse::<Policy>(), Err(PolicyError::Syntax));
+        assert_eq!(
+            format!("multi(2,{})", KEY_A).parse::<Policy>(),
+            Err(PolicyError::InvalidThreshold)
+        );
+
+        let keys = vec![KEY_A; 16].join(",");
+        let policy: Policy = format!("multi(1,{})", keys).parse().unwrap();
+        assert_eq!(policy.compile(), Err(PolicyError::TooLarge));
+    }
+}
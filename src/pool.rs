@@ -0,0 +1,540 @@
+//! A pool of worker threads for verifying a steady stream of inputs.
+//!
+//! Jobs are queued by [`Priority`], so that block validation isn't delayed
+//! by a flood of mempool transactions. Jobs submitted with a
+//! [`CancellationToken`] are skipped if it is cancelled before a worker
+//! takes them, for example when the block they are from is orphaned.
+
+use std::{io, mem, num::NonZeroUsize, sync::Arc, thread};
+
+use crossbeam_channel::{bounded, Receiver, Select, Sender, TrySendError};
+
+use crate::{
+    cancel::CancellationToken,
+    verify::{verify_script, verify_script_v5, Error},
+};
+
+/// A single input to verify.
+///
+/// Transactions and previous outputs are reference-counted, so that jobs for
+/// the inputs of the same transaction can share them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Job {
+    /// Verify with [`verify_script`].
+    Verify {
+        script_pub_key: Vec<u8>,
+        amount: i64,
+        tx_to: Arc<[u8]>,
+        n_in: u32,
+        flags: u32,
+        consensus_branch_id: u32,
+    },
+    /// Verify with [`verify_script_v5`].
+    VerifyV5 {
+        tx_to: Arc<[u8]>,
+        all_prev_outputs: Arc<[u8]>,
+        n_in: u32,
+        flags: u32,
+        consensus_branch_id: u32,
+    },
+}
+
+impl Job {
+    /// Verifies this input on the current thread.
+    pub fn run(&self) -> Result<(), Error> {
+        match self {
+            Job::Verify {
+                script_pub_key,
+                amount,
+                tx_to,
+                n_in,
+                flags,
+                consensus_branch_id,
+            } => verify_script(
+                script_pub_key,
+                *amount,
+                tx_to,
+                *n_in,
+                *flags,
+                *consensus_branch_id,
+            ),
+            Job::VerifyV5 {
+                tx_to,
+                all_prev_outputs,
+                n_in,
+                flags,
+                consensus_branch_id,
+            } => verify_script_v5(tx_to, all_prev_outputs, *n_in, *flags, *consensus_branch_id),
+        }
+    }
+}
+
+/// The channel that receives the result of a submitted [`Job`].
+pub type Completion = Receiver<Result<(), Error>>;
+
+/// Receives the result of a job. It is dropped without being called if the
+/// job is skipped.
+pub(crate) type Callback = Box<dyn FnOnce(Result<(), Error>) + Send>;
+
+type Submission = (Job, Option<CancellationToken>, Callback);
+
+/// The lane a [`Job`] is queued in.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Priority {
+    /// Work for connecting a block, which workers take first.
+    #[default]
+    Block,
+    /// Work for accepting transactions into the mempool, which workers take
+    /// when there is no block work, or for fairness.
+    Mempool,
+}
+
+/// The configuration of a [`VerificationPool`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PoolConfig {
+    /// The number of worker threads, by default one per available CPU.
+    pub threads: usize,
+    /// The maximum number of queued [`Priority::Block`] jobs before
+    /// [`submit`](VerificationPool::submit) blocks.
+    pub block_capacity: usize,
+    /// The maximum number of queued [`Priority::Mempool`] jobs before
+    /// [`submit`](VerificationPool::submit) blocks.
+    pub mempool_capacity: usize,
+    /// The number of block jobs in a row after which a worker takes a waiting
+    /// mempool job, or zero for block work to always preempt mempool work.
+    pub mempool_share: u32,
+    /// The CPU cores to pin the workers to, assigned in turn, or empty to let
+    /// the operating system schedule them.
+    ///
+    /// Pinning keeps verification off the cores used by other CPU-heavy
+    /// subsystems, such as proof verification. It is only supported on
+    /// Linux, and is skipped for a worker if it fails.
+    pub cores: Vec<usize>,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            threads: thread::available_parallelism().map_or(1, NonZeroUsize::get),
+            block_capacity: 1024,
+            mempool_capacity: 1024,
+            mempool_share: 0,
+            cores: vec![],
+        }
+    }
+}
+
+/// The queues of a [`VerificationPool`].
+#[derive(Debug)]
+struct Lanes {
+    block: Sender<Submission>,
+    mempool: Sender<Submission>,
+}
+
+/// A fixed set of worker threads verifying jobs from bounded queues, one per
+/// [`Priority`].
+///
+/// Dropping the pool waits for all queued jobs to finish.
+#[derive(Debug)]
+pub struct VerificationPool {
+    lanes: Option<Lanes>,
+    workers: Vec<thread::JoinHandle<()>>,
+}
+
+impl VerificationPool {
+    /// Starts a pool with `threads` workers, which queues at most
+    /// `queue_capacity` jobs of each priority before [`submit`](Self::submit)
+    /// blocks. Block work always preempts mempool work.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is zero or a worker thread can't be spawned.
+    pub fn new(threads: usize, queue_capacity: usize) -> Self {
+        Self::with_lanes(threads, queue_capacity, queue_capacity, 0)
+    }
+
+    /// Starts a pool with `threads` workers and a queue for each priority.
+    ///
+    /// Workers take [`Priority::Block`] jobs first, except that after
+    /// `mempool_share` block jobs in a row, a worker takes a waiting mempool
+    /// job, so a long block can't starve the mempool. With a `mempool_share`
+    /// of zero, block work always preempts mempool work.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `threads` is zero or a worker thread can't be spawned.
+    pub fn with_lanes(
+        threads: usize,
+        block_capacity: usize,
+        mempool_capacity: usize,
+        mempool_share: u32,
+    ) -> Self {
+        Self::with_config(PoolConfig {
+            threads,
+            block_capacity,
+            mempool_capacity,
+            mempool_share,
+            cores: vec![],
+        })
+    }
+
+    /// Starts a pool configured by `config`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `config.threads` is zero or a worker thread can't be
+    /// spawned.
+    pub fn with_config(config: PoolConfig) -> Self {
+        let PoolConfig {
+            threads,
+            block_capacity,
+            mempool_capacity,
+            mempool_share,
+            cores,
+        } = config;
+        assert!(threads > 0, "a verification pool needs at least one thread");
+
+        let (block, block_jobs) = bounded::<Submission>(block_capacity);
+        let (mempool, mempool_jobs) = bounded::<Submission>(mempool_capacity);
+        let workers = (0..threads)
+            .map(|i| {
+                let block_jobs = block_jobs.clone();
+                let mempool_jobs = mempool_jobs.clone();
+                let core = (!cores.is_empty()).then(|| cores[i % cores.len()]);
+                thread::Builder::new()
+                    .name(format!("zcash_script-verify-{}", i))
+                    .spawn(move || {
+                        if let Some(core) = core {
+                            // Pinning is an optimization, so the worker runs
+                            // unpinned if it fails.
+                            let _ = pin_to_core(core);
+                        }
+                        let mut block_streak = 0;
+                        loop {
+                            let mempool_first = mempool_share > 0 && block_streak >= mempool_share;
+                            let Some(((job, token, callback), priority)) =
+                                next_submission(&block_jobs, &mempool_jobs, mempool_first)
+                            else {
+                                break;
+                            };
+                            block_streak = match priority {
+                                Priority::Block => block_streak + 1,
+                                Priority::Mempool => 0,
+                            };
+                            // Dropping the callback tells the submitter the job
+                            // was skipped.
+                            if token.as_ref().is_some_and(CancellationToken::is_cancelled) {
+                                continue;
+                            }
+                            callback(job.run());
+                        }
+                    })
+                    .expect("can spawn verification worker thread")
+            })
+            .collect();
+
+        VerificationPool {
+            lanes: Some(Lanes { block, mempool }),
+            workers,
+        }
+    }
+
+    /// Queues `job` as [`Priority::Block`] work, blocking while the queue is
+    /// full, and returns the channel that will receive its result.
+    pub fn submit(&self, job: Job) -> Completion {
+        self.submit_with(job, Priority::Block, None)
+    }
+
+    /// Queues `job` like [`submit`](Self::submit), with `priority`.
+    pub fn submit_with_priority(&self, job: Job, priority: Priority) -> Completion {
+        self.submit_with(job, priority, None)
+    }
+
+    /// Queues `job` like [`submit`](Self::submit), but skips it if `token`
+    /// is cancelled before a worker takes it. The channel of a skipped job is
+    /// disconnected without receiving a result.
+    pub fn submit_cancellable(&self, job: Job, token: &CancellationToken) -> Completion {
+        self.submit_with(job, Priority::Block, Some(token.clone()))
+    }
+
+    fn submit_with(
+        &self,
+        job: Job,
+        priority: Priority,
+        token: Option<CancellationToken>,
+    ) -> Completion {
+        let (callback, completion) = channel();
+        self.queue(priority)
+            .send((job, token, callback))
+            .expect("workers run until the pool is dropped");
+        completion
+    }
+
+    /// Queues `job` as [`Priority::Block`] work if the queue has space,
+    /// returning the channel that will receive its result. Otherwise, returns
+    /// the job.
+    pub fn try_submit(&self, job: Job) -> Result<Completion, Job> {
+        self.try_submit_with_priority(job, Priority::Block)
+    }
+
+    /// Queues `job` like [`try_submit`](Self::try_submit), with `priority`.
+    pub fn try_submit_with_priority(
+        &self,
+        job: Job,
+        priority: Priority,
+    ) -> Result<Completion, Job> {
+        let (callback, completion) = channel();
+        self.try_submit_with_callback(job, priority, None, callback)
+            .map(|()| completion)
+    }
+
+    /// Queues `job` if the queue has space, calling `callback` with its
+    /// result. Otherwise, returns the job.
+    pub(crate) fn try_submit_with_callback(
+        &self,
+        job: Job,
+        priority: Priority,
+        token: Option<CancellationToken>,
+        callback: Callback,
+    ) -> Result<(), Job> {
+        match self.queue(priority).try_send((job, token, callback)) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full((job, ..)) | TrySendError::Disconnected((job, ..))) => Err(job),
+        }
+    }
+
+    /// Returns the number of jobs waiting for a worker.
+    pub fn queued(&self) -> usize {
+        self.queued_with_priority(Priority::Block) + self.queued_with_priority(Priority::Mempool)
+    }
+
+    /// Returns the number of jobs with `priority` waiting for a worker.
+    pub fn queued_with_priority(&self, priority: Priority) -> usize {
+        self.queue(priority).len()
+    }
+
+    /// Returns the memory used by the pool's queues, in bytes.
+    ///
+    /// The queues' slots are allocated when the pool starts. This doesn't
+    /// count the buffers of queued jobs, which are shared with the
+    /// submitter, or the worker threads' stacks, whose size is set by
+    /// `RUST_MIN_STACK`.
+    pub fn memory_usage(&self) -> usize {
+        // Each slot also has a stamp.
+        let slot = mem::size_of::<Submission>() + mem::size_of::<usize>();
+        [Priority::Block, Priority::Mempool]
+            .into_iter()
+            .map(|priority| self.queue(priority).capacity().unwrap_or(0) * slot)
+            .sum()
+    }
+
+    fn queue(&self, priority: Priority) -> &Sender<Submission> {
+        let lanes = self.lanes.as_ref().expect("queues are only taken on drop");
+        match priority {
+            Priority::Block => &lanes.block,
+            Priority::Mempool => &lanes.mempool,
+        }
+    }
+}
+
+/// Returns a callback that sends a job's result to a [`Completion`].
+fn channel() -> (Callback, Completion) {
+    let (sender, completion) = bounded(1);
+    let callback: Callback = Box::new(move |result| {
+        // The submitter may have stopped waiting for the result.
+        let _ = sender.send(result);
+    });
+    (callback, completion)
+}
+
+/// Pins the current thread to the CPU `core`.
+#[cfg(target_os = "linux")]
+fn pin_to_core(core: usize) -> io::Result<()> {
+    // SAFETY: `cpu_set_t` is a plain bitmask, for which all zeroes is the
+    // empty set, and `CPU_SET` is only called with an index in range.
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        if core >= 8 * mem::size_of::<libc::cpu_set_t>() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        libc::CPU_SET(core, &mut set);
+        if libc::sched_setaffinity(0, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Pins the current thread to the CPU `core`.
+#[cfg(not(target_os = "linux"))]
+fn pin_to_core(_core: usize) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Returns the next job for a worker, waiting for one if both queues are
+/// empty, or `None` once both are closed and empty.
+///
+/// Block jobs are taken first, unless `mempool_first`.
+fn next_submission(
+    block: &Receiver<Submission>,
+    mempool: &Receiver<Submission>,
+    mempool_first: bool,
+) -> Option<(Submission, Priority)> {
+    let mut lanes = [(block, Priority::Block), (mempool, Priority::Mempool)];
+    if mempool_first {
+        lanes.reverse();
+    }
+    for (queue, priority) in lanes {
+        if let Ok(submission) = queue.try_recv() {
+            return Some((submission, priority));
+        }
+    }
+
+    let mut select = Select::new();
+    for (queue, _) in lanes {
+        select.recv(queue);
+    }
+    let mut open = lanes.len();
+    while open > 0 {
+        let operation = select.select();
+        let index = operation.index();
+        let (queue, priority) = lanes[index];
+        match operation.recv(queue) {
+            Ok(submission) => return Some((submission, priority)),
+            // The pool was dropped, and this queue is empty.
+            Err(_) => {
+                select.remove(index);
+                open -= 1;
+            }
+        }
+    }
+    None
+}
+
+impl Drop for VerificationPool {
+    fn drop(&mut self) {
+        // Closing the queues makes the workers exit once they are empty.
+        self.lanes.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+
+    #[test]
+    fn it_works_pooled() {
+        let coin = i64::pow(10, 8);
+        let tx_to: Arc<[u8]> = SCRIPT_TX.as_slice().into();
+        let job = |consensus_branch_id| Job::Verify {
+            script_pub_key: SCRIPT_PUBKEY.clone(),
+            amount: 212 * coin,
+            tx_to: tx_to.clone(),
+            n_in: 0,
+            flags: 1,
+            consensus_branch_id,
+        };
+
+        let pool = VerificationPool::new(2, 4);
+        let ok = pool.submit(job(0x2bb40e60));
+        let err = pool.submit(job(0x2bb40e61));
+
+        ok.recv().unwrap().unwrap();
+        err.recv().unwrap().unwrap_err();
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let skipped = pool.submit_cancellable(job(0x2bb40e60), &token);
+        assert!(skipped.recv().is_err());
+        let live = pool.submit_cancellable(job(0x2bb40e60), &CancellationToken::new());
+        live.recv().unwrap().unwrap();
+        assert_eq!(
+            pool.memory_usage(),
+            2 * 4 * (mem::size_of::<Submission>() + mem::size_of::<usize>())
+        );
+    }
+
+    #[test]
+    fn prefers_block_work() {
+        let job = Job::VerifyV5 {
+            tx_to: SCRIPT_TX.as_slice().into(),
+            all_prev_outputs: Vec::new().into(),
+            n_in: 0,
+            flags: 1,
+            consensus_branch_id: 0x2bb40e60,
+        };
+        let (block, block_jobs) = bounded(4);
+        let (mempool, mempool_jobs) = bounded(4);
+        for queue in [&mempool, &block, &mempool] {
+            queue.send((job.clone(), None, channel().0)).unwrap();
+        }
+        let next = |mempool_first| {
+            next_submission(&block_jobs, &mempool_jobs, mempool_first).map(|(_, p)| p)
+        };
+        assert_eq!(next(false), Some(Priority::Block));
+        assert_eq!(next(false), Some(Priority::Mempool));
+        block.send((job.clone(), None, channel().0)).unwrap();
+        assert_eq!(next(true), Some(Priority::Mempool));
+        assert_eq!(next(true), Some(Priority::Block));
+        drop((block, mempool));
+        assert_eq!(next(false), None);
+
+        let pool = VerificationPool::with_lanes(1, 2, 2, 4);
+        let result = pool.submit_with_priority(job, Priority::Mempool);
+        assert_eq!(
+            result.recv().unwrap(),
+            Err(Error::AllPrevOutputsDeserialize)
+        );
+        assert_eq!(pool.queued_with_priority(Priority::Mempool), 0);
+    }
+
+    #[test]
+    fn pins_workers() {
+        let config = PoolConfig {
+            threads: 2,
+            #[cfg(target_os = "linux")]
+            cores: vec![allowed_core()],
+            #[cfg(not(target_os = "linux"))]
+            cores: vec![0],
+            ..PoolConfig::default()
+        };
+        let pool = VerificationPool::with_config(config);
+        let job = Job::VerifyV5 {
+            tx_to: SCRIPT_TX.as_slice().into(),
+            all_prev_outputs: Vec::new().into(),
+            n_in: 0,
+            flags: 1,
+            consensus_branch_id: 0x2bb40e60,
+        };
+        assert!(pool.submit(job).recv().unwrap().is_err());
+
+        #[cfg(target_os = "linux")]
+        thread::spawn(|| {
+            // Containers and CI runners may not allow every CPU, so pin to one
+            // the process is allowed to run on.
+            pin_to_core(allowed_core()).unwrap();
+            assert!(pin_to_core(usize::MAX).is_err());
+        })
+        .join()
+        .unwrap();
+    }
+
+    /// Returns the first CPU the current thread is allowed to run on.
+    #[cfg(target_os = "linux")]
+    fn allowed_core() -> usize {
+        // SAFETY: all zeroes is the empty set, which `sched_getaffinity`
+        // overwrites, and `CPU_ISSET` is only called with an index in range.
+        unsafe {
+            let mut set: libc::cpu_set_t = mem::zeroed();
+            let size = mem::size_of::<libc::cpu_set_t>();
+            assert_eq!(libc::sched_getaffinity(0, size, &mut set), 0);
+            (0..8 * size)
+                .find(|&core| libc::CPU_ISSET(core, &set))
+                .expect("the thread can run on some CPU")
+        }
+    }
+}
@@ -0,0 +1,393 @@
+//! A safe owner of precomputed transaction handles.
+//!
+//! [`PrecomputedTx`] wraps the handle returned by
+//! `zcash_script_new_precomputed_tx` (or its v5 variant), and frees it when
+//! dropped. The C++ side copies the deserialized transaction, so the handle
+//! doesn't borrow the serialized bytes.
+
+use std::ffi::c_void;
+
+use crate::{
+    calibrate::calibration,
+    cancel::{CancellationToken, Cancelled},
+    observer,
+    verify::{self, Error},
+    zcash_script_error_t, zcash_script_free_precomputed_tx, zcash_script_input,
+    zcash_script_legacy_sigop_count_precomputed, zcash_script_new_precomputed_tx,
+    zcash_script_new_precomputed_tx_v5, zcash_script_verify_precomputed,
+    zcash_script_verify_precomputed_batch,
+};
+
+/// An input to verify with [`PrecomputedTx::verify_batch`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Input<'a> {
+    pub n_in: u32,
+    pub script_pub_key: &'a [u8],
+    pub amount: i64,
+    pub flags: u32,
+}
+
+/// What [`PrecomputedTx::verify_many`] does when an input fails to verify.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FailurePolicy {
+    /// Stop at the first failing input, as block and transaction validation
+    /// do.
+    Abort,
+    /// Verify every input and report each failure, for diagnostics.
+    CollectAll,
+}
+
+/// A deserialized transaction with its signature hash data precomputed, for
+/// verifying several of its inputs.
+///
+/// This is `Send` and `Sync`, so wrap it in an [`Arc`](std::sync::Arc) to
+/// verify the inputs of one transaction from several threads concurrently.
+#[derive(Debug)]
+pub struct PrecomputedTx {
+    handle: *mut c_void,
+}
+
+// SAFETY: The handle points to a C++ `PrecomputedTransaction`, which is only
+// mutated by `zcash_script_free_precomputed_tx` in `drop`. It isn't tied to
+// the thread that created it.
+unsafe impl Send for PrecomputedTx {}
+
+// SAFETY: All the other functions that take the handle receive it as
+// `const void*`, and only read the `const` transaction and signature hash
+// data. The v5 signature digest is computed in Rust through a shared
+// reference to the precomputed parts, and the secp256k1 verification context
+// is itself read-only, so concurrent verifications don't race.
+unsafe impl Sync for PrecomputedTx {}
+
+impl PrecomputedTx {
+    /// Deserializes `tx_to` and precomputes its signature hash data.
+    ///
+    /// This uses `zcash_script_new_precomputed_tx`, which cannot be used for
+    /// v5+ transactions.
+    pub fn new(tx_to: &[u8]) -> Result<Self, Error> {
+        verify::check_api_version();
+        let mut err = 0;
+        let handle =
+            unsafe { zcash_script_new_precomputed_tx(tx_to.as_ptr(), tx_to.len() as _, &mut err) };
+        Self::from_handle(handle, err)
+    }
+
+    /// Deserializes `tx_to` and precomputes its signature hash data, using
+    /// `all_prev_outputs`, the encoding of all the outputs spent by the
+    /// transaction.
+    ///
+    /// This uses `zcash_script_new_precomputed_tx_v5`, which must be used for
+    /// v5 transactions.
+    pub fn new_v5(tx_to: &[u8], all_prev_outputs: &[u8]) -> Result<Self, Error> {
+        verify::check_api_version();
+        let mut err = 0;
+        let handle = unsafe {
+            zcash_script_new_precomputed_tx_v5(
+                tx_to.as_ptr(),
+                tx_to.len() as _,
+                all_prev_outputs.as_ptr(),
+                all_prev_outputs.len() as _,
+                &mut err,
+            )
+        };
+        Self::from_handle(handle, err)
+    }
+
+    fn from_handle(handle: *mut c_void, err: zcash_script_error_t) -> Result<Self, Error> {
+        if handle.is_null() {
+            return Err(Error::from_code(err));
+        }
+        #[cfg(feature = "debug-handles")]
+        crate::handles::register(handle);
+        Ok(PrecomputedTx { handle })
+    }
+
+    /// Returns the raw handle, for use with the C API.
+    ///
+    /// The handle remains owned by `self`, and must not be freed.
+    pub fn as_ptr(&self) -> *const c_void {
+        self.handle
+    }
+
+    /// Returns `Ok(())` if the input `n_in` correctly spends `script_pub_key`
+    /// under the additional constraints specified by `flags`.
+    pub fn verify(
+        &self,
+        n_in: u32,
+        script_pub_key: &[u8],
+        amount: i64,
+        flags: u32,
+        consensus_branch_id: u32,
+    ) -> Result<(), Error> {
+        observer::observe(flags, Some(script_pub_key), || {
+            let mut err = 0;
+            let ret = unsafe {
+                zcash_script_verify_precomputed(
+                    self.handle,
+                    n_in,
+                    script_pub_key.as_ptr(),
+                    script_pub_key.len() as _,
+                    amount,
+                    flags,
+                    consensus_branch_id,
+                    &mut err,
+                )
+            };
+            verify::from_ret(ret, err)
+        })
+    }
+
+    /// Verifies each of `inputs` as if by [`PrecomputedTx::verify`], in a
+    /// single FFI call, returning the result for each input.
+    ///
+    /// Batches are not reported to the installed
+    /// [`VerificationObserver`](crate::observer::VerificationObserver).
+    pub fn verify_batch(
+        &self,
+        inputs: &[Input<'_>],
+        consensus_branch_id: u32,
+    ) -> Vec<Result<(), Error>> {
+        let ffi_inputs: Vec<_> = inputs
+            .iter()
+            .map(|input| zcash_script_input {
+                nIn: input.n_in,
+                scriptPubKey: input.script_pub_key.as_ptr(),
+                scriptPubKeyLen: input.script_pub_key.len() as _,
+                amount: input.amount,
+                flags: input.flags,
+            })
+            .collect();
+        let mut rets = vec![0; inputs.len()];
+        let mut errs = vec![0; inputs.len()];
+        unsafe {
+            zcash_script_verify_precomputed_batch(
+                self.handle,
+                ffi_inputs.as_ptr(),
+                ffi_inputs.len() as _,
+                consensus_branch_id,
+                rets.as_mut_ptr(),
+                errs.as_mut_ptr(),
+            )
+        };
+        rets.into_iter()
+            .zip(errs)
+            .map(|(ret, err)| verify::from_ret(ret, err))
+            .collect()
+    }
+
+    /// Returns `Ok(())` if all of `inputs` verify, as if by
+    /// [`PrecomputedTx::verify`], and otherwise the input index and error of
+    /// the failing inputs, in order.
+    ///
+    /// With [`FailurePolicy::Abort`], there is only one failure, and the
+    /// inputs after it aren't verified. With [`FailurePolicy::CollectAll`],
+    /// the inputs are verified in a single FFI call, as by
+    /// [`PrecomputedTx::verify_batch`].
+    pub fn verify_many(
+        &self,
+        inputs: &[Input<'_>],
+        consensus_branch_id: u32,
+        policy: FailurePolicy,
+    ) -> Result<(), Vec<(u32, Error)>> {
+        self.verify_many_with(
+            inputs,
+            consensus_branch_id,
+            policy,
+            inputs.len().max(1),
+            None,
+        )
+        .expect("verification without a token isn't cancelled")
+    }
+
+    /// Verifies `inputs` like [`PrecomputedTx::verify_many`], unless `token`
+    /// is cancelled before they have all been verified.
+    ///
+    /// With [`FailurePolicy::CollectAll`], inputs are verified in FFI calls of
+    /// the [calibrated](crate::calibrate) batch size, checking `token` before
+    /// each.
+    pub fn verify_many_cancellable(
+        &self,
+        inputs: &[Input<'_>],
+        consensus_branch_id: u32,
+        policy: FailurePolicy,
+        token: &CancellationToken,
+    ) -> Result<Result<(), Vec<(u32, Error)>>, Cancelled> {
+        self.verify_many_with(
+            inputs,
+            consensus_branch_id,
+            policy,
+            calibration().batch_size(),
+            Some(token),
+        )
+    }
+
+    fn verify_many_with(
+        &self,
+        inputs: &[Input<'_>],
+        consensus_branch_id: u32,
+        policy: FailurePolicy,
+        batch_size: usize,
+        token: Option<&CancellationToken>,
+    ) -> Result<Result<(), Vec<(u32, Error)>>, Cancelled> {
+        let check = || match token {
+            Some(token) if token.is_cancelled() => Err(Cancelled),
+            _ => Ok(()),
+        };
+        let mut failures = vec![];
+        match policy {
+            FailurePolicy::Abort => {
+                for input in inputs {
+                    check()?;
+                    if let Err(err) = self.verify(
+                        input.n_in,
+                        input.script_pub_key,
+                        input.amount,
+                        input.flags,
+                        consensus_branch_id,
+                    ) {
+                        failures.push((input.n_in, err));
+                        break;
+                    }
+                }
+            }
+            FailurePolicy::CollectAll => {
+                for batch in inputs.chunks(batch_size) {
+                    check()?;
+                    failures.extend(
+                        batch
+                            .iter()
+                            .zip(self.verify_batch(batch, consensus_branch_id))
+                            .filter_map(|(input, result)| {
+                                result.err().map(|err| (input.n_in, err))
+                            }),
+                    );
+                }
+            }
+        }
+        Ok(if failures.is_empty() {
+            Ok(())
+        } else {
+            Err(failures)
+        })
+    }
+
+    /// Returns the number of transparent signature operations in the
+    /// transaction, counted with the legacy (pre-P2SH) rules.
+    pub fn legacy_sigop_count(&self) -> u32 {
+        // This never fails for a precomputed transaction.
+        let mut err = 0;
+        unsafe { zcash_script_legacy_sigop_count_precomputed(self.handle, &mut err) }
+    }
+}
+
+impl Drop for PrecomputedTx {
+    fn drop(&mut self) {
+        #[cfg(feature = "debug-handles")]
+        crate::handles::unregister(self.handle);
+        unsafe { zcash_script_free_precomputed_tx(self.handle) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+
+    #[test]
+    fn verifies_and_frees() {
+        let precomputed = PrecomputedTx::new(&SCRIPT_TX).unwrap();
+        precomputed
+            .verify(0, &SCRIPT_PUBKEY, 212 * 100_000_000, 1, 0x2bb40e60)
+            .unwrap();
+        assert_eq!(
+            precomputed.verify(1, &SCRIPT_PUBKEY, 212 * 100_000_000, 1, 0x2bb40e60),
+            Err(Error::TxIndex)
+        );
+        assert_eq!(precomputed.legacy_sigop_count(), 1);
+
+        let input = Input {
+            n_in: 0,
+            script_pub_key: &SCRIPT_PUBKEY,
+            amount: 212 * 100_000_000,
+            flags: 1,
+        };
+        assert_eq!(
+            precomputed.verify_batch(&[input, Input { n_in: 1, ..input }], 0x2bb40e60),
+            [Ok(()), Err(Error::TxIndex)]
+        );
+
+        assert_eq!(
+            PrecomputedTx::new(&SCRIPT_TX[1..]).unwrap_err(),
+            Error::TxDeserialize
+        );
+    }
+
+    #[test]
+    fn verifies_many_with_policy() {
+        let precomputed = PrecomputedTx::new(&SCRIPT_TX).unwrap();
+        let input = Input {
+            n_in: 0,
+            script_pub_key: &SCRIPT_PUBKEY,
+            amount: 212 * 100_000_000,
+            flags: 1,
+        };
+        let wrong_amount = Input {
+            amount: input.amount - 1,
+            ..input
+        };
+        let out_of_range = Input { n_in: 1, ..input };
+        let inputs = [input, wrong_amount, out_of_range];
+
+        assert_eq!(
+            precomputed.verify_many(&[input, input], 0x2bb40e60, FailurePolicy::Abort),
+            Ok(())
+        );
+        assert_eq!(
+            precomputed.verify_many(&inputs, 0x2bb40e60, FailurePolicy::Abort),
+            Err(vec![(0, Error::ScriptInvalid)])
+        );
+        assert_eq!(
+            precomputed.verify_many(&inputs, 0x2bb40e60, FailurePolicy::CollectAll),
+            Err(vec![(0, Error::ScriptInvalid), (1, Error::TxIndex)])
+        );
+
+        let token = CancellationToken::new();
+        let many = vec![input; calibration().batch_size() + 1];
+        assert_eq!(
+            precomputed.verify_many_cancellable(
+                &many,
+                0x2bb40e60,
+                FailurePolicy::CollectAll,
+                &token
+            ),
+            Ok(Ok(()))
+        );
+        token.cancel();
+        for policy in [FailurePolicy::Abort, FailurePolicy::CollectAll] {
+            assert_eq!(
+                precomputed.verify_many_cancellable(&many, 0x2bb40e60, policy, &token),
+                Err(Cancelled)
+            );
+        }
+    }
+
+    #[test]
+    fn verifies_from_many_threads() {
+        let precomputed = std::sync::Arc::new(PrecomputedTx::new(&SCRIPT_TX).unwrap());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let precomputed = precomputed.clone();
+                std::thread::spawn(move || {
+                    for _ in 0..25 {
+                        precomputed
+                            .verify(0, &SCRIPT_PUBKEY, 212 * 100_000_000, 1, 0x2bb40e60)
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,51 @@
+//! Lookup of the outputs spent by a transaction, for verifying all of its
+//! inputs with [`verify_tx`](crate::verify::verify_tx).
+
+use std::collections::BTreeMap;
+
+use zcash_encoding::CompactSize;
+pub use zcash_primitives::transaction::components::transparent::OutPoint;
+
+use crate::script::Script;
+
+/// An output spent by a transaction input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrevOut {
+    /// The output's scriptPubKey.
+    pub script_pub_key: Script,
+    /// The output's value, in zatoshis.
+    pub amount: i64,
+}
+
+/// A source of the outputs spent by transactions, such as a UTXO database.
+pub trait PrevOutSource {
+    /// Returns the unspent output at `outpoint`, or `None` if there is none.
+    fn prev_out(&self, outpoint: &OutPoint) -> Option<PrevOut>;
+}
+
+impl PrevOutSource for BTreeMap<OutPoint, PrevOut> {
+    fn prev_out(&self, outpoint: &OutPoint) -> Option<PrevOut> {
+        self.get(outpoint).cloned()
+    }
+}
+
+impl<F: Fn(&OutPoint) -> Option<PrevOut>> PrevOutSource for F {
+    fn prev_out(&self, outpoint: &OutPoint) -> Option<PrevOut> {
+        self(outpoint)
+    }
+}
+
+/// Encodes the outputs spent by a transaction, in input order, as the
+/// `all_prev_outputs` argument of the v5 C API functions.
+pub fn encode_all_prev_outputs<'a>(
+    prev_outs: impl ExactSizeIterator<Item = (&'a [u8], i64)>,
+) -> Vec<u8> {
+    let mut encoded = vec![];
+    CompactSize::write(&mut encoded, prev_outs.len()).expect("writes to a Vec succeed");
+    for (script_pub_key, amount) in prev_outs {
+        encoded.extend(amount.to_le_bytes());
+        CompactSize::write(&mut encoded, script_pub_key.len()).expect("writes to a Vec succeed");
+        encoded.extend_from_slice(script_pub_key);
+    }
+    encoded
+}
@@ -0,0 +1,151 @@
+//! Conversions between this crate's types and `zcash_primitives`' types.
+//!
+//! Wallets built on librustzcash hold scripts, outputs and transactions as
+//! `zcash_primitives` values. These conversions, and [`verify_transaction`],
+//! let them verify with this crate without serializing anything themselves.
+
+use std::{collections::BTreeMap, fmt};
+
+use zcash_primitives::{
+    legacy,
+    transaction::{
+        components::{
+            amount::{Amount, NonNegativeAmount},
+            transparent::TxOut,
+        },
+        Transaction,
+    },
+};
+
+use crate::{
+    prevout::{OutPoint, PrevOut, PrevOutSource},
+    script::Script,
+    verify::{self, TxError, TxOutcome},
+};
+
+impl From<legacy::Script> for Script {
+    fn from(script: legacy::Script) -> Self {
+        Script(script.0)
+    }
+}
+
+impl From<Script> for legacy::Script {
+    fn from(script: Script) -> Self {
+        legacy::Script(script.0)
+    }
+}
+
+impl From<&TxOut> for PrevOut {
+    fn from(output: &TxOut) -> Self {
+        PrevOut {
+            script_pub_key: output.script_pubkey.0.clone().into(),
+            amount: Amount::from(output.value).into(),
+        }
+    }
+}
+
+impl From<TxOut> for PrevOut {
+    fn from(output: TxOut) -> Self {
+        PrevOut {
+            script_pub_key: output.script_pubkey.into(),
+            amount: Amount::from(output.value).into(),
+        }
+    }
+}
+
+/// An error converting a [`PrevOut`] to a [`TxOut`], when its amount is
+/// negative or more than the total money supply.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidAmount(pub i64);
+
+impl fmt::Display for InvalidAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid output amount {}", self.0)
+    }
+}
+
+impl std::error::Error for InvalidAmount {}
+
+impl TryFrom<PrevOut> for TxOut {
+    type Error = InvalidAmount;
+
+    fn try_from(prev_out: PrevOut) -> Result<Self, Self::Error> {
+        Ok(TxOut {
+            value: NonNegativeAmount::from_nonnegative_i64(prev_out.amount)
+                .map_err(|()| InvalidAmount(prev_out.amount))?,
+            script_pubkey: prev_out.script_pub_key.into(),
+        })
+    }
+}
+
+impl PrevOutSource for BTreeMap<OutPoint, TxOut> {
+    fn prev_out(&self, outpoint: &OutPoint) -> Option<PrevOut> {
+        self.get(outpoint).map(PrevOut::from)
+    }
+}
+
+/// Verifies every transparent input of `tx` under `flags`, like
+/// [`verify_tx_outcome`](verify::verify_tx_outcome), with the consensus
+/// branch ID the transaction was read or built for.
+pub fn verify_transaction<S: PrevOutSource + ?Sized>(
+    tx: &Transaction,
+    prev_outs: &S,
+    flags: u32,
+) -> Result<TxOutcome, TxError> {
+    let mut tx_to = vec![];
+    tx.write(&mut tx_to).expect("writes to a Vec succeed");
+    verify::verify_tx_outcome(&tx_to, prev_outs, flags, tx.consensus_branch_id().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use zcash_primitives::consensus::BranchId;
+
+    use super::*;
+    use crate::{
+        tests::{SCRIPT_PUBKEY, SCRIPT_TX},
+        verify::Error,
+    };
+
+    #[test]
+    fn verifies_primitives_transactions() {
+        let tx = Transaction::read(&SCRIPT_TX[..], BranchId::Blossom).unwrap();
+        let outpoint = tx.transparent_bundle().unwrap().vin[0].prevout.clone();
+        let output = TxOut {
+            value: NonNegativeAmount::from_u64(212 * 100_000_000).unwrap(),
+            script_pubkey: legacy::Script(SCRIPT_PUBKEY.to_vec()),
+        };
+
+        let prev_out = PrevOut::from(&output);
+        assert_eq!(prev_out.script_pub_key, Script(SCRIPT_PUBKEY.to_vec()));
+        assert_eq!(prev_out.amount, 212 * 100_000_000);
+        assert_eq!(TxOut::try_from(prev_out.clone()), Ok(output.clone()));
+        assert_eq!(
+            TxOut::try_from(PrevOut {
+                amount: -1,
+                ..prev_out
+            }),
+            Err(InvalidAmount(-1))
+        );
+
+        let mut utxos = BTreeMap::<OutPoint, TxOut>::new();
+        assert_eq!(
+            verify_transaction(&tx, &utxos, 1),
+            Err(TxError::MissingPrevOut(0))
+        );
+        utxos.insert(outpoint.clone(), output.clone());
+        assert_eq!(
+            verify_transaction(&tx, &utxos, 1),
+            Ok(TxOutcome::Verified(vec![Ok(())]))
+        );
+        let wrong_amount = TxOut {
+            value: NonNegativeAmount::from_u64(1).unwrap(),
+            ..output
+        };
+        utxos.insert(outpoint, wrong_amount);
+        assert_eq!(
+            verify_transaction(&tx, &utxos, 1),
+            Ok(TxOutcome::Verified(vec![Err(Error::ScriptInvalid)]))
+        );
+    }
+}
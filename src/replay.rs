@@ -0,0 +1,267 @@
+//! Self-contained records of verifications, for reproducing failures.
+//!
+//! A [`Replay`] holds everything a verification depends on, its result, and
+//! the version of the code that produced it. Its text format has one
+//! `key value` line per field, so it can be attached to a bug report, saved
+//! with `std::fs::write`, and run again with [`Replay::run`] or
+//! `zcash-script replay <file>`.
+
+use std::{fmt, str::FromStr};
+
+use crate::verify::{verify_script, verify_script_v5, Error};
+
+/// The first line of the text format, which changes if the format does.
+pub const HEADER: &str = "zcash_script replay 1";
+
+/// The verification backend this build uses: zcashd's C++ interpreter.
+pub const BACKEND: &str = "zcashd";
+
+/// The outputs a recorded verification depends on.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SpentOutputs {
+    /// The output spent by the input, verified with [`verify_script`].
+    Output {
+        script_pub_key: Vec<u8>,
+        amount: i64,
+    },
+    /// The encoding of all the outputs spent by a v5 transaction, verified
+    /// with [`verify_script_v5`].
+    All(Vec<u8>),
+}
+
+/// A recorded verification of one transparent input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replay {
+    pub tx_to: Vec<u8>,
+    pub spent: SpentOutputs,
+    pub n_in: u32,
+    pub flags: u32,
+    pub consensus_branch_id: u32,
+    /// The result when the verification was recorded.
+    pub result: Result<(), Error>,
+    /// The version of this crate that recorded it.
+    pub crate_version: String,
+    /// The [`vendored_source_digest`](crate::vendored_source_digest) of the
+    /// build that recorded it.
+    pub source_digest: String,
+    /// The [`BACKEND`] that recorded it.
+    pub backend: String,
+}
+
+impl Replay {
+    /// Verifies input `n_in` of `tx_to`, and records the verification and
+    /// its result.
+    pub fn record(
+        tx_to: &[u8],
+        spent: SpentOutputs,
+        n_in: u32,
+        flags: u32,
+        consensus_branch_id: u32,
+    ) -> Self {
+        let mut replay = Replay {
+            tx_to: tx_to.to_vec(),
+            spent,
+            n_in,
+            flags,
+            consensus_branch_id,
+            result: Ok(()),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            source_digest: crate::vendored_source_digest().to_string(),
+            backend: BACKEND.to_string(),
+        };
+        replay.result = replay.run();
+        replay
+    }
+
+    /// Runs the recorded verification again with this build.
+    pub fn run(&self) -> Result<(), Error> {
+        match &self.spent {
+            SpentOutputs::Output {
+                script_pub_key,
+                amount,
+            } => verify_script(
+                script_pub_key,
+                *amount,
+                &self.tx_to,
+                self.n_in,
+                self.flags,
+                self.consensus_branch_id,
+            ),
+            SpentOutputs::All(all_prev_outputs) => verify_script_v5(
+                &self.tx_to,
+                all_prev_outputs,
+                self.n_in,
+                self.flags,
+                self.consensus_branch_id,
+            ),
+        }
+    }
+
+    /// Returns true if this build recorded the verification, so a different
+    /// result from [`Replay::run`] can't be due to a different version.
+    ///
+    /// Builds that link prebuilt libraries can't attest to their sources, so
+    /// they are never known to be the same build.
+    pub fn is_same_build(&self) -> bool {
+        self.crate_version == env!("CARGO_PKG_VERSION")
+            && self.source_digest != crate::PREBUILT_SOURCE_DIGEST
+            && self.source_digest == crate::vendored_source_digest()
+            && self.backend == BACKEND
+    }
+}
+
+impl fmt::Display for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "crate_version {}", self.crate_version)?;
+        writeln!(f, "source_digest {}", self.source_digest)?;
+        writeln!(f, "backend {}", self.backend)?;
+        writeln!(f, "tx {}", hex::encode(&self.tx_to))?;
+        match &self.spent {
+            SpentOutputs::Output {
+                script_pub_key,
+                amount,
+            } => {
+                writeln!(f, "script_pub_key {}", hex::encode(script_pub_key))?;
+                writeln!(f, "amount {}", amount)?;
+            }
+            SpentOutputs::All(all_prev_outputs) => {
+                writeln!(f, "all_prev_outputs {}", hex::encode(all_prev_outputs))?;
+            }
+        }
+        writeln!(f, "n_in {}", self.n_in)?;
+        writeln!(f, "flags {:#x}", self.flags)?;
+        writeln!(f, "consensus_branch_id {:#010x}", self.consensus_branch_id)?;
+        match self.result {
+            Ok(()) => writeln!(f, "result ok"),
+            Err(err) => writeln!(f, "result error {}", err.code()),
+        }
+    }
+}
+
+/// An error parsing a [`Replay`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The text doesn't start with [`HEADER`].
+    Header,
+    /// A field is missing.
+    Missing(&'static str),
+    /// The value of a field is invalid.
+    Invalid(String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Header => write!(f, "not a replay, or an unsupported version"),
+            ReplayError::Missing(key) => write!(f, "missing {}", key),
+            ReplayError::Invalid(key) => write!(f, "invalid {}", key),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl FromStr for Replay {
+    type Err = ReplayError;
+
+    /// Parses the text format. Blank lines and lines starting with `#` are
+    /// ignored, so reports can be annotated.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        if lines.next() != Some(HEADER) {
+            return Err(ReplayError::Header);
+        }
+        let fields: Vec<_> = lines
+            .map(|line| line.split_once(' ').unwrap_or((line, "")))
+            .collect();
+        let field = |key: &'static str| {
+            fields
+                .iter()
+                .find(|(k, _)| *k == key)
+                .map(|(_, value)| value.trim())
+        };
+        let required = |key| field(key).ok_or(ReplayError::Missing(key));
+        let invalid = |key: &str| ReplayError::Invalid(key.to_string());
+        let bytes = |key| hex::decode(required(key)?).map_err(|_| invalid(key));
+        let number = |key| {
+            let value = required(key)?;
+            match value.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => value.parse(),
+            }
+            .map_err(|_| invalid(key))
+        };
+
+        let spent = match field("all_prev_outputs") {
+            Some(_) => SpentOutputs::All(bytes("all_prev_outputs")?),
+            None => SpentOutputs::Output {
+                script_pub_key: bytes("script_pub_key")?,
+                amount: required("amount")?.parse().map_err(|_| invalid("amount"))?,
+            },
+        };
+        let result = match required("result")? {
+            "ok" => Ok(()),
+            result => match result.strip_prefix("error ").map(str::parse) {
+                Some(Ok(code)) => Err(Error::from_code(code)),
+                _ => return Err(invalid("result")),
+            },
+        };
+        Ok(Replay {
+            tx_to: bytes("tx")?,
+            spent,
+            n_in: number("n_in")?,
+            flags: number("flags")?,
+            consensus_branch_id: number("consensus_branch_id")?,
+            result,
+            crate_version: required("crate_version")?.to_string(),
+            source_digest: required("source_digest")?.to_string(),
+            backend: required("backend")?.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+
+    #[test]
+    fn round_trips_and_replays() {
+        let spent = SpentOutputs::Output {
+            script_pub_key: SCRIPT_PUBKEY.clone(),
+            amount: 212 * 100_000_000,
+        };
+        let replay = Replay::record(&SCRIPT_TX, spent, 0, 1, 0x2bb40e61);
+        assert_eq!(replay.result, Err(Error::ScriptInvalid));
+        assert_eq!(
+            replay.is_same_build(),
+            crate::vendored_source_digest() != crate::PREBUILT_SOURCE_DIGEST
+        );
+
+        let text = replay.to_string();
+        assert!(text.contains("consensus_branch_id 0x2bb40e61\n"));
+        assert!(text.ends_with("result error 0\n"));
+        let annotated = format!("# Fails on the wrong branch.\n{}", text);
+        let parsed: Replay = annotated.parse().unwrap();
+        assert_eq!(parsed, replay);
+        assert_eq!(parsed.run(), parsed.result);
+
+        let fixed = Replay {
+            consensus_branch_id: 0x2bb40e60,
+            result: Ok(()),
+            ..parsed
+        };
+        assert_eq!(fixed.to_string().parse(), Ok(fixed.clone()));
+        assert_eq!(fixed.run(), Ok(()));
+
+        assert_eq!("".parse::<Replay>(), Err(ReplayError::Header));
+        assert_eq!(
+            text.replace("n_in 0\n", "").parse::<Replay>(),
+            Err(ReplayError::Missing("n_in"))
+        );
+    }
+}
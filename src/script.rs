@@ -0,0 +1,582 @@
+//! Parsing and inspection of serialized scripts.
+//!
+//! This mirrors the parts of zcashd's `CScript` (`depend/zcash/src/script/script.h`)
+//! that don't require evaluating the script. Verification itself is always
+//! performed by the C++ interpreter.
+
+use std::fmt;
+
+use hex::FromHex;
+
+use crate::{opcode::Opcode, script_num::ScriptNum};
+
+/// Maximum number of bytes pushable to the stack.
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+
+/// Maximum number of non-push operations per script.
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+
+/// Maximum number of public keys per multisig.
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+
+/// Maximum script length in bytes.
+pub const MAX_SCRIPT_SIZE: usize = 10000;
+
+/// A serialized script, as found in transaction inputs and outputs.
+#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Script(pub Vec<u8>);
+
+/// A single parsed script operation: an opcode, and the data it pushes (if
+/// it is a push operation).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Instruction<'a> {
+    /// The byte offset of the opcode within the script.
+    pub offset: usize,
+    /// The opcode.
+    pub opcode: Opcode,
+    /// The pushed data. Empty for non-push opcodes.
+    pub data: &'a [u8],
+}
+
+/// An error returned when a script can't be parsed into instructions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// The byte offset of the opcode whose operand is truncated.
+    pub offset: usize,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "truncated push at script offset {}", self.offset)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// An iterator over the instructions of a script, matching `CScript::GetOp`.
+///
+/// After the first error, the iterator is exhausted.
+#[derive(Clone, Debug)]
+pub struct Instructions<'a> {
+    script: &'a [u8],
+    pc: usize,
+}
+
+impl<'a> Instructions<'a> {
+    fn read_op(&mut self) -> Result<Instruction<'a>, ParseError> {
+        let offset = self.pc;
+        let err = ParseError { offset };
+        let opcode = Opcode(self.script[self.pc]);
+        self.pc += 1;
+
+        if !opcode.is_push_data() {
+            return Ok(Instruction {
+                offset,
+                opcode,
+                data: &[],
+            });
+        }
+
+        let rest = &self.script[self.pc..];
+        let (size, header) = match opcode {
+            Opcode::OP_PUSHDATA1 => (*rest.first().ok_or(err)? as usize, 1),
+            Opcode::OP_PUSHDATA2 => {
+                let b = rest.get(..2).ok_or(err)?;
+                (u16::from_le_bytes([b[0], b[1]]) as usize, 2)
+            }
+            Opcode::OP_PUSHDATA4 => {
+                let b = rest.get(..4).ok_or(err)?;
+                (u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize, 4)
+            }
+            Opcode(n) => (n as usize, 0),
+        };
+        let data = rest
+            .get(header..)
+            .and_then(|rest| rest.get(..size))
+            .ok_or(err)?;
+        self.pc += header + size;
+
+        Ok(Instruction {
+            offset,
+            opcode,
+            data,
+        })
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.pc >= self.script.len() {
+            return None;
+        }
+        let result = self.read_op();
+        if result.is_err() {
+            self.pc = self.script.len();
+        }
+        Some(result)
+    }
+}
+
+impl Script {
+    /// Returns the serialized bytes of this script.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Returns the length of this script in bytes.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if the script is empty.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Appends an opcode to this script.
+    pub fn push_opcode(&mut self, opcode: Opcode) -> &mut Self {
+        self.0.push(opcode.0);
+        self
+    }
+
+    /// Appends a push of `data` to this script, using the same push opcode as
+    /// `CScript::operator<<`.
+    pub fn push_slice(&mut self, data: &[u8]) -> &mut Self {
+        let (prefix, prefix_len) = push_prefix(data.len());
+        self.0.extend_from_slice(&prefix[..prefix_len]);
+        self.0.extend_from_slice(data);
+        self
+    }
+
+    /// Appends a push of the number `n` to this script, using a small integer
+    /// opcode where possible, matching `CScript::push_int64`.
+    pub fn push_num(&mut self, n: i64) -> &mut Self {
+        match n {
+            0 => self.push_opcode(Opcode::OP_0),
+            -1 => self.push_opcode(Opcode::OP_1NEGATE),
+            1..=16 => self.push_opcode(Opcode(Opcode::OP_1.0 + (n as u8 - 1))),
+            _ => self.push_slice(&ScriptNum::new(n).to_bytes()),
+        }
+    }
+
+    /// Returns an iterator over the instructions of this script.
+    pub fn instructions(&self) -> Instructions<'_> {
+        instructions(&self.0)
+    }
+
+    /// Returns true if this is a pay-to-script-hash script, matching
+    /// `CScript::IsPayToScriptHash`.
+    pub fn is_pay_to_script_hash(&self) -> bool {
+        let s = &self.0;
+        s.len() == 23 && s[0] == Opcode::OP_HASH160.0 && s[1] == 0x14 && s[22] == Opcode::OP_EQUAL.0
+    }
+
+    /// Returns true if this is a pay-to-public-key-hash script, matching
+    /// `CScript::IsPayToPublicKeyHash`.
+    pub fn is_pay_to_public_key_hash(&self) -> bool {
+        let s = &self.0;
+        s.len() == 25
+            && s[0] == Opcode::OP_DUP.0
+            && s[1] == Opcode::OP_HASH160.0
+            && s[2] == 0x14
+            && s[23] == Opcode::OP_EQUALVERIFY.0
+            && s[24] == Opcode::OP_CHECKSIG.0
+    }
+
+    /// Returns true if the script only contains push operations, matching
+    /// `CScript::IsPushOnly`.
+    ///
+    /// Like zcashd, this treats `OP_RESERVED` as a push operation.
+    pub fn is_push_only(&self) -> bool {
+        is_push_only(&self.0)
+    }
+
+    /// Returns true if the script is guaranteed to fail at execution,
+    /// regardless of the initial stack, matching `CScript::IsUnspendable`.
+    pub fn is_unspendable(&self) -> bool {
+        self.0.first() == Some(&Opcode::OP_RETURN.0) || self.0.len() > MAX_SCRIPT_SIZE
+    }
+
+    /// Returns the number of signature operations in this script, matching
+    /// `CScript::GetSigOpCount(fAccurate)`.
+    ///
+    /// Inaccurate counting assumes every `OP_CHECKMULTISIG` checks the
+    /// maximum number of public keys, as the legacy sigop limit does.
+    pub fn sig_op_count(&self, accurate: bool) -> u32 {
+        let mut n = 0;
+        let mut last_opcode: Option<Opcode> = None;
+        for op in self.instructions() {
+            let Ok(Instruction { opcode, .. }) = op else {
+                break;
+            };
+            match opcode {
+                Opcode::OP_CHECKSIG | Opcode::OP_CHECKSIGVERIFY => n += 1,
+                Opcode::OP_CHECKMULTISIG | Opcode::OP_CHECKMULTISIGVERIFY => {
+                    n += match last_opcode {
+                        Some(last) if accurate && last.is_small_integer() => {
+                            u32::from(last.decode_op_n().expect("is a small integer"))
+                        }
+                        _ => MAX_PUBKEYS_PER_MULTISIG as u32,
+                    }
+                }
+                _ => {}
+            }
+            last_opcode = Some(opcode);
+        }
+        n
+    }
+
+    /// Returns the human-readable disassembly of this script, matching
+    /// zcashd's `ScriptToAsmStr` for scriptPubKeys.
+    pub fn to_asm(&self) -> String {
+        let mut asm = String::new();
+        for instruction in self.instructions() {
+            if !asm.is_empty() {
+                asm.push(' ');
+            }
+            match instruction {
+                Err(_) => {
+                    asm.push_str("[error]");
+                    break;
+                }
+                Ok(Instruction { opcode, data, .. }) if opcode.is_push_data() => {
+                    if data.len() <= ScriptNum::DEFAULT_MAX_SIZE {
+                        let num = ScriptNum::decode(data, false, ScriptNum::DEFAULT_MAX_SIZE)
+                            .expect("push is at most the maximum size");
+                        asm.push_str(&num.to_i32().to_string());
+                    } else {
+                        asm.push_str(&hex::encode(data));
+                    }
+                }
+                Ok(Instruction { opcode, .. }) => asm.push_str(opcode.name()),
+            }
+        }
+        asm
+    }
+}
+
+impl From<Vec<u8>> for Script {
+    fn from(bytes: Vec<u8>) -> Self {
+        Script(bytes)
+    }
+}
+
+impl From<&[u8]> for Script {
+    fn from(bytes: &[u8]) -> Self {
+        Script(bytes.to_vec())
+    }
+}
+
+impl FromHex for Script {
+    type Error = hex::FromHexError;
+
+    fn from_hex<T: AsRef<[u8]>>(hex: T) -> Result<Self, Self::Error> {
+        Vec::from_hex(hex).map(Script)
+    }
+}
+
+impl AsRef<[u8]> for Script {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Serializes the script as a hex string.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Script {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode(&self.0))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Script {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = <String as serde::Deserialize>::deserialize(deserializer)?;
+        Script::from_hex(hex).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Generates scripts as sequences of whole instructions, like the `proptest`
+/// strategies of the `test-dependencies` feature, so that fuzzers mostly
+/// produce scripts that parse: small-number pushes, opcodes other than direct
+/// pushes, and minimal data pushes of up to [`MAX_SCRIPT_ELEMENT_SIZE`] bytes.
+#[cfg(feature = "arbitrary")]
+impl<'a> ::arbitrary::Arbitrary<'a> for Script {
+    fn arbitrary(u: &mut ::arbitrary::Unstructured<'a>) -> ::arbitrary::Result<Self> {
+        let mut script = Script::default();
+        u.arbitrary_loop(None, Some(MAX_OPS_PER_SCRIPT as u32), |u| {
+            match u.int_in_range(0..=2)? {
+                0 => script.push_num(u.int_in_range(-1..=16)?),
+                1 => script.push_opcode(Opcode(u.int_in_range(Opcode::OP_1NEGATE.0..=u8::MAX)?)),
+                _ => {
+                    let len = u.int_in_range(0..=MAX_SCRIPT_ELEMENT_SIZE)?;
+                    script.push_slice(u.bytes(len.min(u.len()))?)
+                }
+            };
+            Ok(std::ops::ControlFlow::Continue(()))
+        })?;
+        Ok(script)
+    }
+}
+
+/// Returns an iterator over the instructions of a serialized script.
+pub fn instructions(script: &[u8]) -> Instructions<'_> {
+    Instructions { script, pc: 0 }
+}
+
+/// Returns the opcode and length bytes that `CScript::operator<<` writes
+/// before pushing `len` bytes, and how many of the five bytes it writes.
+pub(crate) fn push_prefix(len: usize) -> ([u8; 5], usize) {
+    let mut prefix = [0; 5];
+    if len < usize::from(Opcode::OP_PUSHDATA1.0) {
+        prefix[0] = len as u8;
+        (prefix, 1)
+    } else if len <= 0xff {
+        prefix[..2].copy_from_slice(&[Opcode::OP_PUSHDATA1.0, len as u8]);
+        (prefix, 2)
+    } else if len <= 0xffff {
+        prefix[0] = Opcode::OP_PUSHDATA2.0;
+        prefix[1..3].copy_from_slice(&(len as u16).to_le_bytes());
+        (prefix, 3)
+    } else {
+        prefix[0] = Opcode::OP_PUSHDATA4.0;
+        prefix[1..].copy_from_slice(&(len as u32).to_le_bytes());
+        (prefix, 5)
+    }
+}
+
+/// Returns true if the serialized script only contains push operations.
+pub(crate) fn is_push_only(script: &[u8]) -> bool {
+    instructions(script).all(|op| matches!(op, Ok(op) if op.opcode <= Opcode::OP_16))
+}
+
+/// Builds a [`Script`] from opcodes and pushes, like
+/// `script!(OP_DUP OP_HASH160 [hash] OP_EQUALVERIFY OP_CHECKSIG)`.
+///
+/// Each token is an [`Opcode`] name, a number literal such as `2` or a
+/// parenthesized number expression such as `(-1)` or `(n)`, pushed as by
+/// [`Script::push_num`], or data in brackets such as `[hash]`, whose value
+/// is anything that is `AsRef<[u8]>`, pushed as by [`Script::push_slice`].
+/// Opcode names are checked at compile time, and pushes panic if their data
+/// is longer than [`MAX_SCRIPT_ELEMENT_SIZE`], which no script could
+/// execute.
+#[macro_export]
+macro_rules! script {
+    ($($tokens:tt)*) => {{
+        #[allow(unused_mut)]
+        let mut script = $crate::script::Script::default();
+        $crate::__script_push!(script; $($tokens)*);
+        script
+    }};
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __script_push {
+    ($script:ident;) => {};
+    ($script:ident; [$data:expr] $($rest:tt)*) => {
+        let data: &[u8] = ::core::convert::AsRef::as_ref(&$data);
+        assert!(
+            data.len() <= $crate::script::MAX_SCRIPT_ELEMENT_SIZE,
+            "push of {} bytes is larger than MAX_SCRIPT_ELEMENT_SIZE",
+            data.len()
+        );
+        $script.push_slice(data);
+        $crate::__script_push!($script; $($rest)*);
+    };
+    ($script:ident; ($n:expr) $($rest:tt)*) => {
+        $script.push_num($n);
+        $crate::__script_push!($script; $($rest)*);
+    };
+    ($script:ident; $n:literal $($rest:tt)*) => {
+        $script.push_num($n);
+        $crate::__script_push!($script; $($rest)*);
+    };
+    ($script:ident; $opcode:ident $($rest:tt)*) => {
+        $script.push_opcode($crate::opcode::Opcode::$opcode);
+        $crate::__script_push!($script; $($rest)*);
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pushdata() {
+        let script = Script::from_hex("4c0301020300").unwrap();
+        let ops = script
+            .instructions()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(ops.len(), 2);
+        assert_eq!(ops[0].opcode, Opcode::OP_PUSHDATA1);
+        assert_eq!(ops[0].data, &[1, 2, 3]);
+        assert_eq!(ops[1].offset, 5);
+        assert_eq!(ops[1].opcode, Opcode::OP_0);
+    }
+
+    #[test]
+    fn truncated_push_is_an_error() {
+        let script = Script(vec![0x4d, 0x01]);
+        let mut ops = script.instructions();
+        assert_eq!(ops.next(), Some(Err(ParseError { offset: 0 })));
+        assert_eq!(ops.next(), None);
+    }
+
+    #[test]
+    fn asm_matches_zcashd() {
+        let hex = "76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac";
+        let script = Script::from_hex(hex).unwrap();
+        assert_eq!(hex::encode(&script), hex);
+        assert_eq!(
+            script.to_asm(),
+            "OP_DUP OP_HASH160 f47cac1e6fec195c055994e8064ffccce0044dd7 OP_EQUALVERIFY OP_CHECKSIG"
+        );
+
+        let script = Script::from_hex("0002ff0001815160b1ba4c").unwrap();
+        assert_eq!(script.to_asm(), "0 255 -1 1 16 OP_NOP2 OP_UNKNOWN [error]");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_as_hex() {
+        let script = Script::from_hex("5253aeac").unwrap();
+        assert_eq!(serde_json::to_string(&script).unwrap(), "\"5253aeac\"");
+        assert_eq!(
+            serde_json::from_str::<Script>("\"5253aeac\"").unwrap(),
+            script
+        );
+        assert!(serde_json::from_str::<Script>("\"525\"").is_err());
+    }
+
+    #[test]
+    fn counts_sigops() {
+        // 2-of-3 multisig, followed by OP_CHECKSIG.
+        let script = Script::from_hex("5253aeac").unwrap();
+        assert_eq!(script.sig_op_count(true), 4);
+        assert_eq!(script.sig_op_count(false), 21);
+    }
+
+    #[test]
+    fn builds_pushes_like_zcashd() {
+        let mut script = Script::default();
+        script
+            .push_num(0)
+            .push_num(-1)
+            .push_num(16)
+            .push_num(17)
+            .push_slice(&[0xab; 75])
+            .push_slice(&[0xcd; 76]);
+
+        let ops = script
+            .instructions()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        let opcodes: Vec<_> = ops.iter().map(|op| op.opcode).collect();
+        assert_eq!(
+            opcodes,
+            [
+                Opcode::OP_0,
+                Opcode::OP_1NEGATE,
+                Opcode::OP_16,
+                Opcode(1),
+                Opcode(75),
+                Opcode::OP_PUSHDATA1,
+            ]
+        );
+        assert_eq!(ops[3].data, &[17]);
+        assert_eq!(ops[5].data.len(), 76);
+    }
+
+    #[test]
+    fn builds_scripts_with_macro() {
+        let hash = [0x11; 20];
+        let p2pkh = crate::script!(OP_DUP OP_HASH160 [hash] OP_EQUALVERIFY OP_CHECKSIG);
+        assert!(p2pkh.is_pay_to_public_key_hash());
+        assert_eq!(p2pkh.as_bytes()[3..23], hash);
+
+        let n = 17;
+        let mut expected = Script::default();
+        expected
+            .push_num(0)
+            .push_num(-1)
+            .push_num(16)
+            .push_num(17)
+            .push_slice(&[0xab; 76])
+            .push_opcode(Opcode::OP_CHECKMULTISIG);
+        assert_eq!(
+            crate::script!(0 (-1) 16 (n) [vec![0xab; 76]] OP_CHECKMULTISIG),
+            expected
+        );
+        assert!(crate::script!().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "larger than MAX_SCRIPT_ELEMENT_SIZE")]
+    fn macro_rejects_oversized_pushes() {
+        crate::script!([[0; MAX_SCRIPT_ELEMENT_SIZE + 1]]);
+    }
+}
+
+/// Proofs for the Kani model checker, run with `cargo kani` and in CI.
+///
+/// Only the Rust parser and signature operation counting are proven. The
+/// stack size, opcode count and other evaluation limits are enforced by
+/// zcashd's C++ interpreter, which Kani can't model, so there is no Rust code
+/// for harnesses to cover them.
+#[cfg(kani)]
+mod verification {
+    use super::*;
+
+    /// The largest script the proofs cover.
+    const MAX_LEN: usize = 8;
+
+    /// Parsing any script never panics, every instruction lies within the
+    /// script, and parsing stops at the first error.
+    #[kani::proof]
+    #[kani::unwind(10)]
+    fn instructions_stay_in_bounds() {
+        let bytes: [u8; MAX_LEN] = kani::any();
+        let len: usize = kani::any_where(|&len| len <= MAX_LEN);
+        let script = &bytes[..len];
+        let mut end = 0;
+        let mut failed = false;
+        for op in instructions(script) {
+            assert!(!failed);
+            match op {
+                Ok(op) => {
+                    assert_eq!(op.offset, end);
+                    // The opcode, the length of the data, and the data.
+                    let length_size = match op.opcode {
+                        Opcode::OP_PUSHDATA1 => 1,
+                        Opcode::OP_PUSHDATA2 => 2,
+                        Opcode::OP_PUSHDATA4 => 4,
+                        _ => 0,
+                    };
+                    end = op.offset + 1 + length_size + op.data.len();
+                    assert!(end <= len);
+                }
+                Err(err) => {
+                    assert_eq!(err.offset, end);
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    /// Counting signature operations never panics, and each opcode counts at
+    /// most [`MAX_PUBKEYS_PER_MULTISIG`].
+    #[kani::proof]
+    #[kani::unwind(10)]
+    fn sig_op_count_is_bounded() {
+        let bytes: [u8; MAX_LEN] = kani::any();
+        let len: usize = kani::any_where(|&len| len <= MAX_LEN);
+        let script = Script::from(&bytes[..len]);
+        let count = script.sig_op_count(kani::any());
+        assert!(count as usize <= len * MAX_PUBKEYS_PER_MULTISIG);
+    }
+}
@@ -0,0 +1,270 @@
+//! zcashd's interpreter errors, with the exact messages it reports.
+//!
+//! [`verify_script_detailed`](crate::verify::verify_script_detailed) and the
+//! [observer](crate::observer) report which of these made a script fail,
+//! this crate's static analyses find them without evaluating the script, and
+//! services replacing zcashd can report them in the words its RPCs and logs
+//! use.
+
+use std::fmt;
+
+use crate::{diagnostics::Violation, spendability::Unspendable};
+
+/// An error from zcashd's script interpreter, matching its `ScriptError`.
+///
+/// Each error's [code](ScriptError::code) is its value in zcashd's
+/// `ScriptError_t`, which zcashd never renumbers, so it is stable across
+/// versions of this crate.
+///
+/// With the `serde` feature, errors are serialized by name, like
+/// `"eval_false"`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+#[repr(u32)]
+pub enum ScriptError {
+    /// `SCRIPT_ERR_OK`.
+    Ok = 0,
+    /// `SCRIPT_ERR_UNKNOWN_ERROR`.
+    UnknownError = 1,
+    /// `SCRIPT_ERR_EVAL_FALSE`.
+    EvalFalse = 2,
+    /// `SCRIPT_ERR_OP_RETURN`.
+    OpReturn = 3,
+    /// `SCRIPT_ERR_SCRIPT_SIZE`.
+    ScriptSize = 4,
+    /// `SCRIPT_ERR_PUSH_SIZE`.
+    PushSize = 5,
+    /// `SCRIPT_ERR_OP_COUNT`.
+    OpCount = 6,
+    /// `SCRIPT_ERR_STACK_SIZE`.
+    StackSize = 7,
+    /// `SCRIPT_ERR_SIG_COUNT`.
+    SigCount = 8,
+    /// `SCRIPT_ERR_PUBKEY_COUNT`.
+    PubKeyCount = 9,
+    /// `SCRIPT_ERR_VERIFY`.
+    Verify = 10,
+    /// `SCRIPT_ERR_EQUALVERIFY`.
+    EqualVerify = 11,
+    /// `SCRIPT_ERR_CHECKMULTISIGVERIFY`.
+    CheckMultisigVerify = 12,
+    /// `SCRIPT_ERR_CHECKSIGVERIFY`.
+    CheckSigVerify = 13,
+    /// `SCRIPT_ERR_NUMEQUALVERIFY`.
+    NumEqualVerify = 14,
+    /// `SCRIPT_ERR_BAD_OPCODE`.
+    BadOpcode = 15,
+    /// `SCRIPT_ERR_DISABLED_OPCODE`.
+    DisabledOpcode = 16,
+    /// `SCRIPT_ERR_INVALID_STACK_OPERATION`.
+    InvalidStackOperation = 17,
+    /// `SCRIPT_ERR_INVALID_ALTSTACK_OPERATION`.
+    InvalidAltstackOperation = 18,
+    /// `SCRIPT_ERR_UNBALANCED_CONDITIONAL`.
+    UnbalancedConditional = 19,
+    /// `SCRIPT_ERR_NEGATIVE_LOCKTIME`.
+    NegativeLockTime = 20,
+    /// `SCRIPT_ERR_UNSATISFIED_LOCKTIME`.
+    UnsatisfiedLockTime = 21,
+    /// `SCRIPT_ERR_SIG_HASHTYPE`.
+    SigHashType = 22,
+    /// `SCRIPT_ERR_SIG_DER`.
+    SigDer = 23,
+    /// `SCRIPT_ERR_MINIMALDATA`.
+    MinimalData = 24,
+    /// `SCRIPT_ERR_SIG_PUSHONLY`.
+    SigPushOnly = 25,
+    /// `SCRIPT_ERR_SIG_HIGH_S`.
+    SigHighS = 26,
+    /// `SCRIPT_ERR_SIG_NULLDUMMY`.
+    SigNullDummy = 27,
+    /// `SCRIPT_ERR_PUBKEYTYPE`.
+    PubKeyType = 28,
+    /// `SCRIPT_ERR_CLEANSTACK`.
+    CleanStack = 29,
+    /// `SCRIPT_ERR_DISCOURAGE_UPGRADABLE_NOPS`.
+    DiscourageUpgradableNops = 30,
+}
+
+impl ScriptError {
+    /// Every error, in code order.
+    const ALL: [ScriptError; 31] = [
+        ScriptError::Ok,
+        ScriptError::UnknownError,
+        ScriptError::EvalFalse,
+        ScriptError::OpReturn,
+        ScriptError::ScriptSize,
+        ScriptError::PushSize,
+        ScriptError::OpCount,
+        ScriptError::StackSize,
+        ScriptError::SigCount,
+        ScriptError::PubKeyCount,
+        ScriptError::Verify,
+        ScriptError::EqualVerify,
+        ScriptError::CheckMultisigVerify,
+        ScriptError::CheckSigVerify,
+        ScriptError::NumEqualVerify,
+        ScriptError::BadOpcode,
+        ScriptError::DisabledOpcode,
+        ScriptError::InvalidStackOperation,
+        ScriptError::InvalidAltstackOperation,
+        ScriptError::UnbalancedConditional,
+        ScriptError::NegativeLockTime,
+        ScriptError::UnsatisfiedLockTime,
+        ScriptError::SigHashType,
+        ScriptError::SigDer,
+        ScriptError::MinimalData,
+        ScriptError::SigPushOnly,
+        ScriptError::SigHighS,
+        ScriptError::SigNullDummy,
+        ScriptError::PubKeyType,
+        ScriptError::CleanStack,
+        ScriptError::DiscourageUpgradableNops,
+    ];
+
+    /// Returns the stable code for this error, its value in zcashd's
+    /// `ScriptError_t`.
+    pub const fn code(self) -> u32 {
+        self as u32
+    }
+
+    /// Returns the error with the given code, or `None` if there is none.
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.get(usize::try_from(code).ok()?).copied()
+    }
+
+    /// Returns the message zcashd's `ScriptErrorString` gives for this error.
+    ///
+    /// The messages are zcashd's, quirks included: `SigPushOnly` reads "Only
+    /// non-push operators", and `CleanStack`, which `ScriptErrorString`
+    /// doesn't handle, is "unknown error".
+    pub fn as_zcashd_str(self) -> &'static str {
+        match self {
+            ScriptError::Ok => "No error",
+            ScriptError::EvalFalse => {
+                "Script evaluated without error but finished with a false/empty top stack element"
+            }
+            ScriptError::Verify => "Script failed an OP_VERIFY operation",
+            ScriptError::EqualVerify => "Script failed an OP_EQUALVERIFY operation",
+            ScriptError::CheckMultisigVerify => "Script failed an OP_CHECKMULTISIGVERIFY operation",
+            ScriptError::CheckSigVerify => "Script failed an OP_CHECKSIGVERIFY operation",
+            ScriptError::NumEqualVerify => "Script failed an OP_NUMEQUALVERIFY operation",
+            ScriptError::ScriptSize => "Script is too big",
+            ScriptError::PushSize => "Push value size limit exceeded",
+            ScriptError::OpCount => "Operation limit exceeded",
+            ScriptError::StackSize => "Stack size limit exceeded",
+            ScriptError::SigCount => "Signature count negative or greater than pubkey count",
+            ScriptError::PubKeyCount => "Pubkey count negative or limit exceeded",
+            ScriptError::BadOpcode => "Opcode missing or not understood",
+            ScriptError::DisabledOpcode => "Attempted to use a disabled opcode",
+            ScriptError::InvalidStackOperation => "Operation not valid with the current stack size",
+            ScriptError::InvalidAltstackOperation => {
+                "Operation not valid with the current altstack size"
+            }
+            ScriptError::OpReturn => "OP_RETURN was encountered",
+            ScriptError::UnbalancedConditional => "Invalid OP_IF construction",
+            ScriptError::NegativeLockTime => "Negative locktime",
+            ScriptError::UnsatisfiedLockTime => "Locktime requirement not satisfied",
+            ScriptError::SigHashType => "Signature hash type missing or not understood",
+            ScriptError::SigDer => "Non-canonical DER signature",
+            ScriptError::MinimalData => "Data push larger than necessary",
+            ScriptError::SigPushOnly => "Only non-push operators allowed in signatures",
+            ScriptError::SigHighS => "Non-canonical signature: S value is unnecessarily high",
+            ScriptError::SigNullDummy => "Dummy CHECKMULTISIG argument must be zero",
+            ScriptError::DiscourageUpgradableNops => "NOPx reserved for soft-fork upgrades",
+            ScriptError::PubKeyType => "Public key is neither compressed or uncompressed",
+            ScriptError::UnknownError | ScriptError::CleanStack => "unknown error",
+        }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_zcashd_str())
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+impl From<Violation> for ScriptError {
+    fn from(violation: Violation) -> Self {
+        match violation {
+            Violation::MinimalData => ScriptError::MinimalData,
+            Violation::SigPushOnly => ScriptError::SigPushOnly,
+            Violation::SigDer => ScriptError::SigDer,
+            Violation::SigHashType => ScriptError::SigHashType,
+            Violation::SigHighS => ScriptError::SigHighS,
+            Violation::PubKeyType => ScriptError::PubKeyType,
+            Violation::SigNullDummy => ScriptError::SigNullDummy,
+            Violation::DiscourageUpgradableNops => ScriptError::DiscourageUpgradableNops,
+            Violation::CleanStack => ScriptError::CleanStack,
+        }
+    }
+}
+
+impl From<Unspendable> for ScriptError {
+    fn from(reason: Unspendable) -> Self {
+        match reason {
+            Unspendable::OpReturn => ScriptError::OpReturn,
+            Unspendable::ScriptSize => ScriptError::ScriptSize,
+            Unspendable::BadOpcode => ScriptError::BadOpcode,
+            Unspendable::PushSize => ScriptError::PushSize,
+            Unspendable::OpCount => ScriptError::OpCount,
+            Unspendable::DisabledOpcode => ScriptError::DisabledOpcode,
+            Unspendable::UnbalancedConditional => ScriptError::UnbalancedConditional,
+            Unspendable::EvalFalse => ScriptError::EvalFalse,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_zcashd_strings() {
+        assert_eq!(ScriptError::Ok.to_string(), "No error");
+        assert_eq!(
+            ScriptError::from(Violation::SigHighS).as_zcashd_str(),
+            "Non-canonical signature: S value is unnecessarily high"
+        );
+        assert_eq!(
+            ScriptError::from(Unspendable::UnbalancedConditional).as_zcashd_str(),
+            "Invalid OP_IF construction"
+        );
+        assert_eq!(
+            ScriptError::SigPushOnly.as_zcashd_str(),
+            "Only non-push operators allowed in signatures"
+        );
+        assert_eq!(ScriptError::CleanStack.as_zcashd_str(), "unknown error");
+    }
+
+    #[test]
+    fn codes_match_zcashd() {
+        for (code, error) in ScriptError::ALL.into_iter().enumerate() {
+            assert_eq!(error.code(), code as u32);
+            assert_eq!(ScriptError::from_code(code as u32), Some(error));
+        }
+        assert_eq!(ScriptError::EvalFalse.code(), 2);
+        assert_eq!(ScriptError::CleanStack.code(), 29);
+        assert_eq!(ScriptError::DiscourageUpgradableNops.code(), 30);
+        // SCRIPT_ERR_ERROR_COUNT isn't an error.
+        assert_eq!(ScriptError::from_code(31), None);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_as_names() {
+        for (error, json) in [
+            (ScriptError::EvalFalse, "\"eval_false\""),
+            (ScriptError::SigHighS, "\"sig_high_s\""),
+        ] {
+            assert_eq!(serde_json::to_string(&error).unwrap(), json);
+            assert_eq!(serde_json::from_str::<ScriptError>(json).unwrap(), error);
+        }
+    }
+}
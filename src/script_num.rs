@@ -0,0 +1,305 @@
+//! Script numbers, matching zcashd's `CScriptNum`
+//! (`depend/zcash/src/script/script.h`).
+
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Neg, Sub},
+};
+
+/// An error returned when a byte vector can't be decoded as a script number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScriptNumError {
+    /// The encoding is longer than the maximum allowed size.
+    Overflow,
+    /// Minimal encoding was required, but the encoding has extra bytes.
+    NonMinimal,
+}
+
+impl fmt::Display for ScriptNumError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScriptNumError::Overflow => write!(f, "script number overflow"),
+            ScriptNumError::NonMinimal => write!(f, "non-minimally encoded script number"),
+        }
+    }
+}
+
+impl std::error::Error for ScriptNumError {}
+
+/// A numeric value on the script stack.
+///
+/// Numeric opcodes only accept operands of at most [`ScriptNum::DEFAULT_MAX_SIZE`]
+/// bytes, but their results may be larger. Like `CScriptNum`, this stores the
+/// value as an `i64`, and arithmetic panics on `i64` overflow, which can't
+/// happen for results of in-range operands.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ScriptNum(i64);
+
+/// The encoding of `i64::MIN` that zcashd produces and accepts.
+const INT64_MIN_ENCODING: [u8; 9] = [0, 0, 0, 0, 0, 0, 0, 0x80, 0x80];
+
+impl ScriptNum {
+    /// The maximum encoded size of a numeric opcode operand.
+    pub const DEFAULT_MAX_SIZE: usize = 4;
+
+    /// Creates a script number with the given value.
+    pub const fn new(value: i64) -> Self {
+        ScriptNum(value)
+    }
+
+    /// Decodes a script number from its stack encoding.
+    ///
+    /// Fails if the encoding is longer than `max_size` bytes, or if
+    /// `require_minimal` is set and the encoding is not minimal (which also
+    /// rejects negative zero).
+    pub fn decode(
+        bytes: &[u8],
+        require_minimal: bool,
+        max_size: usize,
+    ) -> Result<Self, ScriptNumError> {
+        if bytes.len() > max_size {
+            return Err(ScriptNumError::Overflow);
+        }
+        if require_minimal && !is_minimally_encoded(bytes) {
+            return Err(ScriptNumError::NonMinimal);
+        }
+
+        let Some(&last) = bytes.last() else {
+            return Ok(ScriptNum(0));
+        };
+        if bytes == INT64_MIN_ENCODING {
+            return Ok(ScriptNum(i64::MIN));
+        }
+        if bytes.len() > 8 {
+            return Err(ScriptNumError::Overflow);
+        }
+
+        let mut result = bytes
+            .iter()
+            .enumerate()
+            .fold(0u64, |acc, (i, &b)| acc | (u64::from(b) << (8 * i)));
+
+        // If the most significant byte has its sign bit set, clear it and
+        // negate the result.
+        if last & 0x80 != 0 {
+            result &= !(0x80u64 << (8 * (bytes.len() - 1)));
+            Ok(ScriptNum(-(result as i64)))
+        } else {
+            Ok(ScriptNum(result as i64))
+        }
+    }
+
+    /// Returns the value of this number.
+    pub const fn value(self) -> i64 {
+        self.0
+    }
+
+    /// Returns the value of this number clamped to the `i32` range, matching
+    /// `CScriptNum::getint`.
+    pub fn to_i32(self) -> i32 {
+        self.0.clamp(i32::MIN.into(), i32::MAX.into()) as i32
+    }
+
+    /// Returns the minimal stack encoding of this number.
+    pub fn to_bytes(self) -> Vec<u8> {
+        let value = self.0;
+        if value == 0 {
+            return vec![];
+        }
+        if value == i64::MIN {
+            return INT64_MIN_ENCODING.to_vec();
+        }
+
+        let neg = value < 0;
+        let mut abs = value.unsigned_abs();
+        let mut result = vec![];
+        while abs != 0 {
+            result.push((abs & 0xff) as u8);
+            abs >>= 8;
+        }
+
+        // If the most significant byte has its sign bit set, add a byte to hold
+        // the sign. Otherwise, use the spare bit for the sign of negative values.
+        let last = result.last_mut().expect("value is nonzero");
+        if *last & 0x80 != 0 {
+            result.push(if neg { 0x80 } else { 0 });
+        } else if neg {
+            *last |= 0x80;
+        }
+        result
+    }
+
+    /// Checked addition, returning `None` on `i64` overflow.
+    pub fn checked_add(self, rhs: ScriptNum) -> Option<ScriptNum> {
+        self.0.checked_add(rhs.0).map(ScriptNum)
+    }
+
+    /// Checked subtraction, returning `None` on `i64` overflow.
+    pub fn checked_sub(self, rhs: ScriptNum) -> Option<ScriptNum> {
+        self.0.checked_sub(rhs.0).map(ScriptNum)
+    }
+
+    /// Checked negation, returning `None` for `i64::MIN`.
+    pub fn checked_neg(self) -> Option<ScriptNum> {
+        self.0.checked_neg().map(ScriptNum)
+    }
+}
+
+/// Returns true if `bytes` is the minimal encoding of its value.
+pub fn is_minimally_encoded(bytes: &[u8]) -> bool {
+    match bytes {
+        [] => true,
+        // If the most significant byte, excluding the sign bit, is zero, the
+        // encoding is only minimal if the next byte needs that sign bit.
+        [.., prev, last] if last & 0x7f == 0 => prev & 0x80 != 0,
+        [last] => last & 0x7f != 0,
+        _ => true,
+    }
+}
+
+impl From<i64> for ScriptNum {
+    fn from(value: i64) -> Self {
+        ScriptNum(value)
+    }
+}
+
+impl From<ScriptNum> for i64 {
+    fn from(num: ScriptNum) -> Self {
+        num.0
+    }
+}
+
+impl PartialEq<i64> for ScriptNum {
+    fn eq(&self, other: &i64) -> bool {
+        self.0 == *other
+    }
+}
+
+impl PartialOrd<i64> for ScriptNum {
+    fn partial_cmp(&self, other: &i64) -> Option<Ordering> {
+        self.0.partial_cmp(other)
+    }
+}
+
+impl Add for ScriptNum {
+    type Output = ScriptNum;
+
+    fn add(self, rhs: ScriptNum) -> ScriptNum {
+        self.checked_add(rhs)
+            .expect("script number addition overflowed")
+    }
+}
+
+impl Sub for ScriptNum {
+    type Output = ScriptNum;
+
+    fn sub(self, rhs: ScriptNum) -> ScriptNum {
+        self.checked_sub(rhs)
+            .expect("script number subtraction overflowed")
+    }
+}
+
+impl Neg for ScriptNum {
+    type Output = ScriptNum;
+
+    fn neg(self) -> ScriptNum {
+        self.checked_neg()
+            .expect("script number negation overflowed")
+    }
+}
+
+impl fmt::Display for ScriptNum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for value in [
+            0,
+            1,
+            -1,
+            127,
+            128,
+            -128,
+            255,
+            -255,
+            256,
+            i32::MAX.into(),
+            i32::MIN.into(),
+            i64::MAX,
+            i64::MIN,
+        ] {
+            let bytes = ScriptNum::new(value).to_bytes();
+            assert_eq!(
+                ScriptNum::decode(&bytes, true, 9),
+                Ok(ScriptNum::new(value))
+            );
+        }
+        assert_eq!(ScriptNum::new(255).to_bytes(), vec![0xff, 0x00]);
+        assert_eq!(ScriptNum::new(-255).to_bytes(), vec![0xff, 0x80]);
+    }
+
+    #[test]
+    fn rejects_invalid_encodings() {
+        assert_eq!(
+            ScriptNum::decode(&[1, 2, 3, 4, 5], false, ScriptNum::DEFAULT_MAX_SIZE),
+            Err(ScriptNumError::Overflow)
+        );
+        assert_eq!(
+            ScriptNum::decode(&[0x80], true, ScriptNum::DEFAULT_MAX_SIZE),
+            Err(ScriptNumError::NonMinimal)
+        );
+        assert_eq!(
+            ScriptNum::decode(&[1, 0], true, ScriptNum::DEFAULT_MAX_SIZE),
+            Err(ScriptNumError::NonMinimal)
+        );
+        assert_eq!(
+            ScriptNum::decode(&[1, 0], false, ScriptNum::DEFAULT_MAX_SIZE),
+            Ok(ScriptNum::new(1))
+        );
+        assert_eq!(
+            ScriptNum::decode(&[0x80], false, ScriptNum::DEFAULT_MAX_SIZE),
+            Ok(ScriptNum::new(0))
+        );
+    }
+
+    #[test]
+    fn clamps_to_i32() {
+        assert_eq!(ScriptNum::new(i64::MAX).to_i32(), i32::MAX);
+        assert_eq!(ScriptNum::new(i64::MIN).to_i32(), i32::MIN);
+        assert_eq!(ScriptNum::new(-5).to_i32(), -5);
+    }
+}
+
+/// Proofs for the Kani model checker, run with `cargo kani`.
+#[cfg(kani)]
+mod verification {
+    use super::*;
+
+    /// Decoding never panics, and minimal encodings round-trip.
+    #[kani::proof]
+    #[kani::unwind(10)]
+    fn decode_round_trips() {
+        let bytes: [u8; 9] = kani::any();
+        let len: usize = kani::any_where(|&len| len <= bytes.len());
+        let bytes = &bytes[..len];
+        if let Ok(num) = ScriptNum::decode(bytes, true, 9) {
+            assert_eq!(num.to_bytes(), bytes);
+        }
+    }
+
+    /// Every value's encoding decodes to it.
+    #[kani::proof]
+    #[kani::unwind(10)]
+    fn encode_round_trips() {
+        let num = ScriptNum::new(kani::any());
+        assert_eq!(ScriptNum::decode(&num.to_bytes(), true, 9), Ok(num));
+    }
+}
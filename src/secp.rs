@@ -0,0 +1,361 @@
+//! Bindings to the vendored libsecp256k1, which is compiled for zcashd's
+//! signature verification (or linked externally with `external-secp`), for
+//! the key operations this crate performs in Rust.
+
+use std::{
+    os::raw::{c_int, c_uchar, c_uint, c_void},
+    ptr,
+    sync::OnceLock,
+};
+
+/// An opaque `secp256k1_context`.
+#[repr(C)]
+struct Context {
+    _private: [u8; 0],
+}
+
+/// A parsed public key, matching `secp256k1_pubkey`. Its contents are
+/// platform-specific, so it must be serialized to be compared.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct RawPublicKey {
+    data: [u8; 64],
+}
+
+/// A parsed ECDSA signature, matching `secp256k1_ecdsa_signature`.
+#[repr(C)]
+struct RawSignature {
+    data: [u8; 64],
+}
+
+/// A parsed ECDSA signature with its recovery ID, matching
+/// `secp256k1_ecdsa_recoverable_signature`.
+#[repr(C)]
+struct RawRecoverableSignature {
+    data: [u8; 65],
+}
+
+const SECP256K1_CONTEXT_NONE: c_uint = 1;
+const SECP256K1_EC_COMPRESSED: c_uint = (1 << 1) | (1 << 8);
+const SECP256K1_EC_UNCOMPRESSED: c_uint = 1 << 1;
+
+extern "C" {
+    fn secp256k1_context_create(flags: c_uint) -> *mut Context;
+    fn secp256k1_context_randomize(ctx: *mut Context, seed32: *const c_uchar) -> c_int;
+    fn secp256k1_ec_seckey_verify(ctx: *const Context, seckey: *const c_uchar) -> c_int;
+    fn secp256k1_ec_pubkey_create(
+        ctx: *const Context,
+        pubkey: *mut RawPublicKey,
+        seckey: *const c_uchar,
+    ) -> c_int;
+    fn secp256k1_ec_pubkey_parse(
+        ctx: *const Context,
+        pubkey: *mut RawPublicKey,
+        input: *const c_uchar,
+        inputlen: usize,
+    ) -> c_int;
+    fn secp256k1_ec_pubkey_serialize(
+        ctx: *const Context,
+        output: *mut c_uchar,
+        outputlen: *mut usize,
+        pubkey: *const RawPublicKey,
+        flags: c_uint,
+    ) -> c_int;
+    fn secp256k1_ecdsa_sign(
+        ctx: *const Context,
+        sig: *mut RawSignature,
+        msghash32: *const c_uchar,
+        seckey: *const c_uchar,
+        noncefp: *const c_void,
+        ndata: *const c_void,
+    ) -> c_int;
+    fn secp256k1_ecdsa_signature_parse_der(
+        ctx: *const Context,
+        sig: *mut RawSignature,
+        input: *const c_uchar,
+        inputlen: usize,
+    ) -> c_int;
+    fn secp256k1_ecdsa_signature_normalize(
+        ctx: *const Context,
+        sigout: *mut RawSignature,
+        sigin: *const RawSignature,
+    ) -> c_int;
+    fn secp256k1_ecdsa_verify(
+        ctx: *const Context,
+        sig: *const RawSignature,
+        msghash32: *const c_uchar,
+        pubkey: *const RawPublicKey,
+    ) -> c_int;
+    fn secp256k1_ecdsa_signature_serialize_der(
+        ctx: *const Context,
+        output: *mut c_uchar,
+        outputlen: *mut usize,
+        sig: *const RawSignature,
+    ) -> c_int;
+    fn secp256k1_ecdsa_sign_recoverable(
+        ctx: *const Context,
+        sig: *mut RawRecoverableSignature,
+        msghash32: *const c_uchar,
+        seckey: *const c_uchar,
+        noncefp: *const c_void,
+        ndata: *const c_void,
+    ) -> c_int;
+    fn secp256k1_ecdsa_recoverable_signature_serialize_compact(
+        ctx: *const Context,
+        output64: *mut c_uchar,
+        recid: *mut c_int,
+        sig: *const RawRecoverableSignature,
+    ) -> c_int;
+    fn secp256k1_ecdsa_recoverable_signature_parse_compact(
+        ctx: *const Context,
+        sig: *mut RawRecoverableSignature,
+        input64: *const c_uchar,
+        recid: c_int,
+    ) -> c_int;
+    fn secp256k1_ecdsa_recover(
+        ctx: *const Context,
+        pubkey: *mut RawPublicKey,
+        sig: *const RawRecoverableSignature,
+        msghash32: *const c_uchar,
+    ) -> c_int;
+    #[cfg(feature = "hd-wallet")]
+    fn secp256k1_ec_seckey_tweak_add(
+        ctx: *const Context,
+        seckey: *mut c_uchar,
+        tweak32: *const c_uchar,
+    ) -> c_int;
+}
+
+/// The context shared by every key operation.
+struct SharedContext(*mut Context);
+
+// libsecp256k1 contexts can be used from any thread, except by the functions
+// that modify them, which are only called before the context is shared.
+unsafe impl Send for SharedContext {}
+unsafe impl Sync for SharedContext {}
+
+/// The context shared by every key operation, created on first use unless
+/// supplied with [`set_context`].
+static CONTEXT: OnceLock<SharedContext> = OnceLock::new();
+
+/// Returns the shared context, creating it on first use.
+///
+/// The context is randomized, as libsecp256k1 recommends for operations on
+/// secret keys, to protect against side-channel attacks.
+fn context() -> *const Context {
+    CONTEXT
+        .get_or_init(|| {
+            let seed: [u8; 32] = rand::random();
+            // SAFETY: the context isn't shared until it's initialized.
+            unsafe {
+                let ctx = secp256k1_context_create(SECP256K1_CONTEXT_NONE);
+                assert!(!ctx.is_null(), "secp256k1 context creation failed");
+                let randomized = secp256k1_context_randomize(ctx, seed.as_ptr());
+                assert_eq!(randomized, 1, "secp256k1 context randomization failed");
+                SharedContext(ctx)
+            }
+        })
+        .0
+}
+
+/// Returns the `secp256k1_context` this crate uses for key operations,
+/// creating it on first use, so other code using the same libsecp256k1 can
+/// share it.
+///
+/// zcashd's interpreter verifies signatures with libsecp256k1's static
+/// context. The precomputed multiplication tables are static data in the
+/// library, shared by all its contexts, so a process has one copy of them
+/// for each libsecp256k1 it links. With the `external-secp` feature, this
+/// crate uses the same library as other crates linking it.
+///
+/// The context is shared, so it must not be passed to functions that modify
+/// it, such as `secp256k1_context_randomize`.
+pub fn context_ptr() -> *const c_void {
+    context().cast()
+}
+
+/// Supplies the `secp256k1_context` this crate uses for key operations,
+/// instead of creating its own.
+///
+/// Returns false, without using `ctx`, if the context was already created or
+/// supplied.
+///
+/// # Safety
+///
+/// `ctx` must be a context created by the libsecp256k1 this crate links,
+/// which remains valid for the rest of the process, and which isn't modified
+/// while this crate may use it. It should be randomized, as this crate uses
+/// it to sign.
+pub unsafe fn set_context(ctx: *mut c_void) -> bool {
+    CONTEXT.set(SharedContext(ctx.cast())).is_ok()
+}
+
+/// Returns true if `seckey` is a valid secret key, that is, nonzero and less
+/// than the curve order.
+pub(crate) fn seckey_verify(seckey: &[u8; 32]) -> bool {
+    // SAFETY: the secret key is 32 bytes.
+    unsafe { secp256k1_ec_seckey_verify(context(), seckey.as_ptr()) == 1 }
+}
+
+/// Returns the public key for a valid secret key.
+pub(crate) fn pubkey_create(seckey: &[u8; 32]) -> Option<RawPublicKey> {
+    let mut pubkey = RawPublicKey { data: [0; 64] };
+    // SAFETY: the secret key is 32 bytes.
+    let ret = unsafe { secp256k1_ec_pubkey_create(context(), &mut pubkey, seckey.as_ptr()) };
+    (ret == 1).then_some(pubkey)
+}
+
+/// Parses a compressed or uncompressed public key.
+pub(crate) fn pubkey_parse(input: &[u8]) -> Option<RawPublicKey> {
+    let mut pubkey = RawPublicKey { data: [0; 64] };
+    // SAFETY: the length is that of the input.
+    let ret =
+        unsafe { secp256k1_ec_pubkey_parse(context(), &mut pubkey, input.as_ptr(), input.len()) };
+    (ret == 1).then_some(pubkey)
+}
+
+/// Serializes a public key, in 33 bytes if `compressed` and 65 otherwise.
+pub(crate) fn pubkey_serialize(pubkey: &RawPublicKey, compressed: bool) -> Vec<u8> {
+    let mut output = [0; 65];
+    let mut len = output.len();
+    let flags = if compressed {
+        SECP256K1_EC_COMPRESSED
+    } else {
+        SECP256K1_EC_UNCOMPRESSED
+    };
+    // SAFETY: the output buffer is large enough for either encoding.
+    unsafe {
+        secp256k1_ec_pubkey_serialize(context(), output.as_mut_ptr(), &mut len, pubkey, flags);
+    }
+    output[..len].to_vec()
+}
+
+/// Signs `msghash` with a valid secret key, returning the DER-encoded
+/// signature.
+///
+/// The nonce is derived as RFC 6979 specifies, and the signature has a low S
+/// value, as zcashd's `LOW_S` policy requires.
+pub(crate) fn ecdsa_sign(seckey: &[u8; 32], msghash: &[u8; 32]) -> Vec<u8> {
+    let mut sig = RawSignature { data: [0; 64] };
+    let mut output = [0; 72];
+    let mut len = output.len();
+    // SAFETY: the secret key and message hash are 32 bytes, a null nonce
+    // function selects RFC 6979, and the output buffer is large enough for any
+    // DER signature.
+    unsafe {
+        let ret = secp256k1_ecdsa_sign(
+            context(),
+            &mut sig,
+            msghash.as_ptr(),
+            seckey.as_ptr(),
+            ptr::null(),
+            ptr::null(),
+        );
+        assert_eq!(ret, 1, "signing with a valid secret key failed");
+        secp256k1_ecdsa_signature_serialize_der(context(), output.as_mut_ptr(), &mut len, &sig);
+    }
+    output[..len].to_vec()
+}
+
+/// Returns true if the strictly DER-encoded `sig` is a valid signature of
+/// `msghash` by `pubkey`.
+///
+/// Like zcashd's `CPubKey::Verify`, this accepts signatures with a high S
+/// value, which only the `LOW_S` policy rejects.
+pub(crate) fn ecdsa_verify(pubkey: &RawPublicKey, sig: &[u8], msghash: &[u8; 32]) -> bool {
+    let mut parsed = RawSignature { data: [0; 64] };
+    // SAFETY: the length is that of the signature, the message hash is 32
+    // bytes, and normalizing may be done in place.
+    unsafe {
+        let parsed_ok =
+            secp256k1_ecdsa_signature_parse_der(context(), &mut parsed, sig.as_ptr(), sig.len());
+        if parsed_ok != 1 {
+            return false;
+        }
+        let sig: *mut RawSignature = &mut parsed;
+        secp256k1_ecdsa_signature_normalize(context(), sig, sig);
+        secp256k1_ecdsa_verify(context(), sig, msghash.as_ptr(), pubkey) == 1
+    }
+}
+
+/// Returns true if the DER-encoded `sig` parses, and its S value is at most
+/// half the curve order, as zcashd's `LOW_S` policy requires.
+pub(crate) fn is_low_s(sig: &[u8]) -> bool {
+    let mut parsed = RawSignature { data: [0; 64] };
+    // SAFETY: the length is that of the signature, and normalizing may be
+    // done without an output.
+    unsafe {
+        secp256k1_ecdsa_signature_parse_der(context(), &mut parsed, sig.as_ptr(), sig.len()) == 1
+            && secp256k1_ecdsa_signature_normalize(context(), ptr::null_mut(), &parsed) == 0
+    }
+}
+
+/// Signs `msghash` with a valid secret key, returning the compact signature
+/// and its recovery ID.
+pub(crate) fn ecdsa_sign_recoverable(seckey: &[u8; 32], msghash: &[u8; 32]) -> ([u8; 64], u8) {
+    let mut sig = RawRecoverableSignature { data: [0; 65] };
+    let mut output = [0; 64];
+    let mut recid = 0;
+    // SAFETY: the secret key and message hash are 32 bytes, a null nonce
+    // function selects RFC 6979, and the output is 64 bytes.
+    unsafe {
+        let ret = secp256k1_ecdsa_sign_recoverable(
+            context(),
+            &mut sig,
+            msghash.as_ptr(),
+            seckey.as_ptr(),
+            ptr::null(),
+            ptr::null(),
+        );
+        assert_eq!(ret, 1, "signing with a valid secret key failed");
+        secp256k1_ecdsa_recoverable_signature_serialize_compact(
+            context(),
+            output.as_mut_ptr(),
+            &mut recid,
+            &sig,
+        );
+    }
+    (output, recid as u8)
+}
+
+/// Recovers the public key that made the compact signature `sig` with
+/// recovery ID `recid` (from 0 to 3) of `msghash`.
+pub(crate) fn ecdsa_recover(sig: &[u8; 64], recid: u8, msghash: &[u8; 32]) -> Option<RawPublicKey> {
+    let mut parsed = RawRecoverableSignature { data: [0; 65] };
+    let mut pubkey = RawPublicKey { data: [0; 64] };
+    // SAFETY: the signature is 64 bytes and the message hash is 32 bytes.
+    unsafe {
+        let parsed_ok = secp256k1_ecdsa_recoverable_signature_parse_compact(
+            context(),
+            &mut parsed,
+            sig.as_ptr(),
+            recid.into(),
+        );
+        (parsed_ok == 1
+            && secp256k1_ecdsa_recover(context(), &mut pubkey, &parsed, msghash.as_ptr()) == 1)
+            .then_some(pubkey)
+    }
+}
+
+/// Adds `tweak` to `seckey` modulo the curve order, returning false if the
+/// tweak is out of range or the result is zero.
+#[cfg(feature = "hd-wallet")]
+pub(crate) fn seckey_tweak_add(seckey: &mut [u8; 32], tweak: &[u8; 32]) -> bool {
+    // SAFETY: the secret key and the tweak are 32 bytes.
+    unsafe { secp256k1_ec_seckey_tweak_add(context(), seckey.as_mut_ptr(), tweak.as_ptr()) == 1 }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shares_one_context() {
+        let ctx = context_ptr();
+        assert!(!ctx.is_null());
+        assert_eq!(context_ptr(), ctx);
+        // SAFETY: the context is already created, so this one isn't used.
+        assert!(!unsafe { set_context(ptr::null_mut()) });
+        assert_eq!(context_ptr(), ctx);
+    }
+}
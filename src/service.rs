@@ -0,0 +1,251 @@
+//! A [`tower::Service`] over a [`VerificationPool`], enabled by the `tower`
+//! feature.
+//!
+//! [`VerificationService`] lets async node stacks add verification behind
+//! their existing middleware, such as timeouts, buffering and load shedding.
+//! It never waits for queue space: when the queue for a request's priority is
+//! full, the request fails with [`ServiceError::Overloaded`], which
+//! middleware can shed or retry.
+
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+};
+
+use crate::{
+    cancel::CancellationToken,
+    pool::{Job, Priority, VerificationPool},
+    verify::Error,
+};
+
+/// A request to verify a [`Job`].
+#[derive(Clone, Debug)]
+pub struct VerifyRequest {
+    pub job: Job,
+    pub priority: Priority,
+    /// A token that skips the job if it is cancelled before a worker takes
+    /// it.
+    pub token: Option<CancellationToken>,
+}
+
+impl From<Job> for VerifyRequest {
+    /// Returns a request for [`Priority::Block`] work that can't be
+    /// cancelled.
+    fn from(job: Job) -> Self {
+        VerifyRequest {
+            job,
+            priority: Priority::Block,
+            token: None,
+        }
+    }
+}
+
+/// An error returned by [`VerificationService`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServiceError {
+    /// The job didn't verify.
+    Verify(Error),
+    /// The queue for the request's priority was full, so the job wasn't
+    /// verified.
+    Overloaded(Job),
+    /// The request's token was cancelled before the job was verified.
+    Cancelled,
+}
+
+impl fmt::Display for ServiceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ServiceError::Verify(err) => err.fmt(f),
+            ServiceError::Overloaded(_) => write!(f, "verification queue is full"),
+            ServiceError::Cancelled => write!(f, "verification was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for ServiceError {}
+
+/// A [`tower::Service`] that verifies [`VerifyRequest`]s on a shared
+/// [`VerificationPool`].
+///
+/// Clones share the pool.
+#[derive(Clone, Debug)]
+pub struct VerificationService {
+    pool: Arc<VerificationPool>,
+}
+
+impl VerificationService {
+    /// Returns a service that verifies requests on `pool`.
+    pub fn new(pool: Arc<VerificationPool>) -> Self {
+        VerificationService { pool }
+    }
+
+    /// Returns the pool the service verifies requests on.
+    pub fn pool(&self) -> &Arc<VerificationPool> {
+        &self.pool
+    }
+}
+
+impl tower::Service<VerifyRequest> for VerificationService {
+    type Response = ();
+    type Error = ServiceError;
+    type Future = VerifyFuture;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Queue space is checked by `call`, so that requests of different
+        // priorities don't wait for each other.
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: VerifyRequest) -> Self::Future {
+        let shared = Arc::new(Mutex::new(Shared::default()));
+        let notifier = Notifier(shared.clone());
+        let callback = Box::new(move |result| notifier.finish(Some(result)));
+        let state = match self.pool.try_submit_with_callback(
+            request.job,
+            request.priority,
+            request.token,
+            callback,
+        ) {
+            Ok(()) => State::Queued(shared),
+            Err(job) => State::Overloaded(Some(job)),
+        };
+        VerifyFuture { state }
+    }
+}
+
+/// The state shared between a [`VerifyFuture`] and the worker running its
+/// job.
+#[derive(Debug, Default)]
+struct Shared {
+    /// The job's result, or `None` once it was skipped.
+    result: Option<Option<Result<(), Error>>>,
+    waker: Option<Waker>,
+}
+
+/// Wakes the [`VerifyFuture`] when its job finishes, or when it is skipped
+/// and the notifier is dropped.
+struct Notifier(Arc<Mutex<Shared>>);
+
+impl Notifier {
+    fn finish(&self, result: Option<Result<(), Error>>) {
+        let mut shared = self.0.lock().expect("lock is not poisoned");
+        if shared.result.is_none() {
+            shared.result = Some(result);
+        }
+        if let Some(waker) = shared.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl Drop for Notifier {
+    fn drop(&mut self) {
+        // This does nothing if the job finished.
+        self.finish(None);
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Queued(Arc<Mutex<Shared>>),
+    Overloaded(Option<Job>),
+}
+
+/// The future returned by [`VerificationService`], which resolves when the
+/// job has been verified or skipped.
+#[derive(Debug)]
+#[must_use = "futures do nothing unless polled"]
+pub struct VerifyFuture {
+    state: State,
+}
+
+impl Future for VerifyFuture {
+    type Output = Result<(), ServiceError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let shared = match &mut self.state {
+            State::Queued(shared) => shared,
+            State::Overloaded(job) => {
+                let job = job.take().expect("future is not polled after completion");
+                return Poll::Ready(Err(ServiceError::Overloaded(job)));
+            }
+        };
+        let mut shared = shared.lock().expect("lock is not poisoned");
+        match shared.result.take() {
+            Some(Some(result)) => Poll::Ready(result.map_err(ServiceError::Verify)),
+            Some(None) => Poll::Ready(Err(ServiceError::Cancelled)),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        task::Wake,
+        thread::{self, Thread},
+    };
+
+    use tower::Service;
+
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn serves_verification_requests() {
+        let job = |consensus_branch_id| Job::Verify {
+            script_pub_key: SCRIPT_PUBKEY.clone(),
+            amount: 212 * 100_000_000,
+            tx_to: SCRIPT_TX.as_slice().into(),
+            n_in: 0,
+            flags: 1,
+            consensus_branch_id,
+        };
+        let mut service = VerificationService::new(Arc::new(VerificationPool::new(1, 4)));
+
+        assert_eq!(block_on(service.call(job(0x2bb40e60).into())), Ok(()));
+        assert_eq!(
+            block_on(service.call(job(0x2bb40e61).into())),
+            Err(ServiceError::Verify(Error::ScriptInvalid))
+        );
+
+        let token = CancellationToken::new();
+        token.cancel();
+        let request = VerifyRequest {
+            job: job(0x2bb40e60),
+            priority: Priority::Mempool,
+            token: Some(token),
+        };
+        assert_eq!(
+            block_on(service.call(request)),
+            Err(ServiceError::Cancelled)
+        );
+    }
+}
@@ -0,0 +1,654 @@
+//! Signing payloads for external signers, such as hardware wallets.
+//!
+//! Host software computes each input's signature hash with this crate, and
+//! passes it to the signer with the details the signer shows its user or
+//! uses to find the key, so the signer doesn't have to parse the transaction
+//! or implement the signature hash algorithms itself.
+//!
+//! Signers that can answer from the host, such as HSMs, implement
+//! [`ExternalSigner`], and [`finalize_p2pkh`] and [`finalize_multisig`] build
+//! scriptSigs from their signatures without blocking while they respond.
+//!
+//! When the keys of a P2SH multisig output are held by different parties,
+//! [`PartialMultisig`] collects their signatures one at a time, or combines
+//! the signatures each party collected, until enough keys have signed.
+
+use std::{
+    convert::Infallible,
+    fmt::{self, Write},
+    future::Future,
+    pin::Pin,
+};
+
+use crate::{
+    decode::json_string,
+    keys::{PublicKey, SecretKey},
+    opcode::Opcode,
+    script::Script,
+    secp,
+    sighash::{read_v5, signature_hash, signature_hash_v5, Error, SighashType},
+    standard::{solve, Template},
+};
+
+/// The bit set in the BIP 32 child numbers of hardened derivation steps.
+const HARDENED: u32 = 0x8000_0000;
+
+/// An input the host wants signed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SigningRequest {
+    /// The index of the input.
+    pub n_in: u32,
+    /// The script being signed for: the spent scriptPubKey, or the redeem
+    /// script of a P2SH output.
+    pub script_code: Script,
+    /// The value of the spent output, in zatoshis.
+    pub amount: i64,
+    /// The hash type to sign with.
+    pub hash_type: SighashType,
+    /// The BIP 32 path of the signing key, as child numbers with the
+    /// hardened bit set for hardened steps, or empty if the signer finds the
+    /// key some other way.
+    pub derivation_path: Vec<u32>,
+}
+
+/// What an external signer needs to sign one input.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SigningPayload {
+    /// The input to sign.
+    pub request: SigningRequest,
+    /// The consensus branch ID the signature hash commits to.
+    pub consensus_branch_id: u32,
+    /// The signature hash, in the byte order it is signed in.
+    pub sighash: [u8; 32],
+}
+
+impl SigningPayload {
+    /// Serializes this payload as a compact JSON object.
+    ///
+    /// Hashes and scripts are hex, the branch ID is hex as zcashd's RPCs show
+    /// it, and the derivation path is in the `m/44'/133'/0'/0/0` notation.
+    pub fn to_json(&self) -> String {
+        format!(
+            "{{\"nIn\":{},\"sighash\":{},\"amount\":{},\"scriptCode\":{},\"hashType\":{},\
+            \"consensusBranchId\":{},\"derivationPath\":{}}}",
+            self.request.n_in,
+            json_string(&hex::encode(self.sighash)),
+            self.request.amount,
+            json_string(&hex::encode(&self.request.script_code)),
+            self.request.hash_type.to_u8(),
+            json_string(&format!("{:08x}", self.consensus_branch_id)),
+            json_string(&derivation_path_string(&self.request.derivation_path)),
+        )
+    }
+}
+
+/// Formats a BIP 32 path, marking hardened steps with `'`.
+fn derivation_path_string(path: &[u32]) -> String {
+    let mut formatted = "m".to_string();
+    for &child in path {
+        write!(formatted, "/{}", child & !HARDENED).expect("writes to a String succeed");
+        if child & HARDENED != 0 {
+            formatted.push('\'');
+        }
+    }
+    formatted
+}
+
+/// Returns the payloads for signing `requests` in the serialized pre-v5
+/// transaction `tx_to`, whose signature hashes commit to
+/// `consensus_branch_id`.
+pub fn signing_payloads(
+    tx_to: &[u8],
+    requests: &[SigningRequest],
+    consensus_branch_id: u32,
+) -> Result<Vec<SigningPayload>, Error> {
+    requests
+        .iter()
+        .map(|request| {
+            Ok(SigningPayload {
+                sighash: signature_hash(
+                    request.script_code.as_bytes(),
+                    request.amount,
+                    tx_to,
+                    request.n_in,
+                    request.hash_type,
+                    consensus_branch_id,
+                )?,
+                request: request.clone(),
+                consensus_branch_id,
+            })
+        })
+        .collect()
+}
+
+/// Returns the payloads for signing `requests` in the serialized v5
+/// transaction `tx_to`, where `all_prev_outputs` encodes all the outputs it
+/// spends, as for [`signature_hash_v5`].
+///
+/// The branch ID is the one in the transaction.
+pub fn signing_payloads_v5(
+    tx_to: &[u8],
+    all_prev_outputs: &[u8],
+    requests: &[SigningRequest],
+) -> Result<Vec<SigningPayload>, Error> {
+    let consensus_branch_id = read_v5(tx_to)?.consensus_branch_id().into();
+    requests
+        .iter()
+        .map(|request| {
+            Ok(SigningPayload {
+                sighash: signature_hash_v5(
+                    tx_to,
+                    all_prev_outputs,
+                    request.n_in,
+                    request.hash_type,
+                )?,
+                request: request.clone(),
+                consensus_branch_id,
+            })
+        })
+        .collect()
+}
+
+/// The future returned by [`ExternalSigner::sign`].
+pub type SignFuture<'a, E> = Pin<Box<dyn Future<Output = Result<Vec<u8>, E>> + Send + 'a>>;
+
+/// A signer holding keys outside this crate, such as a hardware wallet or an
+/// HSM, which may take a while to respond.
+pub trait ExternalSigner {
+    /// How the signer identifies a key, such as a BIP 32 path or a key label.
+    type KeyId: ?Sized;
+    /// The error returned when the signer can't or won't sign.
+    type Error;
+
+    /// Signs `sighash` with the key `key`, returning the DER-encoded
+    /// signature, without a hash type.
+    fn sign<'a>(
+        &'a self,
+        key: &'a Self::KeyId,
+        sighash: &'a [u8; 32],
+    ) -> SignFuture<'a, Self::Error>;
+}
+
+/// Signs in process, so local keys can be used where an external signer is
+/// expected.
+impl ExternalSigner for SecretKey {
+    type KeyId = ();
+    type Error = Infallible;
+
+    fn sign<'a>(&'a self, _key: &'a (), sighash: &'a [u8; 32]) -> SignFuture<'a, Infallible> {
+        Box::pin(std::future::ready(Ok(SecretKey::sign(self, sighash))))
+    }
+}
+
+/// Returns the scriptSig spending a P2PKH output of `public_key`, with the
+/// signature from `signer` for `payload`.
+pub async fn finalize_p2pkh<S: ExternalSigner + ?Sized>(
+    signer: &S,
+    key: &S::KeyId,
+    public_key: &[u8],
+    payload: &SigningPayload,
+) -> Result<Script, S::Error> {
+    let signature = signer.sign(key, &payload.sighash).await?;
+    Ok(p2pkh_script_sig(
+        &payload.request.hash_type.encode_signature(&signature),
+        public_key,
+    ))
+}
+
+/// Returns the scriptSig spending a P2SH multisig output with the signatures
+/// from `signer` for `payload`, where the payload's script code is the redeem
+/// script.
+///
+/// `keys` must be in the order of their public keys in the redeem script.
+/// They are signed with one at a time.
+pub async fn finalize_multisig<S: ExternalSigner + ?Sized>(
+    signer: &S,
+    keys: &[&S::KeyId],
+    payload: &SigningPayload,
+) -> Result<Script, S::Error> {
+    let mut signatures = Vec::with_capacity(keys.len());
+    for key in keys {
+        signatures.push(signer.sign(key, &payload.sighash).await?);
+    }
+    Ok(multisig_script_sig(payload, &signatures))
+}
+
+/// An error collecting multisig signatures with a [`PartialMultisig`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PartialSignatureError {
+    /// The payload's script code isn't a standard multisig script.
+    NotMultisig,
+    /// The public key isn't one of the multisig script's keys.
+    UnknownKey,
+    /// The signature isn't a valid DER signature of the signature hash by the
+    /// key.
+    InvalidSignature,
+    /// The signature has an S value above half the curve order, which nodes
+    /// don't relay.
+    HighS,
+    /// The partial signatures being combined are for different payloads.
+    PayloadMismatch,
+    /// Fewer keys have signed than the multisig script requires.
+    Incomplete {
+        /// The number of keys that have signed.
+        signed: usize,
+        /// The number of signatures the script requires.
+        required: usize,
+    },
+}
+
+impl fmt::Display for PartialSignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PartialSignatureError::NotMultisig => write!(f, "script code is not a multisig script"),
+            PartialSignatureError::UnknownKey => write!(f, "public key is not in the script"),
+            PartialSignatureError::InvalidSignature => write!(f, "invalid signature for the key"),
+            PartialSignatureError::HighS => write!(f, "signature has a high S value"),
+            PartialSignatureError::PayloadMismatch => {
+                write!(f, "partial signatures are for different payloads")
+            }
+            PartialSignatureError::Incomplete { signed, required } => {
+                write!(f, "{} of {} required signatures", signed, required)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PartialSignatureError {}
+
+/// The signatures collected so far for spending a P2SH multisig output,
+/// where the payload's script code is the redeem script.
+///
+/// Each signature is checked against its key, and must have a low S value,
+/// when it's added, so a finalized scriptSig only fails verification, under
+/// the consensus rules or the `LOW_S` standardness policy, if the payload was
+/// wrong.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PartialMultisig {
+    payload: SigningPayload,
+    required: usize,
+    pubkeys: Vec<Vec<u8>>,
+    /// The DER signature by each key, in the order of the redeem script.
+    signatures: Vec<Option<Vec<u8>>>,
+}
+
+impl PartialMultisig {
+    /// Starts collecting signatures for `payload`, whose script code must be
+    /// a standard multisig script.
+    pub fn new(payload: SigningPayload) -> Result<Self, PartialSignatureError> {
+        let Some(Template::MultiSig { required, pubkeys }) = solve(&payload.request.script_code)
+        else {
+            return Err(PartialSignatureError::NotMultisig);
+        };
+        Ok(PartialMultisig {
+            payload,
+            required: required.into(),
+            signatures: vec![None; pubkeys.len()],
+            pubkeys,
+        })
+    }
+
+    /// Returns the payload the signatures are for.
+    pub fn payload(&self) -> &SigningPayload {
+        &self.payload
+    }
+
+    /// Returns the number of signatures the multisig script requires.
+    pub fn required(&self) -> usize {
+        self.required
+    }
+
+    /// Returns the public keys that have signed, in the order of the redeem
+    /// script.
+    pub fn signed_keys(&self) -> impl Iterator<Item = &[u8]> {
+        self.pubkeys
+            .iter()
+            .zip(&self.signatures)
+            .filter(|(_, signature)| signature.is_some())
+            .map(|(pubkey, _)| &pubkey[..])
+    }
+
+    /// Returns true if enough keys have signed to finalize the scriptSig.
+    pub fn is_complete(&self) -> bool {
+        self.signed_keys().count() >= self.required
+    }
+
+    /// Adds the DER-encoded `signature`, without a hash type, by the key
+    /// `public_key`, as encoded in the redeem script.
+    ///
+    /// A new signature by a key that has already signed replaces its
+    /// previous one.
+    pub fn add_signature(
+        &mut self,
+        public_key: &[u8],
+        signature: &[u8],
+    ) -> Result<(), PartialSignatureError> {
+        let index = self
+            .pubkeys
+            .iter()
+            .position(|pubkey| pubkey == public_key)
+            .ok_or(PartialSignatureError::UnknownKey)?;
+        let valid = PublicKey::from_slice(public_key)
+            .is_some_and(|key| key.verify(&self.payload.sighash, signature));
+        if !valid {
+            return Err(PartialSignatureError::InvalidSignature);
+        }
+        if !secp::is_low_s(signature) {
+            return Err(PartialSignatureError::HighS);
+        }
+        self.signatures[index] = Some(signature.to_vec());
+        Ok(())
+    }
+
+    /// Adds the signatures collected by `other` for the same payload, keeping
+    /// this one's signature where both have one for a key.
+    pub fn combine(&mut self, other: &PartialMultisig) -> Result<(), PartialSignatureError> {
+        if self.payload != other.payload {
+            return Err(PartialSignatureError::PayloadMismatch);
+        }
+        for (signature, theirs) in self.signatures.iter_mut().zip(&other.signatures) {
+            if signature.is_none() {
+                signature.clone_from(theirs);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the scriptSig spending the P2SH output, with the signatures of
+    /// the first [`required`](Self::required) keys that signed, in the order
+    /// `OP_CHECKMULTISIG` checks them.
+    pub fn finalize(&self) -> Result<Script, PartialSignatureError> {
+        let signatures: Vec<_> = self
+            .signatures
+            .iter()
+            .flatten()
+            .take(self.required)
+            .collect();
+        if signatures.len() < self.required {
+            return Err(PartialSignatureError::Incomplete {
+                signed: signatures.len(),
+                required: self.required,
+            });
+        }
+        Ok(multisig_script_sig(&self.payload, &signatures))
+    }
+}
+
+/// Returns the scriptSig spending a P2SH multisig output with the DER
+/// `signatures`, in the order of their keys in the redeem script, which is
+/// the payload's script code.
+fn multisig_script_sig(payload: &SigningPayload, signatures: &[impl AsRef<[u8]>]) -> Script {
+    let hash_type = payload.request.hash_type;
+    let mut script_sig = Script::default();
+    // The extra value consumed by OP_CHECKMULTISIG.
+    script_sig.push_opcode(Opcode::OP_0);
+    for signature in signatures {
+        script_sig.push_slice(&hash_type.encode_signature(signature.as_ref()));
+    }
+    script_sig.push_slice(payload.request.script_code.as_bytes());
+    script_sig
+}
+
+/// Returns the P2PKH scriptSig pushing `signature`, with its hash type, and
+/// `public_key`.
+pub(crate) fn p2pkh_script_sig(signature: &[u8], public_key: &[u8]) -> Script {
+    let mut script_sig = Script::default();
+    script_sig.push_slice(signature).push_slice(public_key);
+    script_sig
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        task::{Context, Poll, Wake},
+        thread::{self, Thread},
+    };
+
+    use super::*;
+    use crate::{
+        flags::VerificationFlags,
+        prevout::encode_all_prev_outputs,
+        standard::{sorted_multisig, Destination},
+        tests::{SCRIPT_PUBKEY, SCRIPT_TX},
+        verify::verify_script,
+    };
+
+    struct ThreadWaker(Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+            thread::park();
+        }
+    }
+
+    /// A v1 transaction spending one output, with the given scriptSig.
+    fn spending_tx(script_sig: &[u8]) -> Vec<u8> {
+        let mut tx = vec![1, 0, 0, 0, 1];
+        tx.extend_from_slice(&[0x11; 36]);
+        tx.push(script_sig.len() as u8);
+        tx.extend_from_slice(script_sig);
+        tx.extend_from_slice(&[0xff; 4]);
+        tx.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0, 0, 1, 0x51]);
+        tx.extend_from_slice(&[0; 4]);
+        tx
+    }
+
+    /// Signs `script_code` in the spending transaction with `finalize`, and
+    /// checks the scriptSig against `script_pub_key` with zcashd's
+    /// interpreter.
+    fn check_finalized(
+        script_pub_key: &Script,
+        script_code: Script,
+        finalize: impl FnOnce(&SigningPayload) -> Script,
+    ) {
+        let request = SigningRequest {
+            n_in: 0,
+            script_code,
+            amount: 0,
+            hash_type: SighashType::ALL,
+            derivation_path: vec![],
+        };
+        let payload = signing_payloads(&spending_tx(&[]), &[request], 0)
+            .unwrap()
+            .remove(0);
+        let script_sig = finalize(&payload);
+        assert_eq!(
+            verify_script(
+                script_pub_key.as_bytes(),
+                0,
+                &spending_tx(script_sig.as_bytes()),
+                0,
+                VerificationFlags::standard().bits(),
+                0
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn finalizes_with_external_signers() {
+        let keys: Vec<_> = (1..=2)
+            .map(|i| SecretKey::from_bytes([i; 32]).unwrap())
+            .collect();
+        let public_key = keys[0].public_key().serialize();
+
+        let Destination::PubKeyHash(hash) = Destination::for_pubkey(&public_key) else {
+            unreachable!("public keys have P2PKH destinations");
+        };
+        let p2pkh = crate::script!(OP_DUP OP_HASH160 [hash] OP_EQUALVERIFY OP_CHECKSIG);
+        check_finalized(&p2pkh, p2pkh.clone(), |payload| {
+            block_on(finalize_p2pkh(&keys[0], &(), &public_key, payload)).unwrap()
+        });
+
+        let public_keys: Vec<_> = keys
+            .iter()
+            .map(|key| key.public_key().serialize().to_vec())
+            .collect();
+        let redeem_script = sorted_multisig(1, &public_keys).unwrap();
+        let Destination::ScriptHash(hash) = Destination::for_script(redeem_script.as_bytes())
+        else {
+            unreachable!("scripts have P2SH destinations");
+        };
+        let p2sh = crate::script!(OP_HASH160 [hash] OP_EQUAL);
+        check_finalized(&p2sh, redeem_script, |payload| {
+            block_on(finalize_multisig(&keys[1], &[&()], payload)).unwrap()
+        });
+    }
+
+    /// Returns the DER signature with S replaced by the curve order minus S,
+    /// which is just as valid.
+    fn high_s(sig: &[u8]) -> Vec<u8> {
+        const ORDER: [u8; 32] = [
+            0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+            0xff, 0xfe, 0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c,
+            0xd0, 0x36, 0x41, 0x41,
+        ];
+        let len_r = usize::from(sig[3]);
+        let s = &sig[len_r + 6..];
+        let mut padded = [0u8; 32];
+        padded[32 - s.len()..].copy_from_slice(s);
+
+        let mut negated = [0u8; 32];
+        let mut borrow = 0;
+        for i in (0..32).rev() {
+            let diff = i16::from(ORDER[i]) - i16::from(padded[i]) - borrow;
+            borrow = i16::from(diff < 0);
+            negated[i] = diff.rem_euclid(256) as u8;
+        }
+        // The high S value has its top bit set, so it needs a zero byte.
+        let mut new_s = vec![0];
+        new_s.extend_from_slice(&negated);
+
+        let mut der = vec![0x30, (4 + len_r + new_s.len()) as u8];
+        der.extend_from_slice(&sig[2..4 + len_r]);
+        der.extend_from_slice(&[0x02, new_s.len() as u8]);
+        der.extend_from_slice(&new_s);
+        der
+    }
+
+    #[test]
+    fn combines_partial_multisig_signatures() {
+        let keys: Vec<_> = (1..=3)
+            .map(|i| SecretKey::from_bytes([i; 32]).unwrap())
+            .collect();
+        let public_keys: Vec<_> = keys
+            .iter()
+            .map(|key| key.public_key().serialize().to_vec())
+            .collect();
+        let redeem_script = sorted_multisig(2, &public_keys).unwrap();
+        let Destination::ScriptHash(hash) = Destination::for_script(redeem_script.as_bytes())
+        else {
+            unreachable!("scripts have P2SH destinations");
+        };
+        let p2sh = crate::script!(OP_HASH160 [hash] OP_EQUAL);
+
+        // Each party signs with their own key, in any order, and one of them
+        // combines the results.
+        check_finalized(&p2sh, redeem_script, |payload| {
+            let mut first = PartialMultisig::new(payload.clone()).unwrap();
+            let mut second = first.clone();
+            let sign = |i: usize| keys[i].sign(&payload.sighash);
+            first.add_signature(&public_keys[2], &sign(2)).unwrap();
+            assert_eq!(
+                first.finalize(),
+                Err(PartialSignatureError::Incomplete {
+                    signed: 1,
+                    required: 2
+                })
+            );
+            assert_eq!(
+                second.add_signature(&public_keys[1], &sign(0)),
+                Err(PartialSignatureError::InvalidSignature)
+            );
+            assert_eq!(
+                second.add_signature(&[2; 33], &sign(0)),
+                Err(PartialSignatureError::UnknownKey)
+            );
+            // A valid signature with a high S value isn't standard.
+            assert_eq!(
+                second.add_signature(&public_keys[0], &high_s(&sign(0))),
+                Err(PartialSignatureError::HighS)
+            );
+            second.add_signature(&public_keys[0], &sign(0)).unwrap();
+            second.add_signature(&public_keys[1], &sign(1)).unwrap();
+
+            first.combine(&second).unwrap();
+            assert!(first.is_complete());
+            assert_eq!(first.signed_keys().count(), 3);
+            first.finalize().unwrap()
+        });
+
+        let not_multisig = SigningPayload {
+            request: SigningRequest {
+                n_in: 0,
+                script_code: p2sh,
+                amount: 0,
+                hash_type: SighashType::ALL,
+                derivation_path: vec![],
+            },
+            consensus_branch_id: 0,
+            sighash: [0; 32],
+        };
+        assert_eq!(
+            PartialMultisig::new(not_multisig),
+            Err(PartialSignatureError::NotMultisig)
+        );
+    }
+
+    #[test]
+    fn exports_payloads() {
+        let request = SigningRequest {
+            n_in: 0,
+            script_code: Script(SCRIPT_PUBKEY.clone()),
+            amount: 212 * 100_000_000,
+            hash_type: SighashType::ALL,
+            derivation_path: vec![44 | HARDENED, 133 | HARDENED, HARDENED, 0, 5],
+        };
+        let payloads = signing_payloads(&SCRIPT_TX, &[request.clone()], 0x2bb40e60).unwrap();
+        let sighash = signature_hash(
+            &SCRIPT_PUBKEY,
+            request.amount,
+            &SCRIPT_TX,
+            0,
+            SighashType::ALL,
+            0x2bb40e60,
+        )
+        .unwrap();
+        assert_eq!(payloads[0].sighash, sighash);
+        assert_eq!(
+            payloads[0].to_json(),
+            format!(
+                "{{\"nIn\":0,\"sighash\":\"{}\",\"amount\":21200000000,\"scriptCode\":\"{}\",\
+                \"hashType\":1,\"consensusBranchId\":\"2bb40e60\",\
+                \"derivationPath\":\"m/44'/133'/0'/0/5\"}}",
+                hex::encode(sighash),
+                hex::encode(&*SCRIPT_PUBKEY)
+            )
+        );
+
+        let out_of_range = SigningRequest { n_in: 1, ..request };
+        assert_eq!(
+            signing_payloads(&SCRIPT_TX, &[out_of_range], 0x2bb40e60),
+            Err(Error::TxIndex)
+        );
+        let all_prev_outputs =
+            encode_all_prev_outputs([(&SCRIPT_PUBKEY[..], 212 * 100_000_000)].into_iter());
+        assert_eq!(
+            signing_payloads_v5(&SCRIPT_TX, &all_prev_outputs, &[]),
+            Err(Error::TxVersion)
+        );
+    }
+}
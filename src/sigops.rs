@@ -0,0 +1,208 @@
+//! Accounting of transparent signature operations against the block limit,
+//! for block assembly and validation.
+//!
+//! This mirrors `GetLegacySigOpCount`, `GetP2SHSigOpCount` and the
+//! `MAX_BLOCK_SIGOPS` check in zcashd's `ConnectBlock`.
+
+use std::fmt;
+
+use zcash_primitives::{consensus::BranchId, transaction::Transaction};
+
+use crate::{
+    opcode::Opcode,
+    script::Script,
+    verify::{legacy_sigop_count, Error},
+};
+
+/// The maximum number of signature operations in a block, matching zcashd's
+/// `MAX_BLOCK_SIGOPS`.
+pub const MAX_BLOCK_SIGOPS: u32 = 20_000;
+
+/// The signature operations of a transaction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TxSigOps {
+    /// The signature operations in the scriptSigs and scriptPubKeys, counted
+    /// with the legacy rules.
+    pub legacy: u32,
+    /// The signature operations in the redeem scripts of P2SH inputs, counted
+    /// accurately.
+    pub p2sh: u32,
+}
+
+impl TxSigOps {
+    /// Counts the signature operations of a serialized transaction.
+    ///
+    /// `prev_script_pub_keys` are the scriptPubKeys of the outputs the
+    /// transaction spends, in input order. They are ignored for coinbase
+    /// transactions.
+    pub fn of(
+        tx_to: &[u8],
+        consensus_branch_id: u32,
+        prev_script_pub_keys: &[Script],
+    ) -> Result<Self, Error> {
+        let legacy = legacy_sigop_count(tx_to)?;
+        let branch_id =
+            BranchId::try_from(consensus_branch_id).map_err(|_| Error::TxDeserialize)?;
+        let tx = Transaction::read(tx_to, branch_id).map_err(|_| Error::TxDeserialize)?;
+
+        let p2sh = match tx.transparent_bundle() {
+            Some(bundle) if !bundle.is_coinbase() => {
+                if bundle.vin.len() != prev_script_pub_keys.len() {
+                    return Err(Error::AllPrevOutputsSizeMismatch);
+                }
+                bundle
+                    .vin
+                    .iter()
+                    .zip(prev_script_pub_keys)
+                    .map(|(input, prev)| {
+                        p2sh_sig_op_count(&Script::from(&input.script_sig.0[..]), prev)
+                    })
+                    .sum()
+            }
+            _ => 0,
+        };
+        Ok(TxSigOps { legacy, p2sh })
+    }
+
+    /// Returns the total number of signature operations.
+    pub fn total(&self) -> u32 {
+        self.legacy + self.p2sh
+    }
+}
+
+/// Returns the number of signature operations in the redeem script revealed
+/// when `script_sig` spends `prev_script_pub_key`, matching
+/// `CScript::GetSigOpCount(scriptSig)`.
+///
+/// Returns 0 if `prev_script_pub_key` isn't P2SH, or if `script_sig` isn't
+/// push-only.
+pub fn p2sh_sig_op_count(script_sig: &Script, prev_script_pub_key: &Script) -> u32 {
+    if !prev_script_pub_key.is_pay_to_script_hash() {
+        return 0;
+    }
+    let mut redeem_script: &[u8] = &[];
+    for op in script_sig.instructions() {
+        match op {
+            Ok(op) if op.opcode <= Opcode::OP_16 => redeem_script = op.data,
+            _ => return 0,
+        }
+    }
+    Script::from(redeem_script).sig_op_count(true)
+}
+
+/// The error returned when adding a transaction would exceed the block's
+/// signature operation limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SigOpLimitExceeded {
+    /// The index of the transaction that exceeds the limit, counting the
+    /// transactions already added.
+    pub index: usize,
+    /// The total the block would have with the transaction.
+    pub total: u64,
+}
+
+impl fmt::Display for SigOpLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "transaction {} brings the block to {} sigops",
+            self.index, self.total
+        )
+    }
+}
+
+impl std::error::Error for SigOpLimitExceeded {}
+
+/// The running total of signature operations in a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockSigOps {
+    limit: u32,
+    total: u32,
+    transactions: usize,
+}
+
+impl BlockSigOps {
+    /// Returns an empty total, limited to [`MAX_BLOCK_SIGOPS`].
+    pub fn new() -> Self {
+        Self::with_limit(MAX_BLOCK_SIGOPS)
+    }
+
+    /// Returns an empty total with a custom limit, for example to reserve
+    /// signature operations for the coinbase transaction.
+    pub fn with_limit(limit: u32) -> Self {
+        BlockSigOps {
+            limit,
+            total: 0,
+            transactions: 0,
+        }
+    }
+
+    /// Adds a transaction's signature operations, unless the total would then
+    /// exceed the limit.
+    pub fn try_add(&mut self, sig_ops: TxSigOps) -> Result<(), SigOpLimitExceeded> {
+        let total = u64::from(self.total) + u64::from(sig_ops.legacy) + u64::from(sig_ops.p2sh);
+        if total > u64::from(self.limit) {
+            return Err(SigOpLimitExceeded {
+                index: self.transactions,
+                total,
+            });
+        }
+        self.total = total as u32;
+        self.transactions += 1;
+        Ok(())
+    }
+
+    /// Returns the number of signature operations added so far.
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Returns the number of signature operations that can still be added.
+    pub fn remaining(&self) -> u32 {
+        self.limit - self.total
+    }
+
+    /// Returns the number of transactions added so far.
+    pub fn transactions(&self) -> usize {
+        self.transactions
+    }
+}
+
+impl Default for BlockSigOps {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+
+    #[test]
+    fn accumulates_sig_ops() {
+        let prev = Script(SCRIPT_PUBKEY.to_vec());
+        let sig_ops = TxSigOps::of(&SCRIPT_TX, 0x2bb4_0e60, &[prev]).unwrap();
+        assert_eq!(sig_ops, TxSigOps { legacy: 1, p2sh: 0 });
+
+        let mut block = BlockSigOps::with_limit(2);
+        assert_eq!(block.try_add(sig_ops), Ok(()));
+        assert_eq!(block.try_add(sig_ops), Ok(()));
+        assert_eq!(
+            block.try_add(sig_ops),
+            Err(SigOpLimitExceeded { index: 2, total: 3 })
+        );
+        assert_eq!((block.total(), block.remaining()), (2, 0));
+
+        // A redeem script with a 2-of-3 OP_CHECKMULTISIG.
+        let mut p2sh = Script::default();
+        p2sh.push_opcode(Opcode::OP_HASH160)
+            .push_slice(&[0; 20])
+            .push_opcode(Opcode::OP_EQUAL);
+        let mut script_sig = Script::default();
+        script_sig.push_num(0).push_slice(&[0x52, 0x53, 0xae]);
+        assert_eq!(p2sh_sig_op_count(&script_sig, &p2sh), 3);
+        script_sig.push_opcode(Opcode::OP_NOP);
+        assert_eq!(p2sh_sig_op_count(&script_sig, &p2sh), 0);
+    }
+}
@@ -0,0 +1,212 @@
+//! Classification of scriptPubKeys that nobody, or anybody, can spend.
+//!
+//! An output is provably unspendable if zcashd's `EvalScript` fails on its
+//! scriptPubKey whatever the scriptSig pushes, which is the case for the
+//! checks it makes before (or regardless of) executing each opcode. An
+//! output is anyone-can-spend if a scriptSig without signatures satisfies it.
+
+use crate::{
+    opcode::Opcode,
+    script::{Instruction, Script, MAX_OPS_PER_SCRIPT, MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE},
+};
+
+/// Why a scriptPubKey can never be spent, named after the `ScriptError` it
+/// fails with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Unspendable {
+    /// The script starts with `OP_RETURN`.
+    OpReturn,
+    /// The script is longer than [`MAX_SCRIPT_SIZE`].
+    ScriptSize,
+    /// The script has a truncated push, or an `OP_VERIF` or `OP_VERNOTIF`,
+    /// which fail even in an unexecuted branch.
+    BadOpcode,
+    /// The script pushes more than [`MAX_SCRIPT_ELEMENT_SIZE`] bytes.
+    PushSize,
+    /// The script has more than [`MAX_OPS_PER_SCRIPT`] non-push opcodes.
+    OpCount,
+    /// The script contains a disabled opcode, such as `OP_CAT`.
+    DisabledOpcode,
+    /// The script's conditionals are unbalanced.
+    UnbalancedConditional,
+    /// The script only pushes constants, and the last one is false.
+    EvalFalse,
+}
+
+/// Who can spend an output.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Spendability {
+    /// Nobody can ever spend the output.
+    Unspendable(Unspendable),
+    /// Anyone can spend the output, without a signature.
+    AnyoneCanSpend,
+    /// Spending the output may require satisfying some condition.
+    Conditional,
+}
+
+/// The opcodes that make `EvalScript` fail wherever they appear.
+const DISABLED_OPCODES: [Opcode; 16] = [
+    Opcode::OP_CAT,
+    Opcode::OP_SUBSTR,
+    Opcode::OP_LEFT,
+    Opcode::OP_RIGHT,
+    Opcode::OP_INVERT,
+    Opcode::OP_AND,
+    Opcode::OP_OR,
+    Opcode::OP_XOR,
+    Opcode::OP_2MUL,
+    Opcode::OP_2DIV,
+    Opcode::OP_MUL,
+    Opcode::OP_DIV,
+    Opcode::OP_MOD,
+    Opcode::OP_LSHIFT,
+    Opcode::OP_RSHIFT,
+    Opcode::OP_CODESEPARATOR,
+];
+
+/// Classifies who can spend an output with the given scriptPubKey.
+///
+/// Outputs are only classified as [`Spendability::Unspendable`] or
+/// [`Spendability::AnyoneCanSpend`] when that is certain under consensus
+/// rules. Any other script is [`Spendability::Conditional`], even if it is
+/// unspendable in practice (for example, because it requires a signature for
+/// an invalid public key).
+pub fn classify(script_pub_key: &Script) -> Spendability {
+    if let Err(reason) = check_static(script_pub_key) {
+        return Spendability::Unspendable(reason);
+    }
+
+    // An empty scriptPubKey is satisfied by any scriptSig that leaves true on
+    // the stack.
+    if script_pub_key.is_empty() {
+        return Spendability::AnyoneCanSpend;
+    }
+
+    // A scriptPubKey that only pushes constants leaves its last push on top of
+    // the stack, whatever the scriptSig pushed below it.
+    let mut last = None;
+    for op in script_pub_key.instructions() {
+        let Ok(Instruction { opcode, data, .. }) = op else {
+            unreachable!("checked above");
+        };
+        last = Some(match opcode {
+            _ if opcode.is_push_data() => cast_to_bool(data),
+            Opcode::OP_1NEGATE => true,
+            _ if opcode.is_small_integer() => true,
+            _ => return Spendability::Conditional,
+        });
+    }
+    match last {
+        Some(true) => Spendability::AnyoneCanSpend,
+        _ => Spendability::Unspendable(Unspendable::EvalFalse),
+    }
+}
+
+/// Returns true if the output can never be spent, matching
+/// [`Script::is_unspendable`] but also detecting every other case that
+/// [`classify`] does.
+pub fn is_provably_unspendable(script_pub_key: &Script) -> bool {
+    matches!(classify(script_pub_key), Spendability::Unspendable(_))
+}
+
+/// Makes the checks that `EvalScript` makes regardless of which branches
+/// execute.
+fn check_static(script: &Script) -> Result<(), Unspendable> {
+    if script.len() > MAX_SCRIPT_SIZE {
+        return Err(Unspendable::ScriptSize);
+    }
+    if script.as_bytes().first() == Some(&Opcode::OP_RETURN.0) {
+        return Err(Unspendable::OpReturn);
+    }
+
+    let mut op_count = 0;
+    let mut depth = 0usize;
+    for op in script.instructions() {
+        let Instruction { opcode, data, .. } = op.map_err(|_| Unspendable::BadOpcode)?;
+        if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+            return Err(Unspendable::PushSize);
+        }
+        if opcode > Opcode::OP_16 {
+            op_count += 1;
+            if op_count > MAX_OPS_PER_SCRIPT {
+                return Err(Unspendable::OpCount);
+            }
+        }
+        if DISABLED_OPCODES.contains(&opcode) {
+            return Err(Unspendable::DisabledOpcode);
+        }
+        match opcode {
+            Opcode::OP_VERIF | Opcode::OP_VERNOTIF => return Err(Unspendable::BadOpcode),
+            Opcode::OP_IF | Opcode::OP_NOTIF => depth += 1,
+            Opcode::OP_ELSE if depth == 0 => return Err(Unspendable::UnbalancedConditional),
+            Opcode::OP_ENDIF => {
+                depth = depth
+                    .checked_sub(1)
+                    .ok_or(Unspendable::UnbalancedConditional)?
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(Unspendable::UnbalancedConditional);
+    }
+    Ok(())
+}
+
+/// Interprets a stack element as a boolean, matching `CastToBool`.
+fn cast_to_bool(data: &[u8]) -> bool {
+    match data.split_last() {
+        None => false,
+        // Negative zero is false.
+        Some((&last, rest)) => rest.iter().any(|&b| b != 0) || (last != 0 && last != 0x80),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex::FromHex;
+
+    fn classify_hex(hex: &str) -> Spendability {
+        classify(&Script::from_hex(hex).unwrap())
+    }
+
+    #[test]
+    fn classifies_scripts() {
+        let p2pkh = "76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac";
+        assert_eq!(classify_hex(p2pkh), Spendability::Conditional);
+
+        assert_eq!(classify_hex(""), Spendability::AnyoneCanSpend);
+        assert_eq!(classify_hex("51"), Spendability::AnyoneCanSpend);
+        assert_eq!(
+            classify_hex("00"),
+            Spendability::Unspendable(Unspendable::EvalFalse)
+        );
+        // Negative zero.
+        assert_eq!(
+            classify_hex("0180"),
+            Spendability::Unspendable(Unspendable::EvalFalse)
+        );
+        assert_eq!(
+            classify_hex("6a0401020304"),
+            Spendability::Unspendable(Unspendable::OpReturn)
+        );
+        assert_eq!(
+            classify_hex("4c"),
+            Spendability::Unspendable(Unspendable::BadOpcode)
+        );
+        // OP_CAT in an unexecuted branch.
+        assert_eq!(
+            classify_hex("00637e6851"),
+            Spendability::Unspendable(Unspendable::DisabledOpcode)
+        );
+        assert_eq!(
+            classify_hex("5163"),
+            Spendability::Unspendable(Unspendable::UnbalancedConditional)
+        );
+        assert_eq!(
+            classify(&Script(vec![0x61; MAX_OPS_PER_SCRIPT + 1])),
+            Spendability::Unspendable(Unspendable::OpCount)
+        );
+    }
+}
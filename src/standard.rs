@@ -0,0 +1,582 @@
+//! Recognition of standard scriptPubKey templates.
+//!
+//! This mirrors `Solver` and `ExtractDestinations` from
+//! `depend/zcash/src/script/standard.cpp`, and `AreInputsStandard` from
+//! `depend/zcash/src/policy/policy.cpp`.
+
+use std::fmt;
+
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+use zcash_address::{Network, ToAddress, ZcashAddress};
+use zcash_primitives::{consensus::BranchId, transaction::Transaction};
+
+use crate::{
+    opcode::Opcode,
+    script::{is_push_only, Script, MAX_PUBKEYS_PER_MULTISIG},
+    verify::Error,
+};
+
+/// The maximum number of signature operations in a standard P2SH redeem
+/// script, matching zcashd's `MAX_P2SH_SIGOPS`.
+pub const MAX_P2SH_SIGOPS: u32 = 15;
+
+/// The Base58Check prefixes of transparent addresses, from zcashd's
+/// `chainparams.cpp`.
+const P2PKH_PREFIX_MAINNET: [u8; 2] = [0x1c, 0xb8];
+const P2SH_PREFIX_MAINNET: [u8; 2] = [0x1c, 0xbd];
+const P2PKH_PREFIX_TESTNET: [u8; 2] = [0x1d, 0x25];
+const P2SH_PREFIX_TESTNET: [u8; 2] = [0x1c, 0xba];
+
+/// The type of a scriptPubKey, matching zcashd's `txnouttype`, or a template
+/// registered with [`register_template`](crate::templates::register_template).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ScriptType {
+    NonStandard,
+    PubKey,
+    PubKeyHash,
+    ScriptHash,
+    MultiSig,
+    NullData,
+    /// A registered template, which zcashd considers non-standard.
+    Custom(&'static str),
+}
+
+impl ScriptType {
+    /// Returns the name of the script type, matching `GetTxnOutputType`, or
+    /// the name of the registered template.
+    pub fn name(self) -> &'static str {
+        match self {
+            ScriptType::NonStandard => "nonstandard",
+            ScriptType::PubKey => "pubkey",
+            ScriptType::PubKeyHash => "pubkeyhash",
+            ScriptType::ScriptHash => "scripthash",
+            ScriptType::MultiSig => "multisig",
+            ScriptType::NullData => "nulldata",
+            ScriptType::Custom(name) => name,
+        }
+    }
+}
+
+/// A recognized standard scriptPubKey, with the data parsed out of it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Template {
+    /// `<pubkey> OP_CHECKSIG`
+    PubKey(Vec<u8>),
+    /// `OP_DUP OP_HASH160 <hash> OP_EQUALVERIFY OP_CHECKSIG`
+    PubKeyHash([u8; 20]),
+    /// `OP_HASH160 <hash> OP_EQUAL`
+    ScriptHash([u8; 20]),
+    /// `<m> <pubkey>... <n> OP_CHECKMULTISIG`
+    MultiSig { required: u8, pubkeys: Vec<Vec<u8>> },
+    /// `OP_RETURN <push>...`
+    NullData,
+}
+
+impl Template {
+    /// Returns the type of this template.
+    pub fn script_type(&self) -> ScriptType {
+        match self {
+            Template::PubKey(_) => ScriptType::PubKey,
+            Template::PubKeyHash(_) => ScriptType::PubKeyHash,
+            Template::ScriptHash(_) => ScriptType::ScriptHash,
+            Template::MultiSig { .. } => ScriptType::MultiSig,
+            Template::NullData => ScriptType::NullData,
+        }
+    }
+}
+
+/// A transparent address that a standard script pays to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Destination {
+    /// A P2PKH address, identified by the hash of the public key.
+    PubKeyHash([u8; 20]),
+    /// A P2SH address, identified by the hash of the redeem script.
+    ScriptHash([u8; 20]),
+}
+
+impl Destination {
+    /// Returns the P2SH destination for the given redeem script, matching
+    /// `CScriptID(script)`.
+    pub fn for_script(redeem_script: &[u8]) -> Self {
+        Destination::ScriptHash(hash160(redeem_script))
+    }
+
+    /// Returns the P2PKH destination for the given public key, matching
+    /// `CPubKey::GetID`.
+    pub fn for_pubkey(pubkey: &[u8]) -> Self {
+        Destination::PubKeyHash(hash160(pubkey))
+    }
+
+    /// Decodes a transparent address for `network`, matching zcashd's
+    /// `DecodeDestination`.
+    ///
+    /// Returns `None` if the address isn't a valid P2PKH or P2SH address for
+    /// the network. Regtest addresses are the same as testnet ones.
+    pub fn decode(address: &str, network: Network) -> Option<Self> {
+        let payload = bs58::decode(address).with_check(None).into_vec().ok()?;
+        if payload.len() != 22 {
+            return None;
+        }
+        let (prefix, hash) = payload.split_at(2);
+        let hash = hash.try_into().expect("checked length");
+        let (p2pkh, p2sh) = match network {
+            Network::Main => (P2PKH_PREFIX_MAINNET, P2SH_PREFIX_MAINNET),
+            Network::Test | Network::Regtest => (P2PKH_PREFIX_TESTNET, P2SH_PREFIX_TESTNET),
+        };
+        if prefix == p2pkh {
+            Some(Destination::PubKeyHash(hash))
+        } else if prefix == p2sh {
+            Some(Destination::ScriptHash(hash))
+        } else {
+            None
+        }
+    }
+
+    /// Encodes this destination as a transparent address for `network`.
+    pub fn encode(&self, network: Network) -> String {
+        match *self {
+            Destination::PubKeyHash(hash) => ZcashAddress::from_transparent_p2pkh(network, hash),
+            Destination::ScriptHash(hash) => ZcashAddress::from_transparent_p2sh(network, hash),
+        }
+        .encode()
+    }
+}
+
+/// The destinations of a standard scriptPubKey, as returned by
+/// [`extract_destinations`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Destinations {
+    /// The number of signatures required to spend the output.
+    pub required: usize,
+    /// The addresses that can sign for the output.
+    pub addresses: Vec<Destination>,
+}
+
+/// Identifies the standard template of a scriptPubKey, matching `Solver`.
+///
+/// Returns `None` for non-standard scripts.
+pub fn solve(script_pub_key: &Script) -> Option<Template> {
+    let s = script_pub_key.as_bytes();
+
+    // Shortcut for pay-to-script-hash, which are more constrained than the other types.
+    if script_pub_key.is_pay_to_script_hash() {
+        let mut hash = [0; 20];
+        hash.copy_from_slice(&s[2..22]);
+        return Some(Template::ScriptHash(hash));
+    }
+
+    // Provably prunable, data-carrying output.
+    if s.first() == Some(&Opcode::OP_RETURN.0) && is_push_only(&s[1..]) {
+        return Some(Template::NullData);
+    }
+
+    if let Some(pubkey) = match_pay_to_pubkey(s) {
+        return Some(Template::PubKey(pubkey.to_vec()));
+    }
+
+    if script_pub_key.is_pay_to_public_key_hash() {
+        let mut hash = [0; 20];
+        hash.copy_from_slice(&s[3..23]);
+        return Some(Template::PubKeyHash(hash));
+    }
+
+    match_multisig(script_pub_key)
+}
+
+/// Returns the addresses paid to by a standard scriptPubKey, matching
+/// `ExtractDestinations`.
+///
+/// Returns `None` for non-standard and null-data scripts, and for multisig
+/// scripts that contain no valid public key.
+pub fn extract_destinations(script_pub_key: &Script) -> Option<Destinations> {
+    let (required, addresses) = match solve(script_pub_key)? {
+        Template::NullData => return None,
+        Template::PubKey(pubkey) => (1, vec![Destination::for_pubkey(&pubkey)]),
+        Template::PubKeyHash(hash) => (1, vec![Destination::PubKeyHash(hash)]),
+        Template::ScriptHash(hash) => (1, vec![Destination::ScriptHash(hash)]),
+        Template::MultiSig { required, pubkeys } => {
+            let addresses: Vec<_> = pubkeys
+                .iter()
+                .map(|pubkey| Destination::for_pubkey(pubkey))
+                .collect();
+            if addresses.is_empty() {
+                return None;
+            }
+            (usize::from(required), addresses)
+        }
+    };
+
+    Some(Destinations {
+        required,
+        addresses,
+    })
+}
+
+/// An error building a multisig script with [`sorted_multisig`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MultisigError {
+    /// A public key isn't a compressed public key.
+    InvalidKey,
+    /// The threshold is zero or larger than the number of keys, or there are
+    /// more than [`MAX_PUBKEYS_PER_MULTISIG`] keys.
+    InvalidThreshold,
+}
+
+impl fmt::Display for MultisigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MultisigError::InvalidKey => write!(f, "invalid compressed public key"),
+            MultisigError::InvalidThreshold => write!(f, "invalid multisig threshold"),
+        }
+    }
+}
+
+impl std::error::Error for MultisigError {}
+
+/// Builds the `required`-of-`pubkeys` multisig script with its keys sorted
+/// lexicographically, as BIP 67 specifies, so that cosigners holding the keys
+/// in any order derive the same script and P2SH address.
+///
+/// Like BIP 67, this only accepts compressed public keys.
+pub fn sorted_multisig(required: usize, pubkeys: &[Vec<u8>]) -> Result<Script, MultisigError> {
+    if required == 0 || required > pubkeys.len() || pubkeys.len() > MAX_PUBKEYS_PER_MULTISIG {
+        return Err(MultisigError::InvalidThreshold);
+    }
+    if !pubkeys.iter().all(|pubkey| is_compressed_pubkey(pubkey)) {
+        return Err(MultisigError::InvalidKey);
+    }
+    let mut sorted: Vec<_> = pubkeys.iter().collect();
+    sorted.sort();
+
+    let mut script = Script::default();
+    script.push_num(required as i64);
+    for pubkey in sorted {
+        script.push_slice(pubkey);
+    }
+    script
+        .push_num(pubkeys.len() as i64)
+        .push_opcode(Opcode::OP_CHECKMULTISIG);
+    Ok(script)
+}
+
+/// Returns whether a multisig script follows BIP 67, that is whether its keys
+/// are all compressed and sorted lexicographically.
+///
+/// Returns `None` if the script isn't a standard multisig script.
+pub fn is_sorted_multisig(script: &Script) -> Option<bool> {
+    let Template::MultiSig { pubkeys, .. } = match_multisig(script)? else {
+        return None;
+    };
+    Some(
+        pubkeys.iter().all(|pubkey| is_compressed_pubkey(pubkey))
+            && pubkeys.windows(2).all(|pair| pair[0] <= pair[1]),
+    )
+}
+
+/// The reason an input is non-standard, as checked by [`check_input_standard`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NonStandardInput {
+    /// The output being spent isn't a standard template.
+    NonStandardPrevout,
+    /// The scriptSig spending a P2SH output isn't push-only, so its redeem
+    /// script can't be found without executing it.
+    NotPushOnly,
+    /// The scriptSig spending a P2SH output is empty.
+    MissingRedeemScript,
+    /// The P2SH redeem script has more than [`MAX_P2SH_SIGOPS`] signature
+    /// operations.
+    TooManySigOps(u32),
+}
+
+impl fmt::Display for NonStandardInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NonStandardInput::NonStandardPrevout => write!(f, "spent output is non-standard"),
+            NonStandardInput::NotPushOnly => write!(f, "scriptSig is not push-only"),
+            NonStandardInput::MissingRedeemScript => write!(f, "scriptSig has no redeem script"),
+            NonStandardInput::TooManySigOps(n) => write!(f, "redeem script has {} sigops", n),
+        }
+    }
+}
+
+impl std::error::Error for NonStandardInput {}
+
+/// Checks that an input spending `prev_script_pub_key` with `script_sig` is
+/// standard, matching the per-input checks of `AreInputsStandard`.
+///
+/// zcashd finds the P2SH redeem script by evaluating the scriptSig. This
+/// instead requires the scriptSig to be push-only, which `IsStandardTx`
+/// already requires of standard transactions.
+pub fn check_input_standard(
+    script_sig: &Script,
+    prev_script_pub_key: &Script,
+) -> Result<(), NonStandardInput> {
+    let template = solve(prev_script_pub_key).ok_or(NonStandardInput::NonStandardPrevout)?;
+    if let Template::ScriptHash(_) = template {
+        if !script_sig.is_push_only() {
+            return Err(NonStandardInput::NotPushOnly);
+        }
+        let redeem_script = script_sig
+            .instructions()
+            .filter_map(Result::ok)
+            .last()
+            .ok_or(NonStandardInput::MissingRedeemScript)?;
+        let sig_ops = Script::from(redeem_script.data).sig_op_count(true);
+        if sig_ops > MAX_P2SH_SIGOPS {
+            return Err(NonStandardInput::TooManySigOps(sig_ops));
+        }
+    }
+    Ok(())
+}
+
+/// Returns true if every input of the transaction is standard, matching
+/// `AreInputsStandard`.
+///
+/// `prev_script_pub_keys` are the scriptPubKeys of the outputs the
+/// transaction spends, in input order. Coinbase transactions are always
+/// standard.
+pub fn are_inputs_standard(
+    tx_to: &[u8],
+    consensus_branch_id: u32,
+    prev_script_pub_keys: &[Script],
+) -> Result<bool, Error> {
+    let branch_id = BranchId::try_from(consensus_branch_id).map_err(|_| Error::TxDeserialize)?;
+    let tx = Transaction::read(tx_to, branch_id).map_err(|_| Error::TxDeserialize)?;
+    let Some(bundle) = tx.transparent_bundle() else {
+        return Ok(true);
+    };
+    if bundle.is_coinbase() {
+        return Ok(true);
+    }
+    if bundle.vin.len() != prev_script_pub_keys.len() {
+        return Err(Error::AllPrevOutputsSizeMismatch);
+    }
+    Ok(bundle
+        .vin
+        .iter()
+        .zip(prev_script_pub_keys)
+        .all(|(input, prev)| {
+            check_input_standard(&Script::from(&input.script_sig.0[..]), prev).is_ok()
+        }))
+}
+
+/// Computes `RIPEMD160(SHA256(data))`, matching zcashd's `Hash160`.
+pub(crate) fn hash160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(Sha256::digest(data)).into()
+}
+
+/// Returns the serialized length of a public key with the given header byte,
+/// matching `CPubKey::GetLen`.
+fn pubkey_len(header: u8) -> usize {
+    match header {
+        2 | 3 => 33,
+        4 | 6 | 7 => 65,
+        _ => 0,
+    }
+}
+
+/// Returns true if `data` has the size implied by its header byte, matching
+/// `CPubKey::ValidSize`.
+pub(crate) fn is_valid_pubkey_size(data: &[u8]) -> bool {
+    data.first()
+        .is_some_and(|&header| pubkey_len(header) == data.len())
+}
+
+fn is_compressed_pubkey(data: &[u8]) -> bool {
+    data.len() == 33 && matches!(data[0], 2 | 3)
+}
+
+fn match_pay_to_pubkey(s: &[u8]) -> Option<&[u8]> {
+    for size in [65, 33] {
+        if s.len() == size + 2 && usize::from(s[0]) == size && s[size + 1] == Opcode::OP_CHECKSIG.0
+        {
+            let pubkey = &s[1..=size];
+            return is_valid_pubkey_size(pubkey).then_some(pubkey);
+        }
+    }
+    None
+}
+
+fn match_multisig(script: &Script) -> Option<Template> {
+    let s = script.as_bytes();
+    if s.last() != Some(&Opcode::OP_CHECKMULTISIG.0) {
+        return None;
+    }
+
+    let mut ops = script.instructions();
+    let first = ops.next()?.ok()?;
+    if !first.opcode.is_small_integer() {
+        return None;
+    }
+    let required = first.opcode.decode_op_n()?;
+
+    let mut pubkeys = Vec::new();
+    let last = loop {
+        let op = ops.next()?.ok()?;
+        if is_valid_pubkey_size(op.data) {
+            pubkeys.push(op.data.to_vec());
+        } else {
+            break op;
+        }
+    };
+    if !last.opcode.is_small_integer() {
+        return None;
+    }
+    let keys = last.opcode.decode_op_n()?;
+    if pubkeys.len() != usize::from(keys) || keys < required {
+        return None;
+    }
+
+    // The only remaining operation must be the final OP_CHECKMULTISIG.
+    (last.offset + 2 == s.len()).then_some(Template::MultiSig { required, pubkeys })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+    use hex::FromHex;
+
+    fn script(hex: &str) -> Script {
+        Script::from_hex(hex).unwrap()
+    }
+
+    #[test]
+    fn solves_standard_templates() {
+        let p2pkh = script("76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac");
+        assert_eq!(
+            solve(&p2pkh).map(|t| t.script_type()),
+            Some(ScriptType::PubKeyHash)
+        );
+
+        let p2sh = script("a9141b8a9bda4b62cd0d0582b55455d0778c86f8628f87");
+        assert_eq!(
+            solve(&p2sh).map(|t| t.script_type()),
+            Some(ScriptType::ScriptHash)
+        );
+
+        let null_data = script("6a0401020304");
+        assert_eq!(solve(&null_data), Some(Template::NullData));
+
+        let multisig =
+            script("5121031f54b095eae066d96b2557c1f99e40e967978a5fd117465dbec0986ca74201a651ae");
+        assert_eq!(
+            solve(&multisig).map(|t| t.script_type()),
+            Some(ScriptType::MultiSig)
+        );
+
+        // OP_CHECKMULTISIG with trailing garbage before it
+        let bad_multisig =
+            script("5121031f54b095eae066d96b2557c1f99e40e967978a5fd117465dbec0986ca74201a65161ae");
+        assert_eq!(solve(&bad_multisig), None);
+    }
+
+    #[test]
+    fn encodes_addresses() {
+        let p2pkh = script("76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac");
+        let destinations = extract_destinations(&p2pkh).unwrap();
+        assert_eq!(destinations.required, 1);
+        assert_eq!(
+            destinations.addresses[0].encode(Network::Main),
+            "t1gALEXb92y6auReVBfwhEuWiyguzNiQ68n"
+        );
+    }
+
+    #[test]
+    fn decodes_addresses() {
+        for network in [Network::Main, Network::Test, Network::Regtest] {
+            for destination in [
+                Destination::PubKeyHash([3; 20]),
+                Destination::ScriptHash([4; 20]),
+            ] {
+                let address = destination.encode(network);
+                assert_eq!(Destination::decode(&address, network), Some(destination));
+            }
+        }
+        let mainnet = "t1gALEXb92y6auReVBfwhEuWiyguzNiQ68n";
+        assert!(Destination::decode(mainnet, Network::Main).is_some());
+        assert_eq!(Destination::decode(mainnet, Network::Test), None);
+        assert_eq!(Destination::decode("t1gALEX", Network::Main), None);
+    }
+
+    #[test]
+    fn checks_input_standardness() {
+        let p2pkh = Script(SCRIPT_PUBKEY.to_vec());
+        assert_eq!(
+            are_inputs_standard(&SCRIPT_TX, 0x2bb4_0e60, &[p2pkh.clone()]),
+            Ok(true)
+        );
+        assert_eq!(
+            are_inputs_standard(&SCRIPT_TX, 0x2bb4_0e60, &[script("51")]),
+            Ok(false)
+        );
+        assert_eq!(
+            are_inputs_standard(&SCRIPT_TX, 0x2bb4_0e60, &[]),
+            Err(Error::AllPrevOutputsSizeMismatch)
+        );
+
+        let redeem_script = Script(vec![Opcode::OP_CHECKSIG.0; 16]);
+        let mut script_sig = Script::default();
+        script_sig.push_slice(redeem_script.as_bytes());
+        let mut p2sh = Script::default();
+        p2sh.push_opcode(Opcode::OP_HASH160)
+            .push_slice(&hash160(redeem_script.as_bytes()))
+            .push_opcode(Opcode::OP_EQUAL);
+        assert_eq!(
+            check_input_standard(&script_sig, &p2sh),
+            Err(NonStandardInput::TooManySigOps(16))
+        );
+        assert_eq!(check_input_standard(&script_sig, &p2pkh), Ok(()));
+    }
+
+    #[test]
+    fn sorts_multisig_keys() {
+        let keys: Vec<Vec<u8>> = [
+            "02ff12471208c14bd580709cb2358d98975247d8765f92bc25eab3b2763ed605f8",
+            "02fe6f0a5a297eb38c391581c4413e084773ea23954d93f7753db7dc0adc188b2f",
+            "03a1b26313f430c4b15bb1fdce663207659d8cac749a0e53d70eff01874496feff",
+        ]
+        .iter()
+        .map(|key| <Vec<u8>>::from_hex(key).unwrap())
+        .collect();
+        let reversed: Vec<_> = keys.iter().rev().cloned().collect();
+
+        let multisig = sorted_multisig(2, &keys).unwrap();
+        assert_eq!(sorted_multisig(2, &reversed), Ok(multisig.clone()));
+        assert_eq!(
+            Destination::for_script(multisig.as_bytes()),
+            Destination::for_script(sorted_multisig(2, &reversed).unwrap().as_bytes())
+        );
+        assert_eq!(is_sorted_multisig(&multisig), Some(true));
+        assert_eq!(
+            solve(&multisig),
+            Some(Template::MultiSig {
+                required: 2,
+                pubkeys: vec![keys[1].clone(), keys[0].clone(), keys[2].clone()],
+            })
+        );
+
+        let mut unsorted = Script::default();
+        unsorted.push_num(2);
+        for key in &keys {
+            unsorted.push_slice(key);
+        }
+        unsorted.push_num(3).push_opcode(Opcode::OP_CHECKMULTISIG);
+        assert_eq!(is_sorted_multisig(&unsorted), Some(false));
+        assert_eq!(is_sorted_multisig(&script("51")), None);
+
+        assert_eq!(
+            sorted_multisig(4, &keys),
+            Err(MultisigError::InvalidThreshold)
+        );
+        assert_eq!(
+            sorted_multisig(0, &keys),
+            Err(MultisigError::InvalidThreshold)
+        );
+        assert_eq!(
+            sorted_multisig(1, &[vec![4; 65]]),
+            Err(MultisigError::InvalidKey)
+        );
+    }
+}
@@ -0,0 +1,145 @@
+//! Statistics about the opcodes and structure of scripts, for measuring how
+//! transparent scripts are used.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    opcode::Opcode,
+    script::{Instruction, Script},
+};
+
+/// Statistics about a single script.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScriptStats {
+    /// The length of the script in bytes.
+    pub size: usize,
+    /// The number of times each opcode occurs, including push opcodes.
+    pub opcodes: BTreeMap<Opcode, u64>,
+    /// The number of pushes of each data size, in bytes.
+    pub push_sizes: BTreeMap<usize, u64>,
+    /// The number of signature operations, counted accurately.
+    pub sig_ops: u32,
+    /// The maximum nesting depth of `OP_IF` and `OP_NOTIF` conditionals.
+    pub max_depth: usize,
+    /// True if the script ends with a truncated push, which is not counted.
+    pub truncated: bool,
+}
+
+impl ScriptStats {
+    /// Computes the statistics for `script`.
+    pub fn of(script: &Script) -> Self {
+        let mut stats = ScriptStats {
+            size: script.len(),
+            sig_ops: script.sig_op_count(true),
+            ..Default::default()
+        };
+
+        let mut depth = 0usize;
+        for op in script.instructions() {
+            let Ok(Instruction { opcode, data, .. }) = op else {
+                stats.truncated = true;
+                break;
+            };
+            *stats.opcodes.entry(opcode).or_default() += 1;
+            if opcode.is_push_data() {
+                *stats.push_sizes.entry(data.len()).or_default() += 1;
+            }
+            match opcode {
+                Opcode::OP_IF | Opcode::OP_NOTIF => {
+                    depth += 1;
+                    stats.max_depth = stats.max_depth.max(depth);
+                }
+                Opcode::OP_ENDIF => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        stats
+    }
+}
+
+/// Statistics aggregated over many scripts.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatsSummary {
+    /// The number of scripts.
+    pub scripts: u64,
+    /// The total length of the scripts in bytes.
+    pub bytes: u64,
+    /// The total number of times each opcode occurs.
+    pub opcodes: BTreeMap<Opcode, u64>,
+    /// The total number of pushes of each data size.
+    pub push_sizes: BTreeMap<usize, u64>,
+    /// The total number of signature operations.
+    pub sig_ops: u64,
+    /// The number of scripts with each maximum conditional nesting depth.
+    pub depths: BTreeMap<usize, u64>,
+    /// The number of scripts ending with a truncated push.
+    pub truncated: u64,
+}
+
+impl StatsSummary {
+    /// Adds the statistics of one script.
+    pub fn add(&mut self, stats: &ScriptStats) {
+        self.scripts += 1;
+        self.bytes += stats.size as u64;
+        for (opcode, count) in &stats.opcodes {
+            *self.opcodes.entry(*opcode).or_default() += count;
+        }
+        for (size, count) in &stats.push_sizes {
+            *self.push_sizes.entry(*size).or_default() += count;
+        }
+        self.sig_ops += u64::from(stats.sig_ops);
+        *self.depths.entry(stats.max_depth).or_default() += 1;
+        self.truncated += u64::from(stats.truncated);
+    }
+
+    /// Returns the opcodes ordered from most to least frequent.
+    pub fn most_common_opcodes(&self) -> Vec<(Opcode, u64)> {
+        let mut opcodes: Vec<_> = self.opcodes.iter().map(|(op, n)| (*op, *n)).collect();
+        opcodes.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        opcodes
+    }
+}
+
+impl<'a> Extend<&'a Script> for StatsSummary {
+    fn extend<I: IntoIterator<Item = &'a Script>>(&mut self, scripts: I) {
+        for script in scripts {
+            self.add(&ScriptStats::of(script));
+        }
+    }
+}
+
+impl<'a> FromIterator<&'a Script> for StatsSummary {
+    fn from_iter<I: IntoIterator<Item = &'a Script>>(scripts: I) -> Self {
+        let mut summary = StatsSummary::default();
+        summary.extend(scripts);
+        summary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex::FromHex;
+
+    #[test]
+    fn aggregates_stats() {
+        let p2pkh = Script(
+            <Vec<u8>>::from_hex("76a914f47cac1e6fec195c055994e8064ffccce0044dd788ac").unwrap(),
+        );
+        // OP_IF OP_IF OP_ENDIF OP_ENDIF, then a truncated push.
+        let nested = Script::from_hex("636368684c").unwrap();
+
+        let stats = ScriptStats::of(&nested);
+        assert_eq!(stats.max_depth, 2);
+        assert!(stats.truncated);
+
+        let summary: StatsSummary = [&p2pkh, &nested].into_iter().collect();
+        assert_eq!(summary.scripts, 2);
+        assert_eq!(summary.bytes, 30);
+        assert_eq!(summary.sig_ops, 1);
+        assert_eq!(summary.push_sizes[&20], 1);
+        assert_eq!(summary.depths[&0], 1);
+        assert_eq!(summary.truncated, 1);
+        assert_eq!(summary.most_common_opcodes()[0], (Opcode::OP_IF, 2));
+    }
+}
@@ -0,0 +1,87 @@
+//! `proptest` strategies for scripts, enabled by the `test-dependencies`
+//! feature.
+//!
+//! Scripts are generated as sequences of whole instructions, so that failing
+//! cases shrink by removing instructions and simplifying each one. A failure
+//! typically shrinks to a few small-number pushes and opcodes, rather than a
+//! large blob of bytes.
+
+use proptest::{
+    arbitrary::{any, Arbitrary},
+    collection::vec,
+    prop_oneof,
+    strategy::{BoxedStrategy, Strategy},
+};
+
+use crate::{
+    opcode::Opcode,
+    script::{Script, MAX_SCRIPT_ELEMENT_SIZE},
+};
+
+/// The maximum number of instructions in a script generated by
+/// [`Script`]'s [`Arbitrary`] implementation.
+pub const DEFAULT_MAX_INSTRUCTIONS: usize = 32;
+
+/// Returns a strategy for the bytes of a single instruction.
+///
+/// Instructions shrink toward small-number pushes, then toward low non-push
+/// opcodes, then toward short data pushes.
+pub fn arb_instruction() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        3 => (0..=16i64).prop_map(|n| {
+            let mut script = Script::default();
+            script.push_num(n);
+            script.0
+        }),
+        3 => (Opcode::OP_1NEGATE.0..=u8::MAX).prop_map(|opcode| vec![opcode]),
+        2 => vec(any::<u8>(), 0..=MAX_SCRIPT_ELEMENT_SIZE).prop_map(|data| {
+            let mut script = Script::default();
+            script.push_slice(&data);
+            script.0
+        }),
+    ]
+}
+
+/// Returns a strategy for scripts of at most `max_instructions` instructions.
+pub fn arb_script(max_instructions: usize) -> impl Strategy<Value = Script> {
+    vec(arb_instruction(), 0..=max_instructions).prop_map(|ops| Script(ops.concat()))
+}
+
+impl Arbitrary for Script {
+    type Parameters = ();
+    type Strategy = BoxedStrategy<Script>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        arb_script(DEFAULT_MAX_INSTRUCTIONS).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::{
+        prop_assert,
+        test_runner::{TestCaseError, TestError, TestRunner},
+    };
+
+    use super::*;
+
+    #[test]
+    fn shrinks_to_few_instructions() {
+        let mut runner = TestRunner::deterministic();
+        let result = runner.run(&any::<Script>(), |script| {
+            prop_assert!(script.instructions().all(|op| op.is_ok()));
+            // Fails for any script containing a signature opcode.
+            if script.sig_op_count(false) > 0 {
+                return Err(TestCaseError::fail("has a sigop"));
+            }
+            Ok(())
+        });
+
+        match result {
+            Err(TestError::Fail(_, script)) => {
+                assert_eq!(script.len(), 1);
+            }
+            result => panic!("unexpected result {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Aggregation of verification failures over time windows.
+//!
+//! Failures are categorized by the verification [`Error`], the interpreter
+//! [`ScriptError`] that made an invalid script fail, and the verification
+//! flags.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    observer::{VerificationEvent, VerificationObserver},
+    script_error::ScriptError,
+    verify::Error,
+};
+
+/// The category of a verification failure.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FailureKey {
+    /// The verification error.
+    pub error: Error,
+    /// The interpreter error, if `error` is [`Error::ScriptInvalid`].
+    pub script_error: Option<ScriptError>,
+    /// The script verification flags.
+    pub flags: u32,
+}
+
+/// The verification counts for a single time window.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TallySnapshot {
+    /// When the window started.
+    pub start: Instant,
+    /// How long the window has been (or was) open.
+    pub elapsed: Duration,
+    /// The number of successful verifications.
+    pub verified: u64,
+    /// The number of failed verifications in each category.
+    pub failures: HashMap<FailureKey, u64>,
+}
+
+impl TallySnapshot {
+    fn new(start: Instant) -> Self {
+        TallySnapshot {
+            start,
+            elapsed: Duration::ZERO,
+            verified: 0,
+            failures: HashMap::new(),
+        }
+    }
+
+    /// Returns the total number of failed verifications.
+    pub fn total_failures(&self) -> u64 {
+        self.failures.values().sum()
+    }
+}
+
+#[derive(Debug)]
+struct Windows {
+    current: TallySnapshot,
+    previous: Option<TallySnapshot>,
+}
+
+/// A [`VerificationObserver`] that counts verification failures by category
+/// over consecutive, fixed-length time windows.
+///
+/// Install it with [`set_observer`](crate::observer::set_observer).
+#[derive(Debug)]
+pub struct FailureTally {
+    window: Duration,
+    windows: Mutex<Windows>,
+}
+
+impl FailureTally {
+    /// Creates a tally whose windows last `window`.
+    pub fn new(window: Duration) -> Self {
+        FailureTally {
+            window,
+            windows: Mutex::new(Windows {
+                current: TallySnapshot::new(Instant::now()),
+                previous: None,
+            }),
+        }
+    }
+
+    /// Returns the counts for the current window.
+    pub fn snapshot(&self) -> TallySnapshot {
+        let mut windows = self.windows(Instant::now());
+        windows.current.elapsed = windows.current.start.elapsed();
+        windows.current.clone()
+    }
+
+    /// Returns the counts for the last completed window, if any.
+    pub fn previous(&self) -> Option<TallySnapshot> {
+        self.windows(Instant::now()).previous.clone()
+    }
+
+    /// Locks the windows, first rotating them if the current one has ended.
+    fn windows(&self, now: Instant) -> std::sync::MutexGuard<'_, Windows> {
+        let mut windows = self.windows.lock().expect("lock is not poisoned");
+        if now.duration_since(windows.current.start) >= self.window {
+            let mut finished = std::mem::replace(&mut windows.current, TallySnapshot::new(now));
+            finished.elapsed = self.window;
+            windows.previous = Some(finished);
+        }
+        windows
+    }
+}
+
+impl VerificationObserver for FailureTally {
+    fn on_verification(&self, event: &VerificationEvent) {
+        let mut windows = self.windows(Instant::now());
+        match event.result {
+            Ok(()) => windows.current.verified += 1,
+            Err(error) => {
+                *windows
+                    .current
+                    .failures
+                    .entry(FailureKey {
+                        error,
+                        script_error: event.script_error,
+                        flags: event.flags,
+                    })
+                    .or_default() += 1
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(result: Result<(), Error>, script_error: Option<ScriptError>) -> VerificationEvent {
+        VerificationEvent {
+            duration: Duration::ZERO,
+            result,
+            script_error,
+            flags: 1,
+            script_type: None,
+        }
+    }
+
+    #[test]
+    fn tallies_failures() {
+        let tally = FailureTally::new(Duration::from_secs(3600));
+        let invalid = Err(Error::ScriptInvalid);
+        tally.on_verification(&event(Ok(()), None));
+        tally.on_verification(&event(invalid, Some(ScriptError::EvalFalse)));
+        tally.on_verification(&event(invalid, Some(ScriptError::EvalFalse)));
+        tally.on_verification(&event(invalid, Some(ScriptError::SigDer)));
+        tally.on_verification(&event(Err(Error::TxIndex), None));
+
+        let snapshot = tally.snapshot();
+        assert_eq!(snapshot.verified, 1);
+        assert_eq!(snapshot.total_failures(), 4);
+        let key = |script_error| FailureKey {
+            error: Error::ScriptInvalid,
+            script_error: Some(script_error),
+            flags: 1,
+        };
+        assert_eq!(snapshot.failures[&key(ScriptError::EvalFalse)], 2);
+        assert_eq!(snapshot.failures[&key(ScriptError::SigDer)], 1);
+        assert_eq!(tally.previous(), None);
+    }
+
+    #[test]
+    fn rotates_windows() {
+        let tally = FailureTally::new(Duration::ZERO);
+        tally.on_verification(&event(Err(Error::ScriptInvalid), None));
+
+        // With an empty window, every access starts a new one.
+        let previous = tally.previous().unwrap();
+        assert_eq!(previous.total_failures(), 1);
+        assert_eq!(tally.snapshot().total_failures(), 0);
+    }
+}
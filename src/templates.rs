@@ -0,0 +1,239 @@
+//! Script templates registered by downstream crates.
+//!
+//! zcashd only recognizes the templates in [`standard`](crate::standard), so
+//! protocol-specific scripts such as HTLCs are non-standard to it. A crate
+//! that uses such scripts can [`register_template`] a [`CustomTemplate`], and
+//! [`script_type`] and [`destinations`] will recognize and decode them like
+//! standard scripts, as will [`decode_script`](crate::decode::decode_script)
+//! and the [observer](crate::observer) events built on them.
+//!
+//! Registered templates don't change consensus or policy: [`solve`] and the
+//! standardness checks still match zcashd, and a script that matches a
+//! standard template is never matched against registered ones.
+
+use std::{
+    ops::RangeInclusive,
+    sync::{Arc, RwLock},
+};
+
+use crate::{
+    opcode::Opcode,
+    script::Script,
+    standard::{extract_destinations, solve, Destinations, ScriptType},
+};
+
+/// An element of a [`CustomTemplate`]'s pattern.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Element {
+    /// Exactly this opcode. Small integers, such as `OP_1`, must be matched
+    /// this way, as they aren't data pushes.
+    Op(Opcode),
+    /// A data push, including `OP_0`, of a length in this range. The data is
+    /// passed to the template's extractor.
+    Push(RangeInclusive<usize>),
+}
+
+/// A scriptPubKey template defined outside zcashd.
+#[derive(Clone, Debug)]
+pub struct CustomTemplate {
+    /// The name of the template, reported as its [`ScriptType::Custom`].
+    pub name: &'static str,
+    /// The instructions a matching script consists of.
+    pub pattern: Vec<Element>,
+    /// Decodes the data matched by the [`Element::Push`]es of the pattern,
+    /// in order, into the addresses that can spend the output. Returns `None`
+    /// to reject the script, for example if a public key is invalid.
+    pub extract: fn(&[&[u8]]) -> Option<Destinations>,
+}
+
+/// A script matched by a registered [`CustomTemplate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CustomMatch {
+    /// The name of the template.
+    pub name: &'static str,
+    /// The data matched by the pattern's pushes.
+    pub pushes: Vec<Vec<u8>>,
+    /// The decoded addresses, which may be empty for outputs that pay to no
+    /// address.
+    pub destinations: Destinations,
+}
+
+impl CustomTemplate {
+    /// Matches `script_pub_key` against this template.
+    pub fn matches(&self, script_pub_key: &Script) -> Option<CustomMatch> {
+        let mut pattern = self.pattern.iter();
+        let mut pushes = vec![];
+        for op in script_pub_key.instructions() {
+            let op = op.ok()?;
+            match pattern.next()? {
+                Element::Op(opcode) if op.opcode == *opcode => {}
+                Element::Push(lengths)
+                    if op.opcode.is_push_data() && lengths.contains(&op.data.len()) =>
+                {
+                    pushes.push(op.data);
+                }
+                _ => return None,
+            }
+        }
+        if pattern.next().is_some() {
+            return None;
+        }
+        let destinations = (self.extract)(&pushes)?;
+        Some(CustomMatch {
+            name: self.name,
+            pushes: pushes.into_iter().map(<[u8]>::to_vec).collect(),
+            destinations,
+        })
+    }
+}
+
+static TEMPLATES: RwLock<Vec<Arc<CustomTemplate>>> = RwLock::new(Vec::new());
+
+/// Registers `template`, replacing any registered template with the same
+/// name.
+///
+/// Templates are tried in the order they were first registered.
+pub fn register_template(template: CustomTemplate) {
+    let mut templates = TEMPLATES.write().expect("lock is not poisoned");
+    let template = Arc::new(template);
+    match templates.iter_mut().find(|t| t.name == template.name) {
+        Some(existing) => *existing = template,
+        None => templates.push(template),
+    }
+}
+
+/// Removes the registered template named `name`, returning true if there
+/// was one.
+pub fn unregister_template(name: &str) -> bool {
+    let mut templates = TEMPLATES.write().expect("lock is not poisoned");
+    let len = templates.len();
+    templates.retain(|t| t.name != name);
+    templates.len() != len
+}
+
+/// Matches `script_pub_key` against the registered templates, returning the
+/// first match.
+///
+/// Unlike [`script_type`], this doesn't check the standard templates first.
+pub fn match_custom(script_pub_key: &Script) -> Option<CustomMatch> {
+    let templates = TEMPLATES.read().expect("lock is not poisoned").clone();
+    templates
+        .iter()
+        .find_map(|template| template.matches(script_pub_key))
+}
+
+/// Returns the type of `script_pub_key`, trying the standard templates and
+/// then the registered ones.
+pub fn script_type(script_pub_key: &Script) -> ScriptType {
+    if let Some(template) = solve(script_pub_key) {
+        return template.script_type();
+    }
+    match_custom(script_pub_key).map_or(ScriptType::NonStandard, |m| ScriptType::Custom(m.name))
+}
+
+/// Returns the addresses paid to by `script_pub_key`, as
+/// [`extract_destinations`] does for standard scripts and the registered
+/// templates' extractors do for the others.
+///
+/// Returns `None` if the script matches no template, or pays to no address.
+pub fn destinations(script_pub_key: &Script) -> Option<Destinations> {
+    if solve(script_pub_key).is_some() {
+        return extract_destinations(script_pub_key);
+    }
+    match_custom(script_pub_key)
+        .map(|m| m.destinations)
+        .filter(|d| !d.addresses.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use zcash_address::Network;
+
+    use super::*;
+    use crate::{decode::decode_script, standard::Destination};
+
+    /// A hash time-locked contract, paying to `recipient` given the preimage
+    /// of `hash`, or to `refund` after `lock_time`.
+    fn htlc(hash: [u8; 32], recipient: [u8; 20], lock_time: i64, refund: [u8; 20]) -> Script {
+        crate::script!(
+            OP_IF OP_SHA256 [hash] OP_EQUALVERIFY OP_DUP OP_HASH160 [recipient]
+            OP_ELSE (lock_time) OP_CHECKLOCKTIMEVERIFY OP_DROP OP_DUP OP_HASH160 [refund]
+            OP_ENDIF OP_EQUALVERIFY OP_CHECKSIG
+        )
+    }
+
+    fn htlc_template() -> CustomTemplate {
+        use Element::{Op, Push};
+
+        CustomTemplate {
+            name: "htlc",
+            pattern: vec![
+                Op(Opcode::OP_IF),
+                Op(Opcode::OP_SHA256),
+                Push(32..=32),
+                Op(Opcode::OP_EQUALVERIFY),
+                Op(Opcode::OP_DUP),
+                Op(Opcode::OP_HASH160),
+                Push(20..=20),
+                Op(Opcode::OP_ELSE),
+                Push(1..=5),
+                Op(Opcode::OP_CHECKLOCKTIMEVERIFY),
+                Op(Opcode::OP_DROP),
+                Op(Opcode::OP_DUP),
+                Op(Opcode::OP_HASH160),
+                Push(20..=20),
+                Op(Opcode::OP_ENDIF),
+                Op(Opcode::OP_EQUALVERIFY),
+                Op(Opcode::OP_CHECKSIG),
+            ],
+            extract: |pushes| {
+                let [_, recipient, _, refund] = pushes else {
+                    return None;
+                };
+                Some(Destinations {
+                    required: 1,
+                    addresses: vec![
+                        Destination::PubKeyHash((*recipient).try_into().ok()?),
+                        Destination::PubKeyHash((*refund).try_into().ok()?),
+                    ],
+                })
+            },
+        }
+    }
+
+    #[test]
+    fn recognizes_registered_templates() {
+        let script = htlc([1; 32], [2; 20], 854882, [3; 20]);
+        assert_eq!(match_custom(&script), None);
+        assert_eq!(script_type(&script), ScriptType::NonStandard);
+
+        register_template(htlc_template());
+        let matched = match_custom(&script).unwrap();
+        assert_eq!(matched.name, "htlc");
+        assert_eq!(matched.pushes[0], [1; 32]);
+        assert_eq!(script_type(&script), ScriptType::Custom("htlc"));
+        assert_eq!(
+            destinations(&script).unwrap().addresses,
+            [
+                Destination::PubKeyHash([2; 20]),
+                Destination::PubKeyHash([3; 20])
+            ]
+        );
+
+        let decoded = decode_script(script.as_bytes(), Network::Main);
+        assert_eq!(decoded.script_type, ScriptType::Custom("htlc"));
+        assert_eq!(decoded.req_sigs, Some(1));
+        assert_eq!(decoded.addresses.map(|a| a.len()), Some(2));
+        assert!(decoded.to_json().contains("\"type\":\"htlc\""));
+
+        // A lock time that is a small integer isn't a data push.
+        assert_eq!(match_custom(&htlc([1; 32], [2; 20], 16, [3; 20])), None);
+        // Standard templates take precedence.
+        let p2pkh = crate::script!(OP_DUP OP_HASH160 [[2; 20]] OP_EQUALVERIFY OP_CHECKSIG);
+        assert_eq!(script_type(&p2pkh), ScriptType::PubKeyHash);
+
+        assert!(unregister_template("htlc"));
+        assert!(!unregister_template("htlc"));
+        assert_eq!(script_type(&script), ScriptType::NonStandard);
+    }
+}
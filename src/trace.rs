@@ -0,0 +1,118 @@
+//! `tracing` instrumentation for verification, enabled by the `tracing` feature.
+//!
+//! Spans are emitted around each verification call, each signature hash
+//! computed by the [`sighash`](crate::sighash) functions, and each
+//! [`ScriptCache::verify`](crate::cache::ScriptCache::verify). The signature
+//! hashes computed inside the C++ interpreter aren't instrumented separately,
+//! and the bundle validity caches are never used for transparent
+//! verification.
+
+use tracing::{field, Span};
+use zcash_primitives::{consensus::BranchId, transaction::Transaction};
+
+use crate::{cache::CacheKey, sighash, txid::TxId, verify::Error};
+
+/// Returns a span for a call to `function`, with the txid recorded if the
+/// span is enabled.
+pub(crate) fn verify_span(
+    function: &'static str,
+    tx_to: &[u8],
+    n_in: u32,
+    flags: u32,
+    consensus_branch_id: u32,
+) -> Span {
+    let span = tracing::debug_span!(
+        "verify",
+        function,
+        txid = field::Empty,
+        n_in,
+        flags,
+        consensus_branch_id,
+        error_code = field::Empty,
+    );
+    if !span.is_disabled() {
+        if let Some(txid) = txid(tx_to, consensus_branch_id) {
+            span.record("txid", field::display(txid));
+        }
+    }
+    span
+}
+
+/// Emits an event for the result of a verification in the current span, and
+/// records its error code.
+pub(crate) fn record_result(result: &Result<(), Error>) {
+    match result {
+        Ok(()) => tracing::trace!("script verified"),
+        Err(err) => {
+            Span::current().record("error_code", err.code());
+            tracing::debug!(error = %err, "script verification failed");
+        }
+    }
+}
+
+/// Returns a span for computing a signature hash with `function`, with the
+/// txid recorded if the span is enabled.
+pub(crate) fn sighash_span(function: &'static str, tx_to: &[u8], n_in: u32, hash_type: u8) -> Span {
+    let span = tracing::trace_span!(
+        "sighash",
+        function,
+        txid = field::Empty,
+        n_in,
+        hash_type,
+        error_code = field::Empty,
+    );
+    if !span.is_disabled() {
+        if let Ok(txid) = crate::txid::txid(tx_to) {
+            span.record("txid", field::display(txid));
+        }
+    }
+    span
+}
+
+/// Emits an event for the result of a signature hash computation in the
+/// current span, and records its error code.
+pub(crate) fn record_sighash_result(result: &Result<[u8; 32], sighash::Error>) {
+    match result {
+        Ok(_) => tracing::trace!("signature hash computed"),
+        Err(err) => {
+            Span::current().record("error_code", err.code());
+            tracing::debug!(error = %err, "signature hash failed");
+        }
+    }
+}
+
+/// Returns a span for a [`ScriptCache`](crate::cache::ScriptCache) lookup of
+/// `key`.
+///
+/// The key's transaction hash is the txid of pre-v5 transactions, but not of
+/// v5 transactions, so it is recorded as `tx_hash`.
+pub(crate) fn cache_span(key: &CacheKey) -> Span {
+    tracing::trace_span!(
+        "script_cache",
+        tx_hash = %TxId(key.tx_hash),
+        n_in = key.n_in,
+        flags = key.flags,
+        consensus_branch_id = key.consensus_branch_id,
+        cache = field::Empty,
+        error_code = field::Empty,
+    )
+}
+
+/// Records the error code of a failed verification in the current span.
+pub(crate) fn record_error_code(result: &Result<(), Error>) {
+    if let Err(err) = result {
+        Span::current().record("error_code", err.code());
+    }
+}
+
+/// Records whether a cache lookup in the current span was a hit or a miss.
+pub(crate) fn record_cache_lookup(hit: bool) {
+    Span::current().record("cache", if hit { "hit" } else { "miss" });
+}
+
+/// Returns the txid of a serialized transaction, if it can be parsed.
+fn txid(tx_to: &[u8], consensus_branch_id: u32) -> Option<String> {
+    let branch_id = BranchId::try_from(consensus_branch_id).ok()?;
+    let tx = Transaction::read(tx_to, branch_id).ok()?;
+    Some(tx.txid().to_string())
+}
@@ -0,0 +1,152 @@
+//! Transaction IDs, for all transaction versions, and ZIP 244 authorizing
+//! data digests.
+//!
+//! The digests are kept in the byte order they are hashed in, and displayed
+//! reversed, as zcashd's `uint256::GetHex` shows them in RPCs and block
+//! explorers.
+
+use std::fmt;
+
+use hex::FromHex;
+use sha2::{Digest, Sha256};
+
+use crate::sighash::{check_pre_v5, read_v5, Error};
+
+macro_rules! digest_type {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+        pub struct $name(pub [u8; 32]);
+
+        impl $name {
+            /// Returns the digest in the byte order it was hashed in.
+            pub fn as_bytes(&self) -> &[u8; 32] {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            /// Formats the digest as hex in reversed byte order, like zcashd.
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                let mut reversed = self.0;
+                reversed.reverse();
+                f.write_str(&hex::encode(reversed))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        impl FromHex for $name {
+            type Error = hex::FromHexError;
+
+            /// Parses hex in reversed byte order, as [`Display`](fmt::Display)
+            /// formats it.
+            fn from_hex<T: AsRef<[u8]>>(hex: T) -> Result<Self, Self::Error> {
+                let mut bytes = <[u8; 32]>::from_hex(hex)?;
+                bytes.reverse();
+                Ok($name(bytes))
+            }
+        }
+    };
+}
+
+digest_type!(
+    /// A transaction ID.
+    TxId
+);
+
+digest_type!(
+    /// A ZIP 244 digest of a v5 transaction's authorizing data, which
+    /// together with its [`TxId`] commits to the whole transaction.
+    AuthDigest
+);
+
+/// Returns the ID of the serialized transaction `tx_to`, of any version.
+pub fn txid(tx_to: &[u8]) -> Result<TxId, Error> {
+    match txid_legacy(tx_to) {
+        Err(Error::TxVersion) => txid_v5(tx_to),
+        result => result,
+    }
+}
+
+/// Returns the ID of the serialized v1 to v4 transaction `tx_to`, the double
+/// SHA-256 hash of its serialization.
+///
+/// Unlike ZIP 244 transaction IDs, these commit to the transaction's
+/// signatures.
+pub fn txid_legacy(tx_to: &[u8]) -> Result<TxId, Error> {
+    check_pre_v5(tx_to)?;
+    Ok(TxId(Sha256::digest(Sha256::digest(tx_to)).into()))
+}
+
+/// Returns the ZIP 244 transaction ID of the serialized v5 transaction
+/// `tx_to`.
+pub fn txid_v5(tx_to: &[u8]) -> Result<TxId, Error> {
+    let tx = read_v5(tx_to)?;
+    Ok(TxId(*tx.txid().as_ref()))
+}
+
+/// Returns the ZIP 244 authorizing data digest of the serialized v5
+/// transaction `tx_to`.
+pub fn auth_digest(tx_to: &[u8]) -> Result<AuthDigest, Error> {
+    let tx = read_v5(tx_to)?;
+    Ok(AuthDigest(
+        tx.auth_commitment()
+            .as_bytes()
+            .try_into()
+            .expect("digests are 32 bytes"),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::Value;
+
+    use super::*;
+    use crate::tests::SCRIPT_TX;
+
+    #[test]
+    fn zip244_vectors() {
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/depend/zcash/src/test/data/zip0244.json"
+        );
+        let json: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        // The first two entries are comments.
+        for test in &json.as_array().unwrap()[2..] {
+            let tx = Vec::from_hex(test[0].as_str().unwrap()).unwrap();
+            let txid = txid_v5(&tx).unwrap();
+            assert_eq!(txid.to_string(), test[1].as_str().unwrap());
+            assert_eq!(TxId::from_hex(test[1].as_str().unwrap()), Ok(txid));
+            assert_eq!(
+                auth_digest(&tx).unwrap().to_string(),
+                test[2].as_str().unwrap()
+            );
+        }
+
+        assert_eq!(txid_v5(&SCRIPT_TX), Err(Error::TxVersion));
+    }
+
+    #[test]
+    fn legacy_txids() {
+        // zcashd's `bitcoin-util-test.json` names this transaction by its ID.
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/depend/zcash/src/test/data/tx394b54bb.hex"
+        );
+        let tx = Vec::from_hex(std::fs::read_to_string(path).unwrap().trim()).unwrap();
+        let expected = "394b54bbde1f6f6f8630eab12e1ce972004751b3cb20a40ceec96bbfe25b6223";
+        assert_eq!(txid_legacy(&tx).unwrap().to_string(), expected);
+        assert_eq!(txid(&tx).unwrap().to_string(), expected);
+
+        assert_eq!(
+            txid(&SCRIPT_TX).unwrap().as_bytes(),
+            &crate::cache::CacheKey::new(&SCRIPT_TX, 0, 0, 0).tx_hash
+        );
+        assert_eq!(txid(&SCRIPT_TX[1..]), Err(Error::TxDeserialize));
+    }
+}
@@ -0,0 +1,137 @@
+//! The stable high-level API.
+//!
+//! Node implementations should depend on this module rather than on the
+//! crate's other modules, which may change in any minor release while the
+//! crate is below 1.0. Items here only change compatibly: a breaking change
+//! is made in a new `v2` module, and `v1` stays alongside it, deprecated, for
+//! at least one release.
+//!
+//! Unlike the C API, flags are a typed [`VerificationFlags`], and errors are
+//! a [`verify::Error`](Error) that distinguishes script failures from other
+//! errors.
+
+pub use crate::{
+    flags::VerificationFlags,
+    precomputed::{FailurePolicy, Input, PrecomputedTx},
+    prevout::{encode_all_prev_outputs, OutPoint, PrevOut, PrevOutSource},
+    script::Script,
+    script_error::ScriptError,
+    sighash::SighashType,
+    txid::TxId,
+    verify::{
+        api_version, check_tx_at, legacy_sigop_count, verify_tx_at, CheckTxError, Error,
+        InputError, TxError, TxOutcome,
+    },
+};
+
+use crate::verify;
+
+/// Returns `Ok(())` if the input `n_in` of the serialized pre-v5 transaction
+/// `tx_to` correctly spends `script_pub_key`, an output worth `amount`
+/// zatoshis, under `flags`.
+pub fn verify_script(
+    script_pub_key: &[u8],
+    amount: i64,
+    tx_to: &[u8],
+    n_in: u32,
+    flags: VerificationFlags,
+    consensus_branch_id: u32,
+) -> Result<(), Error> {
+    verify::verify_script(
+        script_pub_key,
+        amount,
+        tx_to,
+        n_in,
+        flags.bits(),
+        consensus_branch_id,
+    )
+}
+
+/// Returns `Ok(())` if the input `n_in` of the serialized v5 transaction
+/// `tx_to` correctly spends the matching output in `all_prev_outputs`, as
+/// [`encode_all_prev_outputs`] encodes them, under `flags`.
+pub fn verify_script_v5(
+    tx_to: &[u8],
+    all_prev_outputs: &[u8],
+    n_in: u32,
+    flags: VerificationFlags,
+    consensus_branch_id: u32,
+) -> Result<(), Error> {
+    verify::verify_script_v5(
+        tx_to,
+        all_prev_outputs,
+        n_in,
+        flags.bits(),
+        consensus_branch_id,
+    )
+}
+
+/// Verifies every transparent input of the serialized transaction `tx_to`,
+/// of any version, under `flags`, looking up the outputs they spend in
+/// `prev_outs`, and returns the result for each input.
+pub fn verify_tx<S: PrevOutSource + ?Sized>(
+    tx_to: &[u8],
+    prev_outs: &S,
+    flags: VerificationFlags,
+    consensus_branch_id: u32,
+) -> Result<Vec<Result<(), Error>>, TxError> {
+    verify::verify_tx(tx_to, prev_outs, flags.bits(), consensus_branch_id)
+}
+
+/// Verifies every transparent input of the serialized transaction `tx_to`,
+/// like [`verify_tx`], but returns [`TxOutcome::NothingToVerify`] for
+/// shielded-only and coinbase transactions.
+pub fn verify_tx_outcome<S: PrevOutSource + ?Sized>(
+    tx_to: &[u8],
+    prev_outs: &S,
+    flags: VerificationFlags,
+    consensus_branch_id: u32,
+) -> Result<TxOutcome, TxError> {
+    verify::verify_tx_outcome(tx_to, prev_outs, flags.bits(), consensus_branch_id)
+}
+
+/// Returns `Ok(())` if every transparent input of the serialized transaction
+/// `tx_to` verifies under `flags`, with errors that carry the transaction ID
+/// and input index. `policy` selects whether verification stops at the first
+/// failing input.
+pub fn check_tx<S: PrevOutSource + ?Sized>(
+    tx_to: &[u8],
+    prev_outs: &S,
+    flags: VerificationFlags,
+    consensus_branch_id: u32,
+    policy: FailurePolicy,
+) -> Result<(), CheckTxError> {
+    verify::check_tx(tx_to, prev_outs, flags.bits(), consensus_branch_id, policy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+
+    #[test]
+    #[allow(deprecated)]
+    fn matches_deprecated_functions() {
+        let amount = 212 * 100_000_000;
+        let flags = VerificationFlags::P2SH | VerificationFlags::CHECKLOCKTIMEVERIFY;
+        assert_eq!(
+            verify_script(&SCRIPT_PUBKEY, amount, &SCRIPT_TX, 0, flags, 0x2bb40e60),
+            Ok(())
+        );
+        assert_eq!(
+            verify_script(&SCRIPT_PUBKEY, amount - 1, &SCRIPT_TX, 0, flags, 0x2bb40e60),
+            Err(Error::ScriptInvalid)
+        );
+        assert_eq!(
+            crate::verify_script(
+                &SCRIPT_PUBKEY,
+                amount - 1,
+                &SCRIPT_TX,
+                0,
+                flags.bits(),
+                0x2bb40e60
+            ),
+            Err(Error::ScriptInvalid.code())
+        );
+    }
+}
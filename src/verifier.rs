@@ -0,0 +1,202 @@
+//! A verifier that keeps the state worth reusing between calls.
+//!
+//! Verifying an input with [`verify_script`](crate::verify::verify_script)
+//! deserializes the transaction and precomputes its signature hash data each
+//! time. A [`Verifier`] keeps the last transaction it precomputed, so
+//! verifying the inputs of one transaction in turn only does that once, and
+//! optionally a [`ScriptCache`] of successful verifications. The secp256k1
+//! contexts, both zcashd's and this crate's, are created once per process,
+//! so they need no amortizing.
+
+use std::sync::{Arc, Mutex};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    cache::{CacheKey, ScriptCache},
+    precomputed::{Input, PrecomputedTx},
+    verify::Error,
+};
+
+/// The transaction a [`Verifier`] precomputed last.
+#[derive(Debug)]
+struct LastTx {
+    /// The hash of the serialized transaction.
+    tx_hash: [u8; 32],
+    /// The hash of the encoded previous outputs, for v5 transactions.
+    prev_outputs_hash: Option<[u8; 32]>,
+    tx: Arc<PrecomputedTx>,
+}
+
+/// Verifies transparent inputs, reusing precomputed transactions and cached
+/// results between calls.
+///
+/// A verifier can be kept per thread, or shared between threads: it is
+/// `Sync`, and verifications don't hold its lock.
+#[derive(Debug, Default)]
+pub struct Verifier {
+    cache: Option<ScriptCache>,
+    last_tx: Mutex<Option<LastTx>>,
+}
+
+impl Verifier {
+    /// Creates a verifier without a cache of results.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a verifier that caches up to `capacity` successful
+    /// verifications, as a [`ScriptCache`] does.
+    pub fn with_cache(capacity: usize) -> Self {
+        Verifier {
+            cache: Some(ScriptCache::new(capacity)),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the verifier's cache of results, if it has one.
+    pub fn cache(&self) -> Option<&ScriptCache> {
+        self.cache.as_ref()
+    }
+
+    /// Returns `Ok(())` if `input` of the serialized pre-v5 transaction
+    /// `tx_to` is valid, like [`PrecomputedTx::verify`].
+    pub fn verify(
+        &self,
+        tx_to: &[u8],
+        input: Input<'_>,
+        consensus_branch_id: u32,
+    ) -> Result<(), Error> {
+        self.verify_with(tx_to, None, input, consensus_branch_id)
+    }
+
+    /// Returns `Ok(())` if `input` of the serialized v5 transaction `tx_to`
+    /// is valid, where `all_prev_outputs` is the encoding of all the outputs
+    /// spent by the transaction, like [`PrecomputedTx::verify`].
+    pub fn verify_v5(
+        &self,
+        tx_to: &[u8],
+        all_prev_outputs: &[u8],
+        input: Input<'_>,
+        consensus_branch_id: u32,
+    ) -> Result<(), Error> {
+        self.verify_with(tx_to, Some(all_prev_outputs), input, consensus_branch_id)
+    }
+
+    fn verify_with(
+        &self,
+        tx_to: &[u8],
+        all_prev_outputs: Option<&[u8]>,
+        input: Input<'_>,
+        consensus_branch_id: u32,
+    ) -> Result<(), Error> {
+        // Callers pass the spent outputs themselves, so the key commits to
+        // them rather than trusting that they match the input's outpoint.
+        let key = CacheKey::new(tx_to, input.n_in, input.flags, consensus_branch_id)
+            .with_spent_outputs(input.script_pub_key, input.amount, all_prev_outputs);
+        let verify = || {
+            self.precomputed(tx_to, key.tx_hash, all_prev_outputs)?
+                .verify(
+                    input.n_in,
+                    input.script_pub_key,
+                    input.amount,
+                    input.flags,
+                    consensus_branch_id,
+                )
+        };
+        match &self.cache {
+            Some(cache) => cache.verify(key, verify),
+            None => verify(),
+        }
+    }
+
+    /// Returns the precomputed transaction for `tx_to`, reusing the last one
+    /// if it's the same.
+    fn precomputed(
+        &self,
+        tx_to: &[u8],
+        tx_hash: [u8; 32],
+        all_prev_outputs: Option<&[u8]>,
+    ) -> Result<Arc<PrecomputedTx>, Error> {
+        let prev_outputs_hash = all_prev_outputs.map(|encoded| Sha256::digest(encoded).into());
+        let mut last_tx = self.last_tx.lock().expect("lock is not poisoned");
+        if let Some(last) = last_tx.as_ref() {
+            if last.tx_hash == tx_hash && last.prev_outputs_hash == prev_outputs_hash {
+                return Ok(last.tx.clone());
+            }
+        }
+        // Deserialize outside the lock, so other threads can verify the
+        // previous transaction meanwhile.
+        drop(last_tx);
+        let tx = Arc::new(match all_prev_outputs {
+            Some(all_prev_outputs) => PrecomputedTx::new_v5(tx_to, all_prev_outputs)?,
+            None => PrecomputedTx::new(tx_to)?,
+        });
+        *self.last_tx.lock().expect("lock is not poisoned") = Some(LastTx {
+            tx_hash,
+            prev_outputs_hash,
+            tx: tx.clone(),
+        });
+        Ok(tx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prevout::encode_all_prev_outputs,
+        tests::{SCRIPT_PUBKEY, SCRIPT_TX},
+    };
+
+    #[test]
+    fn reuses_state_between_calls() {
+        let verifier = Verifier::with_cache(10);
+        let input = Input {
+            n_in: 0,
+            script_pub_key: &SCRIPT_PUBKEY,
+            amount: 212 * 100_000_000,
+            flags: 1,
+        };
+        assert_eq!(verifier.verify(&SCRIPT_TX, input, 0x2bb40e60), Ok(()));
+        let first = verifier.precomputed(
+            &SCRIPT_TX,
+            CacheKey::new(&SCRIPT_TX, 0, 1, 0x2bb40e60).tx_hash,
+            None,
+        );
+        assert_eq!(verifier.verify(&SCRIPT_TX, input, 0x2bb40e60), Ok(()));
+        assert_eq!(verifier.cache().map(ScriptCache::len), Some(1));
+
+        // A success isn't reused for a different spent output. Failures
+        // aren't cached, and reuse the precomputed transaction.
+        let wrong_amount = Input {
+            amount: input.amount - 1,
+            ..input
+        };
+        assert_eq!(
+            verifier.verify(&SCRIPT_TX, wrong_amount, 0x2bb40e60),
+            Err(Error::ScriptInvalid)
+        );
+        assert_eq!(verifier.cache().map(ScriptCache::len), Some(1));
+        assert_eq!(verifier.cache().map(ScriptCache::hits), Some(1));
+        let second = verifier.precomputed(
+            &SCRIPT_TX,
+            CacheKey::new(&SCRIPT_TX, 0, 1, 0x2bb40e60).tx_hash,
+            None,
+        );
+        assert!(Arc::ptr_eq(&first.unwrap(), &second.unwrap()));
+
+        // Transactions can also be precomputed with their previous outputs.
+        let all_prev_outputs =
+            encode_all_prev_outputs([(&SCRIPT_PUBKEY[..], input.amount)].into_iter());
+        let uncached = Verifier::new();
+        assert_eq!(
+            uncached.verify_v5(&SCRIPT_TX, &all_prev_outputs, input, 0x2bb40e60),
+            Ok(())
+        );
+        assert_eq!(
+            uncached.verify(&[], input, 0x2bb40e60),
+            Err(Error::TxDeserialize)
+        );
+    }
+}
@@ -0,0 +1,1000 @@
+//! Script verification returning idiomatic errors.
+//!
+//! The C API reports failures as an `int` result plus a `zcash_script_error`
+//! out-parameter, and a script that fails to verify is reported with the
+//! `zcash_script_ERR_OK` code. The functions here return an [`Error`] that
+//! distinguishes every case instead.
+
+use std::{fmt, sync::Once};
+
+use zcash_primitives::{
+    consensus::{BlockHeight, BranchId, Parameters},
+    transaction::Transaction,
+};
+
+use crate::{
+    flags::VerificationFlags,
+    observer,
+    precomputed::{FailurePolicy, Input, PrecomputedTx},
+    prevout::{encode_all_prev_outputs, PrevOut, PrevOutSource},
+    script::Script,
+    script_error::ScriptError,
+    txid::{self, TxId},
+    zcash_script_error_t, zcash_script_error_t_zcash_script_ERR_ALL_PREV_OUTPUTS_DESERIALIZE,
+    zcash_script_error_t_zcash_script_ERR_ALL_PREV_OUTPUTS_SIZE_MISMATCH,
+    zcash_script_error_t_zcash_script_ERR_OK, zcash_script_error_t_zcash_script_ERR_TX_DESERIALIZE,
+    zcash_script_error_t_zcash_script_ERR_TX_INDEX,
+    zcash_script_error_t_zcash_script_ERR_TX_SIZE_MISMATCH,
+    zcash_script_error_t_zcash_script_ERR_TX_VERSION,
+    zcash_script_error_t_zcash_script_ERR_VERIFY_SCRIPT, zcash_script_last_script_error,
+    zcash_script_legacy_sigop_count, zcash_script_verify, zcash_script_verify_v5,
+    zcash_script_version,
+};
+
+/// An error returned when verification doesn't succeed.
+///
+/// Each error's [code](Error::code) is the C API's `zcash_script_error`, whose
+/// values are stable across versions of zcash_script. Script failures are
+/// [`Error::ScriptInvalid`], which has the code of `zcash_script_ERR_OK`.
+///
+/// With the `serde` feature, errors are serialized by name, like
+/// `"script_invalid"`, with unknown codes as `{"unknown": 8}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum Error {
+    /// The script was evaluated, and failed.
+    ScriptInvalid,
+    /// The input index is out of range for the transaction.
+    TxIndex,
+    /// The transaction has trailing data after its serialization.
+    TxSizeMismatch,
+    /// The transaction couldn't be deserialized.
+    TxDeserialize,
+    /// The transaction version isn't supported by the function used.
+    TxVersion,
+    /// The number of previous outputs doesn't match the number of inputs.
+    AllPrevOutputsSizeMismatch,
+    /// The previous outputs couldn't be deserialized.
+    AllPrevOutputsDeserialize,
+    /// An exception was thrown during script verification.
+    VerifyScript,
+    /// An error code unknown to this version of the crate.
+    Unknown(zcash_script_error_t),
+}
+
+impl Error {
+    /// Converts the error code of a failed C API call.
+    ///
+    /// `zcash_script_ERR_OK` means that the script itself failed.
+    #[allow(non_upper_case_globals)]
+    pub fn from_code(code: zcash_script_error_t) -> Self {
+        match code {
+            zcash_script_error_t_zcash_script_ERR_OK => Error::ScriptInvalid,
+            zcash_script_error_t_zcash_script_ERR_TX_INDEX => Error::TxIndex,
+            zcash_script_error_t_zcash_script_ERR_TX_SIZE_MISMATCH => Error::TxSizeMismatch,
+            zcash_script_error_t_zcash_script_ERR_TX_DESERIALIZE => Error::TxDeserialize,
+            zcash_script_error_t_zcash_script_ERR_TX_VERSION => Error::TxVersion,
+            zcash_script_error_t_zcash_script_ERR_ALL_PREV_OUTPUTS_SIZE_MISMATCH => {
+                Error::AllPrevOutputsSizeMismatch
+            }
+            zcash_script_error_t_zcash_script_ERR_ALL_PREV_OUTPUTS_DESERIALIZE => {
+                Error::AllPrevOutputsDeserialize
+            }
+            zcash_script_error_t_zcash_script_ERR_VERIFY_SCRIPT => Error::VerifyScript,
+            code => Error::Unknown(code),
+        }
+    }
+
+    /// Returns the C API error code for this error.
+    pub fn code(self) -> zcash_script_error_t {
+        match self {
+            Error::ScriptInvalid => zcash_script_error_t_zcash_script_ERR_OK,
+            Error::TxIndex => zcash_script_error_t_zcash_script_ERR_TX_INDEX,
+            Error::TxSizeMismatch => zcash_script_error_t_zcash_script_ERR_TX_SIZE_MISMATCH,
+            Error::TxDeserialize => zcash_script_error_t_zcash_script_ERR_TX_DESERIALIZE,
+            Error::TxVersion => zcash_script_error_t_zcash_script_ERR_TX_VERSION,
+            Error::AllPrevOutputsSizeMismatch => {
+                zcash_script_error_t_zcash_script_ERR_ALL_PREV_OUTPUTS_SIZE_MISMATCH
+            }
+            Error::AllPrevOutputsDeserialize => {
+                zcash_script_error_t_zcash_script_ERR_ALL_PREV_OUTPUTS_DESERIALIZE
+            }
+            Error::VerifyScript => zcash_script_error_t_zcash_script_ERR_VERIFY_SCRIPT,
+            Error::Unknown(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ScriptInvalid => write!(f, "script verification failed"),
+            Error::TxIndex => write!(f, "input index out of range"),
+            Error::TxSizeMismatch => write!(f, "transaction has trailing data"),
+            Error::TxDeserialize => write!(f, "transaction deserialization failed"),
+            Error::TxVersion => write!(f, "unsupported transaction version"),
+            Error::AllPrevOutputsSizeMismatch => {
+                write!(f, "previous outputs don't match the transaction inputs")
+            }
+            Error::AllPrevOutputsDeserialize => {
+                write!(f, "previous outputs deserialization failed")
+            }
+            Error::VerifyScript => write!(f, "exception during script verification"),
+            Error::Unknown(code) => write!(f, "unknown error code {}", code),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// An error returned when a whole transaction can't be verified by
+/// [`verify_tx`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TxError {
+    /// The transaction couldn't be verified.
+    Tx(Error),
+    /// The output spent by the input with this index wasn't found.
+    MissingPrevOut(usize),
+}
+
+impl From<Error> for TxError {
+    fn from(err: Error) -> Self {
+        TxError::Tx(err)
+    }
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TxError::Tx(err) => err.fmt(f),
+            TxError::MissingPrevOut(index) => {
+                write!(f, "output spent by input {} not found", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TxError {}
+
+/// An error verifying an input, with the transaction and input it occurred
+/// in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InputError {
+    pub txid: TxId,
+    pub n_in: u32,
+    pub error: Error,
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "input {} of {}: {}", self.n_in, self.txid, self.error)
+    }
+}
+
+impl std::error::Error for InputError {}
+
+/// An error returned when a whole transaction doesn't verify with
+/// [`check_tx`] or [`check_tx_with_prevouts`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckTxError {
+    /// The transaction couldn't be deserialized or precomputed, so it has no
+    /// ID.
+    Tx(Error),
+    /// The output spent by input `n_in` of `txid` wasn't found.
+    MissingPrevOut { txid: TxId, n_in: u32 },
+    /// The inputs that failed to verify, in order.
+    Inputs(Vec<InputError>),
+}
+
+impl From<Error> for CheckTxError {
+    fn from(err: Error) -> Self {
+        CheckTxError::Tx(err)
+    }
+}
+
+impl fmt::Display for CheckTxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckTxError::Tx(err) => err.fmt(f),
+            CheckTxError::MissingPrevOut { txid, n_in } => {
+                write!(f, "output spent by input {} of {} not found", n_in, txid)
+            }
+            CheckTxError::Inputs(errors) => {
+                for (i, err) in errors.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str("; ")?;
+                    }
+                    err.fmt(f)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CheckTxError {}
+
+/// The outcome of verifying a whole transaction with [`verify_tx_outcome`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TxOutcome {
+    /// The transaction has no transparent inputs that spend outputs, because
+    /// it only spends shielded notes, or it is a coinbase transaction.
+    NothingToVerify,
+    /// The result for each transparent input.
+    Verified(Vec<Result<(), Error>>),
+}
+
+impl TxOutcome {
+    /// Returns the result for each transparent input, which is empty if there
+    /// was nothing to verify.
+    pub fn into_results(self) -> Vec<Result<(), Error>> {
+        match self {
+            TxOutcome::NothingToVerify => vec![],
+            TxOutcome::Verified(results) => results,
+        }
+    }
+
+    /// Returns true if every transparent input verified, including when
+    /// there was nothing to verify.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            TxOutcome::NothingToVerify => true,
+            TxOutcome::Verified(results) => results.iter().all(Result::is_ok),
+        }
+    }
+}
+
+/// A failed verification of a scriptPubKey, with the context needed to debug
+/// it, returned by [`verify_script_detailed`].
+///
+/// It displays on one line as its [`Error`] and the interpreter's
+/// [`ScriptError`]. The alternate form, `{:#}`, adds the flags and the
+/// disassembled scriptPubKey on further lines. The interpreter doesn't report
+/// the opcode it stopped at, so the whole scriptPubKey is shown.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifyFailure {
+    /// The verification error.
+    pub error: Error,
+    /// The interpreter error, if `error` is [`Error::ScriptInvalid`].
+    pub script_error: Option<ScriptError>,
+    /// The flags the script was verified under.
+    pub flags: VerificationFlags,
+    /// The scriptPubKey being spent.
+    pub script_pub_key: Script,
+}
+
+impl fmt::Display for VerifyFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.error)?;
+        if let Some(script_error) = self.script_error {
+            write!(f, ": {}", script_error)?;
+        }
+        if f.alternate() {
+            write!(f, "\n  flags: {}", self.flags)?;
+            write!(f, "\n  scriptPubKey: {}", self.script_pub_key.to_asm())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for VerifyFailure {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Returns the interpreter's error for the last script the C API verified on
+/// this thread.
+pub(crate) fn last_script_error() -> ScriptError {
+    let code = unsafe { zcash_script_last_script_error() };
+    ScriptError::from_code(code).unwrap_or(ScriptError::UnknownError)
+}
+
+/// Returns the scriptSig of the input `n_in` of the serialized transaction
+/// `tx_to`, or `None` if there is no such input.
+pub(crate) fn script_sig(
+    tx_to: &[u8],
+    n_in: u32,
+    consensus_branch_id: u32,
+) -> Result<Option<Script>, Error> {
+    let branch_id = BranchId::try_from(consensus_branch_id).map_err(|_| Error::TxDeserialize)?;
+    let tx = Transaction::read(tx_to, branch_id).map_err(|_| Error::TxDeserialize)?;
+    Ok(tx
+        .transparent_bundle()
+        .and_then(|bundle| bundle.vin.get(n_in as usize))
+        .map(|input| Script::from(&input.script_sig.0[..])))
+}
+
+/// Returns the redeem script revealed by `script_sig`, if it spends the P2SH
+/// `script_pub_key` under `flags`.
+pub(crate) fn redeem_script<'a>(
+    script_sig: &'a Script,
+    script_pub_key: &Script,
+    flags: VerificationFlags,
+) -> Option<&'a [u8]> {
+    if !flags.contains(VerificationFlags::P2SH) || !script_pub_key.is_pay_to_script_hash() {
+        return None;
+    }
+    match script_sig.instructions().last() {
+        Some(Ok(op)) => Some(op.data),
+        _ => None,
+    }
+}
+
+/// Converts the result of a C API verification call.
+pub(crate) fn from_ret(ret: i32, err: zcash_script_error_t) -> Result<(), Error> {
+    if ret == 1 {
+        Ok(())
+    } else {
+        Err(Error::from_code(err))
+    }
+}
+
+/// Returns `Ok(())` if the input `n_in` of the serialized transaction `tx_to`
+/// correctly spends `script_pub_key` under the additional constraints
+/// specified by `flags`.
+///
+/// This uses `zcash_script_verify`, which cannot be used for v5+
+/// transactions.
+pub fn verify_script(
+    script_pub_key: &[u8],
+    amount: i64,
+    tx_to: &[u8],
+    n_in: u32,
+    flags: u32,
+    consensus_branch_id: u32,
+) -> Result<(), Error> {
+    check_api_version();
+    #[cfg(feature = "tracing")]
+    let _span = crate::trace::verify_span("verify_script", tx_to, n_in, flags, consensus_branch_id)
+        .entered();
+
+    let result = observer::observe(flags, Some(script_pub_key), || {
+        let mut err = 0;
+        let ret = unsafe {
+            zcash_script_verify(
+                script_pub_key.as_ptr(),
+                script_pub_key.len() as _,
+                amount,
+                tx_to.as_ptr(),
+                tx_to.len() as _,
+                n_in,
+                flags,
+                consensus_branch_id,
+                &mut err,
+            )
+        };
+        from_ret(ret, err)
+    });
+
+    #[cfg(feature = "tracing")]
+    crate::trace::record_result(&result);
+
+    result
+}
+
+/// Verifies like [`verify_script`], but returns a [`VerifyFailure`] that
+/// shows the interpreter's error, the flags and the scriptPubKey.
+pub fn verify_script_detailed(
+    script_pub_key: &[u8],
+    amount: i64,
+    tx_to: &[u8],
+    n_in: u32,
+    flags: VerificationFlags,
+    consensus_branch_id: u32,
+) -> Result<(), VerifyFailure> {
+    verify_script(
+        script_pub_key,
+        amount,
+        tx_to,
+        n_in,
+        flags.bits(),
+        consensus_branch_id,
+    )
+    .map_err(|error| VerifyFailure {
+        error,
+        script_error: (error == Error::ScriptInvalid).then(last_script_error),
+        flags,
+        script_pub_key: Script::from(script_pub_key),
+    })
+}
+
+/// Returns `Ok(())` if the input `n_in` of the serialized transaction `tx_to`
+/// correctly spends the matching output in `all_prev_outputs` under the
+/// additional constraints specified by `flags`.
+///
+/// This uses `zcash_script_verify_v5`, which must be used for v5
+/// transactions. `all_prev_outputs` is the encoding of all the outputs spent
+/// by the transaction, as described for the C API.
+pub fn verify_script_v5(
+    tx_to: &[u8],
+    all_prev_outputs: &[u8],
+    n_in: u32,
+    flags: u32,
+    consensus_branch_id: u32,
+) -> Result<(), Error> {
+    check_api_version();
+    #[cfg(feature = "tracing")]
+    let _span =
+        crate::trace::verify_span("verify_script_v5", tx_to, n_in, flags, consensus_branch_id)
+            .entered();
+
+    let result = observer::observe(flags, None, || {
+        let mut err = 0;
+        let ret = unsafe {
+            zcash_script_verify_v5(
+                tx_to.as_ptr(),
+                tx_to.len() as _,
+                all_prev_outputs.as_ptr(),
+                all_prev_outputs.len() as _,
+                n_in,
+                flags,
+                consensus_branch_id,
+                &mut err,
+            )
+        };
+        from_ret(ret, err)
+    });
+
+    #[cfg(feature = "tracing")]
+    crate::trace::record_result(&result);
+
+    result
+}
+
+/// Verifies every transparent input of the serialized transaction `tx_to`
+/// under `flags`, looking up the outputs they spend in `prev_outs`, and
+/// returns the result for each input.
+///
+/// This works for all transaction versions. Coinbase transactions and
+/// transactions without transparent inputs have no inputs to verify, so the
+/// result is empty; [`verify_tx_outcome`] reports them distinctly. The
+/// transaction is parsed to find its inputs' outpoints, so an unknown
+/// `consensus_branch_id` is reported as [`Error::TxDeserialize`].
+pub fn verify_tx<S: PrevOutSource + ?Sized>(
+    tx_to: &[u8],
+    prev_outs: &S,
+    flags: u32,
+    consensus_branch_id: u32,
+) -> Result<Vec<Result<(), Error>>, TxError> {
+    verify_tx_outcome(tx_to, prev_outs, flags, consensus_branch_id).map(TxOutcome::into_results)
+}
+
+/// Verifies every transparent input of the serialized transaction `tx_to`,
+/// like [`verify_tx`], but returns [`TxOutcome::NothingToVerify`] for
+/// shielded-only and coinbase transactions.
+pub fn verify_tx_outcome<S: PrevOutSource + ?Sized>(
+    tx_to: &[u8],
+    prev_outs: &S,
+    flags: u32,
+    consensus_branch_id: u32,
+) -> Result<TxOutcome, TxError> {
+    let branch_id = BranchId::try_from(consensus_branch_id).map_err(|_| Error::TxDeserialize)?;
+    let tx = Transaction::read(tx_to, branch_id).map_err(|_| Error::TxDeserialize)?;
+    let prev_outs = match tx.transparent_bundle() {
+        Some(bundle) if !bundle.is_coinbase() => bundle
+            .vin
+            .iter()
+            .enumerate()
+            .map(|(index, input)| {
+                prev_outs
+                    .prev_out(&input.prevout)
+                    .ok_or(TxError::MissingPrevOut(index))
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => return Ok(TxOutcome::NothingToVerify),
+    };
+
+    let prev_outs: Vec<_> = prev_outs
+        .iter()
+        .map(|prev_out| (prev_out.script_pub_key.as_bytes(), prev_out.amount))
+        .collect();
+    Ok(TxOutcome::Verified(verify_tx_with_prevouts(
+        tx_to,
+        &prev_outs,
+        flags,
+        consensus_branch_id,
+    )?))
+}
+
+/// Verifies every transparent input of the serialized transaction `tx_to`
+/// under `flags`, and returns the result for each input.
+///
+/// `prev_outs` are the scriptPubKey and amount of each output spent by the
+/// transaction, in input order. This works for all transaction versions, but
+/// not for coinbase transactions, whose inputs don't spend outputs. Returns
+/// [`Error::AllPrevOutputsSizeMismatch`] if there isn't one previous output
+/// for each input.
+pub fn verify_tx_with_prevouts(
+    tx_to: &[u8],
+    prev_outs: &[(&[u8], i64)],
+    flags: u32,
+    consensus_branch_id: u32,
+) -> Result<Vec<Result<(), Error>>, Error> {
+    let tx = precompute(tx_to, prev_outs, consensus_branch_id)?;
+    let inputs: Vec<_> = prev_outs
+        .iter()
+        .zip(0..)
+        .map(|(&(script_pub_key, amount), n_in)| Input {
+            n_in,
+            script_pub_key,
+            amount,
+            flags,
+        })
+        .collect();
+    Ok(tx.verify_batch(&inputs, consensus_branch_id))
+}
+
+/// Returns `Ok(())` if every transparent input of the serialized transaction
+/// `tx_to` verifies under `flags`, looking up the outputs they spend in
+/// `prev_outs`, like [`verify_tx_outcome`].
+///
+/// Errors carry the transaction ID and input index, so they can be logged as
+/// they are. `policy` selects whether verification stops at the first
+/// failing input, as in [`PrecomputedTx::verify_many`].
+pub fn check_tx<S: PrevOutSource + ?Sized>(
+    tx_to: &[u8],
+    prev_outs: &S,
+    flags: u32,
+    consensus_branch_id: u32,
+    policy: FailurePolicy,
+) -> Result<(), CheckTxError> {
+    let branch_id = BranchId::try_from(consensus_branch_id).map_err(|_| Error::TxDeserialize)?;
+    let tx = Transaction::read(tx_to, branch_id).map_err(|_| Error::TxDeserialize)?;
+    let txid = TxId(*tx.txid().as_ref());
+    let prev_outs = match tx.transparent_bundle() {
+        Some(bundle) if !bundle.is_coinbase() => bundle
+            .vin
+            .iter()
+            .zip(0..)
+            .map(|(input, n_in)| {
+                prev_outs
+                    .prev_out(&input.prevout)
+                    .ok_or(CheckTxError::MissingPrevOut { txid, n_in })
+            })
+            .collect::<Result<Vec<PrevOut>, _>>()?,
+        _ => return Ok(()),
+    };
+
+    let prev_outs: Vec<_> = prev_outs
+        .iter()
+        .map(|prev_out| (prev_out.script_pub_key.as_bytes(), prev_out.amount))
+        .collect();
+    let precomputed = precompute(tx_to, &prev_outs, consensus_branch_id)?;
+    check_inputs(
+        &precomputed,
+        txid,
+        &prev_outs,
+        flags,
+        consensus_branch_id,
+        policy,
+    )
+}
+
+/// Returns `Ok(())` if every transparent input of the serialized transaction
+/// `tx_to` verifies under `flags`, like [`verify_tx_with_prevouts`], with
+/// errors that carry the transaction ID and input index as in [`check_tx`].
+pub fn check_tx_with_prevouts(
+    tx_to: &[u8],
+    prev_outs: &[(&[u8], i64)],
+    flags: u32,
+    consensus_branch_id: u32,
+    policy: FailurePolicy,
+) -> Result<(), CheckTxError> {
+    let precomputed = precompute(tx_to, prev_outs, consensus_branch_id)?;
+    // zcashd deserialized the transaction, so its ID can be computed.
+    let txid = txid::txid(tx_to).map_err(|_| Error::TxDeserialize)?;
+    check_inputs(
+        &precomputed,
+        txid,
+        prev_outs,
+        flags,
+        consensus_branch_id,
+        policy,
+    )
+}
+
+/// Returns the consensus flags and branch ID of the epoch of the block at
+/// `height` on the network described by `params`.
+fn consensus_rules_at<P: Parameters>(params: &P, height: BlockHeight) -> (u32, u32) {
+    let flags = VerificationFlags::consensus_at(params, height);
+    (flags.bits(), BranchId::for_height(params, height).into())
+}
+
+/// Verifies every transparent input of the serialized transaction `tx_to`,
+/// mined in the block at `height` on the network described by `params`, like
+/// [`verify_tx_outcome`], with the consensus flags and branch ID of that
+/// height's epoch.
+///
+/// This is meant for initial block download, so sync code can verify every
+/// block from genesis in a single pass without its own mapping from heights to
+/// epochs and flags, which could drift from consensus.
+pub fn verify_tx_at<P: Parameters, S: PrevOutSource + ?Sized>(
+    tx_to: &[u8],
+    prev_outs: &S,
+    params: &P,
+    height: BlockHeight,
+) -> Result<TxOutcome, TxError> {
+    let (flags, consensus_branch_id) = consensus_rules_at(params, height);
+    verify_tx_outcome(tx_to, prev_outs, flags, consensus_branch_id)
+}
+
+/// Returns `Ok(())` if every transparent input of the serialized transaction
+/// `tx_to`, mined in the block at `height` on the network described by
+/// `params`, verifies under the consensus rules of that height's epoch, like
+/// [`check_tx`].
+pub fn check_tx_at<P: Parameters, S: PrevOutSource + ?Sized>(
+    tx_to: &[u8],
+    prev_outs: &S,
+    params: &P,
+    height: BlockHeight,
+    policy: FailurePolicy,
+) -> Result<(), CheckTxError> {
+    let (flags, consensus_branch_id) = consensus_rules_at(params, height);
+    check_tx(tx_to, prev_outs, flags, consensus_branch_id, policy)
+}
+
+/// Precomputes `tx_to` with the outputs it spends, checking that there is one
+/// for each input.
+///
+/// zcashd only checks the count for v5 transactions, as earlier versions'
+/// signature hashes don't use the previous outputs, so the inputs without
+/// one would otherwise go unverified.
+fn precompute(
+    tx_to: &[u8],
+    prev_outs: &[(&[u8], i64)],
+    consensus_branch_id: u32,
+) -> Result<PrecomputedTx, Error> {
+    let all_prev_outputs = encode_all_prev_outputs(prev_outs.iter().copied());
+    let precomputed = PrecomputedTx::new_v5(tx_to, &all_prev_outputs)?;
+    let branch_id = BranchId::try_from(consensus_branch_id).map_err(|_| Error::TxDeserialize)?;
+    let tx = Transaction::read(tx_to, branch_id).map_err(|_| Error::TxDeserialize)?;
+    // Like zcashd, coinbase transactions spend no outputs.
+    let spends = match tx.transparent_bundle() {
+        Some(bundle) if !bundle.is_coinbase() => bundle.vin.len(),
+        _ => 0,
+    };
+    if prev_outs.len() != spends {
+        return Err(Error::AllPrevOutputsSizeMismatch);
+    }
+    Ok(precomputed)
+}
+
+fn check_inputs(
+    tx: &PrecomputedTx,
+    txid: TxId,
+    prev_outs: &[(&[u8], i64)],
+    flags: u32,
+    consensus_branch_id: u32,
+    policy: FailurePolicy,
+) -> Result<(), CheckTxError> {
+    let inputs: Vec<_> = prev_outs
+        .iter()
+        .zip(0..)
+        .map(|(&(script_pub_key, amount), n_in)| Input {
+            n_in,
+            script_pub_key,
+            amount,
+            flags,
+        })
+        .collect();
+    tx.verify_many(&inputs, consensus_branch_id, policy)
+        .map_err(|failures| {
+            CheckTxError::Inputs(
+                failures
+                    .into_iter()
+                    .map(|(n_in, error)| InputError { txid, n_in, error })
+                    .collect(),
+            )
+        })
+}
+
+/// Returns the number of transparent signature operations in the serialized
+/// transaction `tx_to`, counted with the legacy (pre-P2SH) rules.
+pub fn legacy_sigop_count(tx_to: &[u8]) -> Result<u32, Error> {
+    let mut err = 0;
+    let count =
+        unsafe { zcash_script_legacy_sigop_count(tx_to.as_ptr(), tx_to.len() as _, &mut err) };
+    if count == u32::MAX {
+        Err(Error::from_code(err))
+    } else {
+        Ok(count)
+    }
+}
+
+/// Returns the version of the C API implemented by the linked library.
+pub fn api_version() -> u32 {
+    unsafe { zcash_script_version() }
+}
+
+/// Panics if the linked library implements a different version of the C API
+/// than this crate was built for, which can only happen when linking a
+/// prebuilt library. The version is only checked once per process.
+pub(crate) fn check_api_version() {
+    static CHECKED: Once = Once::new();
+    CHECKED.call_once(|| {
+        let linked = api_version();
+        assert!(
+            linked == crate::ZCASH_SCRIPT_API_VER,
+            "libzcash_script implements C API version {}, but this crate requires version {}",
+            linked,
+            crate::ZCASH_SCRIPT_API_VER,
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use zcash_primitives::consensus::{MainNetwork, NetworkUpgrade};
+
+    use super::*;
+    use crate::{
+        prevout::{OutPoint, PrevOut},
+        script::Script,
+        tests::{SCRIPT_PUBKEY, SCRIPT_TX},
+    };
+    use hex::FromHex;
+
+    #[test]
+    fn reports_errors() {
+        let amount = 212 * 100_000_000;
+        verify_script(&SCRIPT_PUBKEY, amount, &SCRIPT_TX, 0, 1, 0x2bb40e60).unwrap();
+        assert_eq!(
+            verify_script(&SCRIPT_PUBKEY, amount, &SCRIPT_TX, 0, 1, 0x2bb40e61),
+            Err(Error::ScriptInvalid)
+        );
+        assert_eq!(
+            verify_script(&SCRIPT_PUBKEY, amount, &SCRIPT_TX, 1, 1, 0x2bb40e60),
+            Err(Error::TxIndex)
+        );
+        assert_eq!(
+            verify_script_v5(&SCRIPT_TX, &[], 0, 1, 0x2bb40e60),
+            Err(Error::AllPrevOutputsDeserialize)
+        );
+
+        assert_eq!(legacy_sigop_count(&SCRIPT_TX), Ok(1));
+        assert_eq!(legacy_sigop_count(&[]), Err(Error::TxDeserialize));
+        assert_eq!(api_version(), crate::ZCASH_SCRIPT_API_VER);
+        check_api_version();
+
+        for code in 0..9 {
+            assert_eq!(Error::from_code(code).code(), code);
+        }
+    }
+
+    #[test]
+    fn displays_failure_context() {
+        let amount = 212 * 100_000_000;
+        let flags = VerificationFlags::P2SH | VerificationFlags::CHECKLOCKTIMEVERIFY;
+        let verify = |amount, n_in| {
+            verify_script_detailed(&SCRIPT_PUBKEY, amount, &SCRIPT_TX, n_in, flags, 0x2bb40e60)
+        };
+        assert_eq!(verify(amount, 0), Ok(()));
+
+        let failure = verify(amount - 1, 0).unwrap_err();
+        assert_eq!(failure.error, Error::ScriptInvalid);
+        assert_eq!(failure.script_error, Some(ScriptError::EvalFalse));
+        assert_eq!(
+            failure.to_string(),
+            "script verification failed: Script evaluated without error but finished with a \
+             false/empty top stack element"
+        );
+        assert_eq!(
+            format!("{:#}", failure),
+            format!(
+                "{}\n  flags: P2SH|CHECKLOCKTIMEVERIFY\n  scriptPubKey: {}",
+                failure,
+                Script::from(&SCRIPT_PUBKEY[..]).to_asm()
+            )
+        );
+
+        let failure = verify(amount, 1).unwrap_err();
+        assert_eq!(failure.script_error, None);
+        assert_eq!(failure.to_string(), "input index out of range");
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializes_errors_as_names() {
+        for (error, json) in [
+            (Error::ScriptInvalid, "\"script_invalid\""),
+            (
+                Error::AllPrevOutputsSizeMismatch,
+                "\"all_prev_outputs_size_mismatch\"",
+            ),
+            (Error::Unknown(9), "{\"unknown\":9}"),
+        ] {
+            assert_eq!(serde_json::to_string(&error).unwrap(), json);
+            assert_eq!(serde_json::from_str::<Error>(json).unwrap(), error);
+        }
+    }
+
+    #[test]
+    fn verifies_whole_transactions() {
+        let amount = 212 * 100_000_000;
+        let prev_out = PrevOut {
+            script_pub_key: Script(SCRIPT_PUBKEY.to_vec()),
+            amount,
+        };
+        let lookup = |_: &OutPoint| Some(prev_out.clone());
+        assert_eq!(
+            verify_tx(&SCRIPT_TX, &lookup, 1, 0x2bb40e60),
+            Ok(vec![Ok(())])
+        );
+        assert_eq!(
+            verify_tx(&SCRIPT_TX, &lookup, 1, 0x2bb40e61),
+            Err(TxError::Tx(Error::TxDeserialize))
+        );
+
+        let mut utxos = BTreeMap::new();
+        assert_eq!(
+            verify_tx(&SCRIPT_TX, &utxos, 1, 0x2bb40e60),
+            Err(TxError::MissingPrevOut(0))
+        );
+        let wrong_amount = PrevOut {
+            amount: amount - 1,
+            ..prev_out.clone()
+        };
+        let mut txid = [0; 32];
+        hex::decode_to_slice(
+            "fcaf44919d4a17f6181a02a7ebe0420be6f7dad1ef86755b81d5a9567456653c",
+            &mut txid,
+        )
+        .unwrap();
+        utxos.insert(OutPoint::new(txid, 1), wrong_amount);
+        assert_eq!(
+            verify_tx(&SCRIPT_TX, &utxos, 1, 0x2bb40e60),
+            Ok(vec![Err(Error::ScriptInvalid)])
+        );
+        assert!(!verify_tx_outcome(&SCRIPT_TX, &utxos, 1, 0x2bb40e60)
+            .unwrap()
+            .is_valid());
+
+        // The v4 transaction has one input, which needs one previous output.
+        let prev_out = (&SCRIPT_PUBKEY[..], amount);
+        assert_eq!(
+            verify_tx_with_prevouts(&SCRIPT_TX, &[prev_out], 1, 0x2bb40e60),
+            Ok(vec![Ok(())])
+        );
+        for prev_outs in [&[][..], &[prev_out, prev_out]] {
+            assert_eq!(
+                verify_tx_with_prevouts(&SCRIPT_TX, prev_outs, 1, 0x2bb40e60),
+                Err(Error::AllPrevOutputsSizeMismatch)
+            );
+        }
+    }
+
+    #[test]
+    fn verifies_transactions_at_heights() {
+        let prev_out = PrevOut {
+            script_pub_key: Script(SCRIPT_PUBKEY.to_vec()),
+            amount: 212 * 100_000_000,
+        };
+        let lookup = |_: &OutPoint| Some(prev_out.clone());
+        // The transaction was mined during Blossom.
+        let height = BlockHeight::from_u32(854883);
+        assert_eq!(
+            verify_tx_at(&SCRIPT_TX, &lookup, &MainNetwork, height),
+            Ok(TxOutcome::Verified(vec![Ok(())]))
+        );
+        assert_eq!(
+            check_tx_at(
+                &SCRIPT_TX,
+                &lookup,
+                &MainNetwork,
+                height,
+                FailurePolicy::Abort
+            ),
+            Ok(())
+        );
+
+        // Its signature commits to Blossom's branch ID, so it is invalid in
+        // other epochs.
+        for upgrade in [NetworkUpgrade::Sapling, NetworkUpgrade::Nu5] {
+            let height = MainNetwork.activation_height(upgrade).unwrap();
+            assert_eq!(
+                verify_tx_at(&SCRIPT_TX, &lookup, &MainNetwork, height),
+                Ok(TxOutcome::Verified(vec![Err(Error::ScriptInvalid)])),
+                "{upgrade:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn reports_shielded_only_transactions() {
+        // A v5 transaction with no transparent inputs or outputs, and empty
+        // Sapling and Orchard bundles.
+        let shielded_only = Vec::from_hex(concat!(
+            "050000800a27a726b4d0d6c2",
+            "0000000000000000",
+            "0000",
+            "0000",
+            "00"
+        ))
+        .unwrap();
+        let nu5 = 0xc2d6d0b4;
+        let lookup = |_: &OutPoint| -> Option<PrevOut> { None };
+        assert_eq!(
+            verify_tx_outcome(&shielded_only, &lookup, 1, nu5),
+            Ok(TxOutcome::NothingToVerify)
+        );
+        assert!(TxOutcome::NothingToVerify.is_valid());
+        assert_eq!(verify_tx(&shielded_only, &lookup, 1, nu5), Ok(vec![]));
+        assert_eq!(
+            check_tx(&shielded_only, &lookup, 1, nu5, FailurePolicy::Abort),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn reports_failing_inputs_with_context() {
+        let amount = 212 * 100_000_000;
+        let txid = txid::txid(&SCRIPT_TX).unwrap();
+        let utxos: BTreeMap<OutPoint, PrevOut> = BTreeMap::new();
+        assert_eq!(
+            check_tx(&SCRIPT_TX, &utxos, 1, 0x2bb40e60, FailurePolicy::Abort),
+            Err(CheckTxError::MissingPrevOut { txid, n_in: 0 })
+        );
+
+        let prev_out = PrevOut {
+            script_pub_key: Script(SCRIPT_PUBKEY.to_vec()),
+            amount,
+        };
+        let lookup = |_: &OutPoint| Some(prev_out.clone());
+        assert_eq!(
+            check_tx(&SCRIPT_TX, &lookup, 1, 0x2bb40e60, FailurePolicy::Abort),
+            Ok(())
+        );
+        let wrong_amount = PrevOut {
+            amount: amount - 1,
+            ..prev_out
+        };
+        let lookup = |_: &OutPoint| Some(wrong_amount.clone());
+        let err = check_tx(
+            &SCRIPT_TX,
+            &lookup,
+            1,
+            0x2bb40e60,
+            FailurePolicy::CollectAll,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err,
+            CheckTxError::Inputs(vec![InputError {
+                txid,
+                n_in: 0,
+                error: Error::ScriptInvalid
+            }])
+        );
+        assert_eq!(
+            err.to_string(),
+            format!("input 0 of {}: script verification failed", txid)
+        );
+
+        assert_eq!(
+            check_tx_with_prevouts(
+                &SCRIPT_TX,
+                &[(&SCRIPT_PUBKEY[..], amount - 1)],
+                1,
+                0x2bb40e60,
+                FailurePolicy::Abort
+            ),
+            Err(err)
+        );
+        assert_eq!(
+            check_tx_with_prevouts(&[], &[], 1, 0x2bb40e60, FailurePolicy::Abort),
+            Err(CheckTxError::Tx(Error::TxDeserialize))
+        );
+        // Without a previous output, the input would go unverified.
+        assert_eq!(
+            check_tx_with_prevouts(&SCRIPT_TX, &[], 1, 0x2bb40e60, FailurePolicy::Abort),
+            Err(CheckTxError::Tx(Error::AllPrevOutputsSizeMismatch))
+        );
+    }
+}
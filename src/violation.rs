@@ -0,0 +1,271 @@
+//! Structured records of consensus-rule rejections, for log pipelines.
+//!
+//! Install a [`ViolationSink`] with [`set_sink`] to receive a [`Violation`]
+//! for every rejection made through the checks in this module, which wrap
+//! [`verify::check_tx`] and [`lock_time::check_tx_lock_and_expiry`] with the
+//! block height being validated. Each record can be written as a line of
+//! JSON with [`Violation::to_json`].
+
+use std::{
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use zcash_primitives::transaction::Transaction;
+
+use crate::{
+    decode::json_string,
+    lock_time::{self, LockTimeError},
+    precomputed::FailurePolicy,
+    prevout::PrevOutSource,
+    txid::TxId,
+    verify::{self, CheckTxError},
+};
+
+/// The kind of consensus rule a transaction broke.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Category {
+    /// The transaction couldn't be deserialized, or didn't match the outputs
+    /// it spends.
+    Transaction,
+    /// An input spends an output that wasn't found.
+    MissingPrevOut,
+    /// An input's scripts failed to verify.
+    Script,
+    /// The transaction's lock time or expiry height doesn't allow it at the
+    /// height.
+    LockTime,
+}
+
+impl Category {
+    /// Returns the name used for this category in [`Violation::to_json`].
+    pub fn name(self) -> &'static str {
+        match self {
+            Category::Transaction => "transaction",
+            Category::MissingPrevOut => "missing-prevout",
+            Category::Script => "script",
+            Category::LockTime => "lock-time",
+        }
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A record of a consensus-rule rejection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub category: Category,
+    /// The description of the error.
+    pub error: String,
+    /// The rejected transaction, unless it couldn't be deserialized.
+    pub txid: Option<TxId>,
+    /// The input that broke the rule, for input rules.
+    pub n_in: Option<u32>,
+    /// The height of the block being validated.
+    pub height: u32,
+    /// The script verification flags, for script rules.
+    pub flags: Option<u32>,
+}
+
+impl Violation {
+    /// Returns the records of the rejections in `err`, one per failing input.
+    pub fn from_check_tx(err: &CheckTxError, flags: u32, height: u32) -> Vec<Violation> {
+        let violation = |category, error: &dyn fmt::Display, txid, n_in| Violation {
+            category,
+            error: error.to_string(),
+            txid,
+            n_in,
+            height,
+            flags: Some(flags),
+        };
+        match err {
+            CheckTxError::Tx(error) => vec![violation(Category::Transaction, error, None, None)],
+            CheckTxError::MissingPrevOut { txid, n_in } => vec![violation(
+                Category::MissingPrevOut,
+                &"output not found",
+                Some(*txid),
+                Some(*n_in),
+            )],
+            CheckTxError::Inputs(errors) => errors
+                .iter()
+                .map(|err| violation(Category::Script, &err.error, Some(err.txid), Some(err.n_in)))
+                .collect(),
+        }
+    }
+
+    /// Returns the record as a JSON object, with `null` for missing fields.
+    pub fn to_json(&self) -> String {
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+        format!(
+            "{{\"category\":{},\"error\":{},\"txid\":{},\"nIn\":{},\"height\":{},\"flags\":{}}}",
+            json_string(self.category.name()),
+            json_string(&self.error),
+            optional(self.txid.map(|txid| json_string(&txid.to_string()))),
+            optional(self.n_in.map(|n_in| n_in.to_string())),
+            self.height,
+            optional(self.flags.map(|flags| flags.to_string())),
+        )
+    }
+}
+
+/// A recipient of [`Violation`]s.
+///
+/// This is called on the thread that ran the check, so slow sinks should
+/// queue records for another thread to write.
+pub trait ViolationSink: Send + Sync {
+    /// Called for each rejection.
+    fn on_violation(&self, violation: &Violation);
+}
+
+static SINK: RwLock<Option<Arc<dyn ViolationSink>>> = RwLock::new(None);
+
+/// Installs `sink`, replacing any previously installed sink.
+pub fn set_sink(sink: Arc<dyn ViolationSink>) {
+    *SINK.write().expect("lock is not poisoned") = Some(sink);
+}
+
+/// Removes the installed sink, if any.
+pub fn clear_sink() {
+    *SINK.write().expect("lock is not poisoned") = None;
+}
+
+/// Sends the records returned by `violations` to the installed sink, only
+/// creating them if there is one.
+fn report(violations: impl FnOnce() -> Vec<Violation>) {
+    let Some(sink) = SINK.read().expect("lock is not poisoned").clone() else {
+        return;
+    };
+    for violation in violations() {
+        sink.on_violation(&violation);
+    }
+}
+
+/// Checks the transparent inputs of the serialized transaction `tx_to` in the
+/// block at `height`, like [`verify::check_tx`], reporting a rejection to the
+/// installed sink.
+pub fn check_tx<S: PrevOutSource + ?Sized>(
+    tx_to: &[u8],
+    prev_outs: &S,
+    flags: u32,
+    consensus_branch_id: u32,
+    height: u32,
+    policy: FailurePolicy,
+) -> Result<(), CheckTxError> {
+    verify::check_tx(tx_to, prev_outs, flags, consensus_branch_id, policy).map_err(|err| {
+        report(|| Violation::from_check_tx(&err, flags, height));
+        err
+    })
+}
+
+/// Checks the lock time and expiry height of `tx` in the block at `height`,
+/// like [`lock_time::check_tx_lock_and_expiry`], reporting a rejection to the
+/// installed sink.
+pub fn check_tx_lock_and_expiry(
+    tx: &Transaction,
+    height: u32,
+    median_time_past: i64,
+) -> Result<(), LockTimeError> {
+    lock_time::check_tx_lock_and_expiry(tx, height, median_time_past).map_err(|err| {
+        report(|| {
+            vec![Violation {
+                category: Category::LockTime,
+                error: err.to_string(),
+                txid: Some(TxId(*tx.txid().as_ref())),
+                n_in: None,
+                height,
+                flags: None,
+            }]
+        });
+        err
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use zcash_primitives::consensus::BranchId;
+
+    use super::*;
+    use crate::{
+        prevout::{OutPoint, PrevOut},
+        script::Script,
+        tests::{SCRIPT_PUBKEY, SCRIPT_TX},
+        txid,
+    };
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Violation>>);
+
+    impl ViolationSink for Recorder {
+        fn on_violation(&self, violation: &Violation) {
+            self.0.lock().unwrap().push(violation.clone());
+        }
+    }
+
+    #[test]
+    fn reports_rejections() {
+        let recorder = Arc::new(Recorder::default());
+        set_sink(recorder.clone());
+
+        let wrong_amount = PrevOut {
+            script_pub_key: Script(SCRIPT_PUBKEY.to_vec()),
+            amount: 212 * 100_000_000 - 1,
+        };
+        let lookup = |_: &OutPoint| Some(wrong_amount.clone());
+        let height = 854_900;
+        assert!(check_tx(
+            &SCRIPT_TX,
+            &lookup,
+            1,
+            0x2bb40e60,
+            height,
+            FailurePolicy::Abort
+        )
+        .is_err());
+        let tx = Transaction::read(&SCRIPT_TX[..], BranchId::Blossom).unwrap();
+        assert_eq!(
+            check_tx_lock_and_expiry(&tx, 854_934, 0),
+            Err(LockTimeError::Expired)
+        );
+        assert_eq!(check_tx_lock_and_expiry(&tx, height, 0), Ok(()));
+        clear_sink();
+
+        let txid = txid::txid(&SCRIPT_TX).unwrap();
+        let violations = recorder.0.lock().unwrap();
+        assert_eq!(
+            *violations,
+            [
+                Violation {
+                    category: Category::Script,
+                    error: verify::Error::ScriptInvalid.to_string(),
+                    txid: Some(txid),
+                    n_in: Some(0),
+                    height,
+                    flags: Some(1),
+                },
+                Violation {
+                    category: Category::LockTime,
+                    error: LockTimeError::Expired.to_string(),
+                    txid: Some(txid),
+                    n_in: None,
+                    height: 854_934,
+                    flags: None,
+                },
+            ]
+        );
+        assert_eq!(
+            violations[1].to_json(),
+            format!(
+                "{{\"category\":\"lock-time\",\"error\":{},\"txid\":\"{}\",\"nIn\":null,\
+                \"height\":854934,\"flags\":null}}",
+                json_string(&LockTimeError::Expired.to_string()),
+                txid
+            )
+        );
+    }
+}
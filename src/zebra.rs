@@ -0,0 +1,139 @@
+//! Conversions from `zebra-chain`'s transactions and outputs to what this
+//! crate's whole-transaction verifiers expect.
+//!
+//! Zebra holds transactions, outpoints and unspent outputs as `zebra-chain`
+//! values. These conversions, [`verify_transaction`] and
+//! [`verify_transaction_with_outputs`] let it verify with this crate without
+//! its own translation glue.
+
+use std::collections::HashMap;
+
+use zebra_chain::{
+    parameters::NetworkUpgrade,
+    serialization::ZcashSerialize,
+    transaction::{self, Transaction},
+    transparent,
+};
+
+use crate::{
+    prevout::{OutPoint, PrevOut, PrevOutSource},
+    verify::{self, Error, TxError, TxOutcome},
+};
+
+impl From<&transparent::Output> for PrevOut {
+    fn from(output: &transparent::Output) -> Self {
+        PrevOut {
+            script_pub_key: output.lock_script.as_raw_bytes().to_vec().into(),
+            amount: output.value.into(),
+        }
+    }
+}
+
+impl From<transparent::Output> for PrevOut {
+    fn from(output: transparent::Output) -> Self {
+        PrevOut::from(&output)
+    }
+}
+
+/// Returns `outpoint` as this crate's [`OutPoint`].
+pub fn outpoint(outpoint: &transparent::OutPoint) -> OutPoint {
+    OutPoint::new(outpoint.hash.0, outpoint.index)
+}
+
+impl PrevOutSource for HashMap<transparent::OutPoint, transparent::Output> {
+    fn prev_out(&self, outpoint: &OutPoint) -> Option<PrevOut> {
+        let outpoint = transparent::OutPoint {
+            hash: transaction::Hash(*outpoint.hash()),
+            index: outpoint.n(),
+        };
+        self.get(&outpoint).map(PrevOut::from)
+    }
+}
+
+/// Returns the consensus branch ID of `network_upgrade`, which is zero before
+/// Overwinter.
+fn branch_id(network_upgrade: NetworkUpgrade) -> u32 {
+    network_upgrade.branch_id().map_or(0, u32::from)
+}
+
+/// Verifies every transparent input of `tx` under `flags`, like
+/// [`verify_tx_outcome`](verify::verify_tx_outcome), in the epoch of
+/// `network_upgrade`, looking up the spent outputs in `prev_outs`.
+pub fn verify_transaction<S: PrevOutSource + ?Sized>(
+    tx: &Transaction,
+    prev_outs: &S,
+    flags: u32,
+    network_upgrade: NetworkUpgrade,
+) -> Result<TxOutcome, TxError> {
+    let tx_to = tx
+        .zcash_serialize_to_vec()
+        .expect("writes to a Vec succeed");
+    verify::verify_tx_outcome(&tx_to, prev_outs, flags, branch_id(network_upgrade))
+}
+
+/// Verifies every transparent input of `tx` under `flags`, like
+/// [`verify_tx_with_prevouts`](verify::verify_tx_with_prevouts), in the
+/// epoch of `network_upgrade`, where `spent_outputs` are the outputs spent by
+/// the inputs, in order.
+pub fn verify_transaction_with_outputs(
+    tx: &Transaction,
+    spent_outputs: &[transparent::Output],
+    flags: u32,
+    network_upgrade: NetworkUpgrade,
+) -> Result<Vec<Result<(), Error>>, Error> {
+    let tx_to = tx
+        .zcash_serialize_to_vec()
+        .expect("writes to a Vec succeed");
+    let prev_outs: Vec<(&[u8], i64)> = spent_outputs
+        .iter()
+        .map(|output| (output.lock_script.as_raw_bytes(), output.value.into()))
+        .collect();
+    verify::verify_tx_with_prevouts(&tx_to, &prev_outs, flags, branch_id(network_upgrade))
+}
+
+#[cfg(test)]
+mod tests {
+    use zebra_chain::{amount::Amount, serialization::ZcashDeserialize};
+
+    use super::*;
+    use crate::tests::{SCRIPT_PUBKEY, SCRIPT_TX};
+
+    #[test]
+    fn verifies_zebra_transactions() {
+        let tx = Transaction::zcash_deserialize(&SCRIPT_TX[..]).unwrap();
+        let zebra_outpoint = tx.inputs()[0].outpoint().unwrap();
+        let output = transparent::Output {
+            value: Amount::try_from(212 * 100_000_000).unwrap(),
+            lock_script: transparent::Script::new(&SCRIPT_PUBKEY),
+        };
+
+        let prev_out = PrevOut::from(&output);
+        assert_eq!(prev_out.script_pub_key.as_bytes(), &SCRIPT_PUBKEY[..]);
+        assert_eq!(prev_out.amount, 212 * 100_000_000);
+        assert_eq!(outpoint(&zebra_outpoint).n(), 1);
+
+        let mut utxos = HashMap::<transparent::OutPoint, transparent::Output>::new();
+        assert_eq!(
+            verify_transaction(&tx, &utxos, 1, NetworkUpgrade::Blossom),
+            Err(TxError::MissingPrevOut(0))
+        );
+        utxos.insert(zebra_outpoint, output.clone());
+        assert_eq!(
+            verify_transaction(&tx, &utxos, 1, NetworkUpgrade::Blossom),
+            Ok(TxOutcome::Verified(vec![Ok(())]))
+        );
+        assert_eq!(
+            verify_transaction(&tx, &utxos, 1, NetworkUpgrade::Heartwood),
+            Ok(TxOutcome::Verified(vec![Err(Error::ScriptInvalid)]))
+        );
+
+        assert_eq!(
+            verify_transaction_with_outputs(&tx, &[output], 1, NetworkUpgrade::Blossom),
+            Ok(vec![Ok(())])
+        );
+        assert_eq!(
+            verify_transaction_with_outputs(&tx, &[], 1, NetworkUpgrade::Blossom),
+            Err(Error::AllPrevOutputsSizeMismatch)
+        );
+    }
+}
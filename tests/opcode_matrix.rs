@@ -0,0 +1,166 @@
+//! Runs every opcode against a matrix of stack preconditions, checking the C++
+//! interpreter against this crate's static analyses.
+//!
+//! Each opcode is placed in a scriptPubKey, both executed and in an
+//! unexecuted branch, and spent by each scriptSig in [`preconditions`]. The
+//! results must agree with `spendability::classify`, which claims to know
+//! the outcome for some scripts whatever the scriptSig, and with
+//! `diagnostics::diagnose` for upgradable NOPs. A refactor of either side that
+//! changes any of the thousands of combinations shows up here.
+
+use zcash_script::{
+    diagnostics::{diagnose, Violation},
+    flags::VerificationFlags,
+    opcode::Opcode,
+    script,
+    script::{Script, MAX_PUBKEYS_PER_MULTISIG, MAX_SCRIPT_ELEMENT_SIZE},
+    spendability::{classify, Spendability},
+    verify::verify_script,
+};
+
+/// Blossom and NU5, whose script rules are the same.
+const BRANCH_IDS: [u32; 2] = [0x2bb4_0e60, 0xc2d6_d0b4];
+
+/// The scriptSigs each opcode is run after, so that it starts with an empty
+/// stack, falsy and truthy items, items of the boundary sizes, and a stack
+/// deep enough for `OP_CHECKMULTISIG`.
+fn preconditions() -> Vec<(&'static str, Script)> {
+    vec![
+        ("empty", script!()),
+        ("false", script!(OP_0)),
+        ("true", script!(1)),
+        ("negative", script!(OP_1NEGATE)),
+        ("two items", script!(1 2)),
+        ("empty push", script!([[0x80]])),
+        ("max element", script!([[0x01; MAX_SCRIPT_ELEMENT_SIZE]])),
+        (
+            "deep stack",
+            Script(vec![Opcode::OP_1.0; MAX_PUBKEYS_PER_MULTISIG + 3]),
+        ),
+    ]
+}
+
+fn write_compact_size(out: &mut Vec<u8>, n: usize) {
+    match n {
+        0..=0xfc => out.push(n as u8),
+        _ => {
+            out.push(0xfd);
+            out.extend((n as u16).to_le_bytes());
+        }
+    }
+}
+
+/// Serializes a version 1 transaction with one input, spending `script_sig`,
+/// and one zero-value output.
+fn spending_transaction(script_sig: &Script) -> Vec<u8> {
+    let mut tx = 1u32.to_le_bytes().to_vec();
+    tx.push(1);
+    tx.extend([0; 32]);
+    tx.extend(0u32.to_le_bytes());
+    write_compact_size(&mut tx, script_sig.len());
+    tx.extend(script_sig.as_bytes());
+    tx.extend(u32::MAX.to_le_bytes());
+    tx.push(1);
+    tx.extend(0i64.to_le_bytes());
+    tx.push(0);
+    tx.extend(0u32.to_le_bytes());
+    tx
+}
+
+fn verifies(script_sig: &Script, script_pub_key: &Script, flags: VerificationFlags) -> bool {
+    let tx = spending_transaction(script_sig);
+    let results: Vec<bool> = BRANCH_IDS
+        .iter()
+        .map(|&branch_id| {
+            verify_script(
+                script_pub_key.as_bytes(),
+                0,
+                &tx,
+                0,
+                flags.bits(),
+                branch_id,
+            )
+            .is_ok()
+        })
+        .collect();
+    assert!(
+        results.iter().all(|&ok| ok == results[0]),
+        "result depends on the branch ID for {} spending {}",
+        script_sig.to_asm(),
+        script_pub_key.to_asm()
+    );
+    results[0]
+}
+
+#[test]
+fn executed_opcodes() {
+    let mut failures = vec![];
+    for opcode in (0..=u8::MAX).map(Opcode) {
+        let script_pub_key = Script(vec![opcode.0]);
+        let expected = match classify(&script_pub_key) {
+            Spendability::Unspendable(_) => Some(false),
+            Spendability::AnyoneCanSpend => Some(true),
+            Spendability::Conditional => None,
+        };
+        for (name, script_sig) in preconditions() {
+            let ok = verifies(&script_sig, &script_pub_key, VerificationFlags::NONE);
+            if expected.is_some_and(|expected| expected != ok) {
+                failures.push(format!("{} after {}: {}", opcode.name(), name, ok));
+            }
+
+            // Upgradable NOPs are only discouraged by policy, so a spend that
+            // passes must fail when they are discouraged.
+            let flags = VerificationFlags::DISCOURAGE_UPGRADABLE_NOPS;
+            let discouraged = diagnose(&script_sig, &script_pub_key, flags)
+                .iter()
+                .any(|d| d.violation == Violation::DiscourageUpgradableNops);
+            if discouraged && ok && verifies(&script_sig, &script_pub_key, flags) {
+                failures.push(format!(
+                    "{} after {} is not discouraged",
+                    opcode.name(),
+                    name
+                ));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} failures:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn unexecuted_opcodes() {
+    let mut failures = vec![];
+    // Push opcodes would consume the rest of the script as their operands.
+    for opcode in (Opcode::OP_16.0 + 1..=u8::MAX).map(Opcode) {
+        let mut script_pub_key = Script::default();
+        script_pub_key
+            .push_opcode(Opcode::OP_0)
+            .push_opcode(Opcode::OP_IF)
+            .push_opcode(opcode)
+            .push_opcode(Opcode::OP_ENDIF)
+            .push_num(1);
+        // Only the checks made regardless of execution can fail the script.
+        let expected = !matches!(classify(&script_pub_key), Spendability::Unspendable(_));
+        for (name, script_sig) in preconditions() {
+            let ok = verifies(&script_sig, &script_pub_key, VerificationFlags::NONE);
+            if ok != expected {
+                failures.push(format!(
+                    "unexecuted {} after {}: {}",
+                    opcode.name(),
+                    name,
+                    ok
+                ));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} failures:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
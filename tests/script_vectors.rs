@@ -0,0 +1,163 @@
+//! Runs the script test vectors shipped with zcashd through `zcash_script`.
+//!
+//! `script_valid.json` and `script_invalid.json` are zcashd's copies of the
+//! Bitcoin Core vectors for the opcodes both chains share, updated for the
+//! intentional Zcash consensus differences (such as the removal of DERSIG and
+//! CHECKSEQUENCEVERIFY). A failure here means this crate's build of the C++
+//! interpreter diverges from the behavior the vectors document.
+
+use std::{fs, path::Path};
+
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use zcash_script::{opcode::Opcode, script::Script, verify::verify_script};
+
+const DATA_DIR: &str = "depend/zcash/src/test/data";
+
+/// The consensus branch IDs of every network upgrade, like zcashd's tests.
+const BRANCH_IDS: [u32; 7] = [
+    0,
+    0x5ba8_1b19,
+    0x76b8_09bb,
+    0x2bb4_0e60,
+    0xf5b9_230b,
+    0xe9ff_75a6,
+    0xc2d6_d0b4,
+];
+
+/// Parses a script in the test vector format, matching zcashd's `ParseScript`.
+fn parse_script(s: &str) -> Script {
+    let mut script = Script::default();
+    for word in s.split_whitespace() {
+        let digits = word.strip_prefix('-').unwrap_or(word);
+        if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) {
+            script.push_num(word.parse().expect("number fits in i64"));
+        } else if let Some(hex) = word.strip_prefix("0x").filter(|hex| !hex.is_empty()) {
+            script.0.extend(hex::decode(hex).expect("valid raw hex"));
+        } else if word.len() >= 2 && word.starts_with('\'') && word.ends_with('\'') {
+            script.push_slice(word[1..word.len() - 1].as_bytes());
+        } else {
+            script.push_opcode(parse_opcode(word));
+        }
+    }
+    script
+}
+
+fn parse_opcode(word: &str) -> Opcode {
+    (Opcode::OP_NOP.0..=Opcode::OP_NOP10.0)
+        .map(Opcode)
+        .chain([Opcode::OP_RESERVED])
+        .find(|op| {
+            let name = op.name();
+            name != "OP_UNKNOWN" && (name == word || name.strip_prefix("OP_") == Some(word))
+        })
+        .unwrap_or_else(|| panic!("unknown opcode {}", word))
+}
+
+fn parse_flags(flags: &str) -> u32 {
+    flags
+        .split(',')
+        .filter(|flag| !flag.is_empty())
+        .map(|flag| match flag {
+            "NONE" => 0,
+            "P2SH" => 1 << 0,
+            "STRICTENC" => 1 << 1,
+            "LOW_S" => 1 << 3,
+            "NULLDUMMY" => 1 << 4,
+            "SIGPUSHONLY" => 1 << 5,
+            "MINIMALDATA" => 1 << 6,
+            "DISCOURAGE_UPGRADABLE_NOPS" => 1 << 7,
+            "CLEANSTACK" => 1 << 8,
+            "CHECKLOCKTIMEVERIFY" => 1 << 9,
+            _ => panic!("unknown flag {}", flag),
+        })
+        .fold(0, |flags, flag| flags | flag)
+}
+
+fn write_compact_size(out: &mut Vec<u8>, n: usize) {
+    match n {
+        0..=0xfc => out.push(n as u8),
+        0xfd..=0xffff => {
+            out.push(0xfd);
+            out.extend((n as u16).to_le_bytes());
+        }
+        _ => {
+            out.push(0xfe);
+            out.extend((n as u32).to_le_bytes());
+        }
+    }
+}
+
+/// Serializes a version 1 transaction with one input and one zero-value output.
+fn v1_transaction(prevout: ([u8; 32], u32), script_sig: &[u8], script_pub_key: &[u8]) -> Vec<u8> {
+    let mut tx = 1u32.to_le_bytes().to_vec();
+    tx.push(1);
+    tx.extend(prevout.0);
+    tx.extend(prevout.1.to_le_bytes());
+    write_compact_size(&mut tx, script_sig.len());
+    tx.extend(script_sig);
+    tx.extend(u32::MAX.to_le_bytes());
+    tx.push(1);
+    tx.extend(0i64.to_le_bytes());
+    write_compact_size(&mut tx, script_pub_key.len());
+    tx.extend(script_pub_key);
+    tx.extend(0u32.to_le_bytes());
+    tx
+}
+
+/// Builds the spending transaction of zcashd's `BuildSpendingTransaction`,
+/// which spends the output of `BuildCreditingTransaction`.
+fn spending_transaction(script_sig: &Script, script_pub_key: &Script) -> Vec<u8> {
+    let mut coinbase_sig = Script::default();
+    coinbase_sig.push_num(0).push_num(0);
+    let credit = v1_transaction(
+        ([0; 32], u32::MAX),
+        coinbase_sig.as_bytes(),
+        script_pub_key.as_bytes(),
+    );
+    let txid = Sha256::digest(Sha256::digest(&credit)).into();
+    v1_transaction((txid, 0), script_sig.as_bytes(), &[])
+}
+
+fn run_vectors(file: &str, expect_valid: bool) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join(DATA_DIR)
+        .join(file);
+    let vectors: Vec<Vec<Value>> =
+        serde_json::from_str(&fs::read_to_string(path).expect("vectors are readable"))
+            .expect("vectors are valid JSON");
+
+    let mut failures = vec![];
+    // Entries with fewer than 3 elements are comments.
+    for vector in vectors.iter().filter(|vector| vector.len() >= 3) {
+        let field = |i: usize| vector[i].as_str().expect("fields are strings");
+        let script_sig = parse_script(field(0));
+        let script_pub_key = parse_script(field(1));
+        let flags = parse_flags(field(2));
+        let tx = spending_transaction(&script_sig, &script_pub_key);
+
+        for branch_id in BRANCH_IDS {
+            let result = verify_script(script_pub_key.as_bytes(), 0, &tx, 0, flags, branch_id);
+            if result.is_ok() != expect_valid {
+                failures.push(format!("{:x}: {:?}: {:?}", branch_id, result, vector));
+            }
+        }
+    }
+
+    assert!(
+        failures.is_empty(),
+        "{} failures:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn script_valid() {
+    run_vectors("script_valid.json", true);
+}
+
+#[test]
+fn script_invalid() {
+    run_vectors("script_invalid.json", false);
+}